@@ -0,0 +1,173 @@
+//! Amplitude and bulk-shift calibration of synthetics against observed data
+//!
+//! Synthetics are in reflection-coefficient units and field data in whatever
+//! the processing left behind, and a small datum or wavelet-phase error moves
+//! every event by the same time. Left in, both dominate a least-squares misfit.
+//! The calibration estimates the bulk shift from the cross-correlation in an
+//! analysis window, aligns the synthetic, then fits the least-squares scalar
+//! in the window and, optionally, a slowly varying gain from the ratio of
+//! local RMS amplitudes.
+
+use anyhow::{Result, anyhow};
+use std::ops::Range;
+use super::subsample::{estimate_lag, sinc_shift, LagMethod};
+
+///Settings for calibrating a synthetic to an observed trace
+#[derive(Debug, Clone)]
+pub struct AmplitudeCalibration{
+    ///Samples used for the estimate; `None` uses the whole trace
+    pub window: Option<Range<usize>>,
+    ///Largest bulk shift searched, in samples either way
+    pub max_lag: usize,
+    pub lag_method: LagMethod,
+    ///Half-width in samples of the RMS windows for a time-varying gain; `None` fits a single scalar
+    pub gain_half_width: Option<usize>,
+}
+
+impl Default for AmplitudeCalibration{
+    fn default()-> Self{
+        Self{
+            window: None,
+            max_lag: 20,
+            lag_method: LagMethod::Parabolic,
+            gain_half_width: None,
+        }
+    }
+}
+
+///Estimated mapping from a synthetic to an observed trace
+#[derive(Debug, Clone)]
+pub struct Calibration{
+    ///Delay applied to the synthetic in samples (positive moves it later)
+    pub shift: f64,
+    ///Least-squares scalar in the window after alignment
+    pub scale: f64,
+    ///Time-varying gain per sample, replacing the scalar when present
+    pub gain: Option<Vec<f64>>,
+    ///Normalised correlation at the bulk shift
+    pub correlation: f64,
+    ///RMS of observed minus synthetic in the window, before and after calibration
+    pub misfit_before: f64,
+    pub misfit_after: f64,
+}
+
+impl Calibration{
+    ///Shift and scale a synthetic the way the calibration was estimated
+    pub fn apply(&self, synthetic: &[f64])-> Vec<f64>{
+        let shifted=sinc_shift(synthetic, self.shift, 8);
+        match &self.gain{
+            Some(gain)=> shifted.iter().zip(gain).map(|(x, g)| x*g).collect(),
+            None=> shifted.iter().map(|x| x*self.scale).collect(),
+        }
+    }
+
+    pub fn print_summary(&self){
+        println!("Amplitude calibration: shift {:+.2} samples, scale {:.4}{} (correlation {:.3})",
+            self.shift, self.scale, if self.gain.is_some() { ", time-varying gain" } else { "" }, self.correlation);
+        println!("  RMS misfit {:.4e} -> {:.4e}", self.misfit_before, self.misfit_after);
+    }
+}
+
+fn rms_difference(a: &[f64], b: &[f64])-> f64{
+    (a.iter().zip(b).map(|(x, y)| (x-y)*(x-y)).sum::<f64>()/a.len().max(1) as f64).sqrt()
+}
+
+impl AmplitudeCalibration{
+    pub fn calibrate(&self, synthetic: &[f64], observed: &[f64])-> Result<Calibration>{
+        if synthetic.is_empty() || synthetic.len()!=observed.len(){
+            return Err(anyhow!("Traces must be non-empty and the same length, got {} and {}", synthetic.len(), observed.len()));
+        }
+        let window=self.window.clone().unwrap_or(0..synthetic.len());
+        if window.is_empty() || window.end>synthetic.len(){
+            return Err(anyhow!("Calibration window {:?} is empty or outside the {} samples", window, synthetic.len()));
+        }
+        if self.gain_half_width==Some(0){
+            return Err(anyhow!("Gain half-width must be at least one sample"));
+        }
+
+        //Lag of the observed trace behind the synthetic is the delay to apply to the synthetic
+        let lag=estimate_lag(&synthetic[window.clone()], &observed[window.clone()], self.max_lag, self.lag_method)?;
+        let aligned=sinc_shift(synthetic, lag.lag, 8);
+
+        let (s, o)=(&aligned[window.clone()], &observed[window.clone()]);
+        let energy: f64=s.iter().map(|x| x*x).sum();
+        if energy==0.0{
+            return Err(anyhow!("Synthetic has no energy in the calibration window"));
+        }
+        let scale=s.iter().zip(o).map(|(a, b)| a*b).sum::<f64>()/energy;
+
+        let gain=self.gain_half_width.map(|half| local_gain(s, o, half, scale)).map(|inner| {
+            //Hold the edge values outside the window
+            let mut gain=vec![inner[0]; window.start];
+            gain.extend_from_slice(&inner);
+            gain.resize(synthetic.len(), *inner.last().unwrap());
+            gain
+        });
+
+        let mut calibration=Calibration{ shift: lag.lag, scale, gain, correlation: lag.correlation, misfit_before: rms_difference(&synthetic[window.clone()], o), misfit_after: 0.0 };
+        calibration.misfit_after=rms_difference(&calibration.apply(synthetic)[window], o);
+        Ok(calibration)
+    }
+}
+
+///RMS ratio of `observed` to `synthetic` in a sliding window, signed like `scale`
+///
+/// Where the synthetic is nearly silent the ratio is meaningless, so the
+/// global scalar is used instead.
+fn local_gain(synthetic: &[f64], observed: &[f64], half_width: usize, scale: f64)-> Vec<f64>{
+    let n=synthetic.len();
+    let windowed=|x: &[f64], k: usize| {
+        let range=k.saturating_sub(half_width)..(k+half_width+1).min(n);
+        let count=range.len() as f64;
+        (x[range].iter().map(|v| v*v).sum::<f64>()/count).sqrt()
+    };
+    let synthetic_rms: Vec<f64>=(0..n).map(|k| windowed(synthetic, k)).collect();
+    let floor=1e-3*synthetic_rms.iter().cloned().fold(0.0, f64::max);
+    (0..n).map(|k| {
+        if synthetic_rms[k]>floor { scale.signum()*windowed(observed, k)/synthetic_rms[k] } else { scale }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::noise::{Rng, SeededRng};
+    use crate::operators::{ConvolutionOperator, LinearOperator};
+    use crate::wavelets::RickerWavelet;
+
+    fn synthetic()-> Result<Vec<f64>>{
+        let mut rng=SeededRng::new(17);
+        let reflectivity: Vec<f64>=(0..400).map(|_| rng.normal()*0.1).collect();
+        Ok(ConvolutionOperator::from_ricker(&RickerWavelet::new(30.0, 0.002, 60)?, 400)?.apply(&reflectivity))
+    }
+
+    #[test]
+    fn test_recovers_scalar_and_shift()-> Result<()>{
+        let synthetic=synthetic()?;
+        let observed: Vec<f64>=sinc_shift(&synthetic, 3.4, 8).iter().map(|x| 2.5*x).collect();
+
+        let calibration=AmplitudeCalibration{ window: Some(50..350), ..AmplitudeCalibration::default() }.calibrate(&synthetic, &observed)?;
+        assert_abs_diff_eq!(calibration.shift, 3.4, epsilon=0.1);
+        assert_abs_diff_eq!(calibration.scale, 2.5, epsilon=0.05);
+        assert!(calibration.misfit_after<0.05*calibration.misfit_before, "{:?}", calibration);
+
+        assert!(AmplitudeCalibration{ window: Some(350..500), ..AmplitudeCalibration::default() }.calibrate(&synthetic, &observed).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_varying_gain()-> Result<()>{
+        let synthetic=synthetic()?;
+        let observed: Vec<f64>=synthetic.iter().enumerate().map(|(i, x)| x*(1.0+i as f64/200.0)).collect();
+
+        let single=AmplitudeCalibration::default().calibrate(&synthetic, &observed)?;
+        let varying=AmplitudeCalibration{ gain_half_width: Some(40), ..AmplitudeCalibration::default() }.calibrate(&synthetic, &observed)?;
+        let gain=varying.gain.as_ref().unwrap();
+        assert_eq!(gain.len(), 400);
+        assert_abs_diff_eq!(gain[100], 1.5, epsilon=0.15);
+        assert_abs_diff_eq!(gain[300], 2.5, epsilon=0.25);
+        assert!(varying.misfit_after<0.5*single.misfit_after);
+        Ok(())
+    }
+}
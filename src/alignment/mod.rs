@@ -0,0 +1,201 @@
+//! Trace alignment: dynamic time warping and related shift estimation
+
+use anyhow::{Result, anyhow};
+
+pub mod calibration;
+pub mod subsample;
+
+///Result of aligning a trace to a reference with dynamic time warping
+#[derive(Debug, Clone)]
+pub struct DtwAlignment{
+    ///Matched (reference index, trace index) pairs from start to end
+    pub path: Vec<(usize, usize)>,
+    ///Shift in samples of the trace relative to the reference, per reference sample
+    pub shifts: Vec<f64>,
+    ///Accumulated squared-difference cost along the path
+    pub distance: f64,
+}
+
+///Accumulated DTW cost stored only inside the band, as one run of columns per row
+struct BandedCost{
+    first: Vec<usize>,
+    width: Vec<usize>,
+    offset: Vec<usize>,
+    values: Vec<f64>,
+}
+
+impl BandedCost{
+    ///Rows of `m` columns keeping the cells within `radius` of the diagonal `j = i*slope`
+    fn new(n: usize, m: usize, slope: f64, radius: f64)-> Self{
+        let in_band=|i: usize, j: usize| (j as f64-i as f64*slope).abs()<=radius;
+        let (mut first, mut width, mut offset)=(Vec::with_capacity(n), Vec::with_capacity(n), Vec::with_capacity(n));
+        let mut total=0;
+        for i in 0..n{
+            //Search one sample beyond the radius either side so rounding cannot lose a cell
+            let centre=i as f64*slope;
+            let low=((centre-radius).floor().max(0.0) as usize).saturating_sub(1).min(m);
+            let high=((centre+radius).ceil().max(0.0) as usize+2).min(m);
+            let start=(low..high).find(|&j| in_band(i, j)).unwrap_or(low);
+            let run=(start..high).take_while(|&j| in_band(i, j)).count();
+            first.push(start);
+            width.push(run);
+            offset.push(total);
+            total+=run;
+        }
+        Self{ first, width, offset, values: vec![f64::INFINITY; total] }
+    }
+
+    fn columns(&self, i: usize)-> std::ops::Range<usize>{
+        self.first[i]..self.first[i]+self.width[i]
+    }
+
+    ///Cost of cell `(i, j)`, infinite outside the band
+    fn get(&self, i: usize, j: usize)-> f64{
+        if self.columns(i).contains(&j) { self.values[self.offset[i]+j-self.first[i]] } else { f64::INFINITY }
+    }
+
+    fn set(&mut self, i: usize, j: usize, value: f64){
+        let index=self.offset[i]+j-self.first[i];
+        self.values[index]=value;
+    }
+}
+
+///Dynamic time warping of `trace` against `reference`
+///
+/// `band` is a Sakoe-Chiba constraint: matches more than `band` samples off
+/// the (length-scaled) diagonal are not allowed, which keeps the warp physical.
+/// Only the cells inside the band are stored and visited, so time and memory
+/// grow as the trace length times the band width. Positive shifts mean the
+/// event in `trace` arrives later than in `reference`.
+pub fn dtw_align(reference: &[f64], trace: &[f64], band: usize)-> Result<DtwAlignment>{
+    let (n, m)=(reference.len(), trace.len());
+    if n==0 || m==0{
+        return Err(anyhow!("Cannot align empty traces"));
+    }
+
+    let slope=(m-1) as f64/(n-1).max(1) as f64;
+
+    //Accumulated cost of the band cells, row by row over reference samples
+    let mut cost=BandedCost::new(n, m, slope, band as f64+slope.max(1.0)-1.0);
+    for (i, &value) in reference.iter().enumerate(){
+        for j in cost.columns(i){
+            let local=(value-trace[j]).powi(2);
+            let best_previous=if i==0 && j==0{
+                0.0
+            }else{
+                let mut best=f64::INFINITY;
+                if i>0{
+                    best=best.min(cost.get(i-1, j));
+                }
+                if j>0{
+                    best=best.min(cost.get(i, j-1));
+                }
+                if i>0 && j>0{
+                    best=best.min(cost.get(i-1, j-1));
+                }
+                best
+            };
+            cost.set(i, j, local+best_previous);
+        }
+    }
+
+    let distance=cost.get(n-1, m-1);
+    if !distance.is_finite(){
+        return Err(anyhow!("Band of {} samples is too narrow to connect the trace ends", band));
+    }
+
+    //Backtrack from the end, preferring the diagonal on ties
+    let mut path=vec![(n-1, m-1)];
+    let (mut i, mut j)=(n-1, m-1);
+    while i>0 || j>0{
+        let candidates=[
+            (i>0 && j>0, i.wrapping_sub(1), j.wrapping_sub(1)),
+            (i>0, i.wrapping_sub(1), j),
+            (j>0, i, j.wrapping_sub(1)),
+        ];
+        let (_, next_i, next_j)=candidates.iter()
+            .filter(|c| c.0)
+            .min_by(|a, b| cost.get(a.1, a.2).total_cmp(&cost.get(b.1, b.2)))
+            .copied()
+            .unwrap();
+        i=next_i;
+        j=next_j;
+        path.push((i, j));
+    }
+    path.reverse();
+
+    //Average the matched trace index for every reference sample
+    let mut sums=vec![0.0; n];
+    let mut counts=vec![0usize; n];
+    for &(ri, tj) in &path{
+        sums[ri]+=tj as f64;
+        counts[ri]+=1;
+    }
+    let shifts=(0..n).map(|ri| sums[ri]/counts[ri] as f64-ri as f64).collect();
+
+    Ok(DtwAlignment{ path, shifts, distance })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn pulse(length: usize, centre: f64, width: f64)-> Vec<f64>{
+        (0..length).map(|i| (-((i as f64-centre)/width).powi(2)).exp()).collect()
+    }
+
+    #[test]
+    fn test_identical_traces_have_zero_shift()-> Result<()>{
+        let a=pulse(100, 50.0, 5.0);
+        let alignment=dtw_align(&a, &a, 10)?;
+
+        assert_abs_diff_eq!(alignment.distance, 0.0, epsilon=1e-12);
+        assert!(alignment.shifts.iter().all(|&s| s==0.0));
+        assert_eq!(alignment.path.len(), 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detects_bulk_shift()-> Result<()>{
+        let reference=pulse(120, 50.0, 4.0);
+        let delayed=pulse(120, 56.0, 4.0);
+
+        let alignment=dtw_align(&reference, &delayed, 15)?;
+
+        assert_abs_diff_eq!(alignment.shifts[50], 6.0, epsilon=0.5);
+        assert_eq!(alignment.path.first(), Some(&(0, 0)));
+        assert_eq!(alignment.path.last(), Some(&(119, 119)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_traces_store_only_the_band()-> Result<()>{
+        //A full cost matrix would need 10^10 cells
+        let reference=pulse(100_000, 50_000.0, 20.0);
+        let delayed=pulse(100_000, 50_003.0, 20.0);
+        let alignment=dtw_align(&reference, &delayed, 8)?;
+        assert_abs_diff_eq!(alignment.shifts[50_000], 3.0, epsilon=0.5);
+
+        //Unequal lengths follow the scaled diagonal
+        let stretched=pulse(150, 75.0, 6.0);
+        let alignment=dtw_align(&pulse(100, 50.0, 4.0), &stretched, 3)?;
+        assert_eq!(alignment.path.last(), Some(&(99, 149)));
+        assert_abs_diff_eq!(alignment.shifts[50], 25.0, epsilon=1.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_band_limits_shift()-> Result<()>{
+        let reference=pulse(120, 40.0, 3.0);
+        let delayed=pulse(120, 70.0, 3.0);
+
+        let alignment=dtw_align(&reference, &delayed, 5)?;
+        assert!(alignment.shifts.iter().all(|s| s.abs()<=5.0));
+        assert!(dtw_align(&[], &delayed, 5).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! Post-stack attributes computed over sections
+//!
+//! Every attribute returns an `AttributeSection` on the same [trace][sample]
+//! grid as its input, so it can be rendered or extracted along horizons in
+//! the same way as amplitude.
+
+use anyhow::{Result, anyhow};
+use std::f64::consts::PI;
+use crate::convolution::ConvolutionEngine;
+use crate::io::image::{write_section_png, Colormap};
+
+///An attribute sampled on the section grid
+#[derive(Debug, Clone)]
+pub struct AttributeSection{
+    pub name: String,
+    ///Attribute values indexed [trace][sample]
+    pub values: Vec<Vec<f64>>,
+    ///Sample interval in seconds
+    pub dt: f64,
+}
+
+impl AttributeSection{
+    ///Render the attribute as a PNG; signed attributes use the seismic colormap
+    pub fn to_png(&self, path: &str)-> Result<()>{
+        let signed=self.values.iter().flatten().any(|&v| v<0.0);
+        let colormap=if signed { Colormap::Seismic } else { Colormap::Grayscale };
+        write_section_png(path, &self.values, colormap, None)
+    }
+}
+
+///RMS amplitude in a centred window of `2*half_window+1` samples
+pub fn rms_amplitude(section: &[Vec<f64>], dt: f64, half_window: usize)-> Result<AttributeSection>{
+    check_section(section, dt)?;
+    let values=section.iter().map(|trace| {
+        let squares: Vec<f64>=trace.iter().map(|v| v*v).collect();
+        running_mean(&squares, half_window).into_iter().map(f64::sqrt).collect()
+    }).collect();
+    Ok(AttributeSection{ name: "rms_amplitude".to_string(), values, dt })
+}
+
+///Instantaneous amplitude (envelope) of the analytic signal
+pub fn envelope(section: &[Vec<f64>], dt: f64)-> Result<AttributeSection>{
+    check_section(section, dt)?;
+    let mut engine=ConvolutionEngine::new();
+    let values=section.iter().map(|trace| engine.analytic_signal(trace).iter().map(|z| z.norm()).collect()).collect();
+    Ok(AttributeSection{ name: "envelope".to_string(), values, dt })
+}
+
+///Instantaneous phase in radians, wrapped to (-pi, pi]
+pub fn instantaneous_phase(section: &[Vec<f64>], dt: f64)-> Result<AttributeSection>{
+    check_section(section, dt)?;
+    let mut engine=ConvolutionEngine::new();
+    let values=section.iter().map(|trace| engine.analytic_signal(trace).iter().map(|z| z.arg()).collect()).collect();
+    Ok(AttributeSection{ name: "instantaneous_phase".to_string(), values, dt })
+}
+
+///Instantaneous frequency in Hz from the time derivative of the phase
+///
+/// The phase change across neighbouring samples is taken as the argument of
+/// `z[i+1]*conj(z[i-1])`, which needs no unwrapping and is exact for a pure
+/// sinusoid. Samples with no energy give zero.
+pub fn instantaneous_frequency(section: &[Vec<f64>], dt: f64)-> Result<AttributeSection>{
+    check_section(section, dt)?;
+    let mut engine=ConvolutionEngine::new();
+    let values=section.iter().map(|trace| {
+        let z=engine.analytic_signal(trace);
+        let n=trace.len();
+        (0..n).map(|i| {
+            let (before, after)=(i.saturating_sub(1), (i+1).min(n-1));
+            if before==after{
+                return 0.0;
+            }
+            let change=z[after]*z[before].conj();
+            if change.norm_sqr()==0.0 { 0.0 } else { change.arg()/(2.0*PI*(after-before) as f64*dt) }
+        }).collect()
+    }).collect();
+    Ok(AttributeSection{ name: "instantaneous_frequency".to_string(), values, dt })
+}
+
+///Dominant frequency: envelope-weighted mean instantaneous frequency in a centred window
+///
+/// Weighting by energy suppresses the frequency spikes that instantaneous
+/// frequency shows at envelope minima.
+pub fn dominant_frequency(section: &[Vec<f64>], dt: f64, half_window: usize)-> Result<AttributeSection>{
+    let frequency=instantaneous_frequency(section, dt)?;
+    let energy=envelope(section, dt)?;
+
+    let values=frequency.values.iter().zip(&energy.values).map(|(f, e)| {
+        let power: Vec<f64>=e.iter().map(|v| v*v).collect();
+        let weighted: Vec<f64>=f.iter().zip(&power).map(|(f, p)| f*p).collect();
+        running_mean(&weighted, half_window).iter().zip(running_mean(&power, half_window))
+            .map(|(w, p)| if p>0.0 { w/p } else { 0.0 })
+            .collect()
+    }).collect();
+    Ok(AttributeSection{ name: "dominant_frequency".to_string(), values, dt })
+}
+
+///Semblance coherence over each trace and its neighbours
+///
+/// For the `2*half_traces+1` traces centred on each trace and a time window
+/// of `2*half_window+1` samples, semblance is the energy of the stacked trace
+/// divided by the number of traces times the total energy: 1 for identical
+/// traces, near 1/N for incoherent ones.
+pub fn semblance(section: &[Vec<f64>], dt: f64, half_traces: usize, half_window: usize)-> Result<AttributeSection>{
+    check_section(section, dt)?;
+    let (num_traces, num_samples)=(section.len(), section[0].len());
+
+    let values=(0..num_traces).map(|centre| {
+        let first=centre.saturating_sub(half_traces);
+        let last=(centre+half_traces).min(num_traces-1);
+        let group=&section[first..=last];
+
+        let stacked: Vec<f64>=(0..num_samples).map(|s| group.iter().map(|t| t[s]).sum::<f64>().powi(2)).collect();
+        let energy: Vec<f64>=(0..num_samples).map(|s| group.iter().map(|t| t[s]*t[s]).sum::<f64>()).collect();
+        let stacked=running_mean(&stacked, half_window);
+        let energy=running_mean(&energy, half_window);
+
+        stacked.iter().zip(&energy)
+            .map(|(s, e)| if *e>0.0 { s/(group.len() as f64*e) } else { 0.0 })
+            .collect()
+    }).collect();
+    Ok(AttributeSection{ name: "semblance".to_string(), values, dt })
+}
+
+fn check_section(section: &[Vec<f64>], dt: f64)-> Result<()>{
+    if dt<=0.0{
+        return Err(anyhow!("Sample interval must be positive, got {}", dt));
+    }
+    let samples=section.first().map(|t| t.len()).ok_or_else(|| anyhow!("Section has no traces"))?;
+    if section.iter().any(|t| t.len()!=samples){
+        return Err(anyhow!("All traces must have the same number of samples"));
+    }
+    Ok(())
+}
+
+///Centred running mean, shrinking the window at the trace ends
+fn running_mean(values: &[f64], half_window: usize)-> Vec<f64>{
+    let mut prefix=vec![0.0; values.len()+1];
+    for (i, v) in values.iter().enumerate(){
+        prefix[i+1]=prefix[i]+v;
+    }
+    (0..values.len()).map(|i| {
+        let lo=i.saturating_sub(half_window);
+        let hi=(i+half_window+1).min(values.len());
+        (prefix[hi]-prefix[lo])/(hi-lo) as f64
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::noise::{Rng, SeededRng};
+
+    fn sine_section(frequency: f64, dt: f64)-> Vec<Vec<f64>>{
+        (0..5).map(|_| (0..500).map(|i| (2.0*PI*frequency*i as f64*dt).sin()).collect()).collect()
+    }
+
+    #[test]
+    fn test_instantaneous_attributes_of_sinusoid()-> Result<()>{
+        let dt=0.002;
+        //25 Hz fits a whole number of cycles in 500 samples, so there is no leakage
+        let section=sine_section(25.0, dt);
+
+        let env=envelope(&section, dt)?;
+        let freq=instantaneous_frequency(&section, dt)?;
+        let dominant=dominant_frequency(&section, dt, 10)?;
+        for i in 10..490{
+            assert_abs_diff_eq!(env.values[2][i], 1.0, epsilon=1e-9);
+            assert_abs_diff_eq!(freq.values[2][i], 25.0, epsilon=0.3);
+            assert_abs_diff_eq!(dominant.values[2][i], 25.0, epsilon=0.3);
+        }
+
+        let rms=rms_amplitude(&section, dt, 20)?;
+        assert_abs_diff_eq!(rms.values[0][250], 1.0/2.0f64.sqrt(), epsilon=0.01);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_semblance_separates_coherent_and_random()-> Result<()>{
+        let dt=0.002;
+        let coherent=semblance(&sine_section(25.0, dt), dt, 1, 5)?;
+        assert_abs_diff_eq!(coherent.values[2][250], 1.0, epsilon=1e-9);
+
+        let mut rng=SeededRng::new(7);
+        let random: Vec<Vec<f64>>=(0..5).map(|_| (0..500).map(|_| rng.uniform()-0.5).collect()).collect();
+        let incoherent=semblance(&random, dt, 1, 5)?;
+        let mean=incoherent.values[2].iter().sum::<f64>()/500.0;
+        assert!(mean<0.6);
+
+        assert!(semblance(&[], dt, 1, 5).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_attribute_png()-> Result<()>{
+        let dt=0.002;
+        let phase=instantaneous_phase(&sine_section(25.0, dt), dt)?;
+        let path=std::env::temp_dir().join("phase_attribute.png");
+        let path=path.to_str().unwrap();
+
+        phase.to_png(path)?;
+        assert!(std::fs::metadata(path)?.len()>0);
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+}
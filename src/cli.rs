@@ -0,0 +1,295 @@
+//! Command-line subcommands
+
+use anyhow::{Result, anyhow};
+use crate::compare::{compare_traces, load_trace, DEFAULT_BUNDLE_ENTRY};
+use crate::config::RunConfig;
+use crate::convolution::{benchmark_fft_sizes, print_fft_benchmark};
+use crate::feasibility::{FeasibilityStudy, NoiseSpec, PropertyChange};
+use crate::inversion::sparse::SparseInversion;
+use crate::models::elastic::ElasticModel;
+use crate::noise::empirical::EmpiricalNoise;
+use crate::planner::{Budget, JobSpec, Precision};
+use crate::planner::cost::{BatchJob, Calibration};
+use crate::utils::{import_from_csv, plot_ascii};
+use crate::wavelets::RickerWavelet;
+use crate::wavelets::catalog::{CatalogEntry, WaveletCatalog};
+use crate::wavelets::families::compare_families;
+
+///Default location of the wavelet catalog
+const DEFAULT_CATALOG: &str="wavelets.json";
+
+///Dispatch a subcommand; returns `Ok(false)` when no subcommand was given
+///
+/// `seed` is the run's `--seed`, handed to subcommands that draw random numbers.
+pub fn run(args: &[String], seed: u64)-> Result<bool>{
+    match args.first().map(String::as_str){
+        Some("wavelet")=> run_wavelet_command(&args[1..]).map(|_| true),
+        Some("plan")=> run_plan_command(&args[1..]).map(|_| true),
+        Some("estimate")=> run_estimate_command(&args[1..]).map(|_| true),
+        Some("compare")=> run_compare_command(&args[1..]).map(|_| true),
+        Some("feasibility")=> run_feasibility_command(&args[1..], seed).map(|_| true),
+        Some(other)=> Err(anyhow!("Unknown command '{}'", other)),
+        None=> Ok(false),
+    }
+}
+
+///Extract the value of `--flag <value>` from an argument list, removing both
+///
+/// Returns `Ok(None)` when the flag is absent and an error when it is the
+/// last argument, so a forgotten value is not mistaken for an absent flag.
+pub fn take_option(args: &mut Vec<String>, flag: &str)-> Result<Option<String>>{
+    let Some(position)=args.iter().position(|a| a==flag) else {
+        return Ok(None);
+    };
+    if position+1>=args.len(){
+        return Err(anyhow!("Missing value for {}", flag));
+    }
+    let value=args.remove(position+1);
+    args.remove(position);
+    Ok(Some(value))
+}
+
+///`wavelet add|list|inspect|remove` against the on-disk catalog
+///
+/// Usage:
+///   wavelet add <name> <frequency_hz> <dt_s> <length> [--description text]
+///   wavelet list
+///   wavelet inspect <name>
+///   wavelet remove <name>
+///   wavelet families <frequency_hz> <dt_s> <length> [--time-csv path] [--spectrum-csv path]
+/// All commands accept `--catalog <path>` (default `wavelets.json`).
+fn run_wavelet_command(args: &[String])-> Result<()>{
+    let mut args=args.to_vec();
+    let catalog_path=take_option(&mut args, "--catalog")?.unwrap_or_else(|| DEFAULT_CATALOG.to_string());
+    let description=take_option(&mut args, "--description")?.unwrap_or_default();
+    let time_csv=take_option(&mut args, "--time-csv")?;
+    let spectrum_csv=take_option(&mut args, "--spectrum-csv")?;
+
+    let mut catalog=WaveletCatalog::load(&catalog_path)?;
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice(){
+        ["add", name, frequency, dt, length]=> {
+            let wavelet=RickerWavelet::new(frequency.parse()?, dt.parse()?, length.parse()?)?;
+            catalog.add(CatalogEntry::from_ricker(name, &description, &wavelet))?;
+            catalog.save(&catalog_path)?;
+            println!("Added wavelet '{}' to {}", name, catalog_path);
+        }
+        ["list"]=> {
+            if catalog.is_empty(){
+                println!("(Catalog {} is empty)", catalog_path);
+            }
+            for entry in catalog.entries(){
+                println!("{:<20} {:<10} {:>5} samples  dt={}s  {}", entry.name, entry.kind, entry.samples.len(), entry.dt, entry.description);
+            }
+        }
+        ["inspect", name]=> {
+            let entry=catalog.get(name).ok_or_else(|| anyhow!("Wavelet '{}' not found in {}", name, catalog_path))?;
+            println!("Name: {}", entry.name);
+            println!("Kind: {}", entry.kind);
+            println!("Description: {}", entry.description);
+            for (key, value) in &entry.parameters{
+                println!("{}: {}", key, value);
+            }
+            println!("Sample interval: {} s", entry.dt);
+            println!("Length: {} samples", entry.samples.len());
+            plot_ascii(&entry.samples, 15);
+        }
+        ["remove", name]=> {
+            catalog.remove(name)?;
+            catalog.save(&catalog_path)?;
+            println!("Removed wavelet '{}' from {}", name, catalog_path);
+        }
+        ["families", frequency, dt, length]=> {
+            let comparison=compare_families(frequency.parse()?, dt.parse()?, length.parse()?)?;
+            comparison.print_summary();
+            if let Some(path)=time_csv{
+                comparison.write_time_csv(&path)?;
+                println!("Wavelet overlay written to {}", path);
+            }
+            if let Some(path)=spectrum_csv{
+                comparison.write_spectrum_csv(&path)?;
+                println!("Spectrum overlay written to {}", path);
+            }
+        }
+        _=> return Err(anyhow!("Usage: wavelet add <name> <frequency> <dt> <length> | list | inspect <name> | remove <name> | families <frequency> <dt> <length> [--catalog path]")),
+    }
+
+    Ok(())
+}
+
+///`plan` estimates memory and disk for a job and checks them against a budget
+///
+/// Usage:
+///   plan --nx N --nz N --nt N --shots N --receivers N [--precision f32|f64]
+///        [--snapshot-interval N] [--concurrent N] [--memory-gb X] [--disk-gb X] [--no-auto]
+/// Exits with an error when the job cannot fit the budget.
+fn run_plan_command(args: &[String])-> Result<()>{
+    let mut args=args.to_vec();
+    let mut required=|flag: &str| -> Result<usize> {
+        take_option(&mut args, flag)?.ok_or_else(|| anyhow!("Missing {}", flag))?.parse().map_err(|e| anyhow!("Invalid {}: {}", flag, e))
+    };
+    let (nx, nz, nt, num_shots, num_receivers)=(required("--nx")?, required("--nz")?, required("--nt")?, required("--shots")?, required("--receivers")?);
+
+    let precision=match take_option(&mut args, "--precision")?.as_deref(){
+        None | Some("f64")=> Precision::Double,
+        Some("f32")=> Precision::Single,
+        Some(other)=> return Err(anyhow!("Unknown precision '{}', expected f32 or f64", other)),
+    };
+    let snapshot_interval=take_option(&mut args, "--snapshot-interval")?.map(|v| v.parse()).transpose()?;
+    let concurrent_shots=take_option(&mut args, "--concurrent")?.map(|v| v.parse()).transpose()?.unwrap_or(1);
+    let gigabytes=|value: Option<String>| -> Result<Option<u64>> {
+        Ok(value.map(|v| v.parse::<f64>()).transpose()?.map(|gb| (gb*(1u64<<30) as f64) as u64))
+    };
+    let budget=Budget{
+        memory_bytes: gigabytes(take_option(&mut args, "--memory-gb")?)?,
+        disk_bytes: gigabytes(take_option(&mut args, "--disk-gb")?)?,
+    };
+    let auto_adjust=!args.iter().any(|a| a=="--no-auto");
+
+    let job=JobSpec{ nx, nz, nt, num_shots, num_receivers, precision, snapshot_interval, concurrent_shots };
+    let plan=job.plan(&budget, auto_adjust)?;
+    plan.print_summary();
+
+    if !plan.is_runnable(){
+        return Err(anyhow!("Job does not fit the resource budget"));
+    }
+    Ok(())
+}
+
+///`estimate` predicts FFT count, runtime and disk use of a batch without running it
+///
+/// Usage:
+///   estimate [--config run.json] [--models N] [--wavelets N] [--realizations N]
+///            [--iterations N] [--precision digits] [--gzip] [--no-export] [--fft-benchmark]
+/// Sizes come from the run configuration (defaults without one); the runtime
+/// is calibrated by timing a few transforms on this machine first.
+/// `--fft-benchmark` also times the trace's power-of-two and mixed-radix padding.
+fn run_estimate_command(args: &[String])-> Result<()>{
+    let mut args=args.to_vec();
+    let config=match take_option(&mut args, "--config")?{
+        Some(path)=> RunConfig::load(&path)?,
+        None=> RunConfig::default(),
+    };
+    let mut job=BatchJob::from_config(&config);
+    let mut count=|flag: &str, default: usize| -> Result<usize> {
+        take_option(&mut args, flag)?.map(|v| v.parse().map_err(|e| anyhow!("Invalid {}: {}", flag, e))).transpose().map(|v| v.unwrap_or(default))
+    };
+    job.num_models=count("--models", 1)?;
+    job.num_wavelets=count("--wavelets", 1)?;
+    job.num_realizations=count("--realizations", 1)?;
+    job.inversion_iterations=count("--iterations", job.inversion_iterations)?;
+    let precision=take_option(&mut args, "--precision")?.map(|v| v.parse()).transpose()?;
+    if let Some(options)=job.csv.as_mut(){
+        options.precision=precision;
+        options.gzip=args.iter().any(|a| a=="--gzip");
+    }
+    if args.iter().any(|a| a=="--no-export"){
+        job.csv=None;
+    }
+
+    let calibration=Calibration::measure(job.fft_length().max(2), 50)?;
+    job.estimate(&calibration)?.print_summary();
+    if args.iter().any(|a| a=="--fft-benchmark"){
+        print_fft_benchmark(&benchmark_fft_sizes(&[job.trace_length()], 50)?);
+    }
+    Ok(())
+}
+
+///`compare` reports how a candidate run B differs from a reference run A
+///
+/// Usage:
+///   compare <a.csv|a.zip> <b.csv|b.zip> [--entry path-in-bundle] [--dt s] [--max-lag N]
+/// Bundles are read at `--entry` (default `outputs/synthetic_trace.csv`).
+fn run_compare_command(args: &[String])-> Result<()>{
+    let mut args=args.to_vec();
+    let entry=take_option(&mut args, "--entry")?.unwrap_or_else(|| DEFAULT_BUNDLE_ENTRY.to_string());
+    let dt=take_option(&mut args, "--dt")?.map(|v| v.parse()).transpose()?.unwrap_or(0.001);
+    let max_lag=take_option(&mut args, "--max-lag")?.map(|v| v.parse()).transpose()?.unwrap_or(20);
+
+    let [a, b]=args.as_slice() else {
+        return Err(anyhow!("Usage: compare <a> <b> [--entry path] [--dt s] [--max-lag N]"));
+    };
+    let (trace_a, trace_b)=(load_trace(a, &entry)?, load_trace(b, &entry)?);
+    if trace_a.len()!=trace_b.len(){
+        println!("Warning: lengths differ ({} vs {}), comparing the overlap", trace_a.len(), trace_b.len());
+    }
+
+    println!("A: {}\nB: {}", a, b);
+    compare_traces(&trace_a, &trace_b, dt, max_lag)?.print_summary();
+    Ok(())
+}
+
+///`feasibility` scores whether a property change in one zone is detectable
+///
+/// Usage:
+///   feasibility --model model.csv --zone START:END [--dt s] [--vp-change x] [--vs-change x] [--rho-change x]
+///               [--frequency hz] [--noise-rms x] [--noise-band f1,f2,f3,f4] [--noise-window noise.csv]
+///               [--realizations N] [--lambda x] [--threshold x] [--report path.json]
+/// The model CSV has columns `vp, vs, rho`; changes are fractions (-0.05 is a 5% drop).
+/// `--noise-window` estimates the noise from a trace of recorded noise (columns `sample,amplitude`).
+fn run_feasibility_command(args: &[String], seed: u64)-> Result<()>{
+    let mut args=args.to_vec();
+    let mut number=|flag: &str, default: f64| -> Result<f64> {
+        take_option(&mut args, flag)?.map(|v| v.parse().map_err(|e| anyhow!("Invalid {}: {}", flag, e))).transpose().map(|v| v.unwrap_or(default))
+    };
+    let dt=number("--dt", 0.002)?;
+    let (vp, vs, rho)=(number("--vp-change", 0.0)?, number("--vs-change", 0.0)?, number("--rho-change", 0.0)?);
+    let frequency=number("--frequency", 30.0)?;
+    let noise_rms=number("--noise-rms", 0.01)?;
+    let lambda=number("--lambda", SparseInversion::default().lambda)?;
+    let threshold=number("--threshold", 2.0)?;
+    let realizations=number("--realizations", 50.0)? as usize;
+
+    let model_path=take_option(&mut args, "--model")?.ok_or_else(|| anyhow!("Missing --model"))?;
+    let zone=take_option(&mut args, "--zone")?.ok_or_else(|| anyhow!("Missing --zone START:END"))?;
+    let (start, end)=zone.split_once(':').ok_or_else(|| anyhow!("Zone must be START:END, got '{}'", zone))?;
+    let change=PropertyChange{ start: start.trim().parse()?, end: end.trim().parse()?, vp, vs, rho };
+
+    let noise=match (take_option(&mut args, "--noise-window")?, take_option(&mut args, "--noise-band")?){
+        (Some(path), _)=> {
+            let file=std::fs::File::open(&path).map_err(|e| anyhow!("Failed to open noise window {}: {}", path, e))?;
+            let window=import_from_csv(file)?;
+            NoiseSpec::Empirical(EmpiricalNoise::estimate(&window, dt, 128.min(window.len()))?)
+        }
+        (None, Some(band))=> {
+            let corners: Vec<f64>=band.split(',').map(|v| v.trim().parse()).collect::<Result<_, _>>()?;
+            let corners: [f64; 4]=corners.try_into().map_err(|_| anyhow!("--noise-band needs four frequencies"))?;
+            NoiseSpec::Band{ rms: noise_rms, corners }
+        }
+        (None, None)=> NoiseSpec::White{ rms: noise_rms },
+    };
+    let report_path=take_option(&mut args, "--report")?;
+
+    let baseline=ElasticModel::load_csv(&model_path, dt)?;
+    //Even length puts the Ricker peak on a sample
+    let wavelet=RickerWavelet::new(frequency, dt, 2*(1.5/(frequency*dt)).ceil() as usize)?;
+    let study=FeasibilityStudy{
+        realizations,
+        inversion: SparseInversion{ lambda, ..Default::default() },
+        threshold,
+        seed,
+    };
+
+    let report=study.run(&baseline, &change, &wavelet, &noise)?;
+    report.print_summary();
+    if let Some(path)=report_path{
+        report.to_json(&path)?;
+        println!("Wrote report to {}", path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_take_option()-> Result<()>{
+        let mut args: Vec<String>=["--dt", "0.002", "--report"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(take_option(&mut args, "--dt")?.as_deref(), Some("0.002"));
+        assert_eq!(take_option(&mut args, "--model")?, None);
+        assert!(take_option(&mut args, "--report").is_err());
+        assert_eq!(args, vec!["--report"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! A/B comparison of two runs: difference statistics, spectral ratio and correlation
+
+use anyhow::{Result, anyhow};
+use std::fs::File;
+use crate::io::bundle::read_entry;
+use crate::utils::{import_from_csv, plot_ascii, rms};
+use crate::wavelets::spectrum::spectral_analysis;
+
+///Trace compared by default when an input is a bundle
+pub const DEFAULT_BUNDLE_ENTRY: &str="outputs/synthetic_trace.csv";
+
+///Spectral bins quieter than this fraction of the reference peak are left out of the ratio
+const SPECTRAL_FLOOR: f64=0.1;
+
+///Load a trace from a CSV file or, for `.zip` paths, from `entry` inside a bundle
+pub fn load_trace(path: &str, entry: &str)-> Result<Vec<f64>>{
+    if path.ends_with(".zip"){
+        import_from_csv(read_entry(path, entry)?.as_slice())
+    }else{
+        import_from_csv(File::open(path).map_err(|e| anyhow!("Failed to open file: {}: {}", path, e))?)
+    }
+}
+
+///Differences between a reference trace `a` and a candidate `b`
+#[derive(Debug, Clone)]
+pub struct TraceComparison{
+    ///Samples compared (the shorter of the two lengths)
+    pub samples: usize,
+    ///`b - a` sample by sample
+    pub difference: Vec<f64>,
+    pub mean_difference: f64,
+    pub max_abs_difference: f64,
+    pub rms_difference: f64,
+    ///RMS of the difference relative to the RMS of `a`
+    pub relative_rms: f64,
+    ///Zero-lag normalised correlation
+    pub correlation: f64,
+    ///Lag of `b` relative to `a` with the highest correlation, in samples
+    pub best_lag: isize,
+    pub best_lag_correlation: f64,
+    ///Frequencies in Hz and amplitude ratio `|B|/|A|` where `a` has energy
+    pub spectral_ratio: Vec<(f64, f64)>,
+}
+
+impl TraceComparison{
+    ///Ratio of amplitude spectra averaged over the reference band, 1 when they agree
+    pub fn mean_spectral_ratio(&self)-> f64{
+        if self.spectral_ratio.is_empty(){
+            return 1.0;
+        }
+        self.spectral_ratio.iter().map(|(_, r)| r).sum::<f64>()/self.spectral_ratio.len() as f64
+    }
+
+    ///True when the traces agree sample for sample
+    pub fn is_identical(&self)-> bool{
+        self.difference.iter().all(|&d| d==0.0)
+    }
+
+    pub fn print_summary(&self){
+        println!("Samples compared: {}", self.samples);
+        if self.is_identical(){
+            println!("Traces are identical");
+            return;
+        }
+        println!("Difference mean: {:.6}", self.mean_difference);
+        println!("Difference max |B - A|: {:.6}", self.max_abs_difference);
+        println!("Difference RMS: {:.6} ({:.2}% of reference)", self.rms_difference, 100.0*self.relative_rms);
+        println!("Correlation: {:.4}", self.correlation);
+        println!("Best lag: {} samples (correlation {:.4})", self.best_lag, self.best_lag_correlation);
+        println!("Mean spectral ratio B/A: {:.4}", self.mean_spectral_ratio());
+        for (f, r) in self.spectral_ratio.iter().step_by((self.spectral_ratio.len()/10).max(1)){
+            println!("  {:>8.1} Hz  {:.4}", f, r);
+        }
+        println!("\nDifference (B - A):");
+        plot_ascii(&self.difference[..80.min(self.difference.len())], 12);
+    }
+}
+
+///Compare candidate `b` against reference `a`, both sampled every `dt` seconds
+pub fn compare_traces(a: &[f64], b: &[f64], dt: f64, max_lag: usize)-> Result<TraceComparison>{
+    let samples=a.len().min(b.len());
+    if samples==0{
+        return Err(anyhow!("Cannot compare empty traces"));
+    }
+    if dt<=0.0{
+        return Err(anyhow!("Sample interval must be positive"));
+    }
+    let (a, b)=(&a[..samples], &b[..samples]);
+
+    let difference: Vec<f64>=a.iter().zip(b).map(|(x, y)| y-x).collect();
+    let rms_difference=rms(&difference);
+    let reference_rms=rms(a);
+    let relative_rms=if reference_rms>0.0 { rms_difference/reference_rms } else { f64::INFINITY };
+
+    let max_lag=max_lag.min(samples-1) as isize;
+    let (best_lag, best_lag_correlation)=(-max_lag..=max_lag).map(|lag| (lag, lagged_correlation(a, b, lag)))
+        .fold((0, f64::NEG_INFINITY), |best, c| if c.1>best.1 { c } else { best });
+
+    let (spectrum_a, spectrum_b)=(spectral_analysis(a, dt, 0.0), spectral_analysis(b, dt, 0.0));
+    let floor=SPECTRAL_FLOOR*spectrum_a.amplitude.iter().fold(0.0_f64, |m, &x| m.max(x));
+    let spectral_ratio=spectrum_a.freqs.iter().zip(spectrum_a.amplitude.iter().zip(&spectrum_b.amplitude))
+        .filter(|(_, (&amp_a, _))| amp_a>floor && amp_a>0.0)
+        .map(|(&f, (amp_a, amp_b))| (f, amp_b/amp_a))
+        .collect();
+
+    Ok(TraceComparison{
+        samples,
+        mean_difference: difference.iter().sum::<f64>()/samples as f64,
+        max_abs_difference: difference.iter().fold(0.0, |m, &d| m.max(d.abs())),
+        rms_difference,
+        difference,
+        relative_rms,
+        correlation: lagged_correlation(a, b, 0),
+        best_lag,
+        best_lag_correlation,
+        spectral_ratio,
+    })
+}
+
+///Normalised correlation of `a[i]` with `b[i+lag]` over the overlap
+fn lagged_correlation(a: &[f64], b: &[f64], lag: isize)-> f64{
+    let (a, b)=if lag>=0 {
+        (&a[..a.len()-lag as usize], &b[lag as usize..])
+    }else{
+        (&a[(-lag) as usize..], &b[..b.len()-(-lag) as usize])
+    };
+    let dot: f64=a.iter().zip(b).map(|(x, y)| x*y).sum();
+    let norm=(a.iter().map(|x| x*x).sum::<f64>()*b.iter().map(|y| y*y).sum::<f64>()).sqrt();
+    if norm>0.0 { dot/norm } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::wavelets::RickerWavelet;
+
+    #[test]
+    fn test_compare_scaled_and_shifted()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.001, 200)?;
+        let a=wavelet.samples.clone();
+
+        let scaled: Vec<f64>=a.iter().map(|x| 1.5*x).collect();
+        let comparison=compare_traces(&a, &scaled, 0.001, 10)?;
+        assert_abs_diff_eq!(comparison.correlation, 1.0, epsilon=1e-12);
+        assert_abs_diff_eq!(comparison.relative_rms, 0.5, epsilon=1e-12);
+        assert_abs_diff_eq!(comparison.mean_spectral_ratio(), 1.5, epsilon=1e-9);
+        assert_eq!(comparison.best_lag, 0);
+
+        let mut shifted=vec![0.0; 4];
+        shifted.extend_from_slice(&a[..a.len()-4]);
+        let comparison=compare_traces(&a, &shifted, 0.001, 10)?;
+        assert_eq!(comparison.best_lag, 4);
+        assert!(comparison.best_lag_correlation>0.999);
+        assert!(comparison.correlation<0.9);
+
+        assert!(compare_traces(&a, &a, 0.001, 10)?.is_identical());
+        assert!(compare_traces(&[], &a, 0.001, 10).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_trace_from_csv()-> Result<()>{
+        let path=std::env::temp_dir().join("compare_trace.csv");
+        let path=path.to_str().unwrap();
+        crate::utils::export_to_csv(&[0.25, -0.5, 1.0], path)?;
+        assert_eq!(load_trace(path, DEFAULT_BUNDLE_ENTRY)?, vec![0.25, -0.5, 1.0]);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+//! Run configuration and up-front validation
+//!
+//! A run is described by one JSON document: the reflectivity model, the
+//! wavelet, the pipeline settings and, optionally, the inversion solver and
+//! a finite-difference grid, plus the computed columns added to exports.
+//! The solver may weight target time windows, e.g. a reservoir zone, in its
+//! data misfit.
+//! Missing sections take the defaults of the built-in demonstration run.
+//! `validate` checks the whole configuration for inconsistencies and reports
+//! every problem it finds, without modelling anything, so a long job is not
+//! started with a mistake in it.
+
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use crate::diagnostics::check_wavelet;
+use crate::forward_modelling::PipelineConfig;
+use crate::inversion::sparse::SparseInversion;
+use crate::inversion::target_windows::{sample_weights, TargetWindow};
+use crate::io::columns::{ComputedColumn, ExportConfig};
+use crate::models::ReflectivityModel;
+use crate::wavelets::RickerWavelet;
+
+///Spike reflectivity model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelConfig{
+    pub length: usize,
+    pub layer_positions: Vec<usize>,
+    pub reflection_coefficients: Vec<f64>,
+    ///Two-way time of sample zero in seconds
+    pub t0: f64,
+}
+
+impl Default for ModelConfig{
+    fn default()-> Self{
+        Self{
+            length: 100,
+            layer_positions: vec![20, 40, 60, 80],
+            reflection_coefficients: vec![0.1, -0.05, 0.15, -0.08],
+            t0: 0.0,
+        }
+    }
+}
+
+///Ricker source wavelet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaveletConfig{
+    ///Dominant frequency in Hz
+    pub frequency: f64,
+    ///Sample interval in seconds
+    pub dt: f64,
+    pub length: usize,
+}
+
+impl Default for WaveletConfig{
+    fn default()-> Self{
+        Self{
+            frequency: 30.0,
+            dt: 0.001,
+            length: 200,
+        }
+    }
+}
+
+///Sparse inversion settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolverConfig{
+    pub lambda: f64,
+    pub max_iterations: usize,
+    pub tolerance: f64,
+    ///Time windows whose misfit is weighted differently, e.g. a reservoir zone at ×10
+    #[serde(default)]
+    pub windows: Vec<TargetWindow>,
+}
+
+impl SolverConfig{
+    ///Sparse inversion with these settings for traces of `num_samples` samples from `t0`
+    pub fn sparse_inversion(&self, num_samples: usize, dt: f64, t0: f64)-> Result<SparseInversion>{
+        let data_weights=if self.windows.is_empty() { None } else { Some(sample_weights(&self.windows, num_samples, dt, t0)?) };
+        Ok(SparseInversion{ lambda: self.lambda, max_iterations: self.max_iterations, tolerance: self.tolerance, data_weights, ..SparseInversion::default() })
+    }
+}
+
+///Finite-difference grid for wave-equation modelling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridConfig{
+    ///Grid spacing in metres
+    pub dx: f64,
+    pub dz: f64,
+    ///Time step in seconds
+    pub dt: f64,
+    ///Velocity range of the model in m/s
+    pub min_velocity: f64,
+    pub max_velocity: f64,
+}
+
+///Complete description of a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunConfig{
+    pub model: ModelConfig,
+    pub wavelet: WaveletConfig,
+    pub pipeline: PipelineConfig,
+    pub solver: Option<SolverConfig>,
+    pub grid: Option<GridConfig>,
+    pub export: ExportConfig,
+}
+
+impl RunConfig{
+    ///Read a configuration from JSON
+    pub fn load(path: &str)-> Result<Self>{
+        let text=std::fs::read_to_string(path).with_context(|| format!("Failed to open config file: {}", path))?;
+        serde_json::from_str(&text).with_context(|| format!("Failed to parse config file: {}", path))
+    }
+
+    pub fn reflectivity_model(&self)-> ReflectivityModel{
+        ReflectivityModel::new(self.model.length, self.model.layer_positions.clone(), self.model.reflection_coefficients.clone()).with_t0(self.model.t0)
+    }
+
+    pub fn wavelet(&self)-> Result<RickerWavelet>{
+        RickerWavelet::new(self.wavelet.frequency, self.wavelet.dt, self.wavelet.length)
+    }
+}
+
+///Finite-difference time steps must satisfy `v_max dt sqrt(1/dx²+1/dz²) <= CFL_LIMIT`
+const CFL_LIMIT: f64=1.0;
+///Grid points per shortest wavelength needed to keep numerical dispersion small
+const POINTS_PER_WAVELENGTH: f64=5.0;
+///Highest frequency with significant Ricker energy, as a multiple of the dominant frequency
+const RICKER_MAX_FREQUENCY_RATIO: f64=2.5;
+
+///One inconsistency found in a configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem{
+    ///Dotted path of the offending setting, e.g. `pipeline.high_freq`
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>)-> fmt::Result{
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+///Check a configuration for consistency, returning every problem found
+pub fn validate(config: &RunConfig)-> Vec<Problem>{
+    let mut problems=Vec::new();
+    let mut report=|field: &str, message: String| problems.push(Problem{ field: field.to_string(), message });
+
+    //Model
+    let model=&config.model;
+    if model.length==0{
+        report("model.length", "model must have at least one sample".to_string());
+    }
+    if model.layer_positions.len()!=model.reflection_coefficients.len(){
+        report("model.reflection_coefficients", format!("{} coefficients for {} layer positions", model.reflection_coefficients.len(), model.layer_positions.len()));
+    }
+    for &position in model.layer_positions.iter().filter(|&&p| p>=model.length){
+        report("model.layer_positions", format!("position {} is outside the {}-sample model", position, model.length));
+    }
+    for &coefficient in model.reflection_coefficients.iter().filter(|c| c.is_nan() || c.abs()>=1.0){
+        report("model.reflection_coefficients", format!("coefficient {} must lie strictly between -1 and 1", coefficient));
+    }
+
+    //Wavelet
+    let wavelet=&config.wavelet;
+    let wavelet_ok=wavelet.frequency>0.0 && wavelet.dt>0.0 && wavelet.length>0;
+    if wavelet.frequency<=0.0{
+        report("wavelet.frequency", format!("must be positive, got {}", wavelet.frequency));
+    }
+    if wavelet.dt<=0.0{
+        report("wavelet.dt", format!("must be positive, got {}", wavelet.dt));
+    }
+    if wavelet.length==0{
+        report("wavelet.length", "must be positive".to_string());
+    }
+    if wavelet_ok{
+        let nyquist=0.5/wavelet.dt;
+        if RICKER_MAX_FREQUENCY_RATIO*wavelet.frequency>=nyquist{
+            report("wavelet.frequency", format!("{} Hz Ricker has energy up to {} Hz, above Nyquist ({} Hz)", wavelet.frequency, RICKER_MAX_FREQUENCY_RATIO*wavelet.frequency, nyquist));
+        }
+        if let Some(warning)=config.wavelet().ok().and_then(|w| check_wavelet(&w.samples)){
+            report("wavelet.length", warning.to_string());
+        }
+    }
+
+    //Pipeline
+    let pipeline=&config.pipeline;
+    if pipeline.sample_rate<=0.0{
+        report("pipeline.sample_rate", format!("must be positive, got {}", pipeline.sample_rate));
+    }
+    else if wavelet.dt>0.0 && (pipeline.sample_rate*wavelet.dt-1.0).abs()>1e-6{
+        report("pipeline.sample_rate", format!("{} Hz does not match the wavelet sample interval {} s", pipeline.sample_rate, wavelet.dt));
+    }
+    if pipeline.add_noise && pipeline.noise_level<0.0{
+        report("pipeline.noise_level", format!("must not be negative, got {}", pipeline.noise_level));
+    }
+    if pipeline.apply_filter{
+        if pipeline.low_freq<=0.0{
+            report("pipeline.low_freq", format!("must be positive, got {}", pipeline.low_freq));
+        }
+        if pipeline.low_freq>=pipeline.high_freq{
+            report("pipeline.high_freq", format!("band {}-{} Hz is not increasing", pipeline.low_freq, pipeline.high_freq));
+        }
+        if pipeline.sample_rate>0.0 && pipeline.high_freq>=pipeline.sample_rate/2.0{
+            report("pipeline.high_freq", format!("{} Hz is not below Nyquist ({} Hz)", pipeline.high_freq, pipeline.sample_rate/2.0));
+        }
+    }
+
+    //Solver
+    if let Some(solver)=&config.solver{
+        if solver.lambda.is_nan() || solver.lambda<0.0{
+            report("solver.lambda", format!("must not be negative, got {}", solver.lambda));
+        }
+        if solver.max_iterations==0{
+            report("solver.max_iterations", "must be positive".to_string());
+        }
+        if solver.tolerance.is_nan() || solver.tolerance<=0.0{
+            report("solver.tolerance", format!("must be positive, got {}", solver.tolerance));
+        }
+        for (i, window) in solver.windows.iter().enumerate(){
+            if let Err(e)=window.validate(){
+                report(&format!("solver.windows[{}]", i), e.to_string());
+            }
+        }
+    }
+
+    //Finite-difference grid
+    if let Some(grid)=&config.grid{
+        if !(grid.dx>0.0 && grid.dz>0.0 && grid.dt>0.0){
+            report("grid", "spacings and time step must be positive".to_string());
+        }
+        else if !(grid.min_velocity>0.0 && grid.min_velocity<=grid.max_velocity){
+            report("grid.min_velocity", format!("velocity range {}-{} m/s is invalid", grid.min_velocity, grid.max_velocity));
+        }
+        else{
+            let courant=grid.max_velocity*grid.dt*(1.0/(grid.dx*grid.dx)+1.0/(grid.dz*grid.dz)).sqrt();
+            if courant>CFL_LIMIT{
+                let stable_dt=CFL_LIMIT/(grid.max_velocity*(1.0/(grid.dx*grid.dx)+1.0/(grid.dz*grid.dz)).sqrt());
+                report("grid.dt", format!("Courant number {:.3} exceeds {} (unstable); use dt <= {:.3e} s", courant, CFL_LIMIT, stable_dt));
+            }
+            if wavelet.frequency>0.0{
+                let shortest=grid.min_velocity/(RICKER_MAX_FREQUENCY_RATIO*wavelet.frequency);
+                let points=shortest/grid.dx.max(grid.dz);
+                if points<POINTS_PER_WAVELENGTH{
+                    report("grid.dx", format!("{:.1} points per shortest wavelength ({:.1} m), need {}", points, shortest, POINTS_PER_WAVELENGTH));
+                }
+                if grid.dt>=0.5/(RICKER_MAX_FREQUENCY_RATIO*wavelet.frequency){
+                    report("grid.dt", format!("time step {} s aliases the wavelet's {} Hz content", grid.dt, RICKER_MAX_FREQUENCY_RATIO*wavelet.frequency));
+                }
+            }
+        }
+    }
+
+    //Export columns
+    for (i, column) in config.export.columns.iter().enumerate(){
+        if let ComputedColumn::Depth{ velocity } | ComputedColumn::IncidenceAngle{ velocity, .. }=column{
+            if !(velocity.v0>0.0 && velocity.v0.is_finite()){
+                report(&format!("export.columns[{}].velocity.v0", i), format!("must be positive, got {}", velocity.v0));
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid(){
+        assert!(validate(&RunConfig::default()).is_empty(), "{:?}", validate(&RunConfig::default()));
+    }
+
+    #[test]
+    fn test_reports_every_problem()-> Result<()>{
+        let json=r#"{
+            "model": { "length": 50, "layer_positions": [10, 60], "reflection_coefficients": [0.1, 1.2] },
+            "wavelet": { "frequency": 30.0, "dt": 0.001, "length": 20 },
+            "pipeline": { "apply_filter": true, "low_freq": 80.0, "high_freq": 600.0, "sample_rate": 1000.0 },
+            "solver": { "lambda": -1.0, "max_iterations": 100, "tolerance": 1e-6 },
+            "grid": { "dx": 10.0, "dz": 10.0, "dt": 0.004, "min_velocity": 1500.0, "max_velocity": 4000.0 }
+        }"#;
+        let path=std::env::temp_dir().join("run_config_validate_test.json");
+        std::fs::write(&path, json)?;
+        let config=RunConfig::load(path.to_str().unwrap())?;
+        std::fs::remove_file(&path)?;
+
+        let fields: Vec<String>=validate(&config).iter().map(|p| p.field.clone()).collect();
+        for expected in ["model.layer_positions", "model.reflection_coefficients", "wavelet.length", "pipeline.high_freq", "solver.lambda", "grid.dt"]{
+            assert!(fields.iter().any(|f| f==expected), "missing {} in {:?}", expected, fields);
+        }
+        //Band ordering holds (80 < 600), only Nyquist is violated
+        assert_eq!(fields.iter().filter(|f| *f=="pipeline.high_freq").count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_solver_target_windows()-> Result<()>{
+        let solver: SolverConfig=serde_json::from_str(r#"{
+            "lambda": 0.05, "max_iterations": 500, "tolerance": 1e-6,
+            "windows": [{ "start": 0.04, "end": 0.06, "weight": 10.0 }, { "start": 0.09, "end": 0.08, "weight": 2.0 }]
+        }"#)?;
+        let config=RunConfig{ solver: Some(solver.clone()), ..RunConfig::default() };
+        let fields: Vec<String>=validate(&config).iter().map(|p| p.field.clone()).collect();
+        assert_eq!(fields, vec!["solver.windows[1]".to_string()]);
+        assert!(solver.sparse_inversion(100, 0.001, 0.0).is_err());
+
+        let solver=SolverConfig{ windows: solver.windows[..1].to_vec(), ..solver };
+        let weights=solver.sparse_inversion(100, 0.001, 0.0)?.data_weights.unwrap();
+        assert_eq!((weights[39], weights[40], weights[60], weights[61]), (1.0, 10.0, 10.0, 1.0));
+
+        //Configurations written before windows existed still load
+        let plain: SolverConfig=serde_json::from_str(r#"{ "lambda": 0.05, "max_iterations": 500, "tolerance": 1e-6 }"#)?;
+        assert!(plain.sparse_inversion(100, 0.001, 0.0)?.data_weights.is_none());
+        Ok(())
+    }
+}
@@ -1,11 +1,52 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use num_complex::Complex;
-use rustfft::{FftPlanner, Fft};
-use std::sync::Arc;
+use rustfft::FftPlanner;
+use std::f64::consts::PI;
+use std::time::Instant;
+
+///Edge treatment applied around a spectral convolution or correlation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaperOptions{
+    ///Number of samples at each end of a trace tapered with a half-cosine
+    pub taper_length: usize,
+    ///Trim the output back to the length of the first input, dropping the
+    ///transients that build up where the operator overlaps the trace ends
+    pub trim_transients: bool,
+}
+
+impl Default for TaperOptions{
+    fn default()-> Self{
+        Self{
+            taper_length: 10,
+            trim_transients: true,
+        }
+    }
+}
+
+///How far linear convolutions are zero padded before transforming
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FftSizing{
+    ///Next power of two, which can nearly double an awkward length
+    PowerOfTwo,
+    ///Next length of the form `2^a 3^b 5^c`, which rustfft's mixed-radix
+    ///transforms handle about as fast per sample as a power of two
+    Fast,
+}
+
+impl FftSizing{
+    ///Transform length for at least `n` samples
+    pub fn length(&self, n: usize)-> usize{
+        match self{
+            FftSizing::PowerOfTwo=> next_power_of_2(n),
+            FftSizing::Fast=> next_fast_len(n),
+        }
+    }
+}
 
 /// High performance FFT-based convolution engine for seismic processing
 pub struct ConvolutionEngine{
-    planner::FftPlanner<f64>,
+    planner: FftPlanner<f64>,
+    sizing: FftSizing,
 }
 
 impl ConvolutionEngine{
@@ -13,9 +54,20 @@ impl ConvolutionEngine{
     pub fn new()-> Self {
         Self{
             planner: FftPlanner::new(),
+            sizing: FftSizing::Fast,
         }
     }
 
+    ///Choose how transforms are padded
+    pub fn with_sizing(mut self, sizing: FftSizing)-> Self{
+        self.sizing=sizing;
+        self
+    }
+
+    pub fn sizing(&self)-> FftSizing{
+        self.sizing
+    }
+
     ///Compute convolutin of two real-valued signals using FFT
     ///
     /// This is the core operation for seismic forward modelling:
@@ -28,25 +80,22 @@ impl ConvolutionEngine{
         //Calculate output length for linear convolution
         let output_len=signal_a.len()+signal_b.len()-1;
 
-        //Find next power of 2 for efficient FFT
-        let fft_len=next_power_of_2(output_len);
-
-        printn!("Convolution details:");
-        println!("Signal A lenght: {} samples", signal_a.len());
-        println!("Signal B length: {} samples", signal_b.len());
-        println!("Output length: {} samples", output_len);
-        println!("FFT length: {} samples (padded)", fft_len);
+        //Pad to a length the FFT handles efficiently
+        let fft_len=self.sizing.length(output_len);
 
         //Create FFT and IFFT plans
         let fft=self.planner.plan_fft_forward(fft_len);
-        let ifft=self.prepare_fft_buffer(signal_b, fft_len);
+        let ifft=self.planner.plan_fft_inverse(fft_len);
+
+        let mut buffer_a=self.prepare_fft_buffer(signal_a, fft_len);
+        let mut buffer_b=self.prepare_fft_buffer(signal_b, fft_len);
 
         //Forward FFT
         fft.process(&mut buffer_a);
         fft.process(&mut buffer_b);
 
         //Frequency domain multiplication (convolution theorem)
-        let mut result_buffer: Vec<Complex<f64>> = buffer_a.iter().zip(buffer_b.iter()).map(|a(a, b)| a*b).collect();
+        let mut result_buffer: Vec<Complex<f64>> = buffer_a.iter().zip(buffer_b.iter()).map(|(a, b)| a*b).collect();
 
         //Inverse FFT
         ifft.process(&mut result_buffer);
@@ -74,14 +123,145 @@ impl ConvolutionEngine{
         buffer
     }
 
-    //Compute cross-correlation using fft (for future use in inversion)
+    ///Linear convolution of a long signal with a short operator by overlap-add
+    ///
+    /// `signal_a` is cut into blocks of `block_length` samples. Each block is
+    /// convolved with `signal_b` through one transform of the padded length
+    /// of `block_length+signal_b.len()-1`, and the overlapping tails of
+    /// neighbouring blocks are summed. The operator is transformed once, so
+    /// this is cheaper than `convolve` when the signal is much longer than
+    /// the operator, and gives the same result.
+    pub fn convolve_overlap_add(&mut self, signal_a: &[f64], signal_b: &[f64], block_length: usize)-> Result<Vec<f64>>{
+        if block_length==0{
+            return Err(anyhow!("Overlap-add block length must be positive"));
+        }
+        if signal_a.is_empty() || signal_b.is_empty(){
+            return Ok(vec![]);
+        }
+
+        let fft_len=self.sizing.length(block_length+signal_b.len()-1);
+        let fft=self.planner.plan_fft_forward(fft_len);
+        let ifft=self.planner.plan_fft_inverse(fft_len);
+        let mut operator=self.prepare_fft_buffer(signal_b, fft_len);
+        fft.process(&mut operator);
+
+        let normalization_factor=1.0/fft_len as f64;
+        let mut output=vec![0.0; signal_a.len()+signal_b.len()-1];
+        for (index, block) in signal_a.chunks(block_length).enumerate(){
+            let mut buffer=self.prepare_fft_buffer(block, fft_len);
+            fft.process(&mut buffer);
+            buffer.iter_mut().zip(&operator).for_each(|(x, w)| *x*=w);
+            ifft.process(&mut buffer);
+
+            let start=index*block_length;
+            for (out, value) in output[start..].iter_mut().zip(&buffer[..block.len()+signal_b.len()-1]){
+                *out+=value.re*normalization_factor;
+            }
+        }
+        Ok(output)
+    }
+
+    ///Circular convolution of a periodic signal with an operator, without zero padding
+    ///
+    /// The signal is taken as one period of length `n`, so output sample `j`
+    /// is `sum_k signal[(j-k) mod n] operator[k]`: whatever the operator
+    /// pushes past the last sample wraps around onto the first ones. The
+    /// operator is zero-padded to `n` and must not be longer. The transform
+    /// is exactly `n` points whatever the engine's sizing, since padding would
+    /// change the period. For a linear result, use `convolve`.
+    pub fn convolve_circular(&mut self, signal: &[f64], operator: &[f64])-> Result<Vec<f64>>{
+        if operator.len()>signal.len(){
+            return Err(anyhow!("Operator of {} samples is longer than the {}-sample period", operator.len(), signal.len()));
+        }
+        if signal.is_empty() || operator.is_empty(){
+            return Ok(vec![]);
+        }
+        let response=self.spectrum(&operator.iter().copied().chain(std::iter::repeat(0.0)).take(signal.len()).collect::<Vec<_>>());
+        self.apply_spectrum(signal, &response)
+    }
+
+    ///Unpadded discrete Fourier transform of a real signal, all `n` bins
+    ///
+    /// Bin `k` is frequency `k/(n dt)` for `k<=n/2` and `(k-n)/(n dt)` above.
+    pub fn spectrum(&mut self, signal: &[f64])-> Vec<Complex<f64>>{
+        let mut buffer=self.prepare_fft_buffer(signal, signal.len());
+        if !buffer.is_empty(){
+            self.planner.plan_fft_forward(signal.len()).process(&mut buffer);
+        }
+        buffer
+    }
+
+    ///Multiply the unpadded spectrum of a periodic signal by `response` and transform back
+    ///
+    /// `response` holds one value per bin in the order returned by `spectrum`.
+    /// It should be conjugate symmetric (`response[n-k]` the conjugate of
+    /// `response[k]`) for the output to be real; otherwise only the real part
+    /// is returned. Being a product of DFTs, this is a circular operation,
+    /// and an operator whose impulse response is longer than the signal
+    /// wraps around.
+    pub fn apply_spectrum(&mut self, signal: &[f64], response: &[Complex<f64>])-> Result<Vec<f64>>{
+        let complex: Vec<Complex<f64>>=signal.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        Ok(self.apply_spectrum_complex(&complex, response)?.iter().map(|c| c.re).collect())
+    }
+
+    ///`apply_spectrum` for a complex signal; no symmetry is needed and the complex result is kept
+    ///
+    /// A response that is zero at negative frequencies is a single-sideband
+    /// filter: applied to an analytic trace it keeps the trace analytic.
+    pub fn apply_spectrum_complex(&mut self, signal: &[Complex<f64>], response: &[Complex<f64>])-> Result<Vec<Complex<f64>>>{
+        if response.len()!=signal.len(){
+            return Err(anyhow!("Have {} response values for a {}-sample signal", response.len(), signal.len()));
+        }
+        if signal.is_empty(){
+            return Ok(vec![]);
+        }
+        let n=signal.len();
+        let mut buffer=signal.to_vec();
+        self.planner.plan_fft_forward(n).process(&mut buffer);
+        buffer.iter_mut().zip(response).for_each(|(x, h)| *x*=h);
+        self.planner.plan_fft_inverse(n).process(&mut buffer);
+        Ok(buffer.iter().map(|c| c/n as f64).collect())
+    }
+
+    ///Linear convolution of complex signals, such as analytic traces
+    ///
+    /// Convolution commutes with the Hilbert transform, so convolving the
+    /// analytic signal of a trace with a real operator gives the analytic
+    /// signal of the filtered trace: envelope and instantaneous phase can be
+    /// read off the result without another Hilbert transform. Padding
+    /// follows the engine's sizing, as in `convolve`.
+    pub fn convolve_complex(&mut self, signal_a: &[Complex<f64>], signal_b: &[Complex<f64>])-> Result<Vec<Complex<f64>>>{
+        if signal_a.is_empty() || signal_b.is_empty(){
+            return Ok(vec![]);
+        }
+        let output_len=signal_a.len()+signal_b.len()-1;
+        let fft_len=self.sizing.length(output_len);
+        let fft=self.planner.plan_fft_forward(fft_len);
+        let ifft=self.planner.plan_fft_inverse(fft_len);
+
+        let padded=|signal: &[Complex<f64>]| { let mut buffer=signal.to_vec(); buffer.resize(fft_len, Complex::new(0.0, 0.0)); buffer };
+        let (mut buffer_a, mut buffer_b)=(padded(signal_a), padded(signal_b));
+        fft.process(&mut buffer_a);
+        fft.process(&mut buffer_b);
+        let mut result_buffer: Vec<Complex<f64>>=buffer_a.iter().zip(&buffer_b).map(|(a, b)| a*b).collect();
+        ifft.process(&mut result_buffer);
+
+        let normalization_factor=1.0/fft_len as f64;
+        Ok(result_buffer.iter().take(output_len).map(|c| c*normalization_factor).collect())
+    }
+
+    ///Full cross-correlation `c[lag]=sum_n a[n] b[n+lag]` computed with FFTs
+    ///
+    /// The output has `len_a+len_b-1` samples, negative lags first: index
+    /// `i` holds lag `i-(len_a-1)`, so zero lag is at index `len_a-1`. The
+    /// layout does not depend on the engine's sizing.
     pub fn cross_correlate(&mut self, signal_a: &[f64], signal_b: &[f64])-> Result<Vec<f64>>{
         if signal_a.is_empty()|| signal_b.is_empty(){
             return Ok(vec![]);
         }
 
         let output_len=signal_a.len()+signal_b.len()-1;
-        let fft_len=next_power_of_2(output_len);
+        let fft_len=self.sizing.length(output_len);
 
         let fft=self.planner.plan_fft_forward(fft_len);
         let ifft=self.planner.plan_fft_inverse(fft_len);
@@ -97,8 +277,10 @@ impl ConvolutionEngine{
 
         ifft.process(&mut result_buffer);
 
+        //Negative lags wrap to the end of the circular correlation
         let normalization_factor=1.0/fft_len as f64;
-        let result: Vec<f64>=result_buffer.iter().take(output_len).map(|c| c.re*normalization_factor).collect();
+        let zero_lag=signal_a.len()-1;
+        let result: Vec<f64>=(0..output_len).map(|i| result_buffer[(i+fft_len-zero_lag)%fft_len].re*normalization_factor).collect();
 
         Ok (result)
     }
@@ -107,6 +289,123 @@ impl ConvolutionEngine{
     pub fn auto_correlate(&mut self, signal:&[f64])-> Result<Vec<f64>> {
         self.cross_correlate(signal, signal)
     }
+
+    ///Convolve after tapering the trace ends, optionally trimming edge transients
+    ///
+    /// Abrupt trace ends act like a boxcar window and leak energy across the
+    /// spectrum; the cosine taper brings each end smoothly to zero first.
+    /// Only `signal_a` (the trace) is tapered, `signal_b` is the operator and is
+    /// applied unchanged.
+    pub fn convolve_tapered(&mut self, signal_a: &[f64], signal_b: &[f64], options: &TaperOptions)-> Result<Vec<f64>>{
+        let tapered_a=tapered_copy(signal_a, options.taper_length);
+
+        let result=self.convolve(&tapered_a, signal_b)?;
+        Ok(trim_output(result, signal_a.len(), signal_b.len(), options))
+    }
+
+    ///Cross-correlate after tapering both traces, optionally trimming to lags around zero
+    ///
+    /// Trimmed output has `len_a` samples with zero lag at index `len_a/2`,
+    /// the time-axis convention of `RickerWavelet::new`; lags beyond the
+    /// overlap of the traces are zero.
+    pub fn cross_correlate_tapered(&mut self, signal_a: &[f64], signal_b: &[f64], options: &TaperOptions)-> Result<Vec<f64>>{
+        let tapered_a=tapered_copy(signal_a, options.taper_length);
+        let tapered_b=tapered_copy(signal_b, options.taper_length);
+
+        let result=self.cross_correlate(&tapered_a, &tapered_b)?;
+        Ok(trim_correlation(result, signal_a.len(), options))
+    }
+
+    ///Hilbert transform of a real trace
+    ///
+    /// This is the imaginary part of the analytic signal, i.e. the trace with
+    /// every frequency component rotated by 90 degrees.
+    pub fn hilbert(&mut self, signal: &[f64])-> Vec<f64>{
+        self.analytic_signal(signal).iter().map(|c| c.im).collect()
+    }
+
+    ///Analytic signal `s + i H(s)` of a real trace
+    ///
+    /// The spectrum is the trace's with negative frequencies removed and
+    /// positive ones doubled. Its modulus is the envelope and its argument
+    /// the instantaneous phase; the real part is the trace itself.
+    pub fn analytic_signal(&mut self, signal: &[f64])-> Vec<Complex<f64>>{
+        let n=signal.len();
+        if n==0{
+            return vec![];
+        }
+
+        let fft=self.planner.plan_fft_forward(n);
+        let ifft=self.planner.plan_fft_inverse(n);
+        let mut buffer=self.prepare_fft_buffer(signal, n);
+        fft.process(&mut buffer);
+
+        //Double the positive frequencies and zero the negative ones
+        for (k, value) in buffer.iter_mut().enumerate(){
+            if k==0 || 2*k==n{
+                continue;
+            }
+            *value*=if k<n.div_ceil(2) { 2.0 } else { 0.0 };
+        }
+
+        ifft.process(&mut buffer);
+        signal.iter().zip(&buffer).map(|(&s, c)| Complex::new(s, c.im/n as f64)).collect()
+    }
+
+    ///Shift every frequency of a real trace up by `shift` Hz (down if negative)
+    ///
+    /// Single-sideband modulation: the analytic signal is multiplied by
+    /// `exp(2 pi i shift t)` and the real part kept. Unlike multiplying the
+    /// trace by a cosine, no mirror band appears at the difference
+    /// frequencies. Content shifted past zero or Nyquist folds back.
+    pub fn frequency_shift(&mut self, signal: &[f64], shift: f64, dt: f64)-> Vec<f64>{
+        self.analytic_signal(signal).iter().enumerate().map(|(i, z)| {
+            (z*Complex::from_polar(1.0, 2.0*PI*shift*i as f64*dt)).re
+        }).collect()
+    }
+
+    ///Constant phase rotation, `s cos(p) - H(s) sin(p)` for `p` in degrees
+    pub fn rotate_phase(&mut self, signal: &[f64], phase_deg: f64)-> Vec<f64>{
+        let quadrature=self.hilbert(signal);
+        let (sin, cos)=phase_deg.to_radians().sin_cos();
+        signal.iter().zip(&quadrature).map(|(s, h)| s*cos-h*sin).collect()
+    }
+
+    ///Wiener (optimum) deconvolution of `trace` by `wavelet`, the inverse of `convolve`
+    ///
+    /// Divides spectra as `T W* / (|W|^2 + prewhitening max|W|^2)`; the white
+    /// noise `prewhitening` (e.g. 0.01 for 1%) keeps frequencies where the
+    /// wavelet has little energy from blowing up. The trace is padded to the
+    /// full convolution length so nothing wraps around, and the output has the
+    /// trace's length: deconvolving `convolve(r, w)` returns `r` in its first
+    /// `r.len()` samples, followed by values near zero.
+    pub fn wiener_deconvolve(&mut self, trace: &[f64], wavelet: &[f64], prewhitening: f64)-> Result<Vec<f64>>{
+        if trace.is_empty() || wavelet.is_empty(){
+            return Err(anyhow!("Cannot deconvolve with an empty trace or wavelet"));
+        }
+        if !(prewhitening>=0.0 && prewhitening.is_finite()){
+            return Err(anyhow!("Prewhitening must not be negative, got {}", prewhitening));
+        }
+
+        let fft_len=self.sizing.length(trace.len()+wavelet.len()-1);
+        let fft=self.planner.plan_fft_forward(fft_len);
+        let ifft=self.planner.plan_fft_inverse(fft_len);
+        let mut buffer_t=self.prepare_fft_buffer(trace, fft_len);
+        let mut buffer_w=self.prepare_fft_buffer(wavelet, fft_len);
+        fft.process(&mut buffer_t);
+        fft.process(&mut buffer_w);
+
+        let peak=buffer_w.iter().fold(0.0_f64, |m, c| m.max(c.norm_sqr()));
+        if peak==0.0{
+            return Err(anyhow!("Cannot deconvolve by a wavelet with no energy"));
+        }
+        let stabilisation=(prewhitening*peak).max(f64::MIN_POSITIVE);
+        let mut result_buffer: Vec<Complex<f64>>=buffer_t.iter().zip(&buffer_w).map(|(t, w)| t*w.conj()/(w.norm_sqr()+stabilisation)).collect();
+        ifft.process(&mut result_buffer);
+
+        let normalization_factor=1.0/fft_len as f64;
+        Ok(result_buffer.iter().take(trace.len()).map(|c| c.re*normalization_factor).collect())
+    }
 }
 
 impl Default for ConvolutionEngine{
@@ -115,6 +414,60 @@ impl Default for ConvolutionEngine{
     }
 }
 
+///Apply a half-cosine taper to the first and last `taper_length` samples in place
+///
+/// The taper length is capped at half the signal so the two ends never overlap.
+pub fn apply_cosine_taper(signal: &mut [f64], taper_length: usize){
+    let n=signal.len();
+    let taper_length=taper_length.min(n/2);
+
+    for i in 0..taper_length{
+        let weight=0.5*(1.0-(PI*(i as f64+0.5)/taper_length as f64).cos());
+        signal[i]*=weight;
+        signal[n-1-i]*=weight;
+    }
+}
+
+fn tapered_copy(signal: &[f64], taper_length: usize)-> Vec<f64>{
+    let mut copy=signal.to_vec();
+    apply_cosine_taper(&mut copy, taper_length);
+    copy
+}
+
+///Keep the central `len_a` samples of a full-length result when trimming is enabled
+fn trim_output(result: Vec<f64>, len_a: usize, len_b: usize, options: &TaperOptions)-> Vec<f64>{
+    if !options.trim_transients || result.is_empty(){
+        return result;
+    }
+
+    let start=(len_b-1)/2;
+    result[start..start+len_a].to_vec()
+}
+
+///`len_a` lags of a full cross-correlation centred on zero lag, which sits at `len_a-1`
+fn trim_correlation(result: Vec<f64>, len_a: usize, options: &TaperOptions)-> Vec<f64>{
+    if !options.trim_transients || result.is_empty(){
+        return result;
+    }
+
+    let first=len_a-1-len_a/2;
+    (first..first+len_a).map(|i| result.get(i).copied().unwrap_or(0.0)).collect()
+}
+
+///Direct time-domain linear convolution, the reference the FFT paths are checked against
+pub fn convolve_direct(signal_a: &[f64], signal_b: &[f64])-> Vec<f64>{
+    if signal_a.is_empty() || signal_b.is_empty(){
+        return vec![];
+    }
+    let mut output=vec![0.0; signal_a.len()+signal_b.len()-1];
+    for (i, &a) in signal_a.iter().enumerate(){
+        for (out, &b) in output[i..].iter_mut().zip(signal_b){
+            *out+=a*b;
+        }
+    }
+    output
+}
+
 ///Find the next power of 2 greater than or equal to n
 fn next_power_of_2(n: usize)-> usize{
     if n<=1{
@@ -123,15 +476,88 @@ fn next_power_of_2(n: usize)-> usize{
 
     let mut power=1;
     while power < n{
-        power<=1;
+        power<<=1;
     }
     power
 }
 
+///Smallest length at least `n` of the form `2^a 3^b 5^c`
+pub fn next_fast_len(n: usize)-> usize{
+    if n<=1{
+        return 1;
+    }
+
+    let mut best=next_power_of_2(n);
+    let mut p5=1;
+    while p5<best{
+        let mut p35=p5;
+        while p35<best{
+            //Smallest power-of-two multiple of 3^b 5^c that reaches n
+            best=best.min(p35*next_power_of_2(n.div_ceil(p35)));
+            p35*=3;
+        }
+        p5*=5;
+    }
+    best
+}
+
+///Time per transform at the padded lengths each sizing picks for one required length
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FftSizeTiming{
+    ///Samples that must fit, e.g. a full linear convolution
+    pub required: usize,
+    pub power_of_two: usize,
+    pub fast: usize,
+    pub power_of_two_seconds: f64,
+    pub fast_seconds: f64,
+}
+
+impl FftSizeTiming{
+    ///Power-of-two time over mixed-radix time; above one the smaller transform wins
+    pub fn speedup(&self)-> f64{
+        self.power_of_two_seconds/self.fast_seconds.max(f64::MIN_POSITIVE)
+    }
+}
+
+///Benchmark forward transforms at both paddings of every required length
+pub fn benchmark_fft_sizes(required: &[usize], repeats: usize)-> Result<Vec<FftSizeTiming>>{
+    if repeats==0 || required.contains(&0){
+        return Err(anyhow!("Need at least one repeat and positive lengths"));
+    }
+    let mut planner=FftPlanner::<f64>::new();
+    let mut time=|n: usize| {
+        let fft=planner.plan_fft_forward(n);
+        let mut buffer: Vec<Complex<f64>>=(0..n).map(|i| Complex::new((i as f64*0.37).sin(), 0.0)).collect();
+        let start=Instant::now();
+        for _ in 0..repeats{
+            fft.process(&mut buffer);
+            //Keep values bounded across repeats
+            buffer.iter_mut().for_each(|c| *c/=n as f64);
+        }
+        std::hint::black_box(&buffer);
+        start.elapsed().as_secs_f64()/repeats as f64
+    };
+
+    Ok(required.iter().map(|&n| {
+        let (power_of_two, fast)=(next_power_of_2(n), next_fast_len(n));
+        FftSizeTiming{ required: n, power_of_two, fast, power_of_two_seconds: time(power_of_two), fast_seconds: time(fast) }
+    }).collect())
+}
+
+///Print a table of benchmark results
+pub fn print_fft_benchmark(timings: &[FftSizeTiming]){
+    println!("{:>9} {:>9} {:>10} {:>9} {:>10} {:>8}", "required", "pow2", "pow2 us", "fast", "fast us", "speedup");
+    for t in timings{
+        println!("{:>9} {:>9} {:>10.2} {:>9} {:>10.2} {:>7.2}x",
+            t.required, t.power_of_two, 1e6*t.power_of_two_seconds, t.fast, 1e6*t.fast_seconds, t.speedup());
+    }
+}
+
 #[cfg(test)]
 mod tests{
     use super::*;
     use approx::assert_abs_diff_eq;
+    use crate::noise::{Rng, SeededRng};
 
     #[test]
     fn test_next_power_of_2(){
@@ -143,6 +569,88 @@ mod tests{
         assert_eq!(next_power_of_2(257), 512);
     }
 
+    #[test]
+    fn test_next_fast_len(){
+        assert_eq!(next_fast_len(1), 1);
+        assert_eq!(next_fast_len(7), 8);
+        assert_eq!(next_fast_len(11), 12);
+        assert_eq!(next_fast_len(257), 270);
+        assert_eq!(next_fast_len(1025), 1080);
+        assert_eq!(next_fast_len(4097), 4320);
+        //Every length up to 2000 against a brute-force search
+        let smooth=|mut m: usize| { for p in [2, 3, 5]{ while m.is_multiple_of(p){ m/=p; } } m==1 };
+        for n in 1..2000usize{
+            let expected=(n..).find(|&m| smooth(m)).unwrap();
+            assert_eq!(next_fast_len(n), expected, "{}", n);
+        }
+    }
+
+    #[test]
+    fn test_sizing_gives_same_convolution()-> Result<()>{
+        //Output length 257: a power of two would pad to 512, mixed radix to 270
+        let a: Vec<f64>=(0..200).map(|i| (i as f64*0.3).sin()).collect();
+        let b: Vec<f64>=(0..58).map(|i| (-(i as f64-29.0).powi(2)/50.0).exp()).collect();
+        let fast=ConvolutionEngine::new().convolve(&a, &b)?;
+        let mut pow2_engine=ConvolutionEngine::new().with_sizing(FftSizing::PowerOfTwo);
+        assert_eq!(pow2_engine.sizing(), FftSizing::PowerOfTwo);
+        let pow2=pow2_engine.convolve(&a, &b)?;
+        assert_eq!(fast.len(), 257);
+        for (x, y) in fast.iter().zip(&pow2){
+            assert_abs_diff_eq!(x, y, epsilon=1e-10);
+        }
+        assert_eq!(FftSizing::Fast.length(257), 270);
+
+        let timings=benchmark_fft_sizes(&[257, 1025], 3)?;
+        assert_eq!(timings[1].power_of_two, 2048);
+        assert_eq!(timings[1].fast, 1080);
+        assert!(timings.iter().all(|t| t.fast_seconds>0.0 && t.power_of_two_seconds>0.0));
+        assert!(benchmark_fft_sizes(&[0], 3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_correlation_lag_layout()-> Result<()>{
+        //Output length 5: mixed radix does not pad at all, a power of two pads to 8
+        let a=[1.0, 2.0, 3.0];
+        let b=[0.5, -1.0, 4.0];
+        let direct=|lag: isize| (0..a.len() as isize).filter(|n| (0..b.len() as isize).contains(&(n+lag))).map(|n| a[n as usize]*b[(n+lag) as usize]).sum::<f64>();
+        for sizing in [FftSizing::Fast, FftSizing::PowerOfTwo]{
+            let result=ConvolutionEngine::new().with_sizing(sizing).cross_correlate(&a, &b)?;
+            assert_eq!(result.len(), 5);
+            for (i, value) in result.iter().enumerate(){
+                assert_abs_diff_eq!(*value, direct(i as isize-2), epsilon=1e-12);
+            }
+        }
+        //Autocorrelation peaks at zero lag, index len-1
+        let auto=ConvolutionEngine::new().auto_correlate(&a)?;
+        assert_abs_diff_eq!(auto[2], 14.0, epsilon=1e-12);
+        assert_abs_diff_eq!(auto[0], auto[4], epsilon=1e-12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tapered_correlation_trims_around_zero_lag()-> Result<()>{
+        let mut engine=ConvolutionEngine::new();
+        let options=TaperOptions{ taper_length: 0, trim_transients: true };
+        //b is a delayed by 3 samples, so the correlation peaks at lag +3
+        let a: Vec<f64>=(0..40).map(|i| (-((i as f64-20.0)/2.0).powi(2)).exp()).collect();
+        let b: Vec<f64>=(0..40).map(|i| (-((i as f64-23.0)/2.0).powi(2)).exp()).collect();
+        let trimmed=engine.cross_correlate_tapered(&a, &b, &options)?;
+        assert_eq!(trimmed.len(), 40);
+        let peak=(0..40).max_by(|&i, &j| trimmed[i].total_cmp(&trimmed[j])).unwrap();
+        assert_eq!(peak, 20+3);
+        let full=engine.cross_correlate(&a, &b)?;
+        assert_abs_diff_eq!(trimmed[20], full[39], epsilon=1e-12);
+
+        //A short operator leaves positive lags past its end at zero
+        let short=engine.cross_correlate_tapered(&a, &b[..5], &options)?;
+        assert_eq!(short.len(), 40);
+        assert!(short[20+5..].iter().all(|&x| x==0.0));
+        let untrimmed=engine.cross_correlate_tapered(&a, &b, &TaperOptions{ trim_transients: false, ..options })?;
+        assert_eq!(untrimmed.len(), 79);
+        Ok(())
+    }
+
     #[test]
     fn test_simple_convolution()-> Result<()>{
         let mut engine=ConvolutionEngine::new();
@@ -152,11 +660,11 @@ mod tests{
         let signal_b=vec![1.0, 2.0, 3.0];
 
         let result=engine.convolve(&signal_a, &signal_b)?;
-        let expected=vec![1.0, 2.0, 3.0, 0.0, 0.0];
+        let expected=[1.0, 2.0, 3.0, 0.0, 0.0];
 
         assert_eq!(result.len(), expected.len());
-        for(i, (&actual, &expected)) in result.iter().zip(expected.iter()).enumerate(){
-            assert_abs_diff_eq!(actual, expected, epsilon=1e-10,"Mismatch at index {}: {} vs {}", i, actual, expected);
+        for (&actual, &expected) in result.iter().zip(expected.iter()){
+            assert_abs_diff_eq!(actual, expected, epsilon=1e-10);
         }
 
         Ok(())
@@ -185,10 +693,242 @@ mod tests{
         let result_ba=engine.convolve(&signal_b, &signal_a)?;
 
         assert_eq!(result_ab.len(), result_ba.len());
-        for(i, (&a, &b)) in result_ab.iter().zip(result_ba.iter()).enumerate(){
-            assert_abs_diff_eq!(a,b, epsilon=1e-10, "Symmetry failed at index {}: {} vs {}", i, a ,b);
+        for (&a, &b) in result_ab.iter().zip(result_ba.iter()){
+            assert_abs_diff_eq!(a,b, epsilon=1e-10);
+        }
+
+        Ok(())
+    }
+
+    ///Random signal whose samples span `decades` orders of magnitude, with random signs
+    fn random_signal(rng: &mut SeededRng, length: usize, decades: f64)-> Vec<f64>{
+        (0..length).map(|_| {
+            let magnitude=10f64.powf(decades*(rng.uniform()-0.5));
+            if rng.uniform()<0.5 { -magnitude } else { magnitude }
+        }).collect()
+    }
+
+    ///Largest difference from the direct convolution, relative to the round-off scale `max|a| max|b| min(len)`
+    fn relative_error(result: &[f64], a: &[f64], b: &[f64])-> f64{
+        let reference=convolve_direct(a, b);
+        assert_eq!(result.len(), reference.len());
+        let peak=|x: &[f64]| x.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+        let scale=peak(a)*peak(b)*a.len().min(b.len()) as f64;
+        result.iter().zip(&reference).fold(0.0f64, |m, (x, y)| m.max((x-y).abs()))/scale
+    }
+
+    #[test]
+    fn test_fft_paths_match_direct_convolution()-> Result<()>{
+        let mut rng=SeededRng::new(1010);
+        let mut engine=ConvolutionEngine::new();
+        let mut pow2_engine=ConvolutionEngine::new().with_sizing(FftSizing::PowerOfTwo);
+        //Unit lengths, primes around awkward FFT sizes and a long signal against a short operator
+        let lengths=[(1, 1), (1, 7), (13, 1), (2, 3), (97, 31), (101, 101), (251, 17), (509, 127), (1021, 3), (4001, 61)];
+        for &(la, lb) in &lengths{
+            for decades in [0.0, 6.0, 24.0]{
+                let a=random_signal(&mut rng, la, decades);
+                let b=random_signal(&mut rng, lb, decades);
+                let block=1+(rng.uniform()*2.0*lb as f64) as usize;
+
+                let fast=engine.convolve(&a, &b)?;
+                let pow2=pow2_engine.convolve(&a, &b)?;
+                let overlap_add=engine.convolve_overlap_add(&a, &b, block)?;
+                for (name, result) in [("fast", &fast), ("pow2", &pow2), ("overlap-add", &overlap_add)]{
+                    let error=relative_error(result, &a, &b);
+                    assert!(error<1e-13, "{} {}x{} over {} decades: {:e}", name, la, lb, decades, error);
+                }
+            }
+        }
+
+        //Exact for small integers
+        assert_eq!(convolve_direct(&[1.0, 2.0, 3.0], &[0.0, 1.0, 0.5]), vec![0.0, 1.0, 2.5, 4.0, 1.5]);
+        assert!(convolve_direct(&[], &[1.0]).is_empty());
+        assert!(engine.convolve_overlap_add(&[1.0], &[1.0], 0).is_err());
+        assert!(engine.convolve_overlap_add(&[], &[1.0], 4)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_circular_convolution_wraps_around()-> Result<()>{
+        let mut engine=ConvolutionEngine::new();
+        //The last sample's operator tail lands on the first samples
+        let result=engine.convolve_circular(&[0.0, 0.0, 0.0, 1.0], &[1.0, 2.0, 3.0])?;
+        for (a, b) in result.iter().zip([2.0, 3.0, 0.0, 1.0]){
+            assert_abs_diff_eq!(*a, b, epsilon=1e-12);
+        }
+
+        //Against the definition, at a prime period no power of two would pad to
+        let mut rng=SeededRng::new(1011);
+        let signal=random_signal(&mut rng, 97, 0.0);
+        let operator=random_signal(&mut rng, 20, 0.0);
+        let result=engine.convolve_circular(&signal, &operator)?;
+        for (j, value) in result.iter().enumerate(){
+            let expected: f64=operator.iter().enumerate().map(|(k, w)| w*signal[(j+97-k)%97]).sum();
+            assert_abs_diff_eq!(*value, expected, epsilon=1e-12);
+        }
+        //Folding the linear convolution onto one period gives the same
+        let linear=engine.convolve(&signal, &operator)?;
+        for (j, value) in result.iter().enumerate(){
+            let folded: f64=linear.iter().skip(j).step_by(97).sum();
+            assert_abs_diff_eq!(*value, folded, epsilon=1e-12);
+        }
+
+        //Cyclic deconvolution: dividing by the operator's spectrum undoes it exactly
+        let mut padded=operator.clone();
+        padded.resize(97, 0.0);
+        let inverse: Vec<Complex<f64>>=engine.spectrum(&padded).iter().map(|h| 1.0/h).collect();
+        let recovered=engine.apply_spectrum(&result, &inverse)?;
+        for (a, b) in recovered.iter().zip(&signal){
+            assert_abs_diff_eq!(a, b, epsilon=1e-9);
+        }
+
+        assert!(engine.convolve_circular(&[1.0; 3], &[1.0; 4]).is_err());
+        assert!(engine.apply_spectrum(&[1.0; 3], &inverse).is_err());
+        assert!(engine.convolve_circular(&[], &[]).unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_complex_convolution_keeps_signal_analytic()-> Result<()>{
+        let mut engine=ConvolutionEngine::new();
+        let (n, dt)=(256, 0.002);
+        let trace: Vec<f64>=(0..n).map(|i| {
+            let t=i as f64*dt-0.25;
+            (2.0*PI*30.0*t).cos()*(-(t/0.05).powi(2)).exp()
+        }).collect();
+        let wavelet: Vec<f64>=(0..31).map(|i| (-((i as f64-15.0)/4.0).powi(2)).exp()).collect();
+
+        let analytic=engine.analytic_signal(&trace);
+        assert!(analytic.iter().zip(&trace).all(|(z, s)| z.re==*s));
+        let complex_wavelet: Vec<Complex<f64>>=wavelet.iter().map(|&w| Complex::new(w, 0.0)).collect();
+        let filtered=engine.convolve_complex(&analytic, &complex_wavelet)?;
+        let real=engine.convolve(&trace, &wavelet)?;
+        let reference=engine.analytic_signal(&real);
+        assert_eq!(filtered.len(), real.len());
+        let peak=reference.iter().fold(0.0f64, |m, z| m.max(z.norm()));
+        for (a, b) in filtered.iter().zip(&reference){
+            assert!((a-b).norm()<1e-3*peak);
+        }
+
+        //Complex convolution against the direct sum
+        let a=[Complex::new(1.0, 2.0), Complex::new(0.0, -1.0)];
+        let b=[Complex::new(0.5, 0.0), Complex::new(1.0, 1.0), Complex::new(0.0, 2.0)];
+        let result=engine.convolve_complex(&a, &b)?;
+        let expected=[a[0]*b[0], a[0]*b[1]+a[1]*b[0], a[0]*b[2]+a[1]*b[1], a[1]*b[2]];
+        for (x, y) in result.iter().zip(&expected){
+            assert!((x-y).norm()<1e-12);
+        }
+        assert!(engine.convolve_complex(&[], &b)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_single_sideband_shift()-> Result<()>{
+        let mut engine=ConvolutionEngine::new();
+        let (n, dt)=(400, 0.001);
+        //20 cycles of 50 Hz over the period, moved to 75 Hz with no 25 Hz mirror
+        let tone: Vec<f64>=(0..n).map(|i| (2.0*PI*50.0*i as f64*dt).cos()).collect();
+        let shifted=engine.frequency_shift(&tone, 25.0, dt);
+        for (i, value) in shifted.iter().enumerate(){
+            assert_abs_diff_eq!(*value, (2.0*PI*75.0*i as f64*dt).cos(), epsilon=1e-9);
+        }
+
+        //A response passing only positive frequencies keeps an analytic trace analytic
+        let analytic=engine.analytic_signal(&tone);
+        let response: Vec<Complex<f64>>=(0..n).map(|k| if k<n/2 { Complex::new(1.0, 0.0) } else { Complex::new(0.0, 0.0) }).collect();
+        let filtered=engine.apply_spectrum_complex(&analytic, &response)?;
+        for (a, b) in filtered.iter().zip(&analytic){
+            assert!((a-b).norm()<1e-9);
+        }
+        assert!(engine.apply_spectrum_complex(&analytic[..10], &response).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cosine_taper_shape(){
+        let mut signal=vec![1.0; 20];
+        apply_cosine_taper(&mut signal, 4);
+
+        //Ends are strongly attenuated, interior untouched, taper symmetric
+        assert!(signal[0]<0.2);
+        assert!(signal[3]<1.0);
+        assert_eq!(signal[4], 1.0);
+        assert_eq!(signal[15], 1.0);
+        for i in 0..4{
+            assert_abs_diff_eq!(signal[i], signal[19-i], epsilon=1e-12);
+        }
+    }
+
+    #[test]
+    fn test_tapered_convolution_trim()-> Result<()>{
+        let mut engine=ConvolutionEngine::new();
+
+        let trace=vec![1.0; 50];
+        let wavelet=vec![0.25, 0.5, 0.25];
+        let options=TaperOptions{ taper_length: 5, trim_transients: true };
+
+        let result=engine.convolve_tapered(&trace, &wavelet, &options)?;
+        assert_eq!(result.len(), trace.len());
+
+        //Away from the tapered ends the smoothing wavelet leaves a constant trace unchanged
+        assert_abs_diff_eq!(result[25], 1.0, epsilon=1e-10);
+        assert!(result[0].abs()<0.1);
+
+        let untrimmed=engine.convolve_tapered(&trace, &wavelet, &TaperOptions{ trim_transients: false, ..options })?;
+        assert_eq!(untrimmed.len(), 52);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hilbert_of_cosine_is_sine(){
+        let mut engine=ConvolutionEngine::new();
+        let n=128;
+        let signal: Vec<f64>=(0..n).map(|i| (2.0*PI*8.0*i as f64/n as f64).cos()).collect();
+
+        let transformed=engine.hilbert(&signal);
+        for (i, value) in transformed.iter().enumerate(){
+            assert_abs_diff_eq!(*value, (2.0*PI*8.0*i as f64/n as f64).sin(), epsilon=1e-10);
+        }
+    }
+
+    #[test]
+    fn test_wiener_deconvolution_undoes_convolution()-> Result<()>{
+        let mut engine=ConvolutionEngine::new();
+        let mut reflectivity=vec![0.0; 60];
+        reflectivity[10]=0.2;
+        reflectivity[25]= -0.1;
+        reflectivity[40]=0.15;
+
+        //A wavelet without spectral zeros is inverted exactly
+        let wavelet=vec![1.0, -0.5, 0.2];
+        let trace=engine.convolve(&reflectivity, &wavelet)?;
+        let recovered=engine.wiener_deconvolve(&trace, &wavelet, 1e-12)?;
+        assert_eq!(recovered.len(), trace.len());
+        for (a, b) in recovered.iter().zip(&reflectivity){
+            assert_abs_diff_eq!(a, b, epsilon=1e-8);
         }
 
+        //A band-limited wavelet loses its missing frequencies, but the result still models the trace
+        let ricker: Vec<f64>=(0..41).map(|i| {
+            let x=(PI*30.0*(i as f64-20.0)*0.002).powi(2);
+            (1.0-2.0*x)*(-x).exp()
+        }).collect();
+        let trace=engine.convolve(&reflectivity, &ricker)?;
+        let estimate=engine.wiener_deconvolve(&trace, &ricker, 1e-4)?;
+        let remodelled=engine.convolve(&estimate, &ricker)?;
+        let misfit=trace.iter().zip(&remodelled).map(|(a, b)| (a-b).powi(2)).sum::<f64>()/trace.iter().map(|a| a*a).sum::<f64>();
+        assert!(misfit<1e-3, "{}", misfit);
+        let peak=(0..60).max_by(|&a, &b| estimate[a].partial_cmp(&estimate[b]).unwrap()).unwrap();
+        assert_eq!(peak, 10);
+
+        //More prewhitening damps the estimate
+        let damped=engine.wiener_deconvolve(&trace, &ricker, 0.5)?;
+        assert!(damped[10]<estimate[10]);
+
+        assert!(engine.wiener_deconvolve(&trace, &[0.0; 5], 0.01).is_err());
+        assert!(engine.wiener_deconvolve(&trace, &ricker, -1.0).is_err());
+        assert!(engine.wiener_deconvolve(&[], &ricker, 0.01).is_err());
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,65 @@
+//! Non-fatal warnings collected during a run
+//!
+//! Conditions that do not stop a run but change what it produces (a wavelet
+//! cut off before it decays, a filter band moved below Nyquist, reflectors
+//! that fall outside the model) are recorded as warnings on the results
+//! rather than printed, so callers decide how to report them.
+
+use serde::Serialize;
+use std::fmt;
+
+///Edge amplitude, relative to the peak, above which a wavelet counts as clipped
+pub const CLIPPED_WAVELET_RATIO: f64=0.01;
+
+///A non-fatal condition met while producing results
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag="kind", rename_all="snake_case")]
+pub enum Warning{
+    ///The wavelet has not decayed at its ends; `edge_ratio` is the larger end amplitude over the peak
+    ClippedWavelet{ edge_ratio: f64 },
+    ///The requested filter corner was at or above Nyquist and was lowered to `used` Hz
+    FilterAboveNyquist{ requested: f64, used: f64, nyquist: f64 },
+    ///Layer positions at or beyond the model length were left out of the reflectivity
+    DroppedLayers{ count: usize, length: usize },
+}
+
+impl fmt::Display for Warning{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>)-> fmt::Result{
+        match self{
+            Warning::ClippedWavelet{ edge_ratio }=>
+                write!(f, "wavelet is clipped: end amplitude is {:.1}% of the peak", 100.0*edge_ratio),
+            Warning::FilterAboveNyquist{ requested, used, nyquist }=>
+                write!(f, "filter corner {} Hz is not below Nyquist ({} Hz); using {} Hz", requested, nyquist, used),
+            Warning::DroppedLayers{ count, length }=>
+                write!(f, "{} layer position(s) beyond the {}-sample model were dropped", count, length),
+        }
+    }
+}
+
+///Warning for a wavelet whose ends have not decayed, if any
+pub fn check_wavelet(samples: &[f64])-> Option<Warning>{
+    let peak=samples.iter().fold(0.0_f64, |m, x| m.max(x.abs()));
+    let (first, last)=(samples.first()?, samples.last()?);
+    let edge_ratio=first.abs().max(last.abs())/peak;
+    (peak>0.0 && edge_ratio>CLIPPED_WAVELET_RATIO).then_some(Warning::ClippedWavelet{ edge_ratio })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_wavelet_check_and_messages(){
+        let decayed=[0.0, 0.2, 1.0, 0.2, 0.0];
+        assert_eq!(check_wavelet(&decayed), None);
+        assert_eq!(check_wavelet(&[]), None);
+
+        let warning=check_wavelet(&[0.5, 1.0, 0.1]).unwrap();
+        assert_eq!(warning, Warning::ClippedWavelet{ edge_ratio: 0.5 });
+        assert_eq!(warning.to_string(), "wavelet is clipped: end amplitude is 50.0% of the peak");
+
+        let json=serde_json::to_value(Warning::DroppedLayers{ count: 2, length: 100 }).unwrap();
+        assert_eq!(json["kind"], "dropped_layers");
+        assert_eq!(json["count"], 2);
+    }
+}
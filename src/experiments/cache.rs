@@ -0,0 +1,184 @@
+//! Content-addressed on-disk cache of forward-modelled traces
+//!
+//! Each entry is stored under the SHA-256 of the inputs that produced it, so
+//! a sweep repeated in a later session finds the traces it already modelled
+//! and only computes the grid points that are new. Entries are zstd-compressed
+//! little-endian samples in `<dir>/<first two hex digits>/<hash>.trc`, written
+//! to a temporary file and renamed so an interrupted run never leaves a
+//! partial entry behind.
+
+use anyhow::{Result, Context};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const ENTRY_MAGIC: &[u8; 8]=b"SEISCCH1";
+///Temporary files written by this process; a counter rather than a random
+///suffix so caching never draws random numbers
+static TEMPORARY_COUNTER: AtomicU64=AtomicU64::new(0);
+
+///Hash of the inputs to a cached computation
+///
+/// Start from a tag naming the computation and feed every input that
+/// affects the result; two keys match only if all of them match.
+#[derive(Debug, Clone)]
+pub struct CacheKey{
+    hasher: Sha256,
+}
+
+impl CacheKey{
+    pub fn new(tag: &str)-> Self{
+        let mut key=Self{ hasher: Sha256::new() };
+        key.hasher.update(ENTRY_MAGIC);
+        key.with_str(tag)
+    }
+
+    pub fn with_str(mut self, value: &str)-> Self{
+        self.hasher.update((value.len() as u64).to_le_bytes());
+        self.hasher.update(value.as_bytes());
+        self
+    }
+
+    pub fn with_value(mut self, value: f64)-> Self{
+        self.hasher.update(value.to_bits().to_le_bytes());
+        self
+    }
+
+    pub fn with_values(mut self, values: &[f64])-> Self{
+        self.hasher.update((values.len() as u64).to_le_bytes());
+        values.iter().for_each(|v| self.hasher.update(v.to_bits().to_le_bytes()));
+        self
+    }
+
+    ///Lower-case hex digest
+    pub fn hex(&self)-> String{
+        self.hasher.clone().finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+///Directory of cached traces shared between runs and sessions
+#[derive(Debug, Clone)]
+pub struct TraceCache{
+    dir: PathBuf,
+}
+
+impl TraceCache{
+    ///Open (creating if needed) a cache rooted at `dir`
+    pub fn open(dir: &str)-> Result<Self>{
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {}", dir))?;
+        Ok(Self{ dir: PathBuf::from(dir) })
+    }
+
+    fn entry_path(&self, key: &CacheKey)-> PathBuf{
+        let hex=key.hex();
+        self.dir.join(&hex[..2]).join(format!("{}.trc", hex))
+    }
+
+    pub fn contains(&self, key: &CacheKey)-> bool{
+        self.entry_path(key).exists()
+    }
+
+    ///Stored trace for `key`; unreadable or corrupt entries count as missing
+    pub fn get(&self, key: &CacheKey)-> Option<Vec<f64>>{
+        let raw=std::fs::read(self.entry_path(key)).ok()?;
+        if raw.len()<16 || &raw[..8]!=ENTRY_MAGIC{
+            return None;
+        }
+        let num_samples=usize::try_from(u64::from_le_bytes(raw[8..16].try_into().ok()?)).ok()?;
+        let bytes=zstd::decode_all(&raw[16..]).ok()?;
+        if bytes.len()!=num_samples.checked_mul(8)?{
+            return None;
+        }
+        Some(bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+
+    ///Store `trace` under `key`, replacing any existing entry
+    pub fn put(&self, key: &CacheKey, trace: &[f64])-> Result<()>{
+        let path=self.entry_path(key);
+        let parent=path.parent().unwrap();
+        std::fs::create_dir_all(parent).with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+
+        let bytes: Vec<u8>=trace.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let mut raw=ENTRY_MAGIC.to_vec();
+        raw.extend_from_slice(&(trace.len() as u64).to_le_bytes());
+        raw.extend(zstd::encode_all(&bytes[..], 3)?);
+
+        //Unique temporary name so parallel writers of the same key do not collide
+        let temporary=path.with_extension(format!("tmp{}-{:x}", std::process::id(), TEMPORARY_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        std::fs::write(&temporary, raw).with_context(|| format!("Failed to create file: {}", temporary.display()))?;
+        std::fs::rename(&temporary, &path).with_context(|| format!("Failed to write cache entry: {}", path.display()))?;
+        Ok(())
+    }
+
+    ///Stored trace for `key`, or the result of `compute` after storing it
+    pub fn get_or_compute(&self, key: &CacheKey, compute: impl FnOnce()-> Result<Vec<f64>>)-> Result<Vec<f64>>{
+        if let Some(trace)=self.get(key){
+            return Ok(trace);
+        }
+        let trace=compute()?;
+        self.put(key, &trace)?;
+        Ok(trace)
+    }
+
+    ///Number of stored entries
+    pub fn len(&self)-> Result<usize>{
+        let mut count=0;
+        for shard in std::fs::read_dir(&self.dir)?{
+            let shard=shard?.path();
+            if shard.is_dir(){
+                count+=std::fs::read_dir(&shard)?.filter_map(|e| e.ok()).filter(|e| e.path().extension().is_some_and(|x| x=="trc")).count();
+            }
+        }
+        Ok(count)
+    }
+
+    ///Remove every entry
+    pub fn clear(&self)-> Result<()>{
+        for shard in std::fs::read_dir(&self.dir)?{
+            let shard=shard?.path();
+            if shard.is_dir(){
+                std::fs::remove_dir_all(&shard).with_context(|| format!("Failed to remove: {}", shard.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_reuse()-> Result<()>{
+        let dir=std::env::temp_dir().join(format!("seismic_trace_cache_test_{}", std::process::id()));
+        let _=std::fs::remove_dir_all(&dir);
+        let cache=TraceCache::open(dir.to_str().unwrap())?;
+
+        let key=CacheKey::new("synthetic").with_values(&[0.0, 0.1, -0.2]).with_value(30.0);
+        assert_ne!(key.hex(), CacheKey::new("synthetic").with_values(&[0.0, 0.1, -0.2]).with_value(31.0).hex());
+        assert_ne!(key.hex(), CacheKey::new("other").with_values(&[0.0, 0.1, -0.2]).with_value(30.0).hex());
+
+        let trace=vec![1.5, -2.25, f64::MIN_POSITIVE, 0.0];
+        assert_eq!(cache.get_or_compute(&key, || Ok(trace.clone()))?, trace);
+        //Second lookup, even from a fresh handle, must not recompute
+        let reopened=TraceCache::open(dir.to_str().unwrap())?;
+        assert_eq!(reopened.get_or_compute(&key, || panic!("recomputed a cached trace"))?, trace);
+        assert_eq!(reopened.len()?, 1);
+
+        //A corrupt entry is treated as missing and rewritten
+        std::fs::write(reopened.entry_path(&key), b"garbage")?;
+        assert!(reopened.get(&key).is_none());
+        assert_eq!(reopened.get_or_compute(&key, || Ok(vec![3.0]))?, vec![3.0]);
+
+        //So is a header claiming more samples than fit in memory
+        let mut raw=std::fs::read(reopened.entry_path(&key))?;
+        raw[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(reopened.entry_path(&key), raw)?;
+        assert!(reopened.get(&key).is_none());
+
+        reopened.clear()?;
+        assert!(!reopened.contains(&key));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
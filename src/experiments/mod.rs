@@ -0,0 +1,249 @@
+//! Two-parameter sensitivity sweeps
+//!
+//! A metric is evaluated on every point of a grid spanned by two parameters
+//! (for example wavelet frequency against noise level) and the result is
+//! kept as a table that can be written as CSV or rendered as a heatmap PNG.
+//! Grid points are evaluated in parallel, and forward models can be kept in
+//! a [`cache::TraceCache`] so a repeated sweep only models what is new.
+
+use anyhow::{Result, anyhow, Context};
+use rayon::prelude::*;
+use crate::inversion::sparse::{Dictionary, SparseInversion};
+use crate::io::image::{write_section_png, Colormap};
+use crate::noise::{Rng, SeededRng};
+use crate::operators::{ConvolutionOperator, LinearOperator};
+use crate::wavelets::RickerWavelet;
+use cache::{CacheKey, TraceCache};
+
+pub mod cache;
+
+///One swept parameter: a name and the values it takes
+#[derive(Debug, Clone)]
+pub struct ParameterAxis{
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+impl ParameterAxis{
+    pub fn new(name: &str, values: Vec<f64>)-> Self{
+        Self{ name: name.to_string(), values }
+    }
+
+    ///`count` evenly spaced values from `start` to `end` inclusive
+    pub fn linear(name: &str, start: f64, end: f64, count: usize)-> Self{
+        let step=if count>1 { (end-start)/(count-1) as f64 } else { 0.0 };
+        Self::new(name, (0..count).map(|i| start+i as f64*step).collect())
+    }
+}
+
+///Grid of two parameters to sweep
+#[derive(Debug, Clone)]
+pub struct Sweep2d{
+    pub x: ParameterAxis,
+    pub y: ParameterAxis,
+    ///Name of the metric, used as the CSV column header
+    pub metric: String,
+}
+
+///Metric values over a two-parameter grid
+#[derive(Debug, Clone)]
+pub struct SweepGrid{
+    pub x: ParameterAxis,
+    pub y: ParameterAxis,
+    pub metric: String,
+    ///Metric at `values[ix][iy]`
+    pub values: Vec<Vec<f64>>,
+}
+
+impl Sweep2d{
+    pub fn new(x: ParameterAxis, y: ParameterAxis, metric: &str)-> Self{
+        Self{ x, y, metric: metric.to_string() }
+    }
+
+    ///Evaluate `metric(x, y)` at every grid point
+    pub fn run<F>(&self, metric: F)-> Result<SweepGrid>
+    where F: Fn(f64, f64)-> Result<f64>+Sync
+    {
+        if self.x.values.is_empty() || self.y.values.is_empty(){
+            return Err(anyhow!("Both sweep axes need at least one value"));
+        }
+        let values=self.x.values.par_iter().map(|&x| {
+            self.y.values.iter().map(|&y| {
+                metric(x, y).with_context(|| format!("Metric failed at {}={}, {}={}", self.x.name, x, self.y.name, y))
+            }).collect::<Result<Vec<f64>>>()
+        }).collect::<Result<Vec<Vec<f64>>>>()?;
+        Ok(SweepGrid{ x: self.x.clone(), y: self.y.clone(), metric: self.metric.clone(), values })
+    }
+}
+
+impl SweepGrid{
+    ///Metric at grid point `(ix, iy)`
+    pub fn get(&self, ix: usize, iy: usize)-> f64{
+        self.values[ix][iy]
+    }
+
+    ///Grid point with the largest finite metric, as `(x, y, metric)`
+    pub fn max(&self)-> Option<(f64, f64, f64)>{
+        let mut best: Option<(f64, f64, f64)>=None;
+        for (ix, column) in self.values.iter().enumerate(){
+            for (iy, &value) in column.iter().enumerate(){
+                if value.is_finite() && best.is_none_or(|(_, _, b)| value>b){
+                    best=Some((self.x.values[ix], self.y.values[iy], value));
+                }
+            }
+        }
+        best
+    }
+
+    ///Write one row per grid point: x, y, metric
+    pub fn write_csv(&self, path: &str)-> Result<()>{
+        let mut writer=csv::Writer::from_path(path).with_context(|| format!("Failed to create file: {}", path))?;
+        writer.write_record([self.x.name.as_str(), self.y.name.as_str(), self.metric.as_str()])?;
+        for (ix, column) in self.values.iter().enumerate(){
+            for (iy, value) in column.iter().enumerate(){
+                writer.write_record(&[self.x.values[ix].to_string(), self.y.values[iy].to_string(), value.to_string()])?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    ///Render the grid as a grayscale heatmap, `cell_size` pixels per grid point
+    ///
+    /// `x` runs left to right and `y` bottom to top; black is the smallest
+    /// metric value and white the largest.
+    pub fn write_png(&self, path: &str, cell_size: usize)-> Result<()>{
+        let cell_size=cell_size.max(1);
+        let column=|ix: usize| -> Vec<f64> {
+            self.values[ix].iter().rev().flat_map(|&v| std::iter::repeat_n(v, cell_size)).collect()
+        };
+        let section: Vec<Vec<f64>>=(0..self.values.len()).flat_map(|ix| std::iter::repeat_n(column(ix), cell_size)).collect();
+        write_section_png(path, &section, Colormap::Grayscale, None)
+    }
+
+    pub fn print_summary(&self){
+        println!("{} over {} x {} ({} x {} points)", self.metric, self.x.name, self.y.name, self.x.values.len(), self.y.values.len());
+        if let Some((x, y, value))=self.max(){
+            println!("Maximum {:.4} at {}={}, {}={}", value, self.x.name, x, self.y.name, y);
+        }
+    }
+}
+
+///Ricker wavelet long enough to hold `frequency` without clipping, with an even length
+fn sweep_wavelet(frequency: f64, dt: f64)-> Result<RickerWavelet>{
+    if !(frequency>0.0 && dt>0.0){
+        return Err(anyhow!("Frequency and sample interval must be positive"));
+    }
+    let half=(1.5/(frequency*dt)).ceil() as usize;
+    RickerWavelet::new(frequency, dt, 2*half.max(2))
+}
+
+///Peak absolute amplitude of a thin bed: equal and opposite unit reflections `thickness` samples apart
+///
+/// Sweeping frequency against thickness gives the classic wedge tuning chart.
+pub fn tuning_amplitude(frequency: f64, thickness: f64, dt: f64)-> Result<f64>{
+    let wavelet=sweep_wavelet(frequency, dt)?;
+    let thickness=thickness.round().max(0.0) as usize;
+    let top=wavelet.samples.len();
+    let length=2*top+thickness;
+    let mut reflectivity=vec![0.0; length];
+    reflectivity[top]+=1.0;
+    reflectivity[top+thickness]-=1.0;
+    let trace=ConvolutionOperator::from_ricker(&wavelet, length)?.apply(&reflectivity);
+    Ok(trace.iter().fold(0.0, |m, x| m.max(x.abs())))
+}
+
+///Correlation between `reflectivity` and its sparse inversion from a noisy synthetic
+///
+/// The synthetic uses a Ricker wavelet of `frequency` Hz at interval `dt`
+/// plus white noise of RMS `noise_rms` drawn from a generator seeded with `seed`.
+/// With a cache the noise-free synthetic is looked up there before modelling.
+pub fn inversion_correlation(reflectivity: &[f64], dt: f64, inversion: &SparseInversion, frequency: f64, noise_rms: f64, seed: u64, cache: Option<&TraceCache>)-> Result<f64>{
+    let wavelet=sweep_wavelet(frequency, dt)?;
+    let clean=match cache{
+        Some(cache)=> cache.get_or_compute(&synthetic_key(reflectivity, &wavelet), || synthetic(reflectivity, &wavelet))?,
+        None=> synthetic(reflectivity, &wavelet)?,
+    };
+    let mut rng=SeededRng::new(seed);
+    let noisy: Vec<f64>=clean.iter().map(|c| c+noise_rms*rng.normal()).collect();
+    let estimate=inversion.invert(&noisy, &wavelet, &Dictionary::spikes())?;
+    Ok(correlation(reflectivity, &estimate.reflectivity))
+}
+
+///Noise-free synthetic, trimmed to the reflectivity length
+pub fn synthetic(reflectivity: &[f64], wavelet: &RickerWavelet)-> Result<Vec<f64>>{
+    Ok(ConvolutionOperator::from_ricker(wavelet, reflectivity.len())?.apply(reflectivity))
+}
+
+///Cache key for [`synthetic`]
+pub fn synthetic_key(reflectivity: &[f64], wavelet: &RickerWavelet)-> CacheKey{
+    CacheKey::new("synthetic").with_values(reflectivity).with_values(&wavelet.samples).with_value(wavelet.dt)
+}
+
+fn correlation(a: &[f64], b: &[f64])-> f64{
+    let dot=|x: &[f64], y: &[f64]| x.iter().zip(y).map(|(p, q)| p*q).sum::<f64>();
+    let norm=(dot(a, a)*dot(b, b)).sqrt();
+    if norm>0.0 { dot(a, b)/norm } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_tuning_chart()-> Result<()>{
+        let dt=0.001;
+        let sweep=Sweep2d::new(ParameterAxis::linear("frequency", 20.0, 40.0, 3), ParameterAxis::linear("thickness", 0.0, 40.0, 41), "tuning_amplitude");
+        let grid=sweep.run(|f, t| tuning_amplitude(f, t, dt))?;
+        assert_eq!(grid.values.len(), 3);
+        assert_eq!(grid.values[0].len(), 41);
+
+        //Zero thickness cancels; amplitude peaks near the tuning thickness, ~1/(2.6 f)
+        assert_abs_diff_eq!(grid.get(0, 0), 0.0, epsilon=1e-12);
+        for (ix, &f) in grid.x.values.iter().enumerate(){
+            let peak=(0..41).max_by(|&a, &b| grid.get(ix, a).partial_cmp(&grid.get(ix, b)).unwrap()).unwrap();
+            let tuning=1.0/(2.6*f*dt);
+            assert!((peak as f64-tuning).abs()<=3.0, "peak {} tuning {}", peak, tuning);
+            assert!(grid.get(ix, peak)>1.0);
+        }
+
+        let csv_path=std::env::temp_dir().join("sweep2d_test.csv");
+        let png_path=std::env::temp_dir().join("sweep2d_test.png");
+        grid.write_csv(csv_path.to_str().unwrap())?;
+        grid.write_png(png_path.to_str().unwrap(), 4)?;
+        let csv=std::fs::read_to_string(&csv_path)?;
+        assert!(csv.starts_with("frequency,thickness,tuning_amplitude"));
+        assert_eq!(csv.lines().count(), 1+3*41);
+        assert!(std::fs::metadata(&png_path)?.len()>0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_inversion_correlation_drops_with_noise()-> Result<()>{
+        let mut reflectivity=vec![0.0; 120];
+        reflectivity[30]=0.2;
+        reflectivity[55]= -0.15;
+        reflectivity[85]=0.1;
+        let inversion=SparseInversion{ max_iterations: 300, ..Default::default() };
+        let sweep=Sweep2d::new(ParameterAxis::new("frequency", vec![30.0]), ParameterAxis::new("noise", vec![0.0, 0.1]), "correlation");
+        let grid=sweep.run(|f, noise| inversion_correlation(&reflectivity, 0.002, &inversion, f, noise, 7, None))?;
+
+        assert!(grid.get(0, 0)>0.9);
+        assert!(grid.get(0, 1)<grid.get(0, 0));
+        assert_eq!(grid.max().unwrap().1, 0.0);
+
+        //Cached synthetics give the same metric, one entry per frequency
+        let dir=std::env::temp_dir().join("sweep2d_cache_test");
+        let _=std::fs::remove_dir_all(&dir);
+        let cache=TraceCache::open(dir.to_str().unwrap())?;
+        let cached=sweep.run(|f, noise| inversion_correlation(&reflectivity, 0.002, &inversion, f, noise, 7, Some(&cache)))?;
+        assert_eq!(cached.values, grid.values);
+        assert_eq!(cache.len()?, 1);
+        std::fs::remove_dir_all(&dir)?;
+
+        let failing=Sweep2d::new(ParameterAxis::new("frequency", vec![-1.0]), ParameterAxis::new("noise", vec![0.0]), "correlation");
+        assert!(failing.run(|f, t| tuning_amplitude(f, t, 0.002)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! End-to-end feasibility studies: is a property change detectable?
+//!
+//! A baseline elastic model and a monitor with a property change in one
+//! zone are forward modelled with the target wavelet, each gets independent
+//! noise, and both are inverted for impedance. Repeating this over noise
+//! realizations gives the distribution of the estimated change in the zone.
+//! The same is done for the baseline against itself, which shows how large
+//! a change noise alone produces; the change is called detectable when its
+//! estimate stands clear of that null distribution.
+
+use anyhow::{Result, anyhow, Context};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs::File;
+use std::io::BufWriter;
+use crate::inversion::sparse::{Dictionary, SparseInversion};
+use crate::inversion::uncertainty::reflectivity_to_impedance;
+use crate::models::elastic::ElasticModel;
+use crate::noise::{Rng, SeededRng};
+use crate::noise::empirical::EmpiricalNoise;
+use crate::noise::spectral::{shaped_noise, NoiseSpectrum};
+use crate::operators::{ConvolutionOperator, LinearOperator};
+use crate::utils::rms;
+use crate::wavelets::RickerWavelet;
+
+///Relative property change applied to samples `start..end` of the baseline
+#[derive(Debug, Clone, Serialize)]
+pub struct PropertyChange{
+    pub start: usize,
+    pub end: usize,
+    ///Fractional changes, e.g. -0.05 for a 5% drop
+    pub vp: f64,
+    pub vs: f64,
+    pub rho: f64,
+}
+
+impl PropertyChange{
+    ///Monitor model: the baseline with this change applied
+    pub fn apply(&self, baseline: &ElasticModel)-> Result<ElasticModel>{
+        if self.start>=self.end || self.end>baseline.len(){
+            return Err(anyhow!("Change zone {}..{} is empty or outside the {}-sample model", self.start, self.end, baseline.len()));
+        }
+        let scale=|values: &[f64], change: f64| -> Vec<f64> {
+            values.iter().enumerate().map(|(i, v)| if (self.start..self.end).contains(&i) { v*(1.0+change) } else { *v }).collect()
+        };
+        ElasticModel::new(scale(&baseline.vp, self.vp), scale(&baseline.vs, self.vs), scale(&baseline.rho, self.rho), baseline.dt)
+    }
+}
+
+///Noise added to every modelled trace
+#[derive(Debug, Clone)]
+pub enum NoiseSpec{
+    ///White Gaussian noise with this RMS
+    White{ rms: f64 },
+    ///Gaussian noise with RMS `rms` in a trapezoidal band `[f1, f2, f3, f4]` Hz
+    Band{ rms: f64, corners: [f64; 4] },
+    ///Noise estimated from field data
+    Empirical(EmpiricalNoise),
+}
+
+impl NoiseSpec{
+    fn generate(&self, rng: &mut dyn Rng, length: usize, dt: f64)-> Result<Vec<f64>>{
+        match self{
+            NoiseSpec::White{ rms }=> Ok((0..length).map(|_| rms*rng.normal()).collect()),
+            NoiseSpec::Band{ rms, corners }=> shaped_noise(rng, length, dt, &NoiseSpectrum::band(*corners)?, *rms),
+            NoiseSpec::Empirical(model)=> model.generate(rng, length, dt),
+        }
+    }
+}
+
+///Study settings
+#[derive(Debug, Clone)]
+pub struct FeasibilityStudy{
+    pub realizations: usize,
+    pub inversion: SparseInversion,
+    ///Separation from the noise-only spread, in standard deviations, needed to call the change detectable
+    pub threshold: f64,
+    ///Seed for the noise; realization `i` uses `seed+i`
+    pub seed: u64,
+}
+
+impl Default for FeasibilityStudy{
+    fn default()-> Self{
+        Self{
+            realizations: 50,
+            inversion: SparseInversion::default(),
+            threshold: 2.0,
+            seed: 0,
+        }
+    }
+}
+
+///Outcome of a feasibility study
+#[derive(Debug, Clone, Serialize)]
+pub struct FeasibilityReport{
+    pub change: PropertyChange,
+    pub realizations: usize,
+    ///True mean relative impedance change in the zone
+    pub true_change: f64,
+    ///Mean and spread of the estimated relative impedance change over realizations
+    pub estimated_change: f64,
+    pub estimated_std: f64,
+    ///Spread of the estimated change when nothing changed (noise only)
+    pub null_std: f64,
+    ///|estimated change| / null spread
+    pub z_score: f64,
+    ///Fraction of realizations whose estimate exceeds the threshold times the null spread
+    pub detection_rate: f64,
+    ///RMS of the noise-free 4D difference over the noise RMS, in dB
+    pub difference_snr_db: f64,
+    pub threshold: f64,
+    pub detectable: bool,
+}
+
+impl FeasibilityReport{
+    pub fn print_summary(&self){
+        println!("Feasibility study ({} realizations, zone {}..{})", self.realizations, self.change.start, self.change.end);
+        println!("Property change: vp {:+.1}%, vs {:+.1}%, rho {:+.1}%", 100.0*self.change.vp, 100.0*self.change.vs, 100.0*self.change.rho);
+        println!("True impedance change: {:+.2}%", 100.0*self.true_change);
+        println!("Estimated change: {:+.2}% ± {:.2}%", 100.0*self.estimated_change, 100.0*self.estimated_std);
+        println!("Noise-only spread: {:.2}%", 100.0*self.null_std);
+        println!("4D difference SNR: {:.1} dB", self.difference_snr_db);
+        println!("Z-score: {:.2} (threshold {:.1}), detection rate {:.0}%", self.z_score, self.threshold, 100.0*self.detection_rate);
+        println!("Verdict: {}", if self.detectable { "DETECTABLE" } else { "NOT DETECTABLE" });
+    }
+
+    ///Write the report as JSON
+    pub fn to_json(&self, path: &str)-> Result<()>{
+        let file=File::create(path).with_context(|| format!("Failed to create file: {}", path))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).with_context(|| format!("Failed to write JSON: {}", path))?;
+        Ok(())
+    }
+}
+
+impl FeasibilityStudy{
+    ///Model, add noise, invert and score the change over all realizations
+    pub fn run(&self, baseline: &ElasticModel, change: &PropertyChange, wavelet: &RickerWavelet, noise: &NoiseSpec)-> Result<FeasibilityReport>{
+        if self.realizations<2{
+            return Err(anyhow!("Need at least two realizations, got {}", self.realizations));
+        }
+        let monitor=change.apply(baseline)?;
+        let length=baseline.len();
+        let operator=ConvolutionOperator::from_ricker(wavelet, length)?;
+        let base_clean=operator.apply(&baseline.reflectivity().coefficients);
+        let monitor_clean=operator.apply(&monitor.reflectivity().coefficients);
+
+        let base_impedance=baseline.acoustic_impedance();
+        let initial=base_impedance[0];
+        let zone_change=|impedance: &[f64], reference: &[f64]| -> f64 {
+            (change.start..change.end).map(|i| impedance[i]/reference[i]-1.0).sum::<f64>()/(change.end-change.start) as f64
+        };
+        let true_change=zone_change(&monitor.acoustic_impedance(), &base_impedance);
+
+        let invert=|clean: &[f64], rng: &mut SeededRng| -> Result<Vec<f64>> {
+            let noisy: Vec<f64>=clean.iter().zip(noise.generate(rng, length, baseline.dt)?).map(|(c, n)| c+n).collect();
+            let estimate=self.inversion.invert(&noisy, wavelet, &Dictionary::spikes())?;
+            Ok(reflectivity_to_impedance(&estimate.reflectivity, initial))
+        };
+        let outcomes=(0..self.realizations).into_par_iter().map(|i| {
+            let mut rng=SeededRng::new(self.seed.wrapping_add(i as u64));
+            let base=invert(&base_clean, &mut rng)?;
+            let repeat=invert(&base_clean, &mut rng)?;
+            let monitor=invert(&monitor_clean, &mut rng)?;
+            Ok((zone_change(&monitor, &base), zone_change(&repeat, &base)))
+        }).collect::<Result<Vec<(f64, f64)>>>()?;
+
+        let (estimates, nulls): (Vec<f64>, Vec<f64>)=outcomes.into_iter().unzip();
+        let (estimated_change, estimated_std)=mean_std(&estimates);
+        let (_, null_std)=mean_std(&nulls);
+        let z_score=if null_std>0.0 { estimated_change.abs()/null_std } else { f64::INFINITY };
+        let detection_rate=estimates.iter().filter(|e| e.abs()>self.threshold*null_std).count() as f64/estimates.len() as f64;
+
+        let difference_rms=rms(&monitor_clean.iter().zip(&base_clean).map(|(m, b)| m-b).collect::<Vec<f64>>());
+        let noise_rms=rms(&noise.generate(&mut SeededRng::new(self.seed), length.max(1024), baseline.dt)?);
+        let difference_snr_db=20.0*(difference_rms/noise_rms.max(f64::MIN_POSITIVE)).max(f64::MIN_POSITIVE).log10();
+
+        Ok(FeasibilityReport{
+            change: change.clone(),
+            realizations: self.realizations,
+            true_change,
+            estimated_change,
+            estimated_std,
+            null_std,
+            z_score,
+            detection_rate,
+            difference_snr_db,
+            threshold: self.threshold,
+            detectable: z_score>=self.threshold,
+        })
+    }
+}
+
+fn mean_std(values: &[f64])-> (f64, f64){
+    let n=values.len() as f64;
+    let mean=values.iter().sum::<f64>()/n;
+    let variance=values.iter().map(|v| (v-mean).powi(2)).sum::<f64>()/(n-1.0).max(1.0);
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn layered_model()-> ElasticModel{
+        let vp: Vec<f64>=(0..160).map(|i| if i<60 { 2500.0 } else if i<90 { 2900.0 } else { 2700.0 }).collect();
+        let rho: Vec<f64>=(0..160).map(|i| if i<60 { 2200.0 } else if i<90 { 2300.0 } else { 2250.0 }).collect();
+        ElasticModel::new(vp.clone(), vp.iter().map(|v| v/1.8).collect(), rho, 0.002).unwrap()
+    }
+
+    fn quick_study()-> FeasibilityStudy{
+        FeasibilityStudy{
+            realizations: 8,
+            inversion: SparseInversion{ max_iterations: 300, ..Default::default() },
+            seed: 42,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_strong_change_is_detectable()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        let change=PropertyChange{ start: 60, end: 90, vp: -0.15, vs: 0.0, rho: -0.05 };
+
+        let report=quick_study().run(&layered_model(), &change, &wavelet, &NoiseSpec::White{ rms: 0.002 })?;
+        assert!(report.true_change< -0.15);
+        assert!(report.estimated_change<0.0);
+        assert!(report.detectable, "z-score {}", report.z_score);
+        assert!(report.detection_rate>0.9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_small_change_in_noise_is_not()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        let change=PropertyChange{ start: 60, end: 90, vp: -0.002, vs: 0.0, rho: 0.0 };
+
+        let noise=NoiseSpec::Band{ rms: 0.05, corners: [5.0, 10.0, 60.0, 80.0] };
+        let report=quick_study().run(&layered_model(), &change, &wavelet, &noise)?;
+        assert!(!report.detectable, "z-score {}", report.z_score);
+        assert!(report.difference_snr_db<0.0);
+
+        assert!(PropertyChange{ start: 90, end: 60, vp: 0.1, vs: 0.0, rho: 0.0 }.apply(&layered_model()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,540 @@
+//! Frequency filters for synthetic and observed traces
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+///How a filter treats phase
+///
+/// Phase handling matters whenever synthetics are compared with data: a
+/// minimum-phase filter shifts events later in time, a zero-phase filter
+/// leaves them in place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PhaseMode{
+    ///Causal single pass (recursive filters are minimum phase)
+    Minimum,
+    ///Forward-backward pass: squared amplitude response, no phase shift
+    Zero,
+    ///Symmetric FIR applied centred: the half-operator group delay of the
+    ///causal form is removed, so events stay in place
+    Linear,
+}
+
+///Frequency response of a filter, for plotting and passband checks
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterResponse{
+    ///Frequencies from 0 to Nyquist in Hz
+    pub freqs: Vec<f64>,
+    ///Linear amplitude gain at each frequency
+    pub amplitude: Vec<f64>,
+    ///Phase in radians (wrapped to -pi..pi) at each frequency
+    pub phase: Vec<f64>,
+}
+
+impl FilterResponse{
+    ///Amplitude in dB relative to unit gain
+    pub fn amplitude_db(&self)-> Vec<f64>{
+        self.amplitude.iter().map(|a| 20.0*a.max(1e-12).log10()).collect()
+    }
+
+    ///Gain at the frequency bin closest to `frequency`, zero for an empty response
+    pub fn gain_at(&self, frequency: f64)-> f64{
+        if self.amplitude.is_empty(){
+            return 0.0;
+        }
+        let df=self.freqs.get(1).copied().unwrap_or(1.0);
+        let index=((frequency/df).round() as usize).min(self.amplitude.len()-1);
+        self.amplitude[index]
+    }
+}
+
+///Common interface for trace filters
+pub trait Filter{
+    ///Filter a trace in place
+    fn apply(&self, trace: &mut [f64]);
+
+    ///Sample rate in Hz the filter was designed for
+    fn sample_rate(&self)-> f64;
+
+    ///Response to a unit spike placed at sample `length/2`
+    ///
+    /// Centring the spike leaves room for the acausal half of zero-phase
+    /// responses while still showing the full causal tail of minimum-phase ones.
+    fn impulse_response(&self, length: usize)-> Vec<f64>{
+        let mut spike=vec![0.0; length];
+        if length>0{
+            spike[length/2]=1.0;
+        }
+        self.apply(&mut spike);
+        spike
+    }
+
+    ///Amplitude and phase response evaluated from a `length`-sample impulse response
+    ///
+    /// The phase is referenced to the spike position, so zero-phase and
+    /// centred linear-phase filters report zero phase.
+    fn frequency_response(&self, length: usize)-> FilterResponse{
+        let impulse=self.impulse_response(length);
+        let n=impulse.len();
+        if n==0{
+            return FilterResponse{ freqs: vec![], amplitude: vec![], phase: vec![] };
+        }
+
+        let mut buffer: Vec<Complex<f64>>=impulse.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        FftPlanner::new().plan_fft_forward(n).process(&mut buffer);
+
+        let sample_rate=self.sample_rate();
+        let spike=(n/2) as f64;
+        let bins=n/2+1;
+
+        let freqs=(0..bins).map(|k| k as f64*sample_rate/n as f64).collect();
+        let amplitude=buffer[..bins].iter().map(|c| c.norm()).collect();
+        let phase=buffer[..bins].iter().enumerate().map(|(k, c)| {
+            //Remove the linear phase ramp of the spike delay
+            let shift=Complex::from_polar(1.0, 2.0*PI*k as f64*spike/n as f64);
+            (c*shift).arg()
+        }).collect();
+
+        FilterResponse{ freqs, amplitude, phase }
+    }
+}
+
+///Second-order IIR section in transposed direct form II
+#[derive(Debug, Clone, Copy)]
+struct Biquad{
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad{
+    ///Butterworth low-pass section (RBJ cookbook, Q=1/sqrt(2))
+    fn lowpass(cutoff: f64, sample_rate: f64)-> Self{
+        let (cos_w, alpha)=Self::prewarp(cutoff, sample_rate);
+        let a0=1.0+alpha;
+        Self{
+            b: [(1.0-cos_w)/2.0/a0, (1.0-cos_w)/a0, (1.0-cos_w)/2.0/a0],
+            a: [-2.0*cos_w/a0, (1.0-alpha)/a0],
+        }
+    }
+
+    ///Butterworth high-pass section (RBJ cookbook, Q=1/sqrt(2))
+    fn highpass(cutoff: f64, sample_rate: f64)-> Self{
+        let (cos_w, alpha)=Self::prewarp(cutoff, sample_rate);
+        let a0=1.0+alpha;
+        Self{
+            b: [(1.0+cos_w)/2.0/a0, -(1.0+cos_w)/a0, (1.0+cos_w)/2.0/a0],
+            a: [-2.0*cos_w/a0, (1.0-alpha)/a0],
+        }
+    }
+
+    ///Notch section removing a single frequency; `quality` sets the notch width
+    fn notch(frequency: f64, sample_rate: f64, quality: f64)-> Self{
+        let w0=2.0*PI*frequency/sample_rate;
+        let (cos_w, alpha)=(w0.cos(), w0.sin()/(2.0*quality));
+        let a0=1.0+alpha;
+        Self{
+            b: [1.0/a0, -2.0*cos_w/a0, 1.0/a0],
+            a: [-2.0*cos_w/a0, (1.0-alpha)/a0],
+        }
+    }
+
+    fn prewarp(cutoff: f64, sample_rate: f64)-> (f64, f64){
+        let w0=2.0*PI*cutoff/sample_rate;
+        (w0.cos(), w0.sin()/2.0_f64.sqrt())
+    }
+
+    fn process(&self, trace: &mut [f64]){
+        let (mut z1, mut z2)=(0.0, 0.0);
+        for sample in trace.iter_mut(){
+            let x=*sample;
+            let y=self.b[0]*x+z1;
+            z1=self.b[1]*x-self.a[0]*y+z2;
+            z2=self.b[2]*x-self.a[1]*y;
+            *sample=y;
+        }
+    }
+}
+
+///Butterworth-style bandpass filter with selectable phase behaviour
+#[derive(Debug, Clone)]
+pub struct BandpassFilter{
+    pub low_freq: f64,
+    pub high_freq: f64,
+    pub sample_rate: f64,
+    ///Number of cascaded second-order sections on each band edge
+    pub sections: usize,
+    pub phase_mode: PhaseMode,
+}
+
+impl BandpassFilter{
+    ///Create a bandpass filter, checking the band against Nyquist
+    pub fn new(low_freq: f64, high_freq: f64, sample_rate: f64, phase_mode: PhaseMode)-> Result<Self>{
+        let nyquist=sample_rate/2.0;
+        if low_freq<=0.0 || low_freq>=high_freq{
+            return Err(anyhow!("Invalid band {}-{} Hz: corners must be positive and increasing", low_freq, high_freq));
+        }
+        if high_freq>=nyquist{
+            return Err(anyhow!("High corner {} Hz must be below Nyquist ({} Hz)", high_freq, nyquist));
+        }
+
+        Ok(Self{
+            low_freq,
+            high_freq,
+            sample_rate,
+            sections: 2,
+            phase_mode,
+        })
+    }
+
+    ///Group delay in samples of the causal FIR (non-zero only for linear phase), which `apply` removes
+    pub fn group_delay_samples(&self)-> usize{
+        match self.phase_mode{
+            PhaseMode::Linear=> (self.fir_taps().len()-1)/2,
+            _=> 0,
+        }
+    }
+
+    fn apply_recursive(&self, trace: &mut [f64]){
+        let highpass=Biquad::highpass(self.low_freq, self.sample_rate);
+        let lowpass=Biquad::lowpass(self.high_freq, self.sample_rate);
+        for _ in 0..self.sections{
+            highpass.process(trace);
+            lowpass.process(trace);
+        }
+    }
+
+    ///Hamming-windowed sinc bandpass operator spanning three periods of the low corner
+    fn fir_taps(&self)-> Vec<f64>{
+        let half=((1.5*self.sample_rate/self.low_freq).ceil() as usize).max(1);
+        let length=2*half+1;
+        let (f1, f2)=(self.low_freq/self.sample_rate, self.high_freq/self.sample_rate);
+
+        (0..length).map(|i| {
+            let n=i as f64-half as f64;
+            let ideal=if n==0.0{
+                2.0*(f2-f1)
+            }else{
+                ((2.0*PI*f2*n).sin()-(2.0*PI*f1*n).sin())/(PI*n)
+            };
+            let window=0.54-0.46*(2.0*PI*i as f64/(length-1) as f64).cos();
+            ideal*window
+        }).collect()
+    }
+}
+
+impl Filter for BandpassFilter{
+    fn sample_rate(&self)-> f64{
+        self.sample_rate
+    }
+
+    fn apply(&self, trace: &mut [f64]){
+        match self.phase_mode{
+            PhaseMode::Minimum=> self.apply_recursive(trace),
+            PhaseMode::Zero=> {
+                self.apply_recursive(trace);
+                trace.reverse();
+                self.apply_recursive(trace);
+                trace.reverse();
+            }
+            PhaseMode::Linear=> {
+                //Output n uses input[n+half-k], the causal FIR advanced by its group delay
+                let taps=self.fir_taps();
+                let half=self.group_delay_samples();
+                let input=trace.to_vec();
+                for (n, sample) in trace.iter_mut().enumerate(){
+                    *sample=taps.iter().enumerate()
+                        .filter_map(|(k, &h)| (n+half).checked_sub(k).and_then(|j| input.get(j)).map(|x| h*x))
+                        .sum();
+                }
+            }
+        }
+    }
+}
+
+///Notch filter for monochromatic noise such as 50/60 Hz power-line hum
+#[derive(Debug, Clone)]
+pub struct NotchFilter{
+    ///Fundamental frequency to remove in Hz
+    pub frequency: f64,
+    pub sample_rate: f64,
+    ///Quality factor: centre frequency over -3 dB width (higher is narrower)
+    pub quality: f64,
+    ///Number of harmonics to notch, including the fundamental
+    pub harmonics: usize,
+    ///Minimum is a single causal pass; Zero and Linear use a forward-backward pass
+    pub phase_mode: PhaseMode,
+}
+
+impl NotchFilter{
+    ///Create a notch filter; harmonics at or above Nyquist are skipped when applied
+    pub fn new(frequency: f64, sample_rate: f64, quality: f64, harmonics: usize, phase_mode: PhaseMode)-> Result<Self>{
+        if frequency<=0.0 || frequency>=sample_rate/2.0{
+            return Err(anyhow!("Notch frequency {} Hz must lie between 0 and Nyquist ({} Hz)", frequency, sample_rate/2.0));
+        }
+        if quality<=0.0{
+            return Err(anyhow!("Notch quality factor must be positive, got {}", quality));
+        }
+
+        Ok(Self{
+            frequency,
+            sample_rate,
+            quality,
+            harmonics: harmonics.max(1),
+            phase_mode,
+        })
+    }
+
+    ///Power-line notch at 50 or 60 Hz with its first three harmonics, zero phase
+    pub fn powerline(frequency: f64, sample_rate: f64)-> Result<Self>{
+        Self::new(frequency, sample_rate, 30.0, 3, PhaseMode::Zero)
+    }
+
+    fn apply_recursive(&self, trace: &mut [f64]){
+        let nyquist=self.sample_rate/2.0;
+        for k in 1..=self.harmonics{
+            let frequency=self.frequency*k as f64;
+            if frequency>=nyquist{
+                break;
+            }
+            Biquad::notch(frequency, self.sample_rate, self.quality).process(trace);
+        }
+    }
+}
+
+impl Filter for NotchFilter{
+    fn sample_rate(&self)-> f64{
+        self.sample_rate
+    }
+
+    fn apply(&self, trace: &mut [f64]){
+        self.apply_recursive(trace);
+        if self.phase_mode!=PhaseMode::Minimum{
+            trace.reverse();
+            self.apply_recursive(trace);
+            trace.reverse();
+        }
+    }
+}
+
+///Least-squares estimate of a sinusoid present in a trace
+#[derive(Debug, Clone, Copy)]
+pub struct SinusoidEstimate{
+    pub frequency: f64,
+    pub amplitude: f64,
+    ///Phase in radians of `amplitude*cos(2*pi*f*t+phase)`
+    pub phase: f64,
+}
+
+impl SinusoidEstimate{
+    ///Sample the estimated sinusoid at index `i`
+    pub fn value_at(&self, i: usize, sample_rate: f64)-> f64{
+        self.amplitude*(2.0*PI*self.frequency*i as f64/sample_rate+self.phase).cos()
+    }
+}
+
+///Fit `a*cos(wt)+b*sin(wt)` at a known frequency by least squares
+pub fn estimate_sinusoid(trace: &[f64], frequency: f64, sample_rate: f64)-> SinusoidEstimate{
+    let w=2.0*PI*frequency/sample_rate;
+    let (mut cc, mut ss, mut cs, mut xc, mut xs)=(0.0, 0.0, 0.0, 0.0, 0.0);
+
+    for (i, &x) in trace.iter().enumerate(){
+        let (s, c)=(w*i as f64).sin_cos();
+        cc+=c*c;
+        ss+=s*s;
+        cs+=c*s;
+        xc+=x*c;
+        xs+=x*s;
+    }
+
+    //Solve the 2x2 normal equations; cos and sin are not orthogonal over partial periods
+    let det=cc*ss-cs*cs;
+    let (a, b)=if det.abs()>1e-12{
+        ((xc*ss-xs*cs)/det, (xs*cc-xc*cs)/det)
+    }else{
+        (0.0, 0.0)
+    };
+
+    SinusoidEstimate{
+        frequency,
+        amplitude: a.hypot(b),
+        phase: (-b).atan2(a),
+    }
+}
+
+///Estimate and subtract a sinusoid and its harmonics, returning the estimates
+///
+/// Unlike a notch this leaves signal energy at the noise frequency untouched
+/// apart from the fitted component, which matters for broadband reflections.
+pub fn subtract_sinusoids(trace: &mut [f64], frequency: f64, sample_rate: f64, harmonics: usize)-> Vec<SinusoidEstimate>{
+    let mut estimates=Vec::new();
+
+    for k in 1..=harmonics.max(1){
+        let harmonic=frequency*k as f64;
+        if harmonic>=sample_rate/2.0{
+            break;
+        }
+        let estimate=estimate_sinusoid(trace, harmonic, sample_rate);
+        for (i, sample) in trace.iter_mut().enumerate(){
+            *sample-=estimate.value_at(i, sample_rate);
+        }
+        estimates.push(estimate);
+    }
+
+    estimates
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn sine(frequency: f64, sample_rate: f64, length: usize)-> Vec<f64>{
+        (0..length).map(|i| (2.0*PI*frequency*i as f64/sample_rate).sin()).collect()
+    }
+
+    #[test]
+    fn test_invalid_band(){
+        assert!(BandpassFilter::new(40.0, 10.0, 1000.0, PhaseMode::Zero).is_err());
+        assert!(BandpassFilter::new(5.0, 600.0, 1000.0, PhaseMode::Zero).is_err());
+    }
+
+    #[test]
+    fn test_zero_phase_keeps_pulse_in_place()-> Result<()>{
+        let filter=BandpassFilter::new(5.0, 60.0, 1000.0, PhaseMode::Zero)?;
+
+        let mut trace=vec![0.0; 1001];
+        trace[500]=1.0;
+        filter.apply(&mut trace);
+
+        let peak=trace.iter().enumerate().max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap()).unwrap().0;
+        assert_eq!(peak, 500);
+        for i in 1..50{
+            assert_abs_diff_eq!(trace[500-i], trace[500+i], epsilon=1e-6);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_linear_phase_keeps_events_in_place()-> Result<()>{
+        //At 5 Hz and 1 kHz the operator is 601 taps, longer than the trace
+        let filter=BandpassFilter::new(5.0, 60.0, 1000.0, PhaseMode::Linear)?;
+        assert_eq!(filter.group_delay_samples(), 300);
+
+        let mut trace=vec![0.0; 400];
+        trace[150]=1.0;
+        filter.apply(&mut trace);
+
+        let peak=trace.iter().enumerate().max_by(|a, b| a.1.abs().total_cmp(&b.1.abs())).unwrap().0;
+        assert_eq!(peak, 150);
+        for i in 1..50{
+            assert_abs_diff_eq!(trace[150-i], trace[150+i], epsilon=1e-12);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimum_phase_is_causal()-> Result<()>{
+        let filter=BandpassFilter::new(5.0, 60.0, 1000.0, PhaseMode::Minimum)?;
+
+        let mut trace=vec![0.0; 300];
+        trace[100]=1.0;
+        filter.apply(&mut trace);
+
+        assert!(trace[..100].iter().all(|&x| x==0.0));
+        assert!(trace[100..].iter().any(|&x| x!=0.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_passband_and_stopband()-> Result<()>{
+        for mode in [PhaseMode::Minimum, PhaseMode::Zero, PhaseMode::Linear]{
+            let filter=BandpassFilter::new(10.0, 60.0, 1000.0, mode)?;
+
+            let mut pass=sine(30.0, 1000.0, 2000);
+            filter.apply(&mut pass);
+            let pass_peak=pass[800..1200].iter().fold(0.0f64, |a, &b| a.max(b.abs()));
+
+            let mut stop=sine(250.0, 1000.0, 2000);
+            filter.apply(&mut stop);
+            let stop_peak=stop[800..1200].iter().fold(0.0f64, |a, &b| a.max(b.abs()));
+
+            assert!(pass_peak>0.8, "{:?} passband peak {}", mode, pass_peak);
+            assert!(stop_peak<0.05, "{:?} stopband peak {}", mode, stop_peak);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_notch_removes_powerline()-> Result<()>{
+        let filter=NotchFilter::powerline(50.0, 1000.0)?;
+
+        let mut hum=sine(50.0, 1000.0, 4000);
+        filter.apply(&mut hum);
+        let hum_peak=hum[1500..2500].iter().fold(0.0f64, |a, &b| a.max(b.abs()));
+
+        let mut signal=sine(20.0, 1000.0, 4000);
+        filter.apply(&mut signal);
+        let signal_peak=signal[1500..2500].iter().fold(0.0f64, |a, &b| a.max(b.abs()));
+
+        assert!(hum_peak<0.01, "residual hum {}", hum_peak);
+        assert!(signal_peak>0.95, "signal peak {}", signal_peak);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sinusoid_subtraction()-> Result<()>{
+        let clean=sine(17.0, 1000.0, 1500);
+        let hum=crate::noise::powerline_noise(&mut crate::noise::SeededRng::new(3), 1500, 1000.0, 60.0, 0.5, 2);
+        let mut trace: Vec<f64>=clean.iter().zip(hum.iter()).map(|(a, b)| a+b).collect();
+
+        let estimates=subtract_sinusoids(&mut trace, 60.0, 1000.0, 2);
+
+        assert_eq!(estimates.len(), 2);
+        assert_abs_diff_eq!(estimates[0].amplitude, 0.5, epsilon=0.01);
+        for (cleaned, original) in trace.iter().zip(clean.iter()){
+            assert_abs_diff_eq!(cleaned, original, epsilon=0.02);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bandpass_frequency_response()-> Result<()>{
+        let filter=BandpassFilter::new(10.0, 60.0, 1000.0, PhaseMode::Zero)?;
+        let response=filter.frequency_response(2048);
+
+        assert_eq!(response.freqs.len(), 1025);
+        assert_abs_diff_eq!(response.freqs[1024], 500.0, epsilon=1e-9);
+        assert!(response.gain_at(30.0)>0.8);
+        assert!(response.gain_at(250.0)<1e-3);
+        assert_eq!(filter.frequency_response(0).gain_at(30.0), 0.0);
+
+        //Zero phase throughout the passband
+        let passband=response.freqs.iter().position(|&f| f>=30.0).unwrap();
+        assert_abs_diff_eq!(response.phase[passband], 0.0, epsilon=1e-3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_notch_frequency_response()-> Result<()>{
+        let filter=NotchFilter::new(50.0, 1000.0, 30.0, 1, PhaseMode::Minimum)?;
+        let response=filter.frequency_response(4000);
+
+        assert!(response.gain_at(50.0)<0.05);
+        assert!(response.gain_at(20.0)>0.95);
+        assert!(response.amplitude_db()[200] < -25.0);
+
+        let impulse=filter.impulse_response(101);
+        assert!(impulse[..50].iter().all(|&x| x==0.0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+//! In-memory cache of intermediate pipeline outputs
+//!
+//! The convolution only depends on the reflectivity and the wavelet, so when
+//! a sweep varies the noise seed, noise level or filter band the convolved
+//! trace is the same every run. Stage outputs are stored under a SHA-256
+//! digest of the inputs that determine them, together with the input
+//! lengths, and reused on the next run with the same key.
+
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+
+///Identity of a stage's inputs: their lengths and a SHA-256 digest of their samples
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StageKey{
+    lengths: Vec<usize>,
+    digest: [u8; 32],
+}
+
+///Key of everything the convolution stage depends on
+pub fn convolution_key(reflectivity: &[f64], wavelet: &[f64])-> StageKey{
+    let mut hasher=Sha256::new();
+    hasher.update(b"convolution");
+    for signal in [reflectivity, wavelet]{
+        hasher.update((signal.len() as u64).to_le_bytes());
+        signal.iter().for_each(|x| hasher.update(x.to_bits().to_le_bytes()));
+    }
+    StageKey{ lengths: vec![reflectivity.len(), wavelet.len()], digest: hasher.finalize().into() }
+}
+
+///Bounded store of stage outputs; the oldest entry is evicted when full
+#[derive(Debug, Clone)]
+pub struct StageCache{
+    capacity: usize,
+    entries: HashMap<StageKey, Vec<f64>>,
+    order: VecDeque<StageKey>,
+    hits: usize,
+    misses: usize,
+}
+
+impl StageCache{
+    ///Cache holding up to `capacity` stage outputs
+    pub fn new(capacity: usize)-> Self{
+        Self{
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    ///Stored output for `key`, computing and storing it with `compute` on a miss
+    pub fn get_or_insert_with<E>(&mut self, key: StageKey, compute: impl FnOnce()-> Result<Vec<f64>, E>)-> Result<Vec<f64>, E>{
+        if let Some(stored)=self.entries.get(&key){
+            self.hits+=1;
+            return Ok(stored.clone());
+        }
+        self.misses+=1;
+        let output=compute()?;
+        if self.entries.len()>=self.capacity{
+            if let Some(oldest)=self.order.pop_front(){
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key.clone(), output.clone());
+        self.order.push_back(key);
+        Ok(output)
+    }
+
+    ///Number of lookups answered from the cache
+    pub fn hits(&self)-> usize{
+        self.hits
+    }
+
+    ///Number of lookups that had to compute the output
+    pub fn misses(&self)-> usize{
+        self.misses
+    }
+
+    pub fn len(&self)-> usize{
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.entries.is_empty()
+    }
+
+    ///Drop every stored output and reset the counters
+    pub fn clear(&mut self){
+        self.entries.clear();
+        self.order.clear();
+        self.hits=0;
+        self.misses=0;
+    }
+}
+
+impl Default for StageCache{
+    fn default()-> Self{
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+///Stage outputs kept by `StageCache::default`
+const DEFAULT_CAPACITY: usize=64;
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_cache_hits_and_eviction(){
+        let mut cache=StageCache::new(2);
+        let a=convolution_key(&[1.0, 0.0], &[0.5]);
+        let b=convolution_key(&[1.0, 0.0], &[0.25]);
+        let c=convolution_key(&[0.0, 1.0], &[0.5]);
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+
+        //Same samples split differently between the inputs are different keys
+        assert_ne!(convolution_key(&[1.0, 0.5], &[]), convolution_key(&[1.0], &[0.5]));
+
+        let compute=|v: f64| move || Ok::<Vec<f64>, ()>(vec![v]);
+        assert_eq!(cache.get_or_insert_with(a.clone(), compute(1.0)), Ok(vec![1.0]));
+        assert_eq!(cache.get_or_insert_with(convolution_key(&[1.0, 0.0], &[0.5]), compute(9.0)), Ok(vec![1.0]));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        cache.get_or_insert_with(b, compute(2.0)).unwrap();
+        cache.get_or_insert_with(c, compute(3.0)).unwrap();
+        assert_eq!(cache.len(), 2);
+        //`a` was the oldest and has been evicted
+        assert_eq!(cache.get_or_insert_with(a, compute(4.0)), Ok(vec![4.0]));
+        assert!(cache.get_or_insert_with(convolution_key(&[], &[]), || Err(())).is_err());
+    }
+}
@@ -1,7 +1,24 @@
-use anyhow::Result;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::{SystemTime, UNIX_EPOCH};
 use crate::convolution::ConvolutionEngine;
+use crate::diagnostics::{check_wavelet, Warning};
+use crate::filters::{BandpassFilter, Filter, PhaseMode};
+use crate::gather::{Gather, Trace};
+use crate::processing::{ProcessingChain, ProcessingStage};
+use crate::io::background::BackgroundWriter;
+use crate::io::naming::{NameFields, OutputNaming};
 use crate::models::ReflectivityModel;
+use crate::noise::{Rng, SeededRng};
+use crate::noise::empirical::EmpiricalNoise;
+use crate::utils::rms;
 use crate::wavelets::RickerWavelet;
+use cache::StageCache;
+
+pub mod cache;
+pub mod time_lapse;
 
 ///Seismic forward modelling pipeline
 ///
@@ -13,11 +30,22 @@ pub struct SeismicPipeline{
     /// FFT-based convolution engine
     convolution_engine: ConvolutionEngine,
     /// Pipeline configuration
-    config: PipelinConfig,
+    config: PipelineConfig,
+    /// Extra processing stages run after filtering
+    stages: ProcessingChain,
+    /// Random numbers for the noise stage, seeded with zero unless replaced by `set_rng`
+    rng: Box<dyn Rng>,
+    /// Field-derived noise used instead of uniform noise when set
+    noise_model: Option<EmpiricalNoise>,
+    /// Convolution outputs reused across runs when enabled
+    cache: Option<StageCache>,
+    /// Warnings raised during the current run
+    warnings: Vec<Warning>,
 }
 
 /// Configuration parameters for the seismic pipeline
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PipelineConfig{
     ///Add random noise to the synthetic data
     pub add_noise: bool,
@@ -26,6 +54,8 @@ pub struct PipelineConfig{
     pub low_freq: f64,
     pub high_freq: f64,
     pub sample_rate: f64,
+    ///Phase behaviour of the bandpass filter
+    pub phase_mode: PhaseMode,
 }
 
 impl Default for PipelineConfig{
@@ -35,14 +65,15 @@ impl Default for PipelineConfig{
             noise_level: 0.01,
             apply_filter: false,
             low_freq: 5.0,
-            high_freq: 100.0
+            high_freq: 100.0,
             sample_rate: 1000.0,
+            phase_mode: PhaseMode::Zero,
         }
     }
 }
 
 ///Resuts from forward modelling
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ForwardModellingResults{
     ///Synthetic seismogram
     pub synthetic_trace: Vec<f64>,
@@ -54,16 +85,62 @@ pub struct ForwardModellingResults{
     pub time: Vec<f64>,
     /// Processing statistics
     pub stats: ProcessingStats,
+    ///Where and how these results were produced
+    pub provenance: Provenance,
+    ///Non-fatal conditions met during the run
+    pub warnings: Vec<Warning>,
 }
 
 ///Statistics from the forward modelling process
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ProcessingStats{
     pub reflectivity_sparsity: f64,
     pub wavelet_dominant_freq: f64,
     pub output_snr: f64,
     pub processing_time_ms: f64,
-    pub onvolution_length: usize,
+    pub convolution_length: usize,
+}
+
+///Provenance recorded alongside results so exported files are self-describing
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance{
+    ///Name of the generating tool
+    pub tool: String,
+    ///Crate version that produced the results
+    pub version: String,
+    ///Creation time in seconds since the Unix epoch
+    pub created_unix: u64,
+    ///Pipeline configuration used for the run
+    pub config: PipelineConfig,
+}
+
+impl Provenance{
+    ///Capture provenance for a run with the given configuration
+    pub fn capture(config: &PipelineConfig)-> Self{
+        let created_unix=SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+        Self{
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created_unix,
+            config: config.clone(),
+        }
+    }
+}
+
+impl ForwardModellingResults{
+    ///Export traces, time axis, statistics and provenance as a single JSON document
+    ///
+    /// Dashboards and notebooks can load this one file instead of the separate
+    /// trace, reflectivity and wavelet CSVs.
+    pub fn to_json(&self, path: &str)-> Result<()>{
+        let file=File::create(path).with_context(|| format!("Failed to create file: {}", path))?;
+        let writer=BufWriter::new(file);
+
+        serde_json::to_writer_pretty(writer, self).with_context(|| format!("Failed to write JSON: {}", path))?;
+
+        Ok(())
+    }
 }
 
 impl SeismicPipeline{
@@ -72,6 +149,11 @@ impl SeismicPipeline{
         Self{
             convolution_engine: ConvolutionEngine::new(),
             config: PipelineConfig::default(),
+            stages: ProcessingChain::new(),
+            rng: Box::new(SeededRng::new(0)),
+            noise_model: None,
+            cache: None,
+            warnings: Vec::new(),
         }
     }
 
@@ -79,7 +161,12 @@ impl SeismicPipeline{
     pub fn with_config(config: PipelineConfig)-> Self{
         Self{
             convolution_engine: ConvolutionEngine::new(),
-            config.
+            config,
+            stages: ProcessingChain::new(),
+            rng: Box::new(SeededRng::new(0)),
+            noise_model: None,
+            cache: None,
+            warnings: Vec::new(),
         }
     }
 
@@ -90,26 +177,34 @@ impl SeismicPipeline{
         wavelet: &RickerWavelet,
     )-> Result<ForwardModellingResults>{
         let start_time=std::time::Instant::now();
+        self.warnings.clear();
+        self.check_inputs(reflectivity_model, wavelet);
 
-        //Step 1: Convolve reflectivity with wavelet
-        let mut synthetic_trace=self.convolution_engine.convolve(
-            &refectivity_model.coefficients,
-            &wavelet.samples,
-        )?;
+        //Step 1: Convolve reflectivity with wavelet, reusing a cached result for identical inputs
+        let mut synthetic_trace=self.convolve_model(&reflectivity_model.coefficients, &wavelet.samples)?;
 
         //Step 2: Add noise if requested
         if self.config.add_noise{
-            self.add_noise_to_trace(&mut synthetic_trace);
+            self.add_noise_to_trace(&mut synthetic_trace)?;
         }
 
         //Step 3: Apply filtering if requested
-        is self.config.apply_filter{
+        if self.config.apply_filter{
             self.apply_bandpass_filter(&mut synthetic_trace)?;
         }
 
         //Step 4: generate time vector
         let dt=1.0/self.config.sample_rate;
-        let time: Vec<f64> =(0..synthetic_trace.len()).map(|i| i as f64 *dt).collect();
+        //Full convolution starts at the model start plus the wavelet's (negative) lead-in
+        let t0=reflectivity_model.t0+wavelet.time.first().copied().unwrap_or(0.0);
+
+        //Run any registered processing stages
+        if !self.stages.is_empty(){
+            let mut gather=Gather::new(vec![Trace::new(synthetic_trace, dt).with_t0(t0)])?;
+            self.stages.run(&mut gather)?;
+            synthetic_trace=gather.traces.remove(0).samples;
+        }
+        let time: Vec<f64> =(0..synthetic_trace.len()).map(|i| t0+i as f64 *dt).collect();
 
         //Step 5: Calculate statistics
         let processing_time=start_time.elapsed();
@@ -117,7 +212,7 @@ impl SeismicPipeline{
 
         let signal_power: f64=synthetic_trace.iter().map(|x| x*x).sum();
         let noise_power=if self.config.add_noise{
-            let noise_var=(self.config.noise_level*self.estimate_signal_len(&synthetic_trace)).powi(2);
+            let noise_var=(self.config.noise_level*self.estimate_signal_level(&synthetic_trace)).powi(2);
             noise_var*synthetic_trace.len() as f64
         }else{
             1e-12 //Very small value for numerical stability
@@ -138,6 +233,8 @@ impl SeismicPipeline{
             wavelet: wavelet.samples.clone(),
             time,
             stats,
+            provenance: Provenance::capture(&self.config),
+            warnings: std::mem::take(&mut self.warnings),
         })
     }
 
@@ -166,54 +263,145 @@ impl SeismicPipeline{
         Ok(results)
     }
 
+    /// Generate noise realizations and stream each synthetic trace to CSV
+    ///
+    /// Traces are handed to a background writer thread so the next realization
+    /// is computed while the previous one is written. Returns the statistics of
+    /// every realization.
+    pub fn run_monte_carlo_to_csv(
+        &mut self,
+        reflectivity_model: &ReflectivityModel,
+        wavelet: &RickerWavelet,
+        num_realizations: usize,
+        output_dir: &str,
+    )-> Result<Vec<ProcessingStats>> {
+        let naming=OutputNaming::new(output_dir, "realization_{real:05}.csv")?;
+        self.run_monte_carlo_named(reflectivity_model, wavelet, num_realizations, &naming)
+    }
+
+    ///As `run_monte_carlo_to_csv`, naming each realization's file from a template
+    pub fn run_monte_carlo_named(
+        &mut self,
+        reflectivity_model: &ReflectivityModel,
+        wavelet: &RickerWavelet,
+        num_realizations: usize,
+        naming: &OutputNaming,
+    )-> Result<Vec<ProcessingStats>> {
+        let mut session=naming.start()?;
+        let mut writer=BackgroundWriter::new(WRITER_QUEUE_CAPACITY);
+        let mut stats=Vec::with_capacity(num_realizations);
+        let original_noise_setting=self.config.add_noise;
+        self.config.add_noise=true;
+
+        let outcome=(0..num_realizations).try_for_each(|i| {
+            let result=self.run_forward_modelling(reflectivity_model, wavelet)?;
+            let path=session.path(&NameFields{ model: 0, freq: wavelet.frequency, real: i })?;
+            writer.submit(path, result.synthetic_trace)?;
+            stats.push(result.stats);
+            Ok::<(), anyhow::Error>(())
+        });
+
+        self.config.add_noise=original_noise_setting;
+        outcome?;
+        writer.finish()?;
+
+        Ok(stats)
+    }
+
+    ///Convolution stage, answered from the cache when one is enabled
+    fn convolve_model(&mut self, reflectivity: &[f64], wavelet: &[f64])-> Result<Vec<f64>>{
+        match &mut self.cache{
+            Some(cache)=> {
+                let engine=&mut self.convolution_engine;
+                cache.get_or_insert_with(cache::convolution_key(reflectivity, wavelet), || engine.convolve(reflectivity, wavelet))
+            }
+            None=> self.convolution_engine.convolve(reflectivity, wavelet),
+        }
+    }
+
     /// Add random noiseto the synthetic trace
-    fn add_noise_to_trace(&self, trace: &mut [f64]){
+    ///
+    /// With a noise model set, its field-derived noise is added at its own
+    /// absolute level and `noise_level` is not used.
+    fn add_noise_to_trace(&mut self, trace: &mut [f64])-> Result<()>{
+        if let Some(model)=&self.noise_model{
+            let noise=model.generate(self.rng.as_mut(), trace.len(), 1.0/self.config.sample_rate)?;
+            trace.iter_mut().zip(noise).for_each(|(sample, n)| *sample+=n);
+            return Ok(());
+        }
+
         let signal_level=self.estimate_signal_level(trace);
         let noise_amplitude=self.config.noise_level* signal_level;
 
         for sample in trace.iter_mut(){
-            let noise=noise_amplitude*(2.0*fastrand::f64()-1.0);
+            let noise=noise_amplitude*(2.0*self.rng.uniform()-1.0);
             *sample+=noise;
         }
+        Ok(())
     }
 
     /// Estimate the signal level for noise scaling
     fn estimate_signal_level(&self, trace: &[f64])-> f64{
         // Use RMS as signal level estimate
-        let rms: f64=trace.iter().map(|x| x*x).sum::<f64>()/ trace.len() as f64;
-        rms.sqrt()
+        rms(trace)
     }
 
-    ///Apply simple bandpass filter (placeholder)
-    fn apply_bandpass_filter(&self, trace: &mut [f64])-> Result<()> {
+    ///Record warnings about the model and wavelet before modelling
+    fn check_inputs(&mut self, reflectivity_model: &ReflectivityModel, wavelet: &RickerWavelet){
+        let dropped=reflectivity_model.dropped_layers();
+        if dropped>0{
+            self.warnings.push(Warning::DroppedLayers{ count: dropped, length: reflectivity_model.length });
+        }
+        self.warnings.extend(check_wavelet(&wavelet.samples));
+    }
 
-        println!("Applying bandpass filter: {:.1}-{:.1} Hz",
-    self.config.low_freq, self.sonfig.high_freq);
+    ///Apply the configured bandpass filter
+    ///
+    /// A high corner at or above Nyquist is lowered to 90% of Nyquist with a warning.
+    fn apply_bandpass_filter(&mut self, trace: &mut [f64])-> Result<()> {
+        let nyquist=self.config.sample_rate/2.0;
+        let mut high_freq=self.config.high_freq;
+        if high_freq>=nyquist{
+            high_freq=0.9*nyquist;
+            self.warnings.push(Warning::FilterAboveNyquist{ requested: self.config.high_freq, used: high_freq, nyquist });
+        }
+        println!("Applying bandpass filter: {:.1}-{:.1} Hz ({:?} phase)",
+            self.config.low_freq, high_freq, self.config.phase_mode);
+
+        let filter=BandpassFilter::new(
+            self.config.low_freq,
+            high_freq,
+            self.config.sample_rate,
+            self.config.phase_mode,
+        )?;
+        filter.apply(trace);
 
-    //Simple moving average as a low-pass filter appr
-    let window_size=(self.config.sample_rate/ (2.0 *self.config.high_freq)) as usize;
-    if window_size > 1 && window_size<trace.len()/4{
-        self.apply_moving_average(trace, window_size);
+        Ok(())
     }
 
-    Ok(())
+    ///Register a processing stage to run after filtering
+    pub fn add_stage(&mut self, stage: Box<dyn ProcessingStage>){
+        self.stages.add(stage);
     }
 
-    /// Apply moving average fiilter
-    fn apply_moving_average(&self, trace: &mut [f64], window_size: usize){
-        let mut filtered=vec![0.0; trace.len()];
-        let half_window=window_size/2;
+    ///Draw noise from `rng` instead of the default stream seeded with zero
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>){
+        self.rng=rng;
+    }
 
-        for i in 0..trace.len(){
-            let start=i.saturating_sub(half_window);
-            let end=(i+ half_window+1).min(trace.len());
-            let window_len=end-start;
+    ///Use noise estimated from field data in the noise stage
+    pub fn set_noise_model(&mut self, model: EmpiricalNoise){
+        self.noise_model=Some(model);
+    }
 
-            let sum: f64=trace[start..end].iter().sum();
-            filtered[i]=sum/window_len as f64;
-        }
+    ///Keep up to `capacity` convolution outputs so runs that only change noise or filtering skip the convolution
+    pub fn enable_cache(&mut self, capacity: usize){
+        self.cache=Some(StageCache::new(capacity));
+    }
 
-        trace.copy_from_slice(&filtered);
+    ///Stage cache, if enabled
+    pub fn cache(&self)-> Option<&StageCache>{
+        self.cache.as_ref()
     }
 
     ///Update pipeline configuration
@@ -227,6 +415,9 @@ impl SeismicPipeline{
     }
 }
 
+/// Maximum number of traces waiting for the background writer
+const WRITER_QUEUE_CAPACITY: usize=16;
+
 impl Default for SeismicPipeline{
     fn default()-> Self{
         Self::new()
@@ -239,7 +430,7 @@ pub struct BatchProcessor{
 }
 
 impl BatchProcessor{
-    pub fn new(config; PipelineConfig)-> Self{
+    pub fn new(config: PipelineConfig)-> Self{
         Self{
             pipeline: SeismicPipeline::with_config(config),
         }
@@ -250,7 +441,7 @@ impl BatchProcessor{
         &mut self,
         models: &[ReflectivityModel],
         wavelet: &RickerWavelet,
-    )-> result<Vec<ForwardModellingResults>> {
+    )-> Result<Vec<ForwardModellingResults>> {
         let mut results=Vec::with_capacity(models.len());
 
         for (i, model) in models.iter().enumerate(){
@@ -262,6 +453,58 @@ impl BatchProcessor{
         Ok(results)
     }
 
+    ///Process multiple models and stream each synthetic trace to CSV in the background
+    pub fn process_models_to_csv(
+        &mut self,
+        models: &[ReflectivityModel],
+        wavelet: &RickerWavelet,
+        output_dir: &str,
+    )-> Result<Vec<ProcessingStats>> {
+        self.process_models_named(models, wavelet, &OutputNaming::new(output_dir, "model_{model:05}.csv")?)
+    }
+
+    ///As `process_models_to_csv`, naming each model's file from a template
+    pub fn process_models_named(
+        &mut self,
+        models: &[ReflectivityModel],
+        wavelet: &RickerWavelet,
+        naming: &OutputNaming,
+    )-> Result<Vec<ProcessingStats>> {
+        let mut session=naming.start()?;
+        let mut writer=BackgroundWriter::new(WRITER_QUEUE_CAPACITY);
+        let mut stats=Vec::with_capacity(models.len());
+
+        for (i, model) in models.iter().enumerate(){
+            let result=self.pipeline.run_forward_modelling(model, wavelet)?;
+            writer.submit(session.path(&NameFields{ model: i, freq: wavelet.frequency, real: 0 })?, result.synthetic_trace)?;
+            stats.push(result.stats);
+        }
+
+        writer.finish()?;
+        Ok(stats)
+    }
+
+    ///Model one reflectivity model with every wavelet and write each trace under the template
+    pub fn process_wavelets_named(
+        &mut self,
+        model: &ReflectivityModel,
+        wavelets: &[RickerWavelet],
+        naming: &OutputNaming,
+    )-> Result<Vec<ProcessingStats>> {
+        let mut session=naming.start()?;
+        let mut writer=BackgroundWriter::new(WRITER_QUEUE_CAPACITY);
+        let mut stats=Vec::with_capacity(wavelets.len());
+
+        for wavelet in wavelets{
+            let result=self.pipeline.run_forward_modelling(model, wavelet)?;
+            writer.submit(session.path(&NameFields{ model: 0, freq: wavelet.frequency, real: 0 })?, result.synthetic_trace)?;
+            stats.push(result.stats);
+        }
+
+        writer.finish()?;
+        Ok(stats)
+    }
+
     /// Process one model with multiple wavelets
     pub fn process_wavelets(
         &mut self,
@@ -288,7 +531,7 @@ mod tests{
     fn test_basic_forward_modelling()-> Result<()> {
         let mut pipeline=SeismicPipeline::new();
 
-        let model=ReflectiveModel::new(100, vec![25, 50, 75], vec![0.1, -0.05, 0.15]);
+        let model=ReflectivityModel::new(100, vec![25, 50, 75], vec![0.1, -0.05, 0.15]);
         let wavelet=RickerWavelet::new(30.0, 0.001, 50)?;
 
         let results=pipeline.run_forward_modelling(&model, &wavelet)?;
@@ -300,6 +543,20 @@ mod tests{
 
         Ok(())
     }
+
+    #[test]
+    fn test_start_time_propagates()-> Result<()>{
+        let mut pipeline=SeismicPipeline::new();
+        let model=ReflectivityModel::new(100, vec![25], vec![0.1]).with_t0(0.5);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 50)?;
+
+        let results=pipeline.run_forward_modelling(&model, &wavelet)?;
+        //The reflector at sample 25 peaks at 0.5+25 ms on the output time axis
+        let peak=results.synthetic_trace.iter().enumerate().max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap()).map(|(i, _)| i).unwrap();
+        assert!((results.time[0]-0.475).abs()<1e-9);
+        assert!((results.time[peak]-0.525).abs()<1e-9);
+        Ok(())
+    }
     #[test]
     fn test_pipeline_with_noise()-> Result<()>{
         let config=PipelineConfig{
@@ -320,11 +577,50 @@ mod tests{
         Ok(())
     }
 
+    #[test]
+    fn test_injected_rng_repeats_noise()-> Result<()>{
+        let config=PipelineConfig{ add_noise: true, ..Default::default() };
+        let model=ReflectivityModel::new(50, vec![10, 30], vec![0.2, -0.1]);
+        let wavelet=RickerWavelet::new(25.0, 0.001, 40)?;
+
+        let mut runs=Vec::new();
+        for _ in 0..2{
+            let mut pipeline=SeismicPipeline::with_config(config.clone());
+            pipeline.set_rng(Box::new(SeededRng::new(17)));
+            runs.push(pipeline.run_forward_modelling(&model, &wavelet)?.synthetic_trace);
+        }
+        assert_eq!(runs[0], runs[1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empirical_noise_stage()-> Result<()>{
+        use crate::noise::spectral::{shaped_noise, NoiseSpectrum};
+
+        let field=shaped_noise(&mut SeededRng::new(5), 4000, 0.001, &NoiseSpectrum::band([5.0, 10.0, 30.0, 40.0])?, 0.02)?;
+        let model=ReflectivityModel::new(200, vec![50, 120], vec![0.2, -0.1]);
+        let wavelet=RickerWavelet::new(25.0, 0.001, 40)?;
+
+        let mut clean=SeismicPipeline::new();
+        let reference=clean.run_forward_modelling(&model, &wavelet)?.synthetic_trace;
+
+        let mut pipeline=SeismicPipeline::with_config(PipelineConfig{ add_noise: true, ..Default::default() });
+        pipeline.set_noise_model(EmpiricalNoise::estimate(&field, 0.001, 256)?);
+        let noisy=pipeline.run_forward_modelling(&model, &wavelet)?.synthetic_trace;
+
+        let residual: Vec<f64>=noisy.iter().zip(&reference).map(|(a, b)| a-b).collect();
+        let rms=(residual.iter().map(|x| x*x).sum::<f64>()/residual.len() as f64).sqrt();
+        assert!((rms-0.02).abs()<1e-9);
+
+        Ok(())
+    }
+
     #[test]
     fn test_monte_carlo()-> Result<()>{
         let mut pipeline=SeismicPipeline::new();
 
-        let model=ReflectivityModel::new(30, ve![10, 20], vec![0.15, -0.1]);
+        let model=ReflectivityModel::new(30, vec![10, 20], vec![0.15, -0.1]);
         let wavelet=RickerWavelet::new(40.0, 0.001, 30)?;
 
         let results=pipeline.run_monte_carlo(&model, &wavelet, 3)?;
@@ -333,10 +629,162 @@ mod tests{
 
         //All realizations shoud have the same dimensions
         for result in &results{
-            assert_eq!(result.synthetic_trace.len(), 58);
+            assert_eq!(result.synthetic_trace.len(), 59);
             assert_eq!(result.reflectivity.len(), 30);
         }
 
         Ok(())
     }
+
+    #[test]
+    fn test_results_to_json()-> Result<()>{
+        let mut pipeline=SeismicPipeline::new();
+
+        let model=ReflectivityModel::new(40, vec![10, 25], vec![0.1, -0.1]);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 21)?;
+
+        let results=pipeline.run_forward_modelling(&model, &wavelet)?;
+
+        let path=std::env::temp_dir().join("seismic_results_test.json");
+        let path=path.to_str().unwrap();
+        results.to_json(path)?;
+
+        let document: serde_json::Value=serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(document["synthetic_trace"].as_array().unwrap().len(), 60);
+        assert_eq!(document["time"].as_array().unwrap().len(), 60);
+        assert!(document["stats"]["output_snr"].is_number());
+        assert_eq!(document["provenance"]["version"], env!("CARGO_PKG_VERSION"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_monte_carlo_to_csv()-> Result<()>{
+        let mut pipeline=SeismicPipeline::new();
+
+        let model=ReflectivityModel::new(30, vec![10, 20], vec![0.15, -0.1]);
+        let wavelet=RickerWavelet::new(40.0, 0.001, 30)?;
+
+        let dir=std::env::temp_dir().join("seismic_monte_carlo_csv_test");
+        let stats=pipeline.run_monte_carlo_to_csv(&model, &wavelet, 4, dir.to_str().unwrap())?;
+
+        assert_eq!(stats.len(), 4);
+        assert!(dir.join("realization_00003.csv").exists());
+        assert!(!pipeline.config().add_noise);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_exports()-> Result<()>{
+        let dir=std::env::temp_dir().join("seismic_named_export_test");
+        let dir_str=dir.to_str().unwrap();
+        let model=ReflectivityModel::new(30, vec![10, 20], vec![0.15, -0.1]);
+        let wavelets=[RickerWavelet::new(25.0, 0.001, 30)?, RickerWavelet::new(40.0, 0.001, 30)?];
+
+        let mut pipeline=SeismicPipeline::new();
+        let naming=OutputNaming::new(dir_str, "mc/{freq}Hz_{real:03}.csv")?;
+        pipeline.run_monte_carlo_named(&model, &wavelets[0], 3, &naming)?;
+        assert!(dir.join("mc/25Hz_002.csv").exists());
+
+        let mut batch=BatchProcessor::new(PipelineConfig::default());
+        let naming=OutputNaming::new(dir_str, "batch/{model}_{freq}Hz.csv")?;
+        batch.process_wavelets_named(&model, &wavelets, &naming)?;
+        assert!(dir.join("batch/0_40Hz.csv").exists());
+        //Without {freq} every wavelet would overwrite the same file
+        assert!(batch.process_wavelets_named(&model, &wavelets, &OutputNaming::new(dir_str, "batch/{model}.csv")?).is_err());
+
+        //Cleaning removes the previous run's realizations but not other outputs
+        let naming=OutputNaming::new(dir_str, "mc/{freq}Hz_{real:03}.csv")?.with_clean(true);
+        pipeline.run_monte_carlo_named(&model, &wavelets[1], 1, &naming)?;
+        assert!(!dir.join("mc/25Hz_002.csv").exists());
+        assert!(dir.join("mc/40Hz_000.csv").exists());
+        assert!(dir.join("batch/0_40Hz.csv").exists());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_zero_phase_filter_keeps_event_timing()-> Result<()>{
+        let config=PipelineConfig{
+            apply_filter: true,
+            low_freq: 5.0,
+            high_freq: 80.0,
+            phase_mode: PhaseMode::Zero,
+            ..Default::default()
+        };
+        let mut pipeline=SeismicPipeline::with_config(config);
+
+        let model=ReflectivityModel::new(200, vec![100], vec![0.2]);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 60)?;
+
+        let results=pipeline.run_forward_modelling(&model, &wavelet)?;
+        let peak=results.synthetic_trace.iter().enumerate().max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap()).unwrap().0;
+
+        //Reflector at 100 plus the wavelet's centre offset of 30 samples
+        assert_eq!(peak, 130);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registered_stage_is_applied()-> Result<()>{
+        let mut pipeline=SeismicPipeline::new();
+        pipeline.add_stage(Box::new(crate::processing::PercentileScaling{ percentile: 100.0, target: 2.0 }));
+
+        let model=ReflectivityModel::new(50, vec![20], vec![0.1]);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 30)?;
+
+        let results=pipeline.run_forward_modelling(&model, &wavelet)?;
+        let peak=results.synthetic_trace.iter().fold(0.0f64, |a, &b| a.max(b.abs()));
+
+        assert!((peak-2.0).abs()<1e-12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_skips_repeated_convolution()-> Result<()>{
+        let model=ReflectivityModel::new(80, vec![20, 50], vec![0.2, -0.1]);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 40)?;
+        let reference=SeismicPipeline::new().run_forward_modelling(&model, &wavelet)?.synthetic_trace;
+
+        let mut pipeline=SeismicPipeline::new();
+        pipeline.enable_cache(8);
+        assert_eq!(pipeline.run_forward_modelling(&model, &wavelet)?.synthetic_trace, reference);
+        //A different filter band reuses the convolution
+        pipeline.set_config(PipelineConfig{ apply_filter: true, high_freq: 60.0, ..Default::default() });
+        let filtered=pipeline.run_forward_modelling(&model, &wavelet)?.synthetic_trace;
+        assert_ne!(filtered, reference);
+        assert_eq!((pipeline.cache().unwrap().hits(), pipeline.cache().unwrap().misses()), (1, 1));
+
+        //A new wavelet does not
+        pipeline.run_forward_modelling(&model, &RickerWavelet::new(25.0, 0.001, 40)?)?;
+        assert_eq!(pipeline.cache().unwrap().misses(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_warnings_are_collected()-> Result<()>{
+        let mut pipeline=SeismicPipeline::new();
+        let model=ReflectivityModel::new(100, vec![20, 150], vec![0.1, 0.2]);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 60)?;
+        let results=pipeline.run_forward_modelling(&model, &wavelet)?;
+        assert_eq!(results.warnings, vec![Warning::DroppedLayers{ count: 1, length: 100 }]);
+
+        //A short wavelet and a band reaching Nyquist are flagged, and the run still completes
+        pipeline.set_config(PipelineConfig{ apply_filter: true, high_freq: 600.0, ..Default::default() });
+        let clipped=RickerWavelet::new(10.0, 0.001, 40)?;
+        let results=pipeline.run_forward_modelling(&ReflectivityModel::new(100, vec![20], vec![0.1]), &clipped)?;
+        assert_eq!(results.warnings.len(), 2);
+        assert!(matches!(results.warnings[0], Warning::ClippedWavelet{ .. }));
+        assert_eq!(results.warnings[1], Warning::FilterAboveNyquist{ requested: 600.0, used: 450.0, nyquist: 500.0 });
+
+        Ok(())
+    }
 }
@@ -0,0 +1,211 @@
+//! Time-lapse (4D) difference modelling
+//!
+//! A baseline and a monitor model are forward modelled with the same wavelet,
+//! the monitor trace is warped back onto the baseline to remove time shifts,
+//! and the remaining amplitude difference is summarised with standard 4D
+//! repeatability attributes.
+
+use anyhow::{Result, anyhow};
+use crate::alignment::dtw_align;
+use crate::convolution::ConvolutionEngine;
+use crate::models::ReflectivityModel;
+use crate::utils::rms;
+use crate::wavelets::RickerWavelet;
+use super::SeismicPipeline;
+
+///Change applied to one layer of the baseline model to build the monitor
+#[derive(Debug, Clone, Copy)]
+pub struct LayerChange{
+    ///Index into the model's layer list
+    pub layer: usize,
+    ///Added to the layer's reflection coefficient
+    pub coefficient_delta: f64,
+    ///Time shift in samples applied to this layer and every deeper one
+    ///(e.g. velocity slow-down in the reservoir)
+    pub shift: isize,
+}
+
+///Build a monitor model by applying layer changes to a baseline
+pub fn perturb_model(baseline: &ReflectivityModel, changes: &[LayerChange])-> Result<ReflectivityModel>{
+    let mut positions: Vec<isize>=baseline.layer_positions.iter().map(|&p| p as isize).collect();
+    let mut coefficients=baseline.reflection_coefficients.clone();
+
+    for change in changes{
+        if change.layer>=positions.len(){
+            return Err(anyhow!("Layer {} does not exist (model has {})", change.layer, positions.len()));
+        }
+        coefficients[change.layer]+=change.coefficient_delta;
+        for position in positions.iter_mut().skip(change.layer){
+            *position+=change.shift;
+        }
+    }
+
+    if positions.iter().any(|&p| p<0){
+        return Err(anyhow!("Time shifts move a layer before the start of the model"));
+    }
+    let positions=positions.into_iter().map(|p| p as usize).collect();
+
+    Ok(ReflectivityModel::new(baseline.length, positions, coefficients))
+}
+
+///Summary 4D attributes between baseline and monitor traces
+#[derive(Debug, Clone)]
+pub struct TimeLapseAttributes{
+    ///Normalised RMS difference in percent (0 identical, 200 opposite polarity)
+    pub nrms: f64,
+    ///Predictability in percent (100 means one trace fully predicts the other)
+    pub predictability: f64,
+    ///Largest absolute time shift in seconds found by the alignment
+    pub max_time_shift: f64,
+    ///RMS of the aligned difference trace
+    pub rms_difference: f64,
+}
+
+///Outputs of a time-lapse modelling run
+#[derive(Debug, Clone)]
+pub struct TimeLapseResults{
+    pub baseline_trace: Vec<f64>,
+    pub monitor_trace: Vec<f64>,
+    ///Monitor warped onto the baseline time axis
+    pub aligned_monitor: Vec<f64>,
+    ///Monitor minus baseline without alignment
+    pub raw_difference: Vec<f64>,
+    ///Aligned monitor minus baseline: the amplitude-only 4D signal
+    pub aligned_difference: Vec<f64>,
+    ///Monitor time shift per baseline sample in seconds
+    pub time_shifts: Vec<f64>,
+    pub attributes: TimeLapseAttributes,
+}
+
+impl SeismicPipeline{
+    ///Model baseline and monitor, align them and compute 4D differences
+    ///
+    /// `max_shift` bounds the alignment search in samples.
+    pub fn run_time_lapse(
+        &mut self,
+        baseline: &ReflectivityModel,
+        monitor: &ReflectivityModel,
+        wavelet: &RickerWavelet,
+        max_shift: usize,
+    )-> Result<TimeLapseResults>{
+        let baseline_trace=self.run_forward_modelling(baseline, wavelet)?.synthetic_trace;
+        let monitor_trace=self.run_forward_modelling(monitor, wavelet)?.synthetic_trace;
+        if baseline_trace.len()!=monitor_trace.len(){
+            return Err(anyhow!("Baseline and monitor models must have the same length"));
+        }
+
+        let dt=1.0/self.config().sample_rate;
+        let alignment=dtw_align(&baseline_trace, &monitor_trace, max_shift)?;
+
+        let aligned_monitor: Vec<f64>=alignment.shifts.iter().enumerate()
+            .map(|(i, &shift)| interpolate(&monitor_trace, i as f64+shift))
+            .collect();
+
+        let raw_difference: Vec<f64>=monitor_trace.iter().zip(baseline_trace.iter()).map(|(m, b)| m-b).collect();
+        let aligned_difference: Vec<f64>=aligned_monitor.iter().zip(baseline_trace.iter()).map(|(m, b)| m-b).collect();
+        let time_shifts: Vec<f64>=alignment.shifts.iter().map(|s| s*dt).collect();
+
+        let attributes=TimeLapseAttributes{
+            nrms: nrms(&baseline_trace, &monitor_trace),
+            predictability: predictability(&baseline_trace, &monitor_trace)?,
+            max_time_shift: time_shifts.iter().fold(0.0f64, |a, &b| a.max(b.abs())),
+            rms_difference: rms(&aligned_difference),
+        };
+
+        Ok(TimeLapseResults{
+            baseline_trace,
+            monitor_trace,
+            aligned_monitor,
+            raw_difference,
+            aligned_difference,
+            time_shifts,
+            attributes,
+        })
+    }
+}
+
+///Normalised RMS difference: 200*rms(a-b)/(rms(a)+rms(b))
+pub fn nrms(a: &[f64], b: &[f64])-> f64{
+    let difference: Vec<f64>=a.iter().zip(b.iter()).map(|(x, y)| x-y).collect();
+    let denominator=rms(a)+rms(b);
+    if denominator==0.0{
+        return 0.0;
+    }
+    200.0*rms(&difference)/denominator
+}
+
+///Predictability over all lags: 100*sum(xcorr^2)/sum(acorr_a*acorr_b)
+pub fn predictability(a: &[f64], b: &[f64])-> Result<f64>{
+    let mut engine=ConvolutionEngine::new();
+    let cross=engine.cross_correlate(a, b)?;
+    let auto_a=engine.auto_correlate(a)?;
+    let auto_b=engine.auto_correlate(b)?;
+
+    let numerator: f64=cross.iter().map(|x| x*x).sum();
+    let denominator: f64=auto_a.iter().zip(auto_b.iter()).map(|(x, y)| x*y).sum();
+    if denominator==0.0{
+        return Ok(0.0);
+    }
+    Ok(100.0*numerator/denominator)
+}
+
+///Linear interpolation at a fractional sample position, zero outside the trace
+fn interpolate(trace: &[f64], position: f64)-> f64{
+    if position<0.0 || position>(trace.len()-1) as f64{
+        return 0.0;
+    }
+    let lower=position.floor() as usize;
+    let upper=(lower+1).min(trace.len()-1);
+    let fraction=position-lower as f64;
+    trace[lower]*(1.0-fraction)+trace[upper]*fraction
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_perturb_model()-> Result<()>{
+        let baseline=ReflectivityModel::new(100, vec![20, 50, 80], vec![0.1, -0.1, 0.2]);
+        let monitor=perturb_model(&baseline, &[LayerChange{ layer: 1, coefficient_delta: 0.05, shift: 3 }])?;
+
+        assert_eq!(monitor.layer_positions, vec![20, 53, 83]);
+        assert_abs_diff_eq!(monitor.reflection_coefficients[1], -0.05, epsilon=1e-12);
+        assert!(perturb_model(&baseline, &[LayerChange{ layer: 5, coefficient_delta: 0.0, shift: 0 }]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identical_models_have_no_4d_signal()-> Result<()>{
+        let mut pipeline=SeismicPipeline::new();
+        let model=ReflectivityModel::new(120, vec![30, 70], vec![0.1, -0.1]);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 40)?;
+
+        let results=pipeline.run_time_lapse(&model, &model, &wavelet, 10)?;
+
+        assert_abs_diff_eq!(results.attributes.nrms, 0.0, epsilon=1e-9);
+        assert_abs_diff_eq!(results.attributes.predictability, 100.0, epsilon=1e-6);
+        assert_abs_diff_eq!(results.attributes.rms_difference, 0.0, epsilon=1e-12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alignment_removes_time_shift()-> Result<()>{
+        let mut pipeline=SeismicPipeline::new();
+        let baseline=ReflectivityModel::new(150, vec![40, 90], vec![0.1, 0.15]);
+        let monitor=perturb_model(&baseline, &[LayerChange{ layer: 1, coefficient_delta: 0.0, shift: 4 }])?;
+        let wavelet=RickerWavelet::new(25.0, 0.001, 60)?;
+
+        let results=pipeline.run_time_lapse(&baseline, &monitor, &wavelet, 10)?;
+
+        //A pure time shift gives a large raw difference but a small aligned one
+        assert!(rms(&results.raw_difference)>5.0*results.attributes.rms_difference);
+        //Second reflector peaks at sample 90 plus the wavelet's 30-sample centre offset
+        assert_abs_diff_eq!(results.time_shifts[120], 0.004, epsilon=0.0005);
+
+        Ok(())
+    }
+}
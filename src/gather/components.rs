@@ -0,0 +1,236 @@
+//! Multi-component recordings
+//!
+//! A multi-component station is stored as one `Trace` per component, each
+//! tagged with the `Component` it records, and a gather holds the stations
+//! one after another (Z, X, Y, Z, X, Y, ...). Horizontal components can be
+//! rotated to radial and transverse for a given source azimuth, and a
+//! hodogram (particle motion in the plane of two components) gives the
+//! polarization direction and how linear the motion is.
+
+use anyhow::{Result, anyhow};
+use crate::processing::ProcessingStage;
+use super::{Gather, Trace};
+
+///Direction recorded by a trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Component{
+    ///Vertical, positive up
+    Z,
+    ///First horizontal axis (north or in-line)
+    X,
+    ///Second horizontal axis, 90° clockwise from X seen from above (east or cross-line)
+    Y,
+    ///Horizontal, along the source-receiver azimuth away from the source
+    Radial,
+    ///Horizontal, 90° clockwise from radial
+    Transverse,
+    ///Along the ray, positive up and away from the source (P motion)
+    L,
+    ///Perpendicular to L in the plane of the ray (SV motion)
+    Q,
+}
+
+///Traces recorded by the components of one station
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiComponentTrace{
+    pub traces: Vec<Trace>,
+}
+
+impl MultiComponentTrace{
+    ///Station from tagged traces with a common time axis, each component at most once
+    pub fn new(traces: Vec<Trace>)-> Result<Self>{
+        let first=traces.first().ok_or_else(|| anyhow!("A station needs at least one component"))?;
+        for (i, trace) in traces.iter().enumerate(){
+            let component=trace.component.ok_or_else(|| anyhow!("Trace {} has no component", i))?;
+            first.check_timing(trace).map_err(|e| anyhow!("Component {:?}: {}", component, e))?;
+            if traces[..i].iter().any(|t| t.component==Some(component)){
+                return Err(anyhow!("Component {:?} appears twice", component));
+            }
+        }
+        Ok(Self{ traces })
+    }
+
+    ///Trace of `component`, if recorded
+    pub fn get(&self, component: Component)-> Option<&Trace>{
+        self.traces.iter().find(|t| t.component==Some(component))
+    }
+
+    ///Components present, in storage order
+    pub fn components(&self)-> Vec<Component>{
+        self.traces.iter().filter_map(|t| t.component).collect()
+    }
+
+    fn require(&self, component: Component)-> Result<&Trace>{
+        self.get(component).ok_or_else(|| anyhow!("Station has no {:?} component", component))
+    }
+
+    ///Replace X and Y by radial and transverse for a source-to-receiver azimuth in degrees from X towards Y
+    ///
+    /// `R = cos a X + sin a Y`, `T = -sin a X + cos a Y`; every other component is kept.
+    pub fn rotate_to_radial_transverse(&self, azimuth: f64)-> Result<Self>{
+        let (x, y)=(self.require(Component::X)?, self.require(Component::Y)?);
+        let (sin, cos)=azimuth.to_radians().sin_cos();
+        let combine=|a: f64, b: f64, component: Component| {
+            let samples=x.samples.iter().zip(&y.samples).map(|(&xs, &ys)| a*xs+b*ys).collect();
+            Trace::new(samples, x.dt).with_t0(x.t0).with_component(component)
+        };
+        let mut traces: Vec<Trace>=self.traces.iter().filter(|t| !matches!(t.component, Some(Component::X | Component::Y))).cloned().collect();
+        traces.push(combine(cos, sin, Component::Radial));
+        traces.push(combine(-sin, cos, Component::Transverse));
+        Self::new(traces)
+    }
+
+    ///Particle motion of `horizontal` against `vertical` over samples `window`
+    pub fn hodogram(&self, horizontal: Component, vertical: Component, window: std::ops::Range<usize>)-> Result<Hodogram>{
+        let (a, b)=(self.require(horizontal)?, self.require(vertical)?);
+        if window.is_empty() || window.end>a.len(){
+            return Err(anyhow!("Window {:?} is empty or outside the {} samples", window, a.len()));
+        }
+        let points: Vec<(f64, f64)>=window.map(|i| (a.samples[i], b.samples[i])).collect();
+
+        //Principal axes of the 2x2 covariance of the particle motion
+        let n=points.len() as f64;
+        let (ma, mb)=points.iter().fold((0.0, 0.0), |(sa, sb), &(p, q)| (sa+p/n, sb+q/n));
+        let (mut caa, mut cbb, mut cab)=(0.0, 0.0, 0.0);
+        for &(p, q) in &points{
+            caa+=(p-ma)*(p-ma)/n;
+            cbb+=(q-mb)*(q-mb)/n;
+            cab+=(p-ma)*(q-mb)/n;
+        }
+        let half_trace=0.5*(caa+cbb);
+        let spread=(0.25*(caa-cbb).powi(2)+cab*cab).sqrt();
+        let (major, minor)=(half_trace+spread, (half_trace-spread).max(0.0));
+        let angle=0.5*(2.0*cab).atan2(caa-cbb);
+        let linearity=if major>0.0 { 1.0-minor/major } else { 0.0 };
+        Ok(Hodogram{ points, angle: angle.to_degrees(), linearity })
+    }
+}
+
+///Particle motion in the plane of two components
+#[derive(Debug, Clone)]
+pub struct Hodogram{
+    ///`(first, second)` component amplitude at each sample
+    pub points: Vec<(f64, f64)>,
+    ///Direction of the major axis in degrees from the first component towards the second, in (-90, 90]
+    pub angle: f64,
+    ///`1 - minor/major` of the covariance eigenvalues: 1 for straight-line motion, 0 for circular
+    pub linearity: f64,
+}
+
+impl Gather{
+    ///Gather of consecutive stations
+    pub fn from_stations(stations: &[MultiComponentTrace])-> Result<Self>{
+        Self::new(stations.iter().flat_map(|s| s.traces.iter().cloned()).collect())
+    }
+
+    ///Split into stations; a station ends where its next component would repeat
+    pub fn stations(&self)-> Result<Vec<MultiComponentTrace>>{
+        let mut stations=Vec::new();
+        let mut current: Vec<Trace>=Vec::new();
+        for (i, trace) in self.traces.iter().enumerate(){
+            let component=trace.component.ok_or_else(|| anyhow!("Trace {} has no component", i))?;
+            if current.iter().any(|t| t.component==Some(component)){
+                stations.push(MultiComponentTrace::new(std::mem::take(&mut current))?);
+            }
+            current.push(trace.clone());
+        }
+        if !current.is_empty(){
+            stations.push(MultiComponentTrace::new(current)?);
+        }
+        Ok(stations)
+    }
+
+    ///Gather of the traces recording `component`
+    pub fn component(&self, component: Component)-> Gather{
+        Gather{ traces: self.traces.iter().filter(|t| t.component==Some(component)).cloned().collect() }
+    }
+}
+
+///Rotate every station's horizontals to radial/transverse, one azimuth per station
+#[derive(Debug, Clone)]
+pub struct RadialTransverseRotation{
+    ///Source-to-receiver azimuth of each station in degrees from X towards Y
+    pub azimuths: Vec<f64>,
+}
+
+impl ProcessingStage for RadialTransverseRotation{
+    fn name(&self)-> &str{
+        "radial_transverse_rotation"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        let stations=gather.stations()?;
+        if stations.len()!=self.azimuths.len(){
+            return Err(anyhow!("Have {} azimuths for {} stations", self.azimuths.len(), stations.len()));
+        }
+        let rotated=stations.iter().zip(&self.azimuths).map(|(s, &a)| s.rotate_to_radial_transverse(a)).collect::<Result<Vec<_>>>()?;
+        *gather=Gather::from_stations(&rotated)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    ///Station recording a pulse polarised at `azimuth` degrees in the horizontal plane, plus a vertical
+    fn station(azimuth: f64, vertical: f64)-> Result<MultiComponentTrace>{
+        let pulse: Vec<f64>=(0..100).map(|i| (-((i as f64-50.0)/5.0).powi(2)).exp()).collect();
+        let (sin, cos)=azimuth.to_radians().sin_cos();
+        let scaled=|a: f64, c: Component| Trace::new(pulse.iter().map(|x| a*x).collect(), 0.002).with_component(c);
+        MultiComponentTrace::new(vec![scaled(vertical, Component::Z), scaled(cos, Component::X), scaled(sin, Component::Y)])
+    }
+
+    #[test]
+    fn test_rotation_puts_energy_on_radial()-> Result<()>{
+        let original=station(30.0, 0.5)?;
+        let rotated=original.rotate_to_radial_transverse(30.0)?;
+        assert_eq!(rotated.components(), vec![Component::Z, Component::Radial, Component::Transverse]);
+        let (radial, transverse)=(rotated.get(Component::Radial).unwrap(), rotated.get(Component::Transverse).unwrap());
+        assert_abs_diff_eq!(radial.samples[50], 1.0, epsilon=1e-12);
+        assert!(transverse.samples.iter().all(|x| x.abs()<1e-12));
+        assert_eq!(rotated.get(Component::Z), original.get(Component::Z));
+
+        //Mixed components cannot be combined sample by sample
+        assert!(radial.add(transverse).is_err());
+        assert!(MultiComponentTrace::new(vec![original.traces[0].clone(), original.traces[0].clone()]).is_err());
+        assert!(rotated.rotate_to_radial_transverse(0.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hodogram_angle_and_linearity()-> Result<()>{
+        let linear=station(0.0, 1.0)?.hodogram(Component::X, Component::Z, 30..70)?;
+        assert_abs_diff_eq!(linear.angle, 45.0, epsilon=1e-9);
+        assert_abs_diff_eq!(linear.linearity, 1.0, epsilon=1e-9);
+        assert_eq!(linear.points.len(), 40);
+
+        //Quarter-period phase difference: circular motion
+        let circle=MultiComponentTrace::new(vec![
+            Trace::new((0..200).map(|i| (i as f64*0.1*std::f64::consts::PI).cos()).collect(), 0.002).with_component(Component::X),
+            Trace::new((0..200).map(|i| (i as f64*0.1*std::f64::consts::PI).sin()).collect(), 0.002).with_component(Component::Y),
+        ])?;
+        assert!(circle.hodogram(Component::X, Component::Y, 0..200)?.linearity<1e-9);
+        assert!(circle.hodogram(Component::X, Component::Z, 0..200).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gather_stations_and_rotation_stage()-> Result<()>{
+        let mut gather=Gather::from_stations(&[station(10.0, 0.0)?, station(80.0, 0.0)?])?;
+        assert_eq!(gather.len(), 6);
+        assert_eq!(gather.stations()?.len(), 2);
+        assert_eq!(gather.component(Component::Y).len(), 2);
+
+        RadialTransverseRotation{ azimuths: vec![10.0, 80.0] }.apply(&mut gather)?;
+        let radial=gather.component(Component::Radial);
+        assert_eq!(radial.len(), 2);
+        assert!(radial.traces.iter().all(|t| (t.samples[50]-1.0).abs()<1e-12));
+        assert!(RadialTransverseRotation{ azimuths: vec![0.0] }.apply(&mut gather).is_err());
+
+        let untagged=Gather::new(vec![Trace::new(vec![0.0; 4], 0.002)])?;
+        assert!(untagged.stations().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! Per-sample statistics across the traces of a gather
+//!
+//! Each statistic is evaluated independently at every time sample and returned
+//! as a trace, so the same code serves stack QC and Monte Carlo envelopes.
+
+use anyhow::{Result, anyhow};
+use super::{Gather, Trace};
+
+///Statistic traces computed across a gather
+#[derive(Debug, Clone)]
+pub struct EnsembleStats{
+    pub mean: Trace,
+    ///Sample standard deviation (n-1 normalisation)
+    pub std_dev: Trace,
+    pub median: Trace,
+    ///Mean after discarding the trimmed fraction at each tail
+    pub trimmed_mean: Trace,
+}
+
+impl Gather{
+    ///Mean, standard deviation, median and trimmed mean at every time sample
+    ///
+    /// `trim_fraction` is the fraction (0.0-0.5) of values dropped from each
+    /// tail before averaging for the trimmed mean.
+    pub fn ensemble_stats(&self, trim_fraction: f64)-> Result<EnsembleStats>{
+        if !(0.0..0.5).contains(&trim_fraction){
+            return Err(anyhow!("Trim fraction must be in [0, 0.5), got {}", trim_fraction));
+        }
+        let dt=self.dt().ok_or_else(|| anyhow!("Cannot compute statistics of an empty gather"))?;
+        let t0=self.t0().unwrap_or(0.0);
+
+        let n=self.len();
+        let trim=(trim_fraction*n as f64).floor() as usize;
+        let mut mean=Vec::with_capacity(self.num_samples());
+        let mut std_dev=Vec::with_capacity(self.num_samples());
+        let mut median=Vec::with_capacity(self.num_samples());
+        let mut trimmed_mean=Vec::with_capacity(self.num_samples());
+
+        for column in self.columns(){
+            let m=column.iter().sum::<f64>()/n as f64;
+            let variance=if n>1{
+                column.iter().map(|x| (x-m).powi(2)).sum::<f64>()/(n-1) as f64
+            }else{
+                0.0
+            };
+
+            let mut sorted=column;
+            sorted.sort_by(f64::total_cmp);
+            let kept=&sorted[trim..n-trim];
+
+            mean.push(m);
+            std_dev.push(variance.sqrt());
+            median.push(percentile_sorted(&sorted, 50.0));
+            trimmed_mean.push(kept.iter().sum::<f64>()/kept.len() as f64);
+        }
+
+        Ok(EnsembleStats{
+            mean: Trace::new(mean, dt).with_t0(t0),
+            std_dev: Trace::new(std_dev, dt).with_t0(t0),
+            median: Trace::new(median, dt).with_t0(t0),
+            trimmed_mean: Trace::new(trimmed_mean, dt).with_t0(t0),
+        })
+    }
+
+    ///Percentile (0-100) at every time sample, with linear interpolation
+    ///
+    /// P10 and P90 traces bound a Monte Carlo envelope.
+    pub fn percentile(&self, percent: f64)-> Result<Trace>{
+        if !(0.0..=100.0).contains(&percent){
+            return Err(anyhow!("Percentile must be in [0, 100], got {}", percent));
+        }
+        let dt=self.dt().ok_or_else(|| anyhow!("Cannot compute percentiles of an empty gather"))?;
+        let t0=self.t0().unwrap_or(0.0);
+
+        let samples=self.columns().map(|mut column| {
+            column.sort_by(f64::total_cmp);
+            percentile_sorted(&column, percent)
+        }).collect();
+
+        Ok(Trace::new(samples, dt).with_t0(t0))
+    }
+
+    ///Values of every trace at each time sample
+    fn columns(&self)-> impl Iterator<Item=Vec<f64>>+'_{
+        (0..self.num_samples()).map(move |i| self.traces.iter().map(|t| t.samples[i]).collect())
+    }
+}
+
+///Linearly interpolated percentile of already sorted values
+pub fn percentile_sorted(sorted: &[f64], percent: f64)-> f64{
+    if sorted.is_empty(){
+        return f64::NAN;
+    }
+    let position=percent/100.0*(sorted.len()-1) as f64;
+    let lower=position.floor() as usize;
+    let upper=position.ceil() as usize;
+    let fraction=position-lower as f64;
+    sorted[lower]+(sorted[upper]-sorted[lower])*fraction
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn gather()-> Gather{
+        let values=[1.0, 2.0, 3.0, 4.0, 100.0];
+        Gather::new(values.iter().map(|&v| Trace::new(vec![v, -v], 0.004)).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_ensemble_stats()-> Result<()>{
+        let stats=gather().ensemble_stats(0.2)?;
+
+        assert_abs_diff_eq!(stats.mean.samples[0], 22.0, epsilon=1e-12);
+        assert_abs_diff_eq!(stats.median.samples[0], 3.0, epsilon=1e-12);
+        assert_abs_diff_eq!(stats.median.samples[1], -3.0, epsilon=1e-12);
+        //One value trimmed from each tail: mean of 2, 3, 4
+        assert_abs_diff_eq!(stats.trimmed_mean.samples[0], 3.0, epsilon=1e-12);
+        assert_abs_diff_eq!(stats.std_dev.samples[0], 43.6176, epsilon=1e-4);
+        assert_eq!(stats.mean.dt, 0.004);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_percentiles()-> Result<()>{
+        let g=gather();
+
+        assert_abs_diff_eq!(g.percentile(0.0)?.samples[0], 1.0, epsilon=1e-12);
+        assert_abs_diff_eq!(g.percentile(62.5)?.samples[0], 3.5, epsilon=1e-12);
+        assert!(g.percentile(101.0).is_err());
+        assert!(Gather::new(vec![])?.ensemble_stats(0.1).is_err());
+
+        //A NaN realization sorts to the top instead of panicking
+        let values=[1.0, f64::NAN, 3.0];
+        let g=Gather::new(values.iter().map(|&v| Trace::new(vec![v], 0.004)).collect())?;
+        assert_abs_diff_eq!(g.percentile(0.0)?.samples[0], 1.0, epsilon=1e-12);
+        assert_eq!(g.ensemble_stats(0.0)?.median.samples[0], 3.0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+//! Traces and gathers with checked elementwise arithmetic
+
+use anyhow::{Result, anyhow};
+use crate::convolution::ConvolutionEngine;
+use crate::forward_modelling::ForwardModellingResults;
+
+pub mod components;
+pub mod ensemble;
+
+pub use components::Component;
+
+///A single seismic trace with its sample interval and start time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace{
+    ///Trace amplitudes
+    pub samples: Vec<f64>,
+    ///Sample interval in seconds
+    pub dt: f64,
+    ///Time of the first sample in seconds (recording delay or datum offset)
+    pub t0: f64,
+    ///Recording component of a multi-component trace, `None` for single-component data
+    pub component: Option<Component>,
+}
+
+impl Trace{
+    pub fn new(samples: Vec<f64>, dt: f64)-> Self{
+        Self{ samples, dt, t0: 0.0, component: None }
+    }
+
+    ///Same trace with the first sample at `t0` seconds
+    pub fn with_t0(mut self, t0: f64)-> Self{
+        self.t0=t0;
+        self
+    }
+
+    ///Same trace tagged as recording `component`
+    pub fn with_component(mut self, component: Component)-> Self{
+        self.component=Some(component);
+        self
+    }
+
+    ///New samples with this trace's timing and component
+    fn with_samples(&self, samples: Vec<f64>)-> Trace{
+        Trace{ samples, dt: self.dt, t0: self.t0, component: self.component }
+    }
+
+    ///Synthetic trace from a forward modelling run, keeping its start time
+    pub fn from_results(results: &ForwardModellingResults, dt: f64)-> Self{
+        Self::new(results.synthetic_trace.clone(), dt).with_t0(results.time.first().copied().unwrap_or(0.0))
+    }
+
+    ///Time of sample `i` in seconds
+    pub fn time(&self, i: usize)-> f64{
+        self.t0+i as f64*self.dt
+    }
+
+    ///Time of every sample in seconds
+    pub fn times(&self)-> Vec<f64>{
+        (0..self.len()).map(|i| self.time(i)).collect()
+    }
+
+    ///Nearest sample to time `t`, if it falls inside the trace
+    pub fn sample_at(&self, t: f64)-> Option<usize>{
+        let index=((t-self.t0)/self.dt).round();
+        (index>=0.0 && (index as usize)<self.len()).then_some(index as usize)
+    }
+
+    pub fn len(&self)-> usize{
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.samples.is_empty()
+    }
+
+    ///Full convolution with a wavelet whose first sample is at `wavelet_t0` seconds
+    ///
+    /// The output starts at `t0+wavelet_t0`: a centred wavelet has a negative
+    /// start time, so the lead-in before the first reflector lands before the
+    /// trace's own start.
+    pub fn convolve(&self, engine: &mut ConvolutionEngine, wavelet: &[f64], wavelet_t0: f64)-> Result<Trace>{
+        let samples=engine.convolve(&self.samples, wavelet)?;
+        Ok(self.with_samples(samples).with_t0(self.t0+wavelet_t0))
+    }
+
+    ///Check that two traces share length, sample interval, start time and component
+    pub fn check_compatible(&self, other: &Trace)-> Result<()>{
+        self.check_timing(other)?;
+        if let (Some(a), Some(b))=(self.component, other.component){
+            if a!=b{
+                return Err(anyhow!("Components differ: {:?} vs {:?}", a, b));
+            }
+        }
+        Ok(())
+    }
+
+    ///Check that two traces share length, sample interval and start time, whatever their components
+    pub fn check_timing(&self, other: &Trace)-> Result<()>{
+        if self.len()!=other.len(){
+            return Err(anyhow!("Trace lengths differ: {} vs {} samples", self.len(), other.len()));
+        }
+        if (self.dt-other.dt).abs()>1e-12*self.dt.abs().max(other.dt.abs()){
+            return Err(anyhow!("Sample intervals differ: {} vs {} s", self.dt, other.dt));
+        }
+        if (self.t0-other.t0).abs()>1e-6*self.dt.abs(){
+            return Err(anyhow!("Start times differ: {} vs {} s", self.t0, other.t0));
+        }
+        Ok(())
+    }
+
+    ///Elementwise sum
+    pub fn add(&self, other: &Trace)-> Result<Trace>{
+        self.zip_with(other, |a, b| a+b)
+    }
+
+    ///Elementwise difference `self-other`, e.g. a residual against observed data
+    pub fn subtract(&self, other: &Trace)-> Result<Trace>{
+        self.zip_with(other, |a, b| a-b)
+    }
+
+    ///Multiply every sample by a constant
+    pub fn scale(&self, factor: f64)-> Trace{
+        self.with_samples(self.samples.iter().map(|x| x*factor).collect())
+    }
+
+    fn zip_with(&self, other: &Trace, op: impl Fn(f64, f64)-> f64)-> Result<Trace>{
+        self.check_compatible(other)?;
+        let samples=self.samples.iter().zip(other.samples.iter()).map(|(&a, &b)| op(a, b)).collect();
+        Ok(self.with_samples(samples))
+    }
+}
+
+///A collection of traces sharing length and sample interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gather{
+    pub traces: Vec<Trace>,
+}
+
+impl Gather{
+    ///Build a gather, checking every trace against the first; components may be mixed
+    pub fn new(traces: Vec<Trace>)-> Result<Self>{
+        if let Some(first)=traces.first(){
+            for (i, trace) in traces.iter().enumerate().skip(1){
+                first.check_timing(trace).map_err(|e| anyhow!("Trace {}: {}", i, e))?;
+            }
+        }
+        Ok(Self{ traces })
+    }
+
+    ///Gather of synthetic traces, e.g. the realizations of a Monte Carlo run
+    pub fn from_results(results: &[ForwardModellingResults], dt: f64)-> Result<Self>{
+        Self::new(results.iter().map(|r| Trace::from_results(r, dt)).collect())
+    }
+
+    pub fn len(&self)-> usize{
+        self.traces.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.traces.is_empty()
+    }
+
+    ///Samples per trace (zero for an empty gather)
+    pub fn num_samples(&self)-> usize{
+        self.traces.first().map(|t| t.len()).unwrap_or(0)
+    }
+
+    ///Sample interval of the gather
+    pub fn dt(&self)-> Option<f64>{
+        self.traces.first().map(|t| t.dt)
+    }
+
+    ///Start time of the gather
+    pub fn t0(&self)-> Option<f64>{
+        self.traces.first().map(|t| t.t0)
+    }
+
+    ///Trace-by-trace sum with another gather of the same shape
+    pub fn add(&self, other: &Gather)-> Result<Gather>{
+        self.zip_traces(other, Trace::add)
+    }
+
+    ///Trace-by-trace difference with another gather of the same shape
+    pub fn subtract(&self, other: &Gather)-> Result<Gather>{
+        self.zip_traces(other, Trace::subtract)
+    }
+
+    ///Difference of every trace against a single reference trace
+    pub fn diff_against(&self, reference: &Trace)-> Result<Gather>{
+        let traces=self.traces.iter().map(|t| t.subtract(reference)).collect::<Result<Vec<_>>>()?;
+        Ok(Gather{ traces })
+    }
+
+    ///Mean stack of all traces
+    pub fn stack(&self)-> Result<Trace>{
+        let weights=vec![1.0; self.len()];
+        self.weighted_stack(&weights)
+    }
+
+    ///Weighted stack normalised by the sum of the weights
+    pub fn weighted_stack(&self, weights: &[f64])-> Result<Trace>{
+        let first=self.traces.first().ok_or_else(|| anyhow!("Cannot stack an empty gather"))?;
+        if weights.len()!=self.len(){
+            return Err(anyhow!("Expected {} weights, got {}", self.len(), weights.len()));
+        }
+        let total: f64=weights.iter().sum();
+        if total==0.0{
+            return Err(anyhow!("Stack weights sum to zero"));
+        }
+
+        let mut stacked=vec![0.0; first.len()];
+        for (trace, &weight) in self.traces.iter().zip(weights.iter()){
+            for (s, &x) in stacked.iter_mut().zip(trace.samples.iter()){
+                *s+=weight*x;
+            }
+        }
+        for s in &mut stacked{
+            *s/=total;
+        }
+
+        Ok(Trace::new(stacked, first.dt).with_t0(first.t0))
+    }
+
+    fn zip_traces(&self, other: &Gather, op: impl Fn(&Trace, &Trace)-> Result<Trace>)-> Result<Gather>{
+        if self.len()!=other.len(){
+            return Err(anyhow!("Gathers hold different numbers of traces: {} vs {}", self.len(), other.len()));
+        }
+        let traces=self.traces.iter().zip(other.traces.iter()).map(|(a, b)| op(a, b)).collect::<Result<Vec<_>>>()?;
+        Ok(Gather{ traces })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_trace_arithmetic()-> Result<()>{
+        let a=Trace::new(vec![1.0, 2.0, 3.0], 0.002);
+        let b=Trace::new(vec![0.5, 0.5, 0.5], 0.002);
+
+        assert_eq!(a.add(&b)?.samples, vec![1.5, 2.5, 3.5]);
+        assert_eq!(a.subtract(&b)?.samples, vec![0.5, 1.5, 2.5]);
+        assert_eq!(a.scale(2.0).samples, vec![2.0, 4.0, 6.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_incompatible_traces(){
+        let a=Trace::new(vec![1.0, 2.0], 0.002);
+        assert!(a.add(&Trace::new(vec![1.0], 0.002)).is_err());
+        assert!(a.add(&Trace::new(vec![1.0, 2.0], 0.004)).is_err());
+        assert!(a.add(&Trace::new(vec![1.0, 2.0], 0.002).with_t0(0.1)).is_err());
+        assert!(Gather::new(vec![a.clone(), Trace::new(vec![1.0], 0.002)]).is_err());
+    }
+
+    #[test]
+    fn test_stacking_and_residuals()-> Result<()>{
+        let gather=Gather::new(vec![
+            Trace::new(vec![1.0, 0.0], 0.001),
+            Trace::new(vec![3.0, 2.0], 0.001),
+        ])?;
+
+        assert_eq!(gather.stack()?.samples, vec![2.0, 1.0]);
+        assert_eq!(gather.weighted_stack(&[3.0, 1.0])?.samples, vec![1.5, 0.5]);
+        assert!(gather.weighted_stack(&[1.0]).is_err());
+
+        let residuals=gather.diff_against(&Trace::new(vec![1.0, 1.0], 0.001))?;
+        assert_eq!(residuals.traces[1].samples, vec![2.0, 1.0]);
+
+        let doubled=gather.add(&gather)?;
+        assert_eq!(doubled.traces[1].samples, vec![6.0, 4.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_time_is_kept()-> Result<()>{
+        let trace=Trace::new(vec![1.0, 2.0, 3.0], 0.004).with_t0(0.2);
+        for (t, expected) in trace.times().iter().zip([0.2, 0.204, 0.208]){
+            assert!((t-expected).abs()<1e-12);
+        }
+        assert_eq!(trace.sample_at(0.2041), Some(1));
+        assert_eq!(trace.sample_at(0.1), None);
+
+        let gather=Gather::new(vec![trace.clone(), trace.scale(3.0)])?;
+        assert_eq!(gather.t0(), Some(0.2));
+        assert_eq!(gather.stack()?.t0, 0.2);
+        assert_eq!(trace.add(&trace)?.t0, 0.2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_convolution_shifts_start_time()-> Result<()>{
+        let reflectivity=Trace::new(vec![0.0, 0.0, 1.0, 0.0, 0.0], 0.004).with_t0(1.0);
+        let synthetic=reflectivity.convolve(&mut ConvolutionEngine::new(), &[0.5, 1.0, 0.5], -0.004)?;
+
+        assert_eq!(synthetic.len(), 7);
+        assert!((synthetic.t0-0.996).abs()<1e-12);
+        //The wavelet peak stays at the reflector time
+        let peak=synthetic.samples.iter().enumerate().max_by(|a, b| a.1.partial_cmp(b.1).unwrap()).map(|(i, _)| i).unwrap();
+        assert!((synthetic.time(peak)-reflectivity.time(2)).abs()<1e-12);
+        Ok(())
+    }
+}
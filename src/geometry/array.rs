@@ -0,0 +1,173 @@
+//! Receiver arrays: group forming and wavenumber response
+//!
+//! A field group sums several sensors laid out along the line. Energy whose
+//! wavelength along the line is short compared with the array (slow ground
+//! roll) arrives at the elements with different delays and cancels, while
+//! near-vertical reflections arrive together and pass. The response to a
+//! plane wave depends only on its apparent wavenumber `k = f p`, so one curve
+//! describes the attenuation of every event.
+
+use anyhow::{Result, anyhow};
+use std::f64::consts::PI;
+use crate::gather::{Gather, Trace};
+
+///Weighted sensors at fixed offsets from the group centre
+#[derive(Debug, Clone)]
+pub struct ReceiverArray{
+    ///Offset of each element from the group centre in metres
+    pub offsets: Vec<f64>,
+    ///Weight of each element
+    pub weights: Vec<f64>,
+}
+
+impl ReceiverArray{
+    ///Equally weighted elements `spacing` apart, centred on the group
+    pub fn uniform(elements: usize, spacing: f64)-> Result<Self>{
+        Self::tapered(vec![1.0; elements], spacing)
+    }
+
+    ///Elements `spacing` apart with the given weights, centred on the group
+    pub fn tapered(weights: Vec<f64>, spacing: f64)-> Result<Self>{
+        if weights.is_empty() || spacing.is_nan() || spacing<0.0{
+            return Err(anyhow!("Array needs at least one element and a non-negative spacing"));
+        }
+        if weights.iter().sum::<f64>()<=0.0{
+            return Err(anyhow!("Array weights must have a positive sum"));
+        }
+        let centre=0.5*(weights.len()-1) as f64;
+        let offsets=(0..weights.len()).map(|i| (i as f64-centre)*spacing).collect();
+        Ok(Self{ offsets, weights })
+    }
+
+    ///Distance between the outer elements
+    pub fn length(&self)-> f64{
+        let (min, max)=self.offsets.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(a, b), &x| (a.min(x), b.max(x)));
+        max-min
+    }
+
+    ///Amplitude response to a plane wave of wavenumber `k` cycles/m, normalised to one at `k = 0`
+    pub fn wavenumber_response(&self, k: f64)-> f64{
+        let (re, im)=self.offsets.iter().zip(&self.weights).fold((0.0, 0.0), |(re, im), (&x, &w)| {
+            let phase=2.0*PI*k*x;
+            (re+w*phase.cos(), im+w*phase.sin())
+        });
+        (re*re+im*im).sqrt()/self.weights.iter().sum::<f64>()
+    }
+
+    ///Response to an event of apparent slowness `slowness` s/m at frequency `f` Hz
+    pub fn frequency_response(&self, f: f64, slowness: f64)-> f64{
+        self.wavenumber_response(f*slowness)
+    }
+
+    ///Response sampled at `count` wavenumbers from zero to `max_wavenumber`, as `(k, response)`
+    pub fn response_curve(&self, max_wavenumber: f64, count: usize)-> Vec<(f64, f64)>{
+        (0..count).map(|i| {
+            let k=max_wavenumber*i as f64/(count.max(2)-1) as f64;
+            (k, self.wavenumber_response(k))
+        }).collect()
+    }
+
+    ///Weighted sum of the single-sensor traces `sensor(x)` at each element, for groups centred at `positions`
+    pub fn form_groups(&self, positions: &[f64], dt: f64, sensor: impl Fn(f64)-> Vec<f64>)-> Result<Gather>{
+        let total: f64=self.weights.iter().sum();
+        let traces=positions.iter().map(|&centre| {
+            let mut sum: Vec<f64>=Vec::new();
+            for (&offset, &weight) in self.offsets.iter().zip(&self.weights){
+                let trace=sensor(centre+offset);
+                if sum.is_empty(){
+                    sum=vec![0.0; trace.len()];
+                }
+                sum.iter_mut().zip(&trace).for_each(|(s, x)| *s+=weight*x/total);
+            }
+            Trace::new(sum, dt)
+        }).collect();
+        Gather::new(traces)
+    }
+}
+
+///A linear-moveout event: a Ricker pulse arriving at `intercept + slowness·x`
+///
+/// Slowness is the apparent slowness along the line, so a flat reflection
+/// has zero slowness and ground roll the inverse of its phase velocity; the
+/// sign sets the dip direction.
+#[derive(Debug, Clone, Copy)]
+pub struct LinearEvent{
+    ///Arrival time at `x = 0` in seconds
+    pub intercept: f64,
+    ///Apparent slowness in s/m
+    pub slowness: f64,
+    pub amplitude: f64,
+    ///Dominant frequency of the Ricker pulse in Hz
+    pub frequency: f64,
+}
+
+impl LinearEvent{
+    ///Trace recorded by a single sensor at `x`, exact at fractional arrival times
+    pub fn trace_at(&self, x: f64, dt: f64, num_samples: usize)-> Vec<f64>{
+        let arrival=self.intercept+self.slowness*x;
+        let pi_f_squared=(PI*self.frequency).powi(2);
+        (0..num_samples).map(|i| {
+            let t=i as f64*dt-arrival;
+            self.amplitude*(1.0-2.0*pi_f_squared*t*t)*(-pi_f_squared*t*t).exp()
+        }).collect()
+    }
+}
+
+///Single-sensor trace at `x` holding every event
+pub fn events_at(events: &[LinearEvent], x: f64, dt: f64, num_samples: usize)-> Vec<f64>{
+    let mut trace=vec![0.0; num_samples];
+    for event in events{
+        trace.iter_mut().zip(event.trace_at(x, dt, num_samples)).for_each(|(a, b)| *a+=b);
+    }
+    trace
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn energy(trace: &[f64])-> f64{
+        trace.iter().map(|x| x*x).sum()
+    }
+
+    #[test]
+    fn test_uniform_array_response()-> Result<()>{
+        let array=ReceiverArray::uniform(10, 2.0)?;
+        assert_abs_diff_eq!(array.length(), 18.0, epsilon=1e-12);
+        assert_abs_diff_eq!(array.wavenumber_response(0.0), 1.0, epsilon=1e-12);
+        //First null at one cycle across the array's N·d footprint
+        assert_abs_diff_eq!(array.wavenumber_response(1.0/20.0), 0.0, epsilon=1e-12);
+        //Grating lobe at the element wavenumber
+        assert_abs_diff_eq!(array.wavenumber_response(0.5), 1.0, epsilon=1e-9);
+        assert_abs_diff_eq!(array.frequency_response(15.0, 1.0/300.0), array.wavenumber_response(0.05), epsilon=1e-12);
+
+        let curve=array.response_curve(0.1, 11);
+        assert_eq!(curve.len(), 11);
+        assert_abs_diff_eq!(curve[10].0, 0.1, epsilon=1e-12);
+        assert!(ReceiverArray::tapered(vec![], 2.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_forming_attenuates_ground_roll()-> Result<()>{
+        let (dt, num_samples)=(0.002, 600);
+        let reflection=LinearEvent{ intercept: 0.6, slowness: 1e-5, amplitude: 1.0, frequency: 30.0 };
+        let ground_roll=LinearEvent{ intercept: 0.1, slowness: 1.0/300.0, amplitude: 5.0, frequency: 15.0 };
+        let array=ReceiverArray::uniform(12, 2.0)?;
+        let positions=[50.0, 100.0, 150.0];
+
+        let groups=array.form_groups(&positions, dt, |x| ground_roll.trace_at(x, dt, num_samples))?;
+        for (trace, &x) in groups.traces.iter().zip(&positions){
+            let ratio=energy(&trace.samples)/energy(&ground_roll.trace_at(x, dt, num_samples));
+            assert!(ratio<0.05, "ground roll kept {}", ratio);
+        }
+        let groups=array.form_groups(&positions, dt, |x| reflection.trace_at(x, dt, num_samples))?;
+        let ratio=energy(&groups.traces[1].samples)/energy(&reflection.trace_at(100.0, dt, num_samples));
+        assert!(ratio>0.99, "reflection kept {}", ratio);
+
+        let both=events_at(&[reflection, ground_roll], 0.0, dt, num_samples);
+        assert_abs_diff_eq!(both[300], 1.0, epsilon=1e-9);
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! Illumination QC by ray tracing from the planned survey
+//!
+//! A fan of rays is shot from every source through the velocity model.
+//! Where a ray meets a target horizon it is reflected specularly and traced
+//! back to the surface; if it emerges at a live receiver within the offset
+//! range, the reflection point counts as illuminated. Hit counts and the
+//! range of incidence angles per horizon bin show shadow zones and where the
+//! angle coverage needed for AVO is missing.
+
+use anyhow::{Result, anyhow};
+use rayon::prelude::*;
+use crate::models::velocity::VelocityModel;
+use super::SurveyGeometry;
+
+///Target interface in depth, piecewise linear between control points
+#[derive(Debug, Clone)]
+pub struct DepthHorizon{
+    pub name: String,
+    ///Lateral positions in metres, increasing
+    pub x: Vec<f64>,
+    ///Depth at each position in metres
+    pub z: Vec<f64>,
+}
+
+impl DepthHorizon{
+    pub fn new(name: &str, x: Vec<f64>, z: Vec<f64>)-> Result<Self>{
+        if x.len()<2 || x.len()!=z.len(){
+            return Err(anyhow!("Horizon {} needs at least two matching (x, z) points", name));
+        }
+        if x.windows(2).any(|w| w[1]<=w[0]){
+            return Err(anyhow!("Horizon {} positions must increase", name));
+        }
+        Ok(Self{ name: name.to_string(), x, z })
+    }
+
+    ///Flat horizon at `depth` between `x_min` and `x_max`
+    pub fn flat(name: &str, x_min: f64, x_max: f64, depth: f64)-> Result<Self>{
+        Self::new(name, vec![x_min, x_max], vec![depth, depth])
+    }
+
+    ///Depth and slope dz/dx at `x`, extended flat beyond the end points
+    pub fn depth_and_slope(&self, x: f64)-> (f64, f64){
+        let n=self.x.len();
+        if x<=self.x[0]{
+            return (self.z[0], 0.0);
+        }
+        if x>=self.x[n-1]{
+            return (self.z[n-1], 0.0);
+        }
+        let i=self.x.partition_point(|&p| p<=x)-1;
+        let slope=(self.z[i+1]-self.z[i])/(self.x[i+1]-self.x[i]);
+        (self.z[i]+slope*(x-self.x[i]), slope)
+    }
+}
+
+///Where a traced ray met its target
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit{
+    pub x: f64,
+    pub z: f64,
+    ///Propagation angle from the vertical (positive towards +x), radians
+    pub angle: f64,
+    ///Travel time from the ray start in seconds
+    pub time: f64,
+}
+
+///Trace a ray from `(x, z)` at `angle` from vertical until `surface(x, z)` changes sign
+///
+/// Integrates `dx/ds = sin a`, `dz/ds = cos a`, `da/ds = (v_z sin a - v_x cos a)/v`
+/// with a midpoint rule and step `step` metres. Returns `None` if the ray
+/// leaves the model first.
+pub fn trace_ray(model: &VelocityModel, x: f64, z: f64, angle: f64, step: f64, surface: impl Fn(f64, f64)-> f64)-> Option<RayHit>{
+    let derivative=|x: f64, z: f64, a: f64| {
+        let (v, vx, vz)=model.sample(x, z);
+        (a.sin(), a.cos(), (vz*a.sin()-vx*a.cos())/v, 1.0/v)
+    };
+    let (mut x, mut z, mut a, mut time)=(x, z, angle, 0.0);
+    let start_side=surface(x, z).signum();
+    let max_steps=(4.0*(model.width()+model.depth())/step) as usize;
+
+    for _ in 0..max_steps{
+        let (dx1, dz1, da1, _)=derivative(x, z, a);
+        let (mx, mz, ma)=(x+0.5*step*dx1, z+0.5*step*dz1, a+0.5*step*da1);
+        let (dx2, dz2, da2, slowness)=derivative(mx, mz, ma);
+        let (nx, nz, na)=(x+step*dx2, z+step*dz2, a+step*da2);
+
+        let (before, after)=(surface(x, z), surface(nx, nz));
+        if after==0.0 || after.signum()!=start_side{
+            //Linear interpolation to the crossing
+            let fraction=if before==after { 1.0 } else { before/(before-after) };
+            return Some(RayHit{ x: x+fraction*(nx-x), z: z+fraction*(nz-z), angle: a+fraction*(na-a), time: time+fraction*step*slowness });
+        }
+        if nx<0.0 || nx>model.width() || nz<0.0 || nz>model.depth(){
+            return None;
+        }
+        (x, z, a)=(nx, nz, na);
+        time+=step*slowness;
+    }
+    None
+}
+
+///Settings for an illumination run
+#[derive(Debug, Clone)]
+pub struct IlluminationAnalysis{
+    ///Take-off angles from `-max_angle_deg` to `max_angle_deg`
+    pub max_angle_deg: f64,
+    pub angle_step_deg: f64,
+    ///Ray step in metres; `None` uses half the smaller grid spacing
+    pub step: Option<f64>,
+    ///Width of the horizon bins in metres
+    pub bin_size: f64,
+    ///A ray counts if it emerges this close to a receiver; `None` uses half the receiver spacing
+    pub receiver_tolerance: Option<f64>,
+}
+
+impl Default for IlluminationAnalysis{
+    fn default()-> Self{
+        Self{
+            max_angle_deg: 70.0,
+            angle_step_deg: 0.5,
+            step: None,
+            bin_size: 25.0,
+            receiver_tolerance: None,
+        }
+    }
+}
+
+///Hit counts and incidence-angle coverage along one horizon
+#[derive(Debug, Clone)]
+pub struct HorizonIllumination{
+    pub name: String,
+    pub bin_centres: Vec<f64>,
+    pub hits: Vec<usize>,
+    ///Smallest and largest incidence angle per bin in degrees, NaN where unlit
+    pub min_angle_deg: Vec<f64>,
+    pub max_angle_deg: Vec<f64>,
+}
+
+impl HorizonIllumination{
+    ///Fraction of bins with at least one hit
+    pub fn coverage(&self)-> f64{
+        self.hits.iter().filter(|&&h| h>0).count() as f64/self.hits.len().max(1) as f64
+    }
+
+    pub fn print_summary(&self){
+        let peak=self.hits.iter().copied().max().unwrap_or(0).max(1);
+        println!("Horizon {}: {:.0}% of bins illuminated", self.name, 100.0*self.coverage());
+        println!("{:>10} {:>6} {:>16}  hits", "x (m)", "hits", "angles (deg)");
+        for i in 0..self.hits.len(){
+            let angles=if self.hits[i]>0 { format!("{:.1}-{:.1}", self.min_angle_deg[i], self.max_angle_deg[i]) } else { "-".to_string() };
+            println!("{:>10.0} {:>6} {:>16}  {}", self.bin_centres[i], self.hits[i], angles, "#".repeat(self.hits[i]*30/peak));
+        }
+    }
+}
+
+impl IlluminationAnalysis{
+    ///Shoot every source through `model` and map illumination on each horizon
+    pub fn run(&self, model: &VelocityModel, geometry: &SurveyGeometry, horizons: &[DepthHorizon])-> Result<Vec<HorizonIllumination>>{
+        if !(self.angle_step_deg>0.0 && self.bin_size>0.0){
+            return Err(anyhow!("Angle step and bin size must be positive"));
+        }
+        let step=self.step.unwrap_or(0.5*model.dx.min(model.dz));
+        let tolerance=self.receiver_tolerance.unwrap_or(0.5*geometry.receiver_spacing()).max(1e-6);
+        let num_angles=(2.0*self.max_angle_deg/self.angle_step_deg).floor() as usize+1;
+
+        horizons.iter().map(|horizon| {
+            let (x_min, x_max)=(horizon.x[0], horizon.x[horizon.x.len()-1]);
+            let num_bins=((x_max-x_min)/self.bin_size).ceil().max(1.0) as usize;
+
+            //(bin, incidence angle) for every ray that reaches a live receiver
+            let hits: Vec<(usize, f64)>=geometry.sources.par_iter().flat_map_iter(|&source| {
+                (0..num_angles).filter_map(move |k| {
+                    let take_off=(-self.max_angle_deg+k as f64*self.angle_step_deg).to_radians();
+                    let down=trace_ray(model, source, 0.0, take_off, step, |x, z| z-horizon.depth_and_slope(x).0)?;
+                    if down.x<x_min || down.x>x_max{
+                        return None;
+                    }
+
+                    //Reflect about the horizon normal
+                    let (_, slope)=horizon.depth_and_slope(down.x);
+                    let norm=(1.0+slope*slope).sqrt();
+                    let (nx, nz)=(-slope/norm, 1.0/norm);
+                    let (dx, dz)=(down.angle.sin(), down.angle.cos());
+                    let along_normal=dx*nx+dz*nz;
+                    let incidence=along_normal.abs().min(1.0).acos();
+                    let (rx, rz)=(dx-2.0*along_normal*nx, dz-2.0*along_normal*nz);
+
+                    //Start just above the interface so the up-going ray does not re-trigger on it
+                    let up=trace_ray(model, down.x+rx*1e-3, down.z+rz*1e-3, rx.atan2(rz), step, |_, z| -z)?;
+                    let offset_ok=geometry.max_offset.is_none_or(|max| (up.x-source).abs()<=max);
+                    if !offset_ok || geometry.nearest_receiver_distance(up.x)>tolerance{
+                        return None;
+                    }
+                    let bin=(((down.x-x_min)/self.bin_size) as usize).min(num_bins-1);
+                    Some((bin, incidence.to_degrees()))
+                })
+            }).collect();
+
+            let mut illumination=HorizonIllumination{
+                name: horizon.name.clone(),
+                bin_centres: (0..num_bins).map(|b| x_min+(b as f64+0.5)*self.bin_size).collect(),
+                hits: vec![0; num_bins],
+                min_angle_deg: vec![f64::NAN; num_bins],
+                max_angle_deg: vec![f64::NAN; num_bins],
+            };
+            for (bin, angle) in hits{
+                illumination.hits[bin]+=1;
+                illumination.min_angle_deg[bin]=illumination.min_angle_deg[bin].min(angle);
+                illumination.max_angle_deg[bin]=illumination.max_angle_deg[bin].max(angle);
+            }
+            Ok(illumination)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_flat_reflector_in_constant_velocity()-> Result<()>{
+        let model=VelocityModel::linear_gradient(401, 201, 10.0, 10.0, 2000.0, 0.0)?;
+        let geometry=SurveyGeometry::new(vec![2000.0], SurveyGeometry::line(1000.0, 25.0, 81))?;
+        let horizon=DepthHorizon::flat("target", 0.0, 4000.0, 1000.0)?;
+
+        let analysis=IlluminationAnalysis{ angle_step_deg: 0.25, bin_size: 100.0, ..Default::default() };
+        let maps=analysis.run(&model, &geometry, &[horizon])?;
+        let map=&maps[0];
+
+        //Reflection points sit at the midpoints, 1500-2500 m
+        for (centre, &hits) in map.bin_centres.iter().zip(&map.hits){
+            if hits>0{
+                assert!((1400.0..=2600.0).contains(centre), "hit at {}", centre);
+            }
+        }
+        assert!(map.hits.iter().sum::<usize>()>50);
+        let widest=map.max_angle_deg.iter().copied().filter(|a| !a.is_nan()).fold(0.0, f64::max);
+        assert_abs_diff_eq!(widest, (500.0_f64/1000.0).atan().to_degrees(), epsilon=1.0);
+        assert!(map.coverage()>0.2 && map.coverage()<0.4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ray_obeys_snell_in_gradient()-> Result<()>{
+        let model=VelocityModel::linear_gradient(301, 201, 10.0, 10.0, 1500.0, 0.8)?;
+        let take_off=20.0_f64.to_radians();
+        let hit=trace_ray(&model, 500.0, 0.0, take_off, 2.0, |_, z| z-1500.0).unwrap();
+
+        assert_abs_diff_eq!(hit.z, 1500.0, epsilon=1e-6);
+        //Ray parameter sin(a)/v is conserved in v(z)
+        let p=take_off.sin()/1500.0;
+        assert_abs_diff_eq!(hit.angle.sin()/(1500.0+0.8*1500.0), p, epsilon=1e-3*p);
+        //Vertical travel time through v0+kz is ln(1+kz/v0)/k; the slanted ray takes longer
+        assert!(hit.time>(1.0_f64+0.8*1500.0/1500.0).ln()/0.8);
+
+        assert!(DepthHorizon::new("bad", vec![1.0, 0.0], vec![1.0, 1.0]).is_err());
+        Ok(())
+    }
+}
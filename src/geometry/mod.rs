@@ -0,0 +1,76 @@
+//! Survey geometry: source and receiver layouts for survey design
+
+use anyhow::{Result, anyhow};
+
+pub mod array;
+pub mod illumination;
+
+///Sources and receivers on the surface of a 2D line, positions in metres
+#[derive(Debug, Clone)]
+pub struct SurveyGeometry{
+    pub sources: Vec<f64>,
+    pub receivers: Vec<f64>,
+    ///Largest source-receiver offset recorded; `None` records the whole spread
+    pub max_offset: Option<f64>,
+}
+
+impl SurveyGeometry{
+    pub fn new(sources: Vec<f64>, receivers: Vec<f64>)-> Result<Self>{
+        if sources.is_empty() || receivers.is_empty(){
+            return Err(anyhow!("Need at least one source and one receiver"));
+        }
+        if let Some(x)=sources.iter().chain(&receivers).find(|x| !x.is_finite()){
+            return Err(anyhow!("Source and receiver positions must be finite, got {}", x));
+        }
+        let mut receivers=receivers;
+        receivers.sort_by(f64::total_cmp);
+        Ok(Self{ sources, receivers, max_offset: None })
+    }
+
+    ///Evenly spaced positions `start, start+spacing, ...`
+    pub fn line(start: f64, spacing: f64, count: usize)-> Vec<f64>{
+        (0..count).map(|i| start+i as f64*spacing).collect()
+    }
+
+    ///Record only offsets up to `max_offset` metres
+    pub fn with_max_offset(mut self, max_offset: f64)-> Self{
+        self.max_offset=Some(max_offset);
+        self
+    }
+
+    ///Distance from `x` to the nearest receiver
+    pub fn nearest_receiver_distance(&self, x: f64)-> f64{
+        let index=self.receivers.partition_point(|&r| r<x);
+        [index.checked_sub(1), Some(index)].iter()
+            .filter_map(|i| i.and_then(|i| self.receivers.get(i)))
+            .map(|r| (r-x).abs())
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    ///Typical receiver spacing (median gap), zero for a single receiver
+    pub fn receiver_spacing(&self)-> f64{
+        let mut gaps: Vec<f64>=self.receivers.windows(2).map(|w| w[1]-w[0]).collect();
+        if gaps.is_empty(){
+            return 0.0;
+        }
+        gaps.sort_by(f64::total_cmp);
+        gaps[gaps.len()/2]
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_receivers_sorted_and_validated()-> Result<()>{
+        let geometry=SurveyGeometry::new(vec![0.0], vec![50.0, 0.0, 25.0, 100.0])?;
+        assert_eq!(geometry.receivers, vec![0.0, 25.0, 50.0, 100.0]);
+        assert_eq!(geometry.receiver_spacing(), 25.0);
+        assert_eq!(geometry.nearest_receiver_distance(60.0), 10.0);
+
+        assert!(SurveyGeometry::new(vec![0.0], vec![0.0, f64::NAN]).is_err());
+        assert!(SurveyGeometry::new(vec![f64::INFINITY], vec![0.0]).is_err());
+        Ok(())
+    }
+}
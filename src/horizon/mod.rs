@@ -0,0 +1,273 @@
+//! Interpreted horizons on 2D sections
+//!
+//! A horizon is one time pick per trace. Sections are indexed
+//! [trace][sample] with the first sample at t=0, as produced by the section
+//! generators in `models`.
+
+use anyhow::{Result, anyhow, Context};
+
+pub mod tracker;
+
+///Per-trace time picks for one surface; `None` where the horizon is not picked
+#[derive(Debug, Clone, PartialEq)]
+pub struct Horizon{
+    pub name: String,
+    ///Two-way time in seconds for each trace
+    pub times: Vec<Option<f64>>,
+}
+
+///Windowed amplitude attribute extracted along a horizon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HorizonAttribute{
+    ///Amplitude at the pick, linearly interpolated between samples
+    Amplitude,
+    ///RMS amplitude in a window of the given length (seconds) centred on the pick
+    Rms(f64),
+    ///Largest absolute amplitude in a centred window (seconds), keeping its sign
+    MaxAbsolute(f64),
+}
+
+impl Horizon{
+    pub fn new(name: &str, times: Vec<Option<f64>>)-> Self{
+        Self{ name: name.to_string(), times }
+    }
+
+    ///Horizon at a constant time across `num_traces` traces
+    pub fn flat(name: &str, num_traces: usize, time: f64)-> Self{
+        Self::new(name, vec![Some(time); num_traces])
+    }
+
+    pub fn len(&self)-> usize{
+        self.times.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.times.is_empty()
+    }
+
+    ///Number of traces with a pick
+    pub fn picked(&self)-> usize{
+        self.times.iter().filter(|t| t.is_some()).count()
+    }
+
+    ///RMS time difference to another horizon over traces picked on both
+    pub fn rms_difference(&self, other: &Horizon)-> Option<f64>{
+        let differences: Vec<f64>=self.times.iter().zip(&other.times)
+            .filter_map(|(a, b)| Some((*a)?-(*b)?))
+            .collect();
+        if differences.is_empty(){
+            return None;
+        }
+        Some((differences.iter().map(|d| d*d).sum::<f64>()/differences.len() as f64).sqrt())
+    }
+
+    ///Load picks from a CSV with a header row and columns `trace, time_ms`
+    ///
+    /// Traces absent from the file are left unpicked.
+    pub fn load_csv(path: &str, name: &str, num_traces: usize)-> Result<Self>{
+        let mut reader=csv::Reader::from_path(path).with_context(|| format!("Failed to open horizon file: {}", path))?;
+        let mut times=vec![None; num_traces];
+
+        for (line, record) in reader.records().enumerate(){
+            let record=record?;
+            if record.len()<2{
+                return Err(anyhow!("Row {} has fewer than two columns", line+1));
+            }
+            let trace: usize=record[0].trim().parse().with_context(|| format!("Invalid trace index in row {}", line+1))?;
+            let time_ms: f64=record[1].trim().parse().with_context(|| format!("Invalid time in row {}", line+1))?;
+            let slot=times.get_mut(trace)
+                .ok_or_else(|| anyhow!("Row {} refers to trace {} but the section has {}", line+1, trace, num_traces))?;
+            *slot=Some(time_ms/1000.0);
+        }
+
+        Ok(Self::new(name, times))
+    }
+
+    ///Write picked traces as CSV with columns `trace, time_ms`
+    pub fn to_csv(&self, path: &str)-> Result<()>{
+        let mut writer=csv::Writer::from_path(path).with_context(|| format!("Failed to create file: {}", path))?;
+        writer.write_record(["trace", "time_ms"])?;
+        for (trace, time) in self.times.iter().enumerate(){
+            if let Some(t)=time{
+                writer.write_record(&[trace.to_string(), (t*1000.0).to_string()])?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    ///Extract an attribute along the horizon; unpicked or out-of-range traces give `None`
+    pub fn extract(&self, section: &[Vec<f64>], dt: f64, attribute: HorizonAttribute)-> Result<Vec<Option<f64>>>{
+        check_section(self, section)?;
+
+        Ok(self.times.iter().zip(section).map(|(time, trace)| {
+            let t=(*time)?;
+            let position=t/dt;
+            if position<0.0 || position>(trace.len()-1) as f64{
+                return None;
+            }
+            match attribute{
+                HorizonAttribute::Amplitude=> Some(sample_at(trace, position)),
+                HorizonAttribute::Rms(window)=> {
+                    let values=window_samples(trace, t-window/2.0, t+window/2.0, dt);
+                    if values.is_empty() { return None; }
+                    Some((values.iter().map(|v| v*v).sum::<f64>()/values.len() as f64).sqrt())
+                }
+                HorizonAttribute::MaxAbsolute(window)=> {
+                    window_samples(trace, t-window/2.0, t+window/2.0, dt).into_iter()
+                        .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                }
+            }
+        }).collect())
+    }
+}
+
+///Interval between two horizons
+#[derive(Debug, Clone)]
+pub struct Interval<'a>{
+    pub top: &'a Horizon,
+    pub base: &'a Horizon,
+}
+
+impl<'a> Interval<'a>{
+    pub fn new(top: &'a Horizon, base: &'a Horizon)-> Result<Self>{
+        if top.len()!=base.len(){
+            return Err(anyhow!("Horizons {} ({}) and {} ({}) cover different numbers of traces", top.name, top.len(), base.name, base.len()));
+        }
+        Ok(Self{ top, base })
+    }
+
+    ///Time thickness per trace; `None` where either horizon is unpicked or they cross
+    pub fn isochron(&self)-> Vec<Option<f64>>{
+        self.top.times.iter().zip(&self.base.times).map(|(top, base)| {
+            match (top, base){
+                (Some(t), Some(b)) if b>=t=> Some(b-t),
+                _=> None,
+            }
+        }).collect()
+    }
+
+    ///Samples of each trace lying between the horizons (inclusive)
+    pub fn extract(&self, section: &[Vec<f64>], dt: f64)-> Result<Vec<Vec<f64>>>{
+        check_section(self.top, section)?;
+
+        Ok(self.top.times.iter().zip(&self.base.times).zip(section).map(|((top, base), trace)| {
+            match (top, base){
+                (Some(t), Some(b)) if b>=t=> window_samples(trace, *t, *b, dt),
+                _=> Vec::new(),
+            }
+        }).collect())
+    }
+
+    ///RMS amplitude of the interval on each trace
+    pub fn rms(&self, section: &[Vec<f64>], dt: f64)-> Result<Vec<Option<f64>>>{
+        Ok(self.extract(section, dt)?.iter().map(|values| {
+            if values.is_empty(){
+                None
+            } else {
+                Some((values.iter().map(|v| v*v).sum::<f64>()/values.len() as f64).sqrt())
+            }
+        }).collect())
+    }
+}
+
+fn check_section(horizon: &Horizon, section: &[Vec<f64>])-> Result<()>{
+    if horizon.len()!=section.len(){
+        return Err(anyhow!("Horizon {} has {} traces but the section has {}", horizon.name, horizon.len(), section.len()));
+    }
+    Ok(())
+}
+
+///Linear interpolation at a fractional sample position inside the trace
+fn sample_at(trace: &[f64], position: f64)-> f64{
+    let i=position.floor() as usize;
+    if i+1>=trace.len(){
+        return trace[trace.len()-1];
+    }
+    let w=position-i as f64;
+    trace[i]*(1.0-w)+trace[i+1]*w
+}
+
+///Samples whose times fall in [start, end], clipped to the trace
+fn window_samples(trace: &[f64], start: f64, end: f64, dt: f64)-> Vec<f64>{
+    //Small tolerance so picks on exact sample times are not lost to rounding
+    let first=(start/dt-1e-9).ceil().max(0.0) as usize;
+    let last=((end/dt+1e-9).floor().max(-1.0)+1.0) as usize;
+    let last=last.min(trace.len());
+    if first>=last{
+        return Vec::new();
+    }
+    trace[first..last].to_vec()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn ramp_section()-> Vec<Vec<f64>>{
+        //Amplitude equals the sample index, offset by the trace number
+        (0..4).map(|trace| (0..100).map(|i| (i+trace) as f64).collect()).collect()
+    }
+
+    #[test]
+    fn test_csv_round_trip()-> Result<()>{
+        let horizon=Horizon::new("top_reservoir", vec![Some(0.1), None, Some(0.1234), Some(0.2)]);
+        let path=std::env::temp_dir().join("horizon_round_trip.csv");
+        let path=path.to_str().unwrap();
+
+        horizon.to_csv(path)?;
+        let loaded=Horizon::load_csv(path, "top_reservoir", 4)?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(loaded.picked(), 3);
+        assert!(loaded.times[1].is_none());
+        assert_abs_diff_eq!(loaded.times[2].unwrap(), 0.1234, epsilon=1e-12);
+        assert!(Horizon::load_csv("/nonexistent/horizon.csv", "x", 4).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_attributes_along_horizon()-> Result<()>{
+        let section=ramp_section();
+        let dt=0.004;
+        let horizon=Horizon::new("h", vec![Some(0.1), Some(0.102), None, Some(1.0)]);
+
+        let amplitude=horizon.extract(&section, dt, HorizonAttribute::Amplitude)?;
+        assert_abs_diff_eq!(amplitude[0].unwrap(), 25.0, epsilon=1e-9);
+        assert_abs_diff_eq!(amplitude[1].unwrap(), 26.5, epsilon=1e-9);
+        assert!(amplitude[2].is_none());
+        assert!(amplitude[3].is_none());
+
+        let rms=horizon.extract(&section, dt, HorizonAttribute::Rms(0.008))?;
+        let expected=((24.0f64.powi(2)+25.0f64.powi(2)+26.0f64.powi(2))/3.0).sqrt();
+        assert_abs_diff_eq!(rms[0].unwrap(), expected, epsilon=1e-9);
+
+        let peak=horizon.extract(&section, dt, HorizonAttribute::MaxAbsolute(0.008))?;
+        assert_abs_diff_eq!(peak[0].unwrap(), 26.0, epsilon=1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interval_extraction()-> Result<()>{
+        let section=ramp_section();
+        let top=Horizon::flat("top", 4, 0.04);
+        let base=Horizon::new("base", vec![Some(0.06), Some(0.08), Some(0.02), None]);
+        let interval=Interval::new(&top, &base)?;
+
+        let isochron=interval.isochron();
+        assert_abs_diff_eq!(isochron[1].unwrap(), 0.04, epsilon=1e-12);
+        assert!(isochron[2].is_none());
+        assert!(isochron[3].is_none());
+
+        let samples=interval.extract(&section, 0.004)?;
+        assert_eq!(samples[0], vec![10.0, 11.0, 12.0, 13.0, 14.0, 15.0]);
+        assert!(samples[2].is_empty());
+
+        assert!(Interval::new(&top, &Horizon::flat("short", 2, 0.1)).is_err());
+
+        Ok(())
+    }
+}
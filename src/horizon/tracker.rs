@@ -0,0 +1,191 @@
+//! Peak/trough auto-tracking of horizons
+//!
+//! Tracking starts from a seed pick and walks outwards trace by trace. On
+//! each new trace every extremum of the chosen polarity inside the search
+//! window is a candidate; the one whose waveform correlates best with the
+//! previous pick is kept, provided the correlation passes the gate.
+//! Tracking in a direction stops at the first trace that fails the gate.
+
+use anyhow::{Result, anyhow};
+use super::Horizon;
+
+///Polarity of the event being tracked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventType{
+    Peak,
+    Trough,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackerOptions{
+    pub event: EventType,
+    ///Maximum change in pick between adjacent traces, in samples
+    pub search_window: usize,
+    ///Half-length of the waveform compared between traces, in samples
+    pub correlation_half_window: usize,
+    ///Minimum normalised correlation for a pick to be accepted
+    pub min_correlation: f64,
+}
+
+impl Default for TrackerOptions{
+    fn default()-> Self{
+        Self{
+            event: EventType::Peak,
+            search_window: 3,
+            correlation_half_window: 8,
+            min_correlation: 0.7,
+        }
+    }
+}
+
+///Track an event across `section` ([trace][sample]) from a seed pick
+///
+/// The seed snaps to the nearest extremum of the requested polarity within
+/// the search window. Picks are refined to sub-sample precision with a
+/// parabola through the extremum and its neighbours.
+pub fn track_horizon(
+    section: &[Vec<f64>],
+    dt: f64,
+    name: &str,
+    seed_trace: usize,
+    seed_time: f64,
+    options: &TrackerOptions,
+)-> Result<Horizon>{
+    let seed=section.get(seed_trace)
+        .ok_or_else(|| anyhow!("Seed trace {} is outside the section ({} traces)", seed_trace, section.len()))?;
+    let seed_sample=(seed_time/dt).round();
+    if seed_sample<0.0 || seed_sample as usize>=seed.len(){
+        return Err(anyhow!("Seed time {} s is outside the trace", seed_time));
+    }
+
+    let start=candidates(seed, seed_sample as usize, options.search_window, options.event).into_iter()
+        .min_by_key(|&c| c.abs_diff(seed_sample as usize))
+        .ok_or_else(|| anyhow!("No {:?} found near the seed at {} s", options.event, seed_time))?;
+
+    let mut picks: Vec<Option<usize>>=vec![None; section.len()];
+    picks[seed_trace]=Some(start);
+
+    let forward: Vec<usize>=(seed_trace+1..section.len()).collect();
+    let backward: Vec<usize>=(0..seed_trace).rev().collect();
+    for direction in [forward, backward]{
+        let mut previous=(seed_trace, start);
+        for trace in direction{
+            let best=candidates(&section[trace], previous.1, options.search_window, options.event).into_iter()
+                .map(|c| (c, window_correlation(&section[previous.0], previous.1, &section[trace], c, options.correlation_half_window)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            match best{
+                Some((sample, correlation)) if correlation>=options.min_correlation=> {
+                    picks[trace]=Some(sample);
+                    previous=(trace, sample);
+                }
+                _=> break,
+            }
+        }
+    }
+
+    let times=picks.iter().zip(section).map(|(pick, trace)| pick.map(|i| refine(trace, i)*dt)).collect();
+    Ok(Horizon::new(name, times))
+}
+
+///Local extrema of the requested polarity within `centre ± window`
+fn candidates(trace: &[f64], centre: usize, window: usize, event: EventType)-> Vec<usize>{
+    let sign=match event{
+        EventType::Peak=> 1.0,
+        EventType::Trough=> -1.0,
+    };
+    let lo=centre.saturating_sub(window).max(1);
+    let hi=(centre+window).min(trace.len().saturating_sub(2));
+
+    (lo..=hi).filter(|&i| {
+        let (a, b, c)=(sign*trace[i-1], sign*trace[i], sign*trace[i+1]);
+        b>0.0 && b>=a && b>c
+    }).collect()
+}
+
+///Normalised correlation of the waveforms around two picks
+fn window_correlation(trace_a: &[f64], centre_a: usize, trace_b: &[f64], centre_b: usize, half_window: usize)-> f64{
+    let (mut cross, mut energy_a, mut energy_b)=(0.0, 0.0, 0.0);
+    for offset in -(half_window as isize)..=half_window as isize{
+        let (i, j)=(centre_a as isize+offset, centre_b as isize+offset);
+        if i<0 || j<0 || i as usize>=trace_a.len() || j as usize>=trace_b.len(){
+            continue;
+        }
+        let (a, b)=(trace_a[i as usize], trace_b[j as usize]);
+        cross+=a*b;
+        energy_a+=a*a;
+        energy_b+=b*b;
+    }
+    if energy_a==0.0 || energy_b==0.0 { 0.0 } else { cross/(energy_a*energy_b).sqrt() }
+}
+
+///Sub-sample position of an extremum from a parabola through three samples
+fn refine(trace: &[f64], i: usize)-> f64{
+    if i==0 || i+1>=trace.len(){
+        return i as f64;
+    }
+    let (a, b, c)=(trace[i-1], trace[i], trace[i+1]);
+    let denominator=a-2.0*b+c;
+    if denominator==0.0{
+        return i as f64;
+    }
+    i as f64+(0.5*(a-c)/denominator).clamp(-0.5, 0.5)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::wavelets::RickerWavelet;
+
+    ///Dipping reflector at 0.2 s + 1 ms per trace, with a weaker flat reflector
+    ///that gets close to it at the far end
+    fn dipping_section(dt: f64)-> Result<(Vec<Vec<f64>>, Horizon)>{
+        let wavelet=RickerWavelet::new(25.0, dt, 80)?;
+        let centre=wavelet.samples.len()/2;
+        let truth: Vec<Option<f64>>=(0..40).map(|t| Some(0.2+0.001*t as f64)).collect();
+
+        let section=truth.iter().map(|time| {
+            let mut trace=vec![0.0; 250];
+            for (event, amplitude) in [(time.unwrap(), 1.0), (0.32, -0.4)]{
+                let position=event/dt;
+                for (k, &w) in wavelet.samples.iter().enumerate(){
+                    let i=(position+k as f64-centre as f64).round();
+                    if i>=0.0 && (i as usize)<trace.len(){
+                        trace[i as usize]+=amplitude*w;
+                    }
+                }
+            }
+            trace
+        }).collect();
+
+        Ok((section, Horizon::new("truth", truth)))
+    }
+
+    #[test]
+    fn test_tracks_dipping_peak()-> Result<()>{
+        let dt=0.002;
+        let (section, truth)=dipping_section(dt)?;
+
+        let picked=track_horizon(&section, dt, "picked", 20, 0.221, &TrackerOptions::default())?;
+        assert_eq!(picked.picked(), 40);
+        assert!(picked.rms_difference(&truth).unwrap()<dt);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_correlation_gate_stops_tracking()-> Result<()>{
+        let dt=0.002;
+        let (mut section, _)=dipping_section(dt)?;
+        //Blank trace 30 so there is nothing to pick there
+        section[30]=vec![0.0; 250];
+
+        let picked=track_horizon(&section, dt, "picked", 0, 0.2, &TrackerOptions::default())?;
+        assert_eq!(picked.picked(), 30);
+        assert!(picked.times[31].is_none());
+
+        assert!(track_horizon(&section, dt, "bad", 99, 0.2, &TrackerOptions::default()).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! Global layer inversion by simulated annealing
+//!
+//! The unknowns are the layers of a `ReflectivityModel`: where each
+//! interface sits and how strong it is. Misfit as a function of interface
+//! position is riddled with local minima a wavelet period apart, so gradient
+//! methods started more than half a period away lock onto the wrong cycle.
+//! Annealing instead proposes random moves, shifting one interface by a few
+//! samples or nudging one coefficient, and accepts a move that raises the
+//! misfit by `Δ` with probability `exp(-Δ/T)`. The temperature `T` starts
+//! high enough to climb out of those minima and is lowered geometrically
+//! until only improvements are accepted. A shifted interface takes the
+//! least-squares amplitude at its new position, so a move onto the right
+//! sample is not rejected for carrying the amplitude (or polarity) that
+//! fitted the wrong one. Each move touches at most two
+//! samples, so the misfit is updated over a wavelet's length instead of
+//! being recomputed.
+
+use anyhow::{Result, anyhow};
+use crate::models::ReflectivityModel;
+use crate::noise::{Rng, SeededRng};
+use crate::wavelets::RickerWavelet;
+use super::mcmc::{columns, Column};
+use super::sparse::wavelet_centre;
+
+///Settings for simulated annealing
+#[derive(Debug, Clone)]
+pub struct SimulatedAnnealing{
+    ///Proposed moves in total
+    pub iterations: usize,
+    ///Starting temperature as a fraction of the starting model's misfit
+    pub initial_temperature: f64,
+    ///Factor applied to the temperature after every move
+    pub cooling: f64,
+    ///Largest interface shift in samples
+    pub max_shift: usize,
+    ///Standard deviation of a coefficient change
+    pub coefficient_step: f64,
+    ///Coefficients are kept within ±this bound
+    pub max_coefficient: f64,
+    pub seed: u64,
+}
+
+impl Default for SimulatedAnnealing{
+    fn default()-> Self{
+        Self{
+            iterations: 20000,
+            initial_temperature: 0.3,
+            cooling: 0.9995,
+            max_shift: 5,
+            coefficient_step: 0.02,
+            max_coefficient: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+///Best model found by annealing
+#[derive(Debug, Clone)]
+pub struct AnnealingResult{
+    pub model: ReflectivityModel,
+    ///`||d - W r||^2 / ||d||^2` of the best model
+    pub misfit: f64,
+    ///Relative misfit of the current model every 100 moves
+    pub misfit_history: Vec<f64>,
+    pub acceptance_rate: f64,
+}
+
+impl AnnealingResult{
+    pub fn print_summary(&self){
+        println!("Simulated annealing: relative misfit {:.4e}, acceptance rate {:.1}%", self.misfit, 100.0*self.acceptance_rate);
+        for (position, coefficient) in self.model.layer_positions.iter().zip(&self.model.reflection_coefficients){
+            println!("  Layer at sample {:>5}: {:+.4}", position, coefficient);
+        }
+    }
+}
+
+///Add `delta` at one model sample to the residual `d - W r`, returning the change in its energy
+fn update(residual: &mut [f64], column: &Column, delta: f64)-> f64{
+    column.values.iter().enumerate().map(|(k, &w)| {
+        let r=&mut residual[column.start+k];
+        let before=*r*(*r);
+        *r-=delta*w;
+        *r*(*r)-before
+    }).sum()
+}
+
+impl SimulatedAnnealing{
+    fn validate(&self)-> Result<()>{
+        if self.iterations==0 || self.max_shift==0{
+            return Err(anyhow!("Need at least one iteration and a shift of at least one sample"));
+        }
+        if !(self.cooling>0.0 && self.cooling<=1.0){
+            return Err(anyhow!("Cooling factor must be in (0, 1], got {}", self.cooling));
+        }
+        if !(self.initial_temperature>0.0 && self.coefficient_step>0.0 && self.max_coefficient>0.0){
+            return Err(anyhow!("Temperature, coefficient step and coefficient bound must be positive"));
+        }
+        Ok(())
+    }
+
+    ///Fit the layers of `initial` to a trace sampled at `wavelet.dt` on the model's samples
+    pub fn invert(&self, trace: &[f64], wavelet: &RickerWavelet, initial: &ReflectivityModel)-> Result<AnnealingResult>{
+        self.invert_with_wavelet(trace, &wavelet.samples, wavelet_centre(wavelet), initial)
+    }
+
+    ///Fit with an arbitrary wavelet whose time zero is at sample `centre`
+    pub fn invert_with_wavelet(&self, trace: &[f64], wavelet: &[f64], centre: usize, initial: &ReflectivityModel)-> Result<AnnealingResult>{
+        self.validate()?;
+        let length=initial.length;
+        if trace.len()!=length{
+            return Err(anyhow!("Trace has {} samples for a {}-sample model", trace.len(), length));
+        }
+        if wavelet.is_empty() || centre>=wavelet.len(){
+            return Err(anyhow!("Wavelet centre {} is outside a {}-sample wavelet", centre, wavelet.len()));
+        }
+        if initial.layer_positions.is_empty() || initial.dropped_layers()>0{
+            return Err(anyhow!("Starting model needs at least one layer, all inside its {} samples", length));
+        }
+        let data_energy=trace.iter().map(|x| x*x).sum::<f64>();
+        if data_energy==0.0{
+            return Err(anyhow!("Cannot invert a trace with no energy"));
+        }
+
+        let columns=columns(wavelet, centre, length);
+        let mut positions=initial.layer_positions.clone();
+        let mut coefficients: Vec<f64>=initial.reflection_coefficients.iter().map(|c| c.clamp(-self.max_coefficient, self.max_coefficient)).collect();
+        let mut residual=trace.to_vec();
+        for (&p, &c) in positions.iter().zip(&coefficients){
+            update(&mut residual, &columns[p], c);
+        }
+        let mut misfit=residual.iter().map(|r| r*r).sum::<f64>();
+        let (mut best, mut best_misfit)=((positions.clone(), coefficients.clone()), misfit);
+
+        let mut rng=SeededRng::new(self.seed);
+        let mut temperature=self.initial_temperature*misfit;
+        let mut accepted=0;
+        let mut history=Vec::with_capacity(self.iterations/100+1);
+
+        for iteration in 0..self.iterations{
+            let layer=((rng.uniform()*positions.len() as f64) as usize).min(positions.len()-1);
+            let (position, coefficient)=(positions[layer], coefficients[layer]);
+
+            //Propose a move, apply it to the residual and remember how to undo it
+            let (change, moved)=if rng.uniform()<0.5{
+                let step=1+((rng.uniform()*self.max_shift as f64) as usize).min(self.max_shift-1);
+                let target=if rng.uniform()<0.5 { position.checked_sub(step) } else { Some(position+step) };
+                match target.filter(|&t| t<length && !positions.contains(&t)){
+                    Some(target)=> {
+                        let removed=update(&mut residual, &columns[position], -coefficient);
+                        let column=&columns[target];
+                        let (fit, energy)=column.values.iter().enumerate().fold((0.0, 0.0), |(fit, energy), (k, &w)| (fit+w*residual[column.start+k], energy+w*w));
+                        coefficients[layer]=if energy>0.0 { (fit/energy).clamp(-self.max_coefficient, self.max_coefficient) } else { coefficient };
+                        (removed+update(&mut residual, column, coefficients[layer]), Some(target))
+                    }
+                    //Off the model or onto another interface: rejected below, nothing to undo
+                    None=> (f64::INFINITY, None),
+                }
+            } else {
+                let proposed=(coefficient+self.coefficient_step*rng.normal()).clamp(-self.max_coefficient, self.max_coefficient);
+                coefficients[layer]=proposed;
+                (update(&mut residual, &columns[position], proposed-coefficient), None)
+            };
+
+            if change<=0.0 || rng.uniform()<(-change/temperature.max(f64::MIN_POSITIVE)).exp(){
+                misfit+=change;
+                accepted+=1;
+                if let Some(target)=moved{
+                    positions[layer]=target;
+                }
+                if misfit<best_misfit{
+                    best_misfit=misfit;
+                    best=(positions.clone(), coefficients.clone());
+                }
+            } else {
+                match moved{
+                    Some(target)=> {
+                        update(&mut residual, &columns[target], -coefficients[layer]);
+                        update(&mut residual, &columns[position], coefficient);
+                        coefficients[layer]=coefficient;
+                    }
+                    None=> {
+                        update(&mut residual, &columns[position], coefficient-coefficients[layer]);
+                        coefficients[layer]=coefficient;
+                    }
+                }
+            }
+
+            temperature*=self.cooling;
+            if iteration%100==0{
+                history.push(misfit/data_energy);
+            }
+        }
+
+        //Layers in depth order
+        let (mut positions, mut coefficients)=best;
+        let mut order: Vec<usize>=(0..positions.len()).collect();
+        order.sort_by_key(|&i| positions[i]);
+        (positions, coefficients)=(order.iter().map(|&i| positions[i]).collect(), order.iter().map(|&i| coefficients[i]).collect());
+
+        Ok(AnnealingResult{
+            model: ReflectivityModel::new(length, positions, coefficients).with_t0(initial.t0),
+            misfit: best_misfit.max(0.0)/data_energy,
+            misfit_history: history,
+            acceptance_rate: accepted as f64/self.iterations as f64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::operators::{ConvolutionOperator, LinearOperator};
+
+    #[test]
+    fn test_recovers_shifted_layers()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        let truth=ReflectivityModel::new(150, vec![30, 70, 110], vec![0.12, -0.08, 0.1]);
+        let trace=ConvolutionOperator::from_ricker(&wavelet, 150)?.apply(&truth.coefficients);
+
+        //Start several samples off on every interface with weak, wrong-sign or misplaced coefficients
+        let initial=ReflectivityModel::new(150, vec![36, 64, 115], vec![0.05, 0.02, 0.05]).with_t0(0.1);
+        let result=SimulatedAnnealing{ seed: 5, ..SimulatedAnnealing::default() }.invert(&trace, &wavelet, &initial)?;
+
+        assert_eq!(result.model.layer_positions, truth.layer_positions);
+        for (a, b) in result.model.reflection_coefficients.iter().zip(&truth.reflection_coefficients){
+            assert!((a-b).abs()<0.01, "{} vs {}", a, b);
+        }
+        assert!(result.misfit<1e-3, "{}", result.misfit);
+        assert_eq!(result.model.t0, 0.1);
+        assert_eq!(result.misfit_history.len(), 200);
+        assert!(result.misfit_history.last()<result.misfit_history.first());
+        assert!(result.acceptance_rate>0.0 && result.acceptance_rate<1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_bad_inputs()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        let model=ReflectivityModel::new(50, vec![10], vec![0.1]);
+        let trace=vec![0.1; 50];
+        let annealing=SimulatedAnnealing::default();
+        assert!(annealing.invert(&trace[..40], &wavelet, &model).is_err());
+        assert!(annealing.invert(&[0.0; 50], &wavelet, &model).is_err());
+        assert!(annealing.invert(&trace, &wavelet, &ReflectivityModel::new(50, vec![60], vec![0.1])).is_err());
+        assert!(SimulatedAnnealing{ cooling: 1.5, ..SimulatedAnnealing::default() }.invert(&trace, &wavelet, &model).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+//! Blind deconvolution by alternating wavelet and reflectivity estimation
+//!
+//! Each iteration inverts for sparse reflectivity with the current wavelet,
+//! then re-solves for the wavelet by damped least squares given that
+//! reflectivity. The trace only constrains the product of the two, so after
+//! every wavelet update the estimate is re-aligned to the initial wavelet
+//! (removing a bulk shift traded into the reflectivity), its polarity is
+//! matched to the initial wavelet and it is scaled to unit energy.
+
+use anyhow::{Result, anyhow};
+use crate::utils::linalg::solve_linear_system;
+use crate::wavelets::RickerWavelet;
+use super::sparse::{Dictionary, SparseInversion, wavelet_centre};
+
+///Settings for alternating blind deconvolution
+#[derive(Debug, Clone)]
+pub struct BlindDeconvolution{
+    pub max_iterations: usize,
+    ///Stop when the wavelet changes by less than this (unit-energy wavelets)
+    pub tolerance: f64,
+    ///Tikhonov damping of the wavelet update, relative to the reflectivity energy
+    pub damping: f64,
+    ///Largest bulk shift, in samples, removed when re-aligning the wavelet
+    pub max_shift: usize,
+    pub sparsity: SparseInversion,
+}
+
+impl Default for BlindDeconvolution{
+    fn default()-> Self{
+        Self{
+            max_iterations: 20,
+            tolerance: 1e-3,
+            damping: 1e-3,
+            max_shift: 10,
+            sparsity: SparseInversion{ lambda: 0.02, max_iterations: 500, tolerance: 1e-6, ..SparseInversion::default() },
+        }
+    }
+}
+
+///Diagnostics for one outer iteration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlindIteration{
+    ///Data misfit relative to the trace energy
+    pub relative_misfit: f64,
+    ///L2 change of the unit-energy wavelet
+    pub wavelet_change: f64,
+    ///Bulk shift in samples removed from the wavelet update
+    pub shift_removed: isize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlindResult{
+    ///Wavelet estimate on the time axis of the initial wavelet, unit energy
+    pub wavelet: Vec<f64>,
+    pub reflectivity: Vec<f64>,
+    pub history: Vec<BlindIteration>,
+    pub converged: bool,
+}
+
+impl BlindDeconvolution{
+    ///Jointly estimate wavelet and reflectivity starting from `initial`
+    pub fn run(&self, trace: &[f64], initial: &RickerWavelet)-> Result<BlindResult>{
+        if trace.len()<initial.samples.len(){
+            return Err(anyhow!("Trace ({} samples) is shorter than the wavelet ({})", trace.len(), initial.samples.len()));
+        }
+        let trace_energy: f64=trace.iter().map(|v| v*v).sum();
+        if trace_energy==0.0{
+            return Err(anyhow!("Cannot deconvolve a trace with no energy"));
+        }
+
+        let centre=wavelet_centre(initial);
+        let reference=unit_energy(&initial.samples)?;
+        let mut wavelet=reference.clone();
+        let mut reflectivity=vec![0.0; trace.len()];
+        let mut history=Vec::new();
+        let mut converged=false;
+
+        for _ in 0..self.max_iterations{
+            reflectivity=self.sparsity.invert_with_wavelet(trace, &wavelet, centre, &Dictionary::spikes())?.reflectivity;
+            if reflectivity.iter().all(|&r| r==0.0){
+                return Err(anyhow!("Sparse inversion returned no reflectivity; reduce the sparsity weight"));
+            }
+
+            let updated=self.update_wavelet(trace, &reflectivity, wavelet.len(), centre)?;
+            let (aligned, shift)=align_to(&updated, &reference, self.max_shift);
+            let aligned=unit_energy(&aligned)?;
+
+            let change=aligned.iter().zip(&wavelet).map(|(a, b)| (a-b).powi(2)).sum::<f64>().sqrt();
+            wavelet=aligned;
+
+            let predicted=convolve_centred(&reflectivity, &wavelet, centre);
+            let scale=least_squares_scale(&predicted, trace);
+            let misfit: f64=predicted.iter().zip(trace).map(|(p, d)| (scale*p-d).powi(2)).sum();
+            history.push(BlindIteration{ relative_misfit: misfit/trace_energy, wavelet_change: change, shift_removed: shift });
+
+            if change<self.tolerance{
+                converged=true;
+                break;
+            }
+        }
+
+        //Put the overall scale back into the reflectivity for the final wavelet
+        let final_reflectivity=self.sparsity.invert_with_wavelet(trace, &wavelet, centre, &Dictionary::spikes())?.reflectivity;
+        if final_reflectivity.iter().any(|&r| r!=0.0){
+            reflectivity=final_reflectivity;
+        }
+
+        Ok(BlindResult{ wavelet, reflectivity, history, converged })
+    }
+
+    ///Damped least-squares wavelet given reflectivity: (R^T R + mu I) w = R^T d
+    fn update_wavelet(&self, trace: &[f64], reflectivity: &[f64], length: usize, centre: usize)-> Result<Vec<f64>>{
+        //Column k of R is the reflectivity delayed by k-centre samples
+        let columns: Vec<Vec<f64>>=(0..length).map(|k| {
+            let mut unit=vec![0.0; length];
+            unit[k]=1.0;
+            convolve_centred(reflectivity, &unit, centre)
+        }).collect();
+
+        let energy: f64=reflectivity.iter().map(|r| r*r).sum();
+        let mu=self.damping*energy;
+        let normal: Vec<Vec<f64>>=(0..length).map(|i| {
+            (0..length).map(|j| {
+                let dot: f64=columns[i].iter().zip(&columns[j]).map(|(a, b)| a*b).sum();
+                if i==j { dot+mu } else { dot }
+            }).collect()
+        }).collect();
+        let rhs: Vec<f64>=columns.iter().map(|c| c.iter().zip(trace).map(|(a, b)| a*b).sum()).collect();
+
+        solve_linear_system(normal, rhs)
+    }
+}
+
+///Convolution trimmed to the length of `signal`, with wavelet time zero at `centre`
+pub fn convolve_centred(signal: &[f64], wavelet: &[f64], centre: usize)-> Vec<f64>{
+    let mut output=vec![0.0; signal.len()];
+    for (i, &s) in signal.iter().enumerate(){
+        if s==0.0{
+            continue;
+        }
+        for (k, &w) in wavelet.iter().enumerate(){
+            let j=i as isize+k as isize-centre as isize;
+            if j>=0 && (j as usize)<output.len(){
+                output[j as usize]+=s*w;
+            }
+        }
+    }
+    output
+}
+
+fn unit_energy(wavelet: &[f64])-> Result<Vec<f64>>{
+    let norm=wavelet.iter().map(|w| w*w).sum::<f64>().sqrt();
+    if norm==0.0{
+        return Err(anyhow!("Wavelet estimate collapsed to zero"));
+    }
+    Ok(wavelet.iter().map(|w| w/norm).collect())
+}
+
+///Shift `wavelet` to best match `reference` and fix its polarity; returns the shift removed
+fn align_to(wavelet: &[f64], reference: &[f64], max_shift: usize)-> (Vec<f64>, isize){
+    let n=wavelet.len() as isize;
+    let correlation=|lag: isize| -> f64 {
+        (0..n).filter(|&i| i+lag>=0 && i+lag<n).map(|i| reference[i as usize]*wavelet[(i+lag) as usize]).sum()
+    };
+    let lag=(-(max_shift as isize)..=max_shift as isize)
+        .max_by(|&a, &b| correlation(a).abs().total_cmp(&correlation(b).abs()))
+        .unwrap_or(0);
+    let sign=if correlation(lag)<0.0 { -1.0 } else { 1.0 };
+
+    let aligned=(0..n).map(|i| {
+        let j=i+lag;
+        if j>=0 && j<n { sign*wavelet[j as usize] } else { 0.0 }
+    }).collect();
+    (aligned, lag)
+}
+
+fn least_squares_scale(predicted: &[f64], observed: &[f64])-> f64{
+    let energy: f64=predicted.iter().map(|p| p*p).sum();
+    if energy==0.0 { 0.0 } else { predicted.iter().zip(observed).map(|(p, d)| p*d).sum::<f64>()/energy }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::noise::{Rng, SeededRng};
+
+    fn correlation(a: &[f64], b: &[f64])-> f64{
+        let dot: f64=a.iter().zip(b).map(|(x, y)| x*y).sum();
+        dot/(a.iter().map(|x| x*x).sum::<f64>()*b.iter().map(|y| y*y).sum::<f64>()).sqrt()
+    }
+
+    fn sparse_reflectivity(length: usize)-> Vec<f64>{
+        let mut rng=SeededRng::new(12);
+        let mut reflectivity=vec![0.0; length];
+        for i in (40..length-40).step_by(23){
+            reflectivity[i+(rng.uniform()*8.0) as usize]=if rng.uniform()<0.5 { 0.1 } else { -0.1 }*(0.5+rng.uniform());
+        }
+        reflectivity
+    }
+
+    #[test]
+    fn test_recovers_wavelet_from_wrong_start()-> Result<()>{
+        let truth=RickerWavelet::new(25.0, 0.002, 60)?;
+        let initial=RickerWavelet::new(40.0, 0.002, 60)?;
+        let reflectivity=sparse_reflectivity(400);
+        let trace=convolve_centred(&reflectivity, &truth.samples, wavelet_centre(&truth));
+
+        let result=BlindDeconvolution{ max_iterations: 8, ..BlindDeconvolution::default() }.run(&trace, &initial)?;
+        let before=correlation(&initial.samples, &truth.samples);
+        let after=correlation(&result.wavelet, &truth.samples);
+
+        assert!(after>0.95);
+        assert!(after>before);
+        let first=result.history.first().unwrap().relative_misfit;
+        let last=result.history.last().unwrap().relative_misfit;
+        assert!(last<=first);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alignment_removes_shift_and_polarity(){
+        let reference: Vec<f64>=(0..21).map(|i| (-((i as f64-10.0)/3.0).powi(2)).exp()).collect();
+        let mut shifted=vec![0.0; 21];
+        for i in 0..18{
+            shifted[i+3]= -reference[i];
+        }
+
+        let (aligned, lag)=align_to(&shifted, &reference, 5);
+        assert_eq!(lag, 3);
+        assert!(correlation(&aligned, &reference)>0.99);
+    }
+}
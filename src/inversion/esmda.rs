@@ -0,0 +1,180 @@
+//! Ensemble smoother with multiple data assimilation (ES-MDA)
+//!
+//! An ensemble of models is pushed through the forward model and updated with
+//! the Kalman-type step `m_j += C_md (C_dd + α C_D)⁻¹ (d_obs + √α e_j - g(m_j))`,
+//! where the covariances are estimated from the ensemble itself. Repeating the
+//! step with inflation factors `α_i` (with `Σ 1/α_i = 1`) handles mildly
+//! nonlinear models. No derivatives are needed, and the final ensemble is an
+//! approximate posterior sample summarised with the same percentile envelopes
+//! as the Monte Carlo inversion.
+
+use anyhow::{Result, anyhow};
+use rayon::prelude::*;
+use crate::gather::{Gather, Trace};
+use crate::noise::{Rng, SeededRng};
+use crate::optimization::cg::dot;
+use crate::utils::linalg::solve_linear_system;
+
+///Settings for ES-MDA
+#[derive(Debug, Clone)]
+pub struct EnsembleSmoother{
+    ///Inflation factor of each assimilation step; the reciprocals must sum to one
+    pub inflation: Vec<f64>,
+    ///Standard deviation of the data noise
+    pub noise_std: f64,
+    ///Seed for the data perturbations
+    pub seed: u64,
+}
+
+impl Default for EnsembleSmoother{
+    fn default()-> Self{
+        Self{
+            inflation: vec![4.0; 4],
+            noise_std: 0.01,
+            seed: 0,
+        }
+    }
+}
+
+///Updated ensemble with its envelopes and the misfit after each step
+#[derive(Debug, Clone)]
+pub struct EsmdaResult{
+    ///One model per ensemble member
+    pub ensemble: Gather,
+    pub mean: Trace,
+    pub p10: Trace,
+    pub p50: Trace,
+    pub p90: Trace,
+    ///Mean RMS data misfit of the members, before the first step and after each one
+    pub data_misfit: Vec<f64>,
+}
+
+impl EnsembleSmoother{
+    ///Assimilate `observed` into the `prior` ensemble through `forward`
+    pub fn run<F>(&self, forward: F, observed: &[f64], prior: &Gather)-> Result<EsmdaResult>
+    where F: Fn(&[f64])-> Result<Vec<f64>> + Sync{
+        let members=prior.len();
+        if members<2{
+            return Err(anyhow!("ES-MDA needs at least two ensemble members, got {}", members));
+        }
+        if self.noise_std<=0.0{
+            return Err(anyhow!("Noise standard deviation must be positive, got {}", self.noise_std));
+        }
+        if self.inflation.is_empty() || self.inflation.iter().any(|&a| a<=0.0){
+            return Err(anyhow!("Inflation factors must be positive"));
+        }
+        let reciprocal_sum: f64=self.inflation.iter().map(|a| 1.0/a).sum();
+        if (reciprocal_sum-1.0).abs()>1e-6{
+            return Err(anyhow!("Reciprocals of the inflation factors sum to {}, not 1", reciprocal_sum));
+        }
+        let dt=prior.dt().unwrap_or(1.0);
+
+        let mut models: Vec<Vec<f64>>=prior.traces.iter().map(|t| t.samples.clone()).collect();
+        let mut predictions=predict(&forward, &models, observed.len())?;
+        let mut data_misfit=vec![mean_rms_misfit(&predictions, observed)];
+        let mut rng=SeededRng::new(self.seed);
+
+        for &alpha in &self.inflation{
+            let perturbed: Vec<Vec<f64>>=(0..members).map(|_| {
+                observed.iter().map(|d| d+alpha.sqrt()*self.noise_std*rng.normal()).collect()
+            }).collect();
+
+            let model_anomalies=anomalies(&models);
+            let data_anomalies=anomalies(&predictions);
+            let c=alpha*self.noise_std*self.noise_std;
+
+            //Woodbury: (ΔDΔDᵀ/(N-1) + cI)⁻¹ through an N x N system instead of one the size of the data
+            let system: Vec<Vec<f64>>=(0..members).map(|k| (0..members).map(|l| {
+                let gram=dot(&data_anomalies[k], &data_anomalies[l]);
+                if k==l { gram+c*(members-1) as f64 } else { gram }
+            }).collect()).collect();
+
+            for ((model, prediction), target) in models.iter_mut().zip(&predictions).zip(&perturbed){
+                let innovation: Vec<f64>=target.iter().zip(prediction).map(|(t, p)| t-p).collect();
+                let projected: Vec<f64>=data_anomalies.iter().map(|a| dot(a, &innovation)).collect();
+                let z=solve_linear_system(system.clone(), projected)?;
+                let mut y=innovation;
+                for (a, zk) in data_anomalies.iter().zip(&z){
+                    y.iter_mut().zip(a).for_each(|(yi, ai)| *yi-=zk*ai);
+                }
+                for (anomaly, a) in model_anomalies.iter().zip(&data_anomalies){
+                    let weight=dot(a, &y)/(c*(members-1) as f64);
+                    model.iter_mut().zip(anomaly).for_each(|(m, dm)| *m+=weight*dm);
+                }
+            }
+
+            predictions=predict(&forward, &models, observed.len())?;
+            data_misfit.push(mean_rms_misfit(&predictions, observed));
+        }
+
+        let ensemble=Gather::new(models.into_iter().map(|m| Trace::new(m, dt).with_t0(prior.t0().unwrap_or(0.0))).collect())?;
+        Ok(EsmdaResult{
+            mean: ensemble.ensemble_stats(0.0)?.mean,
+            p10: ensemble.percentile(10.0)?,
+            p50: ensemble.percentile(50.0)?,
+            p90: ensemble.percentile(90.0)?,
+            ensemble,
+            data_misfit,
+        })
+    }
+}
+
+fn predict<F>(forward: &F, models: &[Vec<f64>], expected: usize)-> Result<Vec<Vec<f64>>>
+where F: Fn(&[f64])-> Result<Vec<f64>> + Sync{
+    let predictions=models.par_iter().map(|m| forward(m)).collect::<Result<Vec<_>>>()?;
+    if predictions.iter().any(|p| p.len()!=expected){
+        return Err(anyhow!("Forward model must return {} samples to match the observed data", expected));
+    }
+    Ok(predictions)
+}
+
+///Members minus the ensemble mean
+fn anomalies(members: &[Vec<f64>])-> Vec<Vec<f64>>{
+    let n=members.len() as f64;
+    let mean: Vec<f64>=(0..members[0].len()).map(|i| members.iter().map(|m| m[i]).sum::<f64>()/n).collect();
+    members.iter().map(|m| m.iter().zip(&mean).map(|(a, b)| a-b).collect()).collect()
+}
+
+fn mean_rms_misfit(predictions: &[Vec<f64>], observed: &[f64])-> f64{
+    predictions.iter().map(|p| {
+        (p.iter().zip(observed).map(|(a, b)| (a-b).powi(2)).sum::<f64>()/observed.len().max(1) as f64).sqrt()
+    }).sum::<f64>()/predictions.len() as f64
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::operators::{ConvolutionOperator, LinearOperator};
+    use crate::wavelets::RickerWavelet;
+
+    #[test]
+    fn test_esmda_fits_data_and_shrinks_spread()-> Result<()>{
+        let mut rng=SeededRng::new(81);
+        let wavelet=RickerWavelet::new(30.0, 0.002, 41)?;
+        let operator=ConvolutionOperator::from_ricker(&wavelet, 60)?;
+        let mut truth=vec![0.0; 60];
+        truth[20]=0.1;
+        truth[38]= -0.08;
+        let observed: Vec<f64>=operator.apply(&truth).iter().map(|d| d+0.002*rng.normal()).collect();
+
+        let prior=Gather::new((0..80).map(|_| Trace::new((0..60).map(|_| 0.05*rng.normal()).collect(), 0.002)).collect())?;
+        let prior_spread: f64=prior.ensemble_stats(0.0)?.std_dev.samples.iter().sum();
+
+        let smoother=EnsembleSmoother{ noise_std: 0.002, ..EnsembleSmoother::default() };
+        let result=smoother.run(|m| Ok(operator.apply(m)), &observed, &prior)?;
+
+        assert_eq!(result.data_misfit.len(), 5);
+        assert!(*result.data_misfit.last().unwrap()<0.2*result.data_misfit[0]);
+        let posterior_spread: f64=result.ensemble.ensemble_stats(0.0)?.std_dev.samples.iter().sum();
+        assert!(posterior_spread<prior_spread);
+        //The mean model predicts the data
+        let predicted=operator.apply(&result.mean.samples);
+        let error=predicted.iter().zip(&observed).map(|(a, b)| (a-b).powi(2)).sum::<f64>().sqrt();
+        let scale=observed.iter().map(|d| d*d).sum::<f64>().sqrt();
+        assert!(error<0.2*scale);
+
+        let bad=EnsembleSmoother{ inflation: vec![2.0], ..smoother };
+        assert!(bad.run(|m| Ok(operator.apply(m)), &observed, &prior).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+//! Joint wavelet and reflectivity inversion by alternating Gauss-Newton steps
+//!
+//! The trace `d = w * r` is bilinear: linear in the reflectivity for a fixed
+//! wavelet and linear in the wavelet for a fixed reflectivity. Each
+//! iteration therefore takes a damped Gauss-Newton step in one block with
+//! the other held fixed, which for a linear block is the damped
+//! least-squares solution, solved matrix-free by CGLS: first the
+//! reflectivity given the wavelet, then the wavelet given the
+//! reflectivity. Only the product is constrained, so after each wavelet
+//! step the pair is rescaled to keep the wavelet at the energy of the
+//! starting wavelet, and flipped if its polarity has reversed. The misfit
+//! is recorded after both half-steps so convergence can be studied.
+
+use anyhow::{Result, anyhow};
+use crate::operators::{ConvolutionOperator, LinearOperator};
+use crate::optimization::cg::{dot, norm, CgOptions};
+use crate::wavelets::RickerWavelet;
+use super::least_squares::LeastSquaresInversion;
+use super::sparse::wavelet_centre;
+
+///Settings for joint wavelet and reflectivity inversion
+#[derive(Debug, Clone)]
+pub struct JointInversion{
+    pub max_iterations: usize,
+    ///Stop when the wavelet changes by less than this fraction of its norm
+    pub tolerance: f64,
+    ///Damping of the reflectivity step, relative to the largest eigenvalue of `WᵀW`
+    pub reflectivity_damping: f64,
+    ///Damping of the wavelet step, relative to the largest eigenvalue of `RᵀR`
+    pub wavelet_damping: f64,
+    ///CGLS iterations and tolerance of each step
+    pub cg: CgOptions,
+}
+
+impl Default for JointInversion{
+    fn default()-> Self{
+        Self{
+            max_iterations: 20,
+            tolerance: 1e-4,
+            reflectivity_damping: 1e-3,
+            wavelet_damping: 1e-3,
+            cg: CgOptions{ max_iterations: 200, tolerance: 1e-8 },
+        }
+    }
+}
+
+///Relative misfits `||d - w*r||² / ||d||²` after each half of one iteration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointIteration{
+    pub after_reflectivity: f64,
+    pub after_wavelet: f64,
+    ///Change of the wavelet relative to its norm
+    pub wavelet_change: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct JointResult{
+    ///Wavelet on the time axis of the starting wavelet, with its energy
+    pub wavelet: Vec<f64>,
+    pub reflectivity: Vec<f64>,
+    ///Relative misfit of the starting wavelet with its best reflectivity is the first entry's `after_reflectivity`
+    pub history: Vec<JointIteration>,
+    pub converged: bool,
+}
+
+impl JointResult{
+    pub fn print_summary(&self){
+        println!("Joint wavelet/reflectivity inversion: {} iterations ({})", self.history.len(), if self.converged { "converged" } else { "not converged" });
+        for (i, step) in self.history.iter().enumerate(){
+            println!("  {:>3}: misfit {:.4e} (reflectivity) {:.4e} (wavelet), wavelet change {:.3e}", i+1, step.after_reflectivity, step.after_wavelet, step.wavelet_change);
+        }
+    }
+}
+
+///Convolution of a fixed reflectivity with the wavelet as the unknown
+///
+/// Wavelet sample `k` lands `k-centre` samples after each reflector, the
+/// same convention as `ConvolutionOperator`.
+#[derive(Debug, Clone)]
+pub struct ReflectivityOperator{
+    pub reflectivity: Vec<f64>,
+    pub centre: usize,
+    pub wavelet_length: usize,
+}
+
+impl LinearOperator for ReflectivityOperator{
+    fn shape(&self)-> (usize, usize){
+        (self.reflectivity.len(), self.wavelet_length)
+    }
+
+    fn apply(&self, wavelet: &[f64])-> Vec<f64>{
+        let n=self.reflectivity.len();
+        let mut output=vec![0.0; n];
+        for (i, &r) in self.reflectivity.iter().enumerate(){
+            if r==0.0{
+                continue;
+            }
+            for (k, &w) in wavelet.iter().enumerate().take(self.wavelet_length){
+                if let Some(j)=(i+k).checked_sub(self.centre).filter(|&j| j<n){
+                    output[j]+=r*w;
+                }
+            }
+        }
+        output
+    }
+
+    fn apply_adjoint(&self, trace: &[f64])-> Vec<f64>{
+        let n=self.reflectivity.len();
+        (0..self.wavelet_length).map(|k| {
+            self.reflectivity.iter().enumerate()
+                .filter_map(|(i, &r)| (i+k).checked_sub(self.centre).filter(|&j| j<n).map(|j| r*trace[j]))
+                .sum()
+        }).collect()
+    }
+}
+
+fn relative_misfit(trace: &[f64], predicted: &[f64], energy: f64)-> f64{
+    trace.iter().zip(predicted).map(|(d, p)| (d-p).powi(2)).sum::<f64>()/energy
+}
+
+impl JointInversion{
+    ///Estimate wavelet and reflectivity from a trace sampled at `initial.dt`, starting from `initial`
+    pub fn invert(&self, trace: &[f64], initial: &RickerWavelet)-> Result<JointResult>{
+        self.invert_with_wavelet(trace, &initial.samples, wavelet_centre(initial))
+    }
+
+    ///Start from an arbitrary wavelet whose time zero is at sample `centre`
+    pub fn invert_with_wavelet(&self, trace: &[f64], initial: &[f64], centre: usize)-> Result<JointResult>{
+        if initial.is_empty() || centre>=initial.len(){
+            return Err(anyhow!("Wavelet centre {} is outside a {}-sample wavelet", centre, initial.len()));
+        }
+        if trace.len()<initial.len(){
+            return Err(anyhow!("Trace ({} samples) is shorter than the wavelet ({})", trace.len(), initial.len()));
+        }
+        let energy=dot(trace, trace);
+        let wavelet_norm=norm(initial);
+        if energy==0.0 || wavelet_norm==0.0{
+            return Err(anyhow!("Cannot invert with a trace or starting wavelet that has no energy"));
+        }
+        if !(self.reflectivity_damping>=0.0 && self.wavelet_damping>=0.0){
+            return Err(anyhow!("Damping must not be negative"));
+        }
+
+        let solver=|damping: f64| LeastSquaresInversion{ damping, cg: self.cg };
+        let mut wavelet=initial.to_vec();
+        let mut reflectivity=vec![0.0; trace.len()];
+        let mut history=Vec::new();
+        let mut converged=false;
+
+        for _ in 0..self.max_iterations{
+            let step=solver(self.reflectivity_damping).invert_with_operator(trace, &ConvolutionOperator::new(&wavelet, centre, trace.len())?)?;
+            reflectivity=step.reflectivity;
+            let after_reflectivity=relative_misfit(trace, &step.predicted, energy);
+            if reflectivity.iter().all(|&r| r==0.0){
+                return Err(anyhow!("Reflectivity step returned zero; reduce the reflectivity damping"));
+            }
+
+            let operator=ReflectivityOperator{ reflectivity: reflectivity.clone(), centre, wavelet_length: wavelet.len() };
+            let step=solver(self.wavelet_damping).invert_with_operator(trace, &operator)?;
+            let size=norm(&step.reflectivity);
+            if size==0.0{
+                return Err(anyhow!("Wavelet step returned zero; reduce the wavelet damping"));
+            }
+            //Keep the starting wavelet's energy and polarity; the reflectivity takes the inverse scale
+            let scale=if dot(&step.reflectivity, initial)<0.0 { -wavelet_norm/size } else { wavelet_norm/size };
+            let updated: Vec<f64>=step.reflectivity.iter().map(|w| w*scale).collect();
+            reflectivity.iter_mut().for_each(|r| *r/=scale);
+            let after_wavelet=relative_misfit(trace, &step.predicted, energy);
+
+            let change=updated.iter().zip(&wavelet).map(|(a, b)| (a-b).powi(2)).sum::<f64>().sqrt()/wavelet_norm;
+            wavelet=updated;
+            history.push(JointIteration{ after_reflectivity, after_wavelet, wavelet_change: change });
+            if change<self.tolerance{
+                converged=true;
+                break;
+            }
+        }
+
+        Ok(JointResult{ wavelet, reflectivity, history, converged })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::noise::{Rng, SeededRng};
+    use crate::operators::adjoint_mismatch;
+
+    fn correlation(a: &[f64], b: &[f64])-> f64{
+        dot(a, b)/(norm(a)*norm(b))
+    }
+
+    #[test]
+    fn test_operator_adjoint()-> Result<()>{
+        let mut rng=SeededRng::new(2);
+        let reflectivity: Vec<f64>=(0..80).map(|_| rng.uniform()-0.5).collect();
+        let operator=ReflectivityOperator{ reflectivity, centre: 7, wavelet_length: 15 };
+        assert_eq!(operator.shape(), (80, 15));
+        assert!(adjoint_mismatch(&operator, &mut rng)<1e-12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_improves_wavelet_and_fit()-> Result<()>{
+        let truth=RickerWavelet::new(25.0, 0.002, 61)?;
+        let initial=RickerWavelet::new(35.0, 0.002, 61)?;
+        let mut rng=SeededRng::new(11);
+        let mut reflectivity=vec![0.0; 400];
+        for i in (40..360).step_by(17){
+            reflectivity[i+(rng.uniform()*6.0) as usize]=0.2*rng.normal();
+        }
+        let trace=ConvolutionOperator::from_ricker(&truth, 400)?.apply(&reflectivity);
+
+        let result=JointInversion{ max_iterations: 10, ..JointInversion::default() }.invert(&trace, &initial)?;
+        assert_eq!(result.history.len(), 10);
+        assert!(!result.converged);
+        let (first, last)=(result.history[0], result.history[9]);
+        assert!(last.after_wavelet<0.2*first.after_reflectivity, "{:?}", result.history);
+        assert!(last.wavelet_change<first.wavelet_change);
+        assert!(correlation(&result.wavelet, &truth.samples)>0.92);
+        assert!(correlation(&initial.samples, &truth.samples)<0.88);
+        assert!((norm(&result.wavelet)-norm(&initial.samples)).abs()<1e-9);
+
+        assert!(JointInversion::default().invert(&trace[..30], &initial).is_err());
+        assert!(JointInversion::default().invert(&[0.0; 400], &initial).is_err());
+        Ok(())
+    }
+}
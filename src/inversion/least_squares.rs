@@ -0,0 +1,169 @@
+//! Damped least-squares reflectivity inversion by conjugate gradients
+//!
+//! Solves `min ||d - W r||^2 + damping ||r||^2` with CGLS, conjugate gradients
+//! applied to the normal equations without forming them: each iteration
+//! needs one convolution with the wavelet and one correlation, so the
+//! operator is never stored as a matrix. Started from zero, the early
+//! iterates are smooth and band-limited, and stopping early regularises
+//! much like the damping does. The data residual after every iteration is
+//! returned to show how the solve converged.
+
+use anyhow::{Result, anyhow};
+use crate::forward_modelling::ForwardModellingResults;
+use crate::operators::{ConvolutionOperator, LinearOperator};
+use crate::optimization::cg::{dot, norm, CgOptions};
+use crate::wavelets::RickerWavelet;
+use super::sparse::wavelet_centre;
+
+///Settings for least-squares inversion
+#[derive(Debug, Clone)]
+pub struct LeastSquaresInversion{
+    ///Weight of the `||r||^2` penalty, relative to the largest eigenvalue of `W^T W`
+    pub damping: f64,
+    ///Iteration limit and tolerance on the normal-equation residual `||W^T(d - W r) - damping r|| / ||W^T d||`
+    pub cg: CgOptions,
+}
+
+impl Default for LeastSquaresInversion{
+    fn default()-> Self{
+        Self{
+            damping: 1e-4,
+            cg: CgOptions{ max_iterations: 200, tolerance: 1e-8 },
+        }
+    }
+}
+
+///Output of a least-squares inversion
+#[derive(Debug, Clone)]
+pub struct LeastSquaresResult{
+    pub reflectivity: Vec<f64>,
+    ///Modelled trace `W r`
+    pub predicted: Vec<f64>,
+    ///`||d - W r|| / ||d||` before the first and after every iteration
+    pub residual_history: Vec<f64>,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+impl LeastSquaresResult{
+    pub fn print_summary(&self){
+        println!("Least-squares inversion: {} iterations ({})", self.iterations, if self.converged { "converged" } else { "not converged" });
+        println!("  Relative data residual {:.4e} -> {:.4e}", self.residual_history[0], self.residual_history.last().copied().unwrap_or(f64::NAN));
+    }
+}
+
+impl LeastSquaresInversion{
+    ///Invert a trace sampled at `wavelet.dt` with the wavelet centred on its time zero
+    pub fn invert(&self, trace: &[f64], wavelet: &RickerWavelet)-> Result<LeastSquaresResult>{
+        self.invert_with_operator(trace, &ConvolutionOperator::from_ricker(wavelet, trace.len())?)
+    }
+
+    ///Invert the output of `SeismicPipeline::run_forward_modelling` for the reflectivity on the model's samples
+    ///
+    /// The pipeline returns the full convolution, which leads the model by the
+    /// wavelet's centre sample; the matching part of the trace is inverted.
+    pub fn invert_results(&self, results: &ForwardModellingResults, wavelet: &RickerWavelet)-> Result<LeastSquaresResult>{
+        let (centre, length)=(wavelet_centre(wavelet), results.reflectivity.len());
+        if centre+length>results.synthetic_trace.len(){
+            return Err(anyhow!("Trace of {} samples is too short for a {}-sample model", results.synthetic_trace.len(), length));
+        }
+        self.invert(&results.synthetic_trace[centre..centre+length], wavelet)
+    }
+
+    ///Invert with any square forward operator
+    pub fn invert_with_operator(&self, trace: &[f64], operator: &impl LinearOperator)-> Result<LeastSquaresResult>{
+        let (rows, columns)=operator.shape();
+        if trace.is_empty() || trace.len()!=rows{
+            return Err(anyhow!("Operator expects {} data samples, got {}", rows, trace.len()));
+        }
+        if self.damping.is_nan() || self.damping<0.0{
+            return Err(anyhow!("Damping must not be negative, got {}", self.damping));
+        }
+        let data_norm=norm(trace);
+        let mut reflectivity=vec![0.0; columns];
+        if data_norm==0.0{
+            return Ok(LeastSquaresResult{ reflectivity, predicted: vec![0.0; rows], residual_history: vec![0.0], iterations: 0, converged: true });
+        }
+        let damping=self.damping*crate::operators::normal_eigenvalue(operator, 30);
+
+        //CGLS: r data residual, s normal-equation residual
+        let mut residual=trace.to_vec();
+        let mut s=operator.apply_adjoint(&residual);
+        let s_initial=norm(&s);
+        let mut direction=s.clone();
+        let mut gamma=dot(&s, &s);
+        let mut history=vec![1.0];
+        let mut iterations=0;
+        let mut converged=s_initial==0.0;
+
+        while !converged && iterations<self.cg.max_iterations{
+            iterations+=1;
+            let q=operator.apply(&direction);
+            let curvature=dot(&q, &q)+damping*dot(&direction, &direction);
+            if curvature<=0.0{
+                break;
+            }
+            let alpha=gamma/curvature;
+            reflectivity.iter_mut().zip(&direction).for_each(|(x, p)| *x+=alpha*p);
+            residual.iter_mut().zip(&q).for_each(|(r, q)| *r-=alpha*q);
+            s=operator.apply_adjoint(&residual).iter().zip(&reflectivity).map(|(g, x)| g-damping*x).collect();
+
+            let gamma_next=dot(&s, &s);
+            direction.iter_mut().zip(&s).for_each(|(p, s)| *p=s+gamma_next/gamma*(*p));
+            gamma=gamma_next;
+            history.push(norm(&residual)/data_norm);
+            converged=gamma.sqrt()<=self.cg.tolerance*s_initial;
+        }
+
+        let predicted=trace.iter().zip(&residual).map(|(d, r)| d-r).collect();
+        Ok(LeastSquaresResult{ reflectivity, predicted, residual_history: history, iterations, converged })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::forward_modelling::SeismicPipeline;
+    use crate::models::ReflectivityModel;
+
+    #[test]
+    fn test_fits_pipeline_output()-> Result<()>{
+        let model=ReflectivityModel::new(200, vec![40, 90, 95, 150], vec![0.1, -0.08, 0.06, 0.12]);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 120)?;
+        let results=SeismicPipeline::new().run_forward_modelling(&model, &wavelet)?;
+
+        let result=LeastSquaresInversion{ damping: 1e-6, ..LeastSquaresInversion::default() }.invert_results(&results, &wavelet)?;
+        assert_eq!(result.reflectivity.len(), 200);
+        assert_eq!(result.residual_history.len(), result.iterations+1);
+        assert!(result.residual_history.last().unwrap()<&1e-3, "{:?}", result.residual_history.last());
+        //With damping the data residual may creep up slightly once the penalty starts to bite
+        let best=result.residual_history.iter().cloned().fold(f64::INFINITY, f64::min);
+        assert!(*result.residual_history.last().unwrap()<1.01*best);
+        //The isolated reflectors come back at the right sample and sign
+        for (position, sign) in [(40, 1.0), (150, 1.0)]{
+            let peak=(position-3..position+4).max_by(|&a, &b| (sign*result.reflectivity[a]).partial_cmp(&(sign*result.reflectivity[b])).unwrap()).unwrap();
+            assert_eq!(peak, position);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_damping_and_errors()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        let mut reflectivity=vec![0.0; 100];
+        reflectivity[50]=0.2;
+        let trace=ConvolutionOperator::from_ricker(&wavelet, 100)?.apply(&reflectivity);
+
+        let light=LeastSquaresInversion::default().invert(&trace, &wavelet)?;
+        let heavy=LeastSquaresInversion{ damping: 1.0, ..LeastSquaresInversion::default() }.invert(&trace, &wavelet)?;
+        assert!(light.converged);
+        assert!(norm(&heavy.reflectivity)<0.5*norm(&light.reflectivity));
+        assert!(heavy.residual_history.last()>light.residual_history.last());
+
+        let zero=LeastSquaresInversion::default().invert(&[0.0; 100], &wavelet)?;
+        assert!(zero.reflectivity.iter().all(|&r| r==0.0));
+        assert!(LeastSquaresInversion{ damping: -1.0, ..LeastSquaresInversion::default() }.invert(&trace, &wavelet).is_err());
+        assert!(LeastSquaresInversion::default().invert(&[], &wavelet).is_err());
+        Ok(())
+    }
+}
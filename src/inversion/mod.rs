@@ -0,0 +1,14 @@
+//! Inversion of seismic traces for reflectivity and impedance
+
+pub mod annealing;
+pub mod blind;
+pub mod esmda;
+pub mod joint;
+pub mod least_squares;
+pub mod mcmc;
+pub mod noise_covariance;
+pub mod rto;
+pub mod sparse;
+pub mod spectral;
+pub mod target_windows;
+pub mod uncertainty;
@@ -0,0 +1,156 @@
+//! Coloured-noise covariance estimation and whitening
+//!
+//! A plain least-squares misfit assumes white noise. When the noise is
+//! band-limited its samples are correlated and the misfit over-weights the
+//! frequencies where noise is strong. Modelling the noise as stationary, its
+//! covariance is Toeplitz and fully described by the autocovariance, which
+//! is estimated from a window of data containing noise only. An
+//! autoregressive fit to that autocovariance gives a prediction error filter
+//! `P` with `P^T P ~ C^-1`, so `||P(d - Gm)||^2` is the correctly weighted
+//! misfit.
+
+use anyhow::{Result, anyhow};
+use crate::utils::linalg::levinson_durbin;
+
+///Stationary noise described by its autocovariance
+#[derive(Debug, Clone)]
+pub struct NoiseCovariance{
+    ///Autocovariance at lags 0..=max_lag
+    pub autocovariance: Vec<f64>,
+}
+
+impl NoiseCovariance{
+    ///Estimate from a noise-only window with the biased autocovariance estimator
+    ///
+    /// The biased estimator is always positive semi-definite, which the
+    /// whitening filter relies on.
+    pub fn estimate(window: &[f64], max_lag: usize)-> Result<Self>{
+        if window.len()<=max_lag{
+            return Err(anyhow!("Noise window of {} samples is too short for {} lags", window.len(), max_lag));
+        }
+        let n=window.len();
+        let mean=window.iter().sum::<f64>()/n as f64;
+        let centred: Vec<f64>=window.iter().map(|v| v-mean).collect();
+
+        let autocovariance: Vec<f64>=(0..=max_lag).map(|lag| {
+            centred.iter().zip(&centred[lag..]).map(|(a, b)| a*b).sum::<f64>()/n as f64
+        }).collect();
+        if autocovariance[0]<=0.0{
+            return Err(anyhow!("Noise window has zero variance"));
+        }
+
+        Ok(Self{ autocovariance })
+    }
+
+    ///White noise of the given variance
+    pub fn white(variance: f64)-> Self{
+        Self{ autocovariance: vec![variance] }
+    }
+
+    pub fn variance(&self)-> f64{
+        self.autocovariance[0]
+    }
+
+    ///Dense `n x n` Toeplitz covariance matrix, zero beyond the estimated lags
+    pub fn matrix(&self, n: usize)-> Vec<Vec<f64>>{
+        (0..n).map(|i| {
+            (0..n).map(|j| self.autocovariance.get(i.abs_diff(j)).copied().unwrap_or(0.0)).collect()
+        }).collect()
+    }
+
+    ///Autoregressive whitening filter of the given order
+    pub fn whitening_filter(&self, order: usize)-> Result<WhiteningFilter>{
+        let order=order.min(self.autocovariance.len()-1);
+        let (coefficients, error)=levinson_durbin(&self.autocovariance, order)?;
+        Ok(WhiteningFilter{ coefficients, scale: 1.0/error.sqrt() })
+    }
+}
+
+///Causal prediction error filter normalised to unit output variance
+#[derive(Debug, Clone)]
+pub struct WhiteningFilter{
+    ///Filter taps, the first equal to 1
+    pub coefficients: Vec<f64>,
+    ///1/sigma of the prediction error
+    pub scale: f64,
+}
+
+impl WhiteningFilter{
+    ///Filter a trace, keeping its length
+    pub fn apply(&self, signal: &[f64])-> Vec<f64>{
+        (0..signal.len()).map(|n| {
+            self.scale*self.coefficients.iter().enumerate()
+                .take(n+1)
+                .map(|(k, a)| a*signal[n-k])
+                .sum::<f64>()
+        }).collect()
+    }
+
+    ///Adjoint (time-reversed) filter, needed for gradients through the misfit
+    pub fn apply_adjoint(&self, signal: &[f64])-> Vec<f64>{
+        let len=signal.len();
+        (0..len).map(|n| {
+            self.scale*self.coefficients.iter().enumerate()
+                .filter(|(k, _)| n+k<len)
+                .map(|(k, a)| a*signal[n+k])
+                .sum::<f64>()
+        }).collect()
+    }
+
+    ///Upper bound on the squared operator norm, used for solver step sizes
+    pub fn gain_bound(&self)-> f64{
+        (self.scale*self.coefficients.iter().map(|a| a.abs()).sum::<f64>()).powi(2)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::noise::{Rng, SeededRng};
+
+    fn ar1_noise(phi: f64, n: usize)-> Vec<f64>{
+        let mut rng=SeededRng::new(21);
+        let mut previous=0.0;
+        (0..n).map(|_| {
+            previous=phi*previous+rng.normal();
+            previous
+        }).collect()
+    }
+
+    #[test]
+    fn test_estimate_and_whiten_ar1()-> Result<()>{
+        let noise=ar1_noise(0.8, 20000);
+        let covariance=NoiseCovariance::estimate(&noise, 5)?;
+
+        //Lag-one correlation of AR(1) noise equals phi
+        assert_abs_diff_eq!(covariance.autocovariance[1]/covariance.variance(), 0.8, epsilon=0.02);
+
+        let filter=covariance.whitening_filter(2)?;
+        assert_abs_diff_eq!(filter.coefficients[1], -0.8, epsilon=0.02);
+
+        let whitened=filter.apply(&noise);
+        let check=NoiseCovariance::estimate(&whitened, 3)?;
+        assert_abs_diff_eq!(check.variance(), 1.0, epsilon=0.05);
+        assert!((check.autocovariance[1]/check.variance()).abs()<0.03);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_adjoint_and_matrix()-> Result<()>{
+        let filter=NoiseCovariance::estimate(&ar1_noise(0.5, 2000), 3)?.whitening_filter(3)?;
+        let x: Vec<f64>=(0..30).map(|i| (i as f64*0.7).sin()).collect();
+        let y: Vec<f64>=(0..30).map(|i| (i as f64*0.3).cos()).collect();
+
+        let lhs: f64=filter.apply(&x).iter().zip(&y).map(|(a, b)| a*b).sum();
+        let rhs: f64=x.iter().zip(filter.apply_adjoint(&y)).map(|(a, b)| a*b).sum();
+        assert_abs_diff_eq!(lhs, rhs, epsilon=1e-10);
+
+        let matrix=NoiseCovariance{ autocovariance: vec![2.0, 0.5] }.matrix(3);
+        assert_eq!(matrix, vec![vec![2.0, 0.5, 0.0], vec![0.5, 2.0, 0.5], vec![0.0, 0.5, 2.0]]);
+        assert!(NoiseCovariance::estimate(&[1.0; 10], 2).is_err());
+
+        Ok(())
+    }
+}
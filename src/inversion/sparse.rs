@@ -0,0 +1,535 @@
+//! Sparse reflectivity inversion by basis pursuit over a dictionary of atoms
+//!
+//! Plain sparse-spike inversion represents reflectivity with isolated
+//! spikes, which tends to merge the top and base of a bed below tuning into
+//! one spike. Adding thin-bed atoms (even and odd reflector pairs) lets a
+//! single coefficient describe a whole bed, so the L1 penalty no longer
+//! favours the merged answer.
+//!
+//! The problem `min 0.5*||d - W*A*c||^2 + lambda*||c||_1` is solved with
+//! FISTA (or plain ISTA), where `W` is convolution with the wavelet and `A`
+//! expands atom coefficients into reflectivity. The threshold can follow a
+//! continuation schedule: starting high keeps only the strongest atoms in
+//! early iterates, and lowering it geometrically to `lambda` lets weaker
+//! reflectors enter one by one, which converges faster and is less prone
+//! to splitting a thin bed between neighbouring atoms. With a robust misfit the data term is
+//! reweighted by IRLS and the weighted problem re-solved, warm-started from
+//! the previous coefficients. Per-sample data weights (see
+//! `target_windows`) scale the misfit of chosen zones on top of any IRLS
+//! weights.
+
+use anyhow::{Result, anyhow};
+use std::f64::consts::FRAC_1_SQRT_2;
+use crate::wavelets::RickerWavelet;
+use crate::operators::{normal_eigenvalue, ConvolutionOperator, LinearOperator};
+use crate::optimization::{IrlsOptions, Misfit, irls_weights};
+use super::noise_covariance::WhiteningFilter;
+
+///Reflectivity pattern that can be placed at any sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Atom{
+    ///Single reflector
+    Spike,
+    ///Two equal reflectors the given number of samples apart
+    EvenPair(usize),
+    ///Two opposite reflectors the given number of samples apart
+    OddPair(usize),
+}
+
+impl Atom{
+    ///Sample offsets and unit-norm weights of the pattern
+    pub fn pattern(&self)-> Vec<(usize, f64)>{
+        match *self{
+            Atom::Spike=> vec![(0, 1.0)],
+            Atom::EvenPair(n)=> vec![(0, FRAC_1_SQRT_2), (n, FRAC_1_SQRT_2)],
+            Atom::OddPair(n)=> vec![(0, FRAC_1_SQRT_2), (n, -FRAC_1_SQRT_2)],
+        }
+    }
+}
+
+///Set of atoms the reflectivity is decomposed into
+#[derive(Debug, Clone)]
+pub struct Dictionary{
+    pub atoms: Vec<Atom>,
+}
+
+impl Dictionary{
+    ///Spikes only: classic sparse-spike inversion
+    pub fn spikes()-> Self{
+        Self{ atoms: vec![Atom::Spike] }
+    }
+
+    ///Spikes plus even and odd pairs from 1 to `max_thickness` samples apart
+    pub fn thin_bed(max_thickness: usize)-> Self{
+        let mut atoms=vec![Atom::Spike];
+        for n in 1..=max_thickness{
+            atoms.push(Atom::EvenPair(n));
+            atoms.push(Atom::OddPair(n));
+        }
+        Self{ atoms }
+    }
+}
+
+///How the L1 threshold evolves over the iterations
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdSchedule{
+    ///`lambda` from the first iteration
+    Constant,
+    ///Start at `start` times the largest correlation of the data with the dictionary and multiply by `decay` each iteration until `lambda` is reached
+    Continuation{ start: f64, decay: f64 },
+}
+
+impl ThresholdSchedule{
+    fn validate(&self, lambda: f64)-> Result<()>{
+        if let ThresholdSchedule::Continuation{ start, decay }=*self{
+            if !(start>=lambda && start.is_finite()){
+                return Err(anyhow!("Continuation must start at or above the final sparsity weight {}, got {}", lambda, start));
+            }
+            if !(decay>0.0 && decay<1.0){
+                return Err(anyhow!("Continuation decay must be in (0, 1), got {}", decay));
+            }
+        }
+        Ok(())
+    }
+
+    ///Threshold at `iteration` given the final threshold and the largest data correlation
+    fn threshold(&self, iteration: usize, target: f64, scale: f64)-> f64{
+        match *self{
+            ThresholdSchedule::Constant=> target,
+            ThresholdSchedule::Continuation{ start, decay }=> (start*scale*decay.powi(iteration.min(i32::MAX as usize) as i32)).max(target),
+        }
+    }
+}
+
+///Solver settings for basis pursuit
+#[derive(Debug, Clone)]
+pub struct SparseInversion{
+    ///Weight of the L1 penalty, relative to the largest correlation of the data with the dictionary
+    pub lambda: f64,
+    ///How the threshold reaches `lambda`
+    pub schedule: ThresholdSchedule,
+    ///FISTA momentum; plain ISTA when false
+    pub accelerated: bool,
+    pub max_iterations: usize,
+    ///Stop when the relative change in coefficients falls below this
+    pub tolerance: f64,
+    ///Noise whitening applied to the residual; `None` assumes white noise
+    pub whitening: Option<WhiteningFilter>,
+    ///Data misfit; robust choices are solved by IRLS around the FISTA solver
+    pub misfit: Misfit,
+    pub irls: IrlsOptions,
+    ///Misfit weight per data sample, e.g. from target windows; `None` weights every sample equally
+    pub data_weights: Option<Vec<f64>>,
+}
+
+impl Default for SparseInversion{
+    fn default()-> Self{
+        Self{
+            lambda: 0.01,
+            schedule: ThresholdSchedule::Constant,
+            accelerated: true,
+            max_iterations: 2000,
+            tolerance: 1e-7,
+            whitening: None,
+            misfit: Misfit::L2,
+            irls: IrlsOptions::default(),
+            data_weights: None,
+        }
+    }
+}
+
+///Output of a sparse inversion
+#[derive(Debug, Clone)]
+pub struct SparseResult{
+    ///Reflectivity assembled from the atom coefficients
+    pub reflectivity: Vec<f64>,
+    ///Coefficients per atom, each indexed by sample
+    pub coefficients: Vec<Vec<f64>>,
+    ///Objective value after each iteration
+    pub objective_history: Vec<f64>,
+    pub iterations: usize,
+}
+
+impl SparseInversion{
+    ///Invert a trace sampled at `wavelet.dt` for sparse reflectivity
+    pub fn invert(&self, trace: &[f64], wavelet: &RickerWavelet, dictionary: &Dictionary)-> Result<SparseResult>{
+        self.invert_with_wavelet(trace, &wavelet.samples, wavelet_centre(wavelet), dictionary)
+    }
+
+    ///Invert with an arbitrary wavelet whose time zero is at sample `centre`
+    pub fn invert_with_wavelet(&self, trace: &[f64], wavelet: &[f64], centre: usize, dictionary: &Dictionary)-> Result<SparseResult>{
+        if wavelet.is_empty() || centre>=wavelet.len(){
+            return Err(anyhow!("Wavelet centre {} is outside a {}-sample wavelet", centre, wavelet.len()));
+        }
+        if trace.is_empty(){
+            return Err(anyhow!("Cannot invert an empty trace"));
+        }
+        if dictionary.atoms.is_empty(){
+            return Err(anyhow!("Dictionary has no atoms"));
+        }
+        if self.lambda<0.0{
+            return Err(anyhow!("Sparsity weight must be non-negative, got {}", self.lambda));
+        }
+
+        self.schedule.validate(self.lambda)?;
+        self.misfit.validate()?;
+        if let Some(w)=&self.data_weights{
+            if w.len()!=trace.len(){
+                return Err(anyhow!("Have {} data weights for a {}-sample trace", w.len(), trace.len()));
+            }
+            if w.iter().any(|v| !(v.is_finite() && *v>=0.0)){
+                return Err(anyhow!("Data weights must be finite and non-negative"));
+            }
+        }
+
+        let operator=DictionaryOperator::new(wavelet, centre, &dictionary.atoms, trace.len())?;
+        let gain=self.whitening.as_ref().map_or(1.0, |filter| filter.gain_bound());
+        let lipschitz=operator.lipschitz()*gain;
+        let scale=max_abs(&operator.apply_adjoint(&self.weighted(trace.to_vec(), self.data_weights.as_deref())));
+        let threshold=self.lambda*scale;
+
+        let mut weights: Option<Vec<f64>>=self.data_weights.clone();
+        let mut coefficients=vec![0.0; dictionary.atoms.len()*trace.len()];
+        let mut history=Vec::new();
+        let mut iterations=0;
+
+        //A single pass for L2; otherwise reweight and re-solve until the weights settle
+        let outer=if self.misfit.is_l2() { 1 } else { self.irls.max_iterations.max(1) };
+        for pass in 0..outer{
+            let max_weight=weights.as_ref().map_or(1.0, |w| w.iter().fold(0.0f64, |m, &v| m.max(v)));
+            //Warm-started IRLS passes are already close, so only the first follows the schedule
+            let schedule=if pass==0 { self.schedule } else { ThresholdSchedule::Constant };
+            let pass=self.fista(&operator, trace, weights.as_deref(), coefficients, (threshold, scale, schedule), lipschitz*max_weight);
+            coefficients=pass.0;
+            history.extend(pass.1);
+            iterations+=pass.2;
+
+            if self.misfit.is_l2(){
+                break;
+            }
+            let residual: Vec<f64>=operator.apply(&coefficients).iter().zip(trace).map(|(p, d)| p-d).collect();
+            let residual=match &self.whitening{
+                Some(filter)=> filter.apply(&residual),
+                None=> residual,
+            };
+            let mut updated=irls_weights(&residual, &self.misfit);
+            if let Some(data)=&self.data_weights{
+                updated.iter_mut().zip(data).for_each(|(w, d)| *w*=d);
+            }
+            let change=weights.as_ref().map_or(f64::INFINITY, |w| w.iter().zip(&updated).fold(0.0f64, |m, (a, b)| m.max((a-b).abs())));
+            weights=Some(updated);
+            if change<self.irls.tolerance{
+                break;
+            }
+        }
+
+        Ok(SparseResult{
+            reflectivity: operator.expand(&coefficients),
+            coefficients: coefficients.chunks(trace.len()).map(|c| c.to_vec()).collect(),
+            objective_history: history,
+            iterations,
+        })
+    }
+
+    ///Apply the misfit weighting `P^T W P` to a residual (P whitening, W IRLS weights)
+    fn weighted(&self, residual: Vec<f64>, weights: Option<&[f64]>)-> Vec<f64>{
+        let whitened=match &self.whitening{
+            Some(filter)=> filter.apply(&residual),
+            None=> residual,
+        };
+        let scaled: Vec<f64>=match weights{
+            Some(w)=> whitened.iter().zip(w).map(|(r, w)| r*w).collect(),
+            None=> whitened,
+        };
+        match &self.whitening{
+            Some(filter)=> filter.apply_adjoint(&scaled),
+            None=> scaled,
+        }
+    }
+
+    ///Weighted squared norm `r^T P^T W P r`
+    fn weighted_norm(&self, residual: Vec<f64>, weights: Option<&[f64]>)-> f64{
+        let whitened=match &self.whitening{
+            Some(filter)=> filter.apply(&residual),
+            None=> residual,
+        };
+        match weights{
+            Some(w)=> whitened.iter().zip(w).map(|(r, w)| w*r*r).sum(),
+            None=> whitened.iter().map(|r| r*r).sum(),
+        }
+    }
+
+    ///FISTA (or ISTA) iterations from `initial` with the final threshold, data correlation scale and schedule;
+    ///returns coefficients, objective history and iteration count
+    fn fista(
+        &self,
+        operator: &DictionaryOperator,
+        trace: &[f64],
+        weights: Option<&[f64]>,
+        initial: Vec<f64>,
+        (target, scale, schedule): (f64, f64, ThresholdSchedule),
+        lipschitz: f64,
+    )-> (Vec<f64>, Vec<f64>, usize){
+        let step=1.0/lipschitz;
+        let mut coefficients=initial;
+        let mut momentum=coefficients.clone();
+        let mut t=1.0f64;
+        let mut history=Vec::new();
+        let mut iterations=0;
+
+        for iteration in 0..self.max_iterations{
+            iterations+=1;
+            let threshold=schedule.threshold(iteration, target, scale);
+            let residual: Vec<f64>=operator.apply(&momentum).iter().zip(trace).map(|(p, d)| p-d).collect();
+            let gradient=operator.apply_adjoint(&self.weighted(residual, weights));
+
+            let updated: Vec<f64>=momentum.iter().zip(&gradient).map(|(c, g)| soft_threshold(c-step*g, step*threshold)).collect();
+            let t_next=if self.accelerated { 0.5*(1.0+(1.0+4.0*t*t).sqrt()) } else { 1.0 };
+            momentum=updated.iter().zip(&coefficients).map(|(u, c)| u+(t-1.0)/t_next*(u-c)).collect();
+
+            let change=updated.iter().zip(&coefficients).map(|(u, c)| (u-c).powi(2)).sum::<f64>().sqrt();
+            let norm=updated.iter().map(|u| u*u).sum::<f64>().sqrt();
+            coefficients=updated;
+            t=t_next;
+
+            let residual: Vec<f64>=operator.apply(&coefficients).iter().zip(trace).map(|(p, d)| p-d).collect();
+            history.push(0.5*self.weighted_norm(residual, weights)+threshold*coefficients.iter().map(|c| c.abs()).sum::<f64>());
+
+            //Convergence only counts once the threshold has settled
+            if threshold<=target && norm>0.0 && change/norm<self.tolerance{
+                break;
+            }
+        }
+
+        (coefficients, history, iterations)
+    }
+}
+
+///Wavelet convolution composed with atom expansion, and its adjoint
+struct DictionaryOperator<'a>{
+    convolution: ConvolutionOperator,
+    atoms: &'a [Atom],
+    length: usize,
+}
+
+impl<'a> DictionaryOperator<'a>{
+    fn new(wavelet: &[f64], centre: usize, atoms: &'a [Atom], length: usize)-> Result<Self>{
+        Ok(Self{ convolution: ConvolutionOperator::new(wavelet, centre, length)?, atoms, length })
+    }
+
+    ///Reflectivity from stacked atom coefficients
+    fn expand(&self, coefficients: &[f64])-> Vec<f64>{
+        let mut reflectivity=vec![0.0; self.length];
+        for (atom, c) in self.atoms.iter().zip(coefficients.chunks(self.length)){
+            for (offset, weight) in atom.pattern(){
+                for (i, &value) in c.iter().enumerate().take(self.length.saturating_sub(offset)){
+                    reflectivity[i+offset]+=weight*value;
+                }
+            }
+        }
+        reflectivity
+    }
+
+    ///Largest eigenvalue of G^T G by power iteration, padded slightly for safety
+    fn lipschitz(&self)-> f64{
+        1.05*normal_eigenvalue(self, 30).max(f64::EPSILON)
+    }
+}
+
+impl LinearOperator for DictionaryOperator<'_>{
+    fn shape(&self)-> (usize, usize){
+        (self.length, self.atoms.len()*self.length)
+    }
+
+    fn apply(&self, coefficients: &[f64])-> Vec<f64>{
+        self.convolution.apply(&self.expand(coefficients))
+    }
+
+    fn apply_adjoint(&self, trace: &[f64])-> Vec<f64>{
+        let correlated=self.convolution.apply_adjoint(trace);
+
+        let mut coefficients=Vec::with_capacity(self.atoms.len()*self.length);
+        for atom in self.atoms{
+            let pattern=atom.pattern();
+            coefficients.extend((0..self.length).map(|i| {
+                pattern.iter().filter(|(offset, _)| i+offset<self.length).map(|(offset, weight)| weight*correlated[i+offset]).sum::<f64>()
+            }));
+        }
+        coefficients
+    }
+}
+
+///Index of the wavelet sample closest to time zero
+pub fn wavelet_centre(wavelet: &RickerWavelet)-> usize{
+    wavelet.time.iter().enumerate()
+        .min_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+fn soft_threshold(x: f64, threshold: f64)-> f64{
+    x.signum()*(x.abs()-threshold).max(0.0)
+}
+
+fn max_abs(values: &[f64])-> f64{
+    values.iter().fold(0.0f64, |m, v| m.max(v.abs()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::noise::{Rng, SeededRng};
+    use crate::operators::adjoint_mismatch;
+
+    fn synthetic(wavelet: &RickerWavelet, reflectivity: &[f64])-> Vec<f64>{
+        let operator=DictionaryOperator::new(&wavelet.samples, wavelet_centre(wavelet), &[Atom::Spike], reflectivity.len()).unwrap();
+        operator.apply(reflectivity)
+    }
+
+    fn error(estimate: &[f64], truth: &[f64])-> f64{
+        estimate.iter().zip(truth).map(|(a, b)| (a-b).powi(2)).sum::<f64>().sqrt()
+    }
+
+    #[test]
+    fn test_adjoint_is_consistent(){
+        let wavelet=RickerWavelet::new(30.0, 0.002, 40).unwrap();
+        let atoms=Dictionary::thin_bed(3).atoms;
+        let operator=DictionaryOperator::new(&wavelet.samples, wavelet_centre(&wavelet), &atoms, 50).unwrap();
+
+        assert_eq!(operator.shape(), (50, atoms.len()*50));
+        assert!(adjoint_mismatch(&operator, &mut SeededRng::new(3))<1e-12);
+    }
+
+    #[test]
+    fn test_isolated_spikes_recovered()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        let mut truth=vec![0.0; 200];
+        truth[50]=0.2;
+        truth[130]= -0.15;
+
+        let result=SparseInversion::default().invert(&synthetic(&wavelet, &truth), &wavelet, &Dictionary::spikes())?;
+        assert_abs_diff_eq!(result.reflectivity[50], 0.2, epsilon=0.01);
+        assert_abs_diff_eq!(result.reflectivity[130], -0.15, epsilon=0.01);
+        assert!(result.objective_history.last().unwrap()<&result.objective_history[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dictionary_captures_thin_bed()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        //Even pair 4 samples (8 ms) apart, below the ~13 ms tuning thickness
+        let mut truth=vec![0.0; 200];
+        truth[100]=0.1;
+        truth[104]=0.1;
+        let trace=synthetic(&wavelet, &truth);
+
+        let dictionary=Dictionary::thin_bed(6);
+        let spikes=SparseInversion::default().invert(&trace, &wavelet, &Dictionary::spikes())?;
+        let thin_bed=SparseInversion::default().invert(&trace, &wavelet, &dictionary)?;
+
+        //The bed is carried by a single pair atom rather than a cluster of spikes
+        let significant=|result: &SparseResult| {
+            let all: Vec<f64>=result.coefficients.iter().flatten().copied().collect();
+            let largest=max_abs(&all);
+            all.iter().filter(|c| c.abs()>0.05*largest).count()
+        };
+        assert!(significant(&thin_bed)<significant(&spikes));
+
+        let (atom, position, _)=thin_bed.coefficients.iter().enumerate()
+            .flat_map(|(a, c)| c.iter().enumerate().map(move |(i, &v)| (a, i, v)))
+            .max_by(|x, y| x.2.abs().partial_cmp(&y.2.abs()).unwrap())
+            .unwrap();
+        assert_eq!(dictionary.atoms[atom], Atom::EvenPair(4));
+        assert_eq!(position, 100);
+        assert!(error(&thin_bed.reflectivity, &truth)<0.03);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_threshold_schedule_and_ista()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        //Odd pair 4 samples (8 ms) apart: top and base of a bed below tuning
+        let mut truth=vec![0.0; 200];
+        truth[90]=0.1;
+        truth[94]= -0.1;
+        let trace=synthetic(&wavelet, &truth);
+        let dictionary=Dictionary::thin_bed(6);
+
+        let solver=SparseInversion{ lambda: 0.002, ..SparseInversion::default() };
+        let constant=solver.invert(&trace, &wavelet, &dictionary)?;
+        let continuation=SparseInversion{ schedule: ThresholdSchedule::Continuation{ start: 0.5, decay: 0.9 }, ..solver.clone() }.invert(&trace, &wavelet, &dictionary)?;
+        let ista=SparseInversion{ accelerated: false, ..solver.clone() }.invert(&trace, &wavelet, &dictionary)?;
+
+        for result in [&constant, &continuation]{
+            assert!(error(&result.reflectivity, &truth)<0.03, "{}", error(&result.reflectivity, &truth));
+            assert!(result.reflectivity[90]>0.08 && result.reflectivity[94]< -0.08);
+        }
+        //Same budget, but without momentum ISTA is still smearing the pair over neighbouring samples
+        assert!(constant.objective_history.last()<ista.objective_history.last());
+        assert!(error(&constant.reflectivity, &truth)<error(&ista.reflectivity, &truth));
+
+        let bad=|schedule| SparseInversion{ schedule, ..SparseInversion::default() }.invert(&trace, &wavelet, &dictionary).is_err();
+        assert!(bad(ThresholdSchedule::Continuation{ start: 0.001, decay: 0.9 }));
+        assert!(bad(ThresholdSchedule::Continuation{ start: 0.5, decay: 1.0 }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_whitened_misfit_with_coloured_noise()-> Result<()>{
+        use crate::inversion::noise_covariance::NoiseCovariance;
+
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        let mut truth=vec![0.0; 300];
+        truth[80]=0.2;
+        truth[200]= -0.15;
+
+        //Strongly correlated AR(1) noise
+        let mut rng=SeededRng::new(5);
+        let mut previous=0.0;
+        let noise: Vec<f64>=(0..600).map(|_| { previous=0.9*previous+0.002*rng.normal(); previous }).collect();
+        let trace: Vec<f64>=synthetic(&wavelet, &truth).iter().zip(&noise).map(|(s, n)| s+n).collect();
+
+        let filter=NoiseCovariance::estimate(&noise[300..], 10)?.whitening_filter(4)?;
+        let solver=SparseInversion{ lambda: 0.05, whitening: Some(filter), ..SparseInversion::default() };
+        let result=solver.invert(&trace, &wavelet, &Dictionary::spikes())?;
+
+        assert_abs_diff_eq!(result.reflectivity[80], 0.2, epsilon=0.03);
+        assert_abs_diff_eq!(result.reflectivity[200], -0.15, epsilon=0.03);
+        assert!(result.objective_history.last().unwrap()<&result.objective_history[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_huber_misfit_resists_outliers()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 60)?;
+        let mut truth=vec![0.0; 300];
+        truth[80]=0.2;
+        truth[200]= -0.15;
+
+        let mut trace=synthetic(&wavelet, &truth);
+        let mut rng=SeededRng::new(17);
+        for value in trace.iter_mut(){
+            *value+=0.005*rng.normal();
+        }
+        //Erratic noise bursts, e.g. from bad receivers
+        for i in [40, 130, 150, 250]{
+            trace[i]+=0.3;
+        }
+
+        let solve=|misfit: Misfit| SparseInversion{ lambda: 0.02, misfit, ..SparseInversion::default() }.invert(&trace, &wavelet, &Dictionary::spikes());
+        let l2=solve(Misfit::L2)?;
+        let huber=solve(Misfit::Huber{ delta: 1.5 })?;
+        let student=solve(Misfit::StudentT{ nu: 3.0 })?;
+
+        assert!(error(&huber.reflectivity, &truth)<0.25*error(&l2.reflectivity, &truth));
+        assert!(error(&student.reflectivity, &truth)<0.25*error(&l2.reflectivity, &truth));
+        assert_abs_diff_eq!(huber.reflectivity[80], 0.2, epsilon=0.02);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Propagation of data noise into impedance uncertainty
+//!
+//! Each Monte Carlo noise realization from the forward pipeline is inverted
+//! independently and integrated to impedance. The spread of the resulting
+//! impedance traces at each sample shows how much of the property estimate
+//! the noise level leaves undetermined.
+
+use anyhow::{Result, anyhow};
+use crate::forward_modelling::SeismicPipeline;
+use crate::gather::{Gather, Trace};
+use crate::models::ReflectivityModel;
+use crate::models::impedance::integrate_reflectivity;
+use crate::wavelets::RickerWavelet;
+use super::sparse::{Dictionary, SparseInversion, wavelet_centre};
+
+///Inverted impedance realizations and their percentile envelopes
+#[derive(Debug, Clone)]
+pub struct ImpedanceUncertainty{
+    ///One impedance trace per noise realization
+    pub realizations: Gather,
+    pub p10: Trace,
+    pub p50: Trace,
+    pub p90: Trace,
+}
+
+impl ImpedanceUncertainty{
+    ///P90-P10 width of the envelope at each sample
+    pub fn spread(&self)-> Vec<f64>{
+        self.p90.samples.iter().zip(&self.p10.samples).map(|(high, low)| high-low).collect()
+    }
+}
+
+///Integrate reflectivity to impedance: `Z[i]=Z[i-1]*(1+r[i])/(1-r[i])`
+///
+/// The interface above sample i sits at position i, matching
+/// `ElasticModel::reflectivity`.
+pub fn reflectivity_to_impedance(reflectivity: &[f64], initial_impedance: f64)-> Vec<f64>{
+    integrate_reflectivity(reflectivity, initial_impedance)
+}
+
+impl SeismicPipeline{
+    ///Invert Monte Carlo noise realizations and summarise impedance as P10/P50/P90
+    ///
+    /// `initial_impedance` is the impedance above the first sample, normally
+    /// taken from a well or a low-frequency model.
+    pub fn run_monte_carlo_inversion(
+        &mut self,
+        reflectivity_model: &ReflectivityModel,
+        wavelet: &RickerWavelet,
+        num_realizations: usize,
+        inversion: &SparseInversion,
+        initial_impedance: f64,
+    )-> Result<ImpedanceUncertainty>{
+        if num_realizations==0{
+            return Err(anyhow!("Need at least one realization"));
+        }
+        if initial_impedance<=0.0{
+            return Err(anyhow!("Initial impedance must be positive, got {}", initial_impedance));
+        }
+
+        let dt=1.0/self.config().sample_rate;
+        let centre=wavelet_centre(wavelet);
+        let length=reflectivity_model.coefficients.len();
+        let realizations=self.run_monte_carlo(reflectivity_model, wavelet, num_realizations)?;
+
+        let mut traces=Vec::with_capacity(num_realizations);
+        for result in &realizations{
+            //The pipeline returns the full convolution; keep the part aligned with the model
+            let trace=&result.synthetic_trace[centre..centre+length];
+            let estimate=inversion.invert_with_wavelet(trace, &wavelet.samples, centre, &Dictionary::spikes())?;
+            traces.push(Trace::new(reflectivity_to_impedance(&estimate.reflectivity, initial_impedance), dt));
+        }
+
+        let realizations=Gather::new(traces)?;
+        Ok(ImpedanceUncertainty{
+            p10: realizations.percentile(10.0)?,
+            p50: realizations.percentile(50.0)?,
+            p90: realizations.percentile(90.0)?,
+            realizations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::forward_modelling::PipelineConfig;
+    use crate::noise::SeededRng;
+
+    #[test]
+    fn test_reflectivity_to_impedance(){
+        let impedance=reflectivity_to_impedance(&[0.0, 0.2, 0.0, -0.2], 1000.0);
+        assert_eq!(impedance[0], 1000.0);
+        assert_abs_diff_eq!(impedance[1], 1500.0, epsilon=1e-9);
+        assert_abs_diff_eq!(impedance[3], 1000.0, epsilon=1e-9);
+    }
+
+    #[test]
+    fn test_monte_carlo_impedance_envelope()-> Result<()>{
+        let config=PipelineConfig{ noise_level: 0.05, ..PipelineConfig::default() };
+        let mut pipeline=SeismicPipeline::with_config(config);
+        let model=ReflectivityModel::new(300, vec![100, 200], vec![0.1, -0.05]);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 100)?;
+        let inversion=SparseInversion{ lambda: 0.1, max_iterations: 300, ..SparseInversion::default() };
+
+        pipeline.set_rng(Box::new(SeededRng::new(4)));
+        let uncertainty=pipeline.run_monte_carlo_inversion(&model, &wavelet, 12, &inversion, 5000.0)?;
+        let truth=reflectivity_to_impedance(&model.coefficients, 5000.0);
+
+        assert_eq!(uncertainty.realizations.traces.len(), 12);
+        for i in 0..300{
+            assert!(uncertainty.p10.samples[i]<=uncertainty.p50.samples[i]);
+            assert!(uncertainty.p50.samples[i]<=uncertainty.p90.samples[i]);
+        }
+        assert_abs_diff_eq!(uncertainty.p50.samples[250], truth[250], epsilon=0.05*truth[250]);
+        //Uncertainty accumulates with depth as reflectivity errors integrate
+        assert!(uncertainty.spread()[250]>0.0);
+
+        assert!(pipeline.run_monte_carlo_inversion(&model, &wavelet, 0, &inversion, 5000.0).is_err());
+
+        Ok(())
+    }
+}
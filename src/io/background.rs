@@ -0,0 +1,110 @@
+//! Background trace writer so computation and file I/O overlap
+
+use anyhow::{Result, anyhow};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread::JoinHandle;
+use crate::utils::export_to_csv;
+
+///A trace queued for writing
+struct WriteJob{
+    path: String,
+    data: Vec<f64>,
+}
+
+///Writes traces to CSV on a dedicated thread fed by a bounded channel
+///
+/// The bound keeps memory flat when computation outruns the disk: `submit`
+/// blocks once `capacity` traces are waiting to be written.
+pub struct BackgroundWriter{
+    sender: Option<SyncSender<WriteJob>>,
+    handle: Option<JoinHandle<Result<usize>>>,
+}
+
+impl BackgroundWriter{
+    ///Spawn the writer thread with a queue of at most `capacity` pending traces
+    pub fn new(capacity: usize)-> Self{
+        let (sender, receiver)=sync_channel::<WriteJob>(capacity.max(1));
+
+        let handle=std::thread::spawn(move || {
+            let mut written=0;
+            for job in receiver{
+                export_to_csv(&job.data, &job.path)?;
+                written+=1;
+            }
+            Ok(written)
+        });
+
+        Self{
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+
+    ///Queue a trace for writing, blocking while the queue is full
+    pub fn submit(&mut self, path: String, data: Vec<f64>)-> Result<()>{
+        let sender=self.sender.as_ref().ok_or_else(|| anyhow!("Background writer already finished"))?;
+
+        if sender.send(WriteJob{ path, data }).is_err(){
+            //The thread only hangs up after a failed write; surface that error
+            return Err(self.join().err().unwrap_or_else(|| anyhow!("Background writer stopped unexpectedly")));
+        }
+
+        Ok(())
+    }
+
+    ///Wait for all queued traces to be written and return how many were written
+    pub fn finish(mut self)-> Result<usize>{
+        self.join()
+    }
+
+    fn join(&mut self)-> Result<usize>{
+        self.sender.take();
+
+        match self.handle.take(){
+            Some(handle)=> handle.join().map_err(|_| anyhow!("Background writer thread panicked"))?,
+            None=> Err(anyhow!("Background writer already finished")),
+        }
+    }
+}
+
+impl Drop for BackgroundWriter{
+    fn drop(&mut self){
+        if self.handle.is_some(){
+            let _=self.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_background_writes_all_traces()-> Result<()>{
+        let dir=std::env::temp_dir().join("seismic_background_writer_test");
+        std::fs::create_dir_all(&dir)?;
+
+        let mut writer=BackgroundWriter::new(2);
+        for i in 0..10{
+            let path=dir.join(format!("trace_{}.csv", i));
+            writer.submit(path.to_str().unwrap().to_string(), vec![i as f64; 8])?;
+        }
+        assert_eq!(writer.finish()?, 10);
+
+        let contents=std::fs::read_to_string(dir.join("trace_9.csv"))?;
+        assert_eq!(contents.lines().count(), 9);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_error_is_reported()-> Result<()>{
+        let mut writer=BackgroundWriter::new(1);
+
+        //The queue is empty, so the job is accepted; the write itself fails on the thread
+        writer.submit("/nonexistent_dir/trace.csv".to_string(), vec![1.0])?;
+        assert!(writer.finish().is_err());
+        Ok(())
+    }
+}
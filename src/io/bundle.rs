@@ -0,0 +1,191 @@
+//! Reproducibility bundles: one zip holding everything needed to re-run an experiment
+//!
+//! A bundle collects the configuration, random seed, inputs, outputs and QC
+//! plots of a run, plus `manifest.json` listing every file with its size and
+//! SHA-256 hash. Re-running with the recorded command and seed should
+//! reproduce the outputs bit-for-bit, which the hashes make easy to check.
+
+use anyhow::{Result, Context, anyhow};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+///One file recorded in the manifest
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ManifestEntry{
+    pub name: String,
+    pub bytes: usize,
+    pub sha256: String,
+}
+
+///Description of a bundle, written as `manifest.json` inside it
+#[derive(Debug, Clone, Serialize)]
+pub struct Manifest{
+    pub tool: String,
+    pub version: String,
+    pub created_unix: u64,
+    ///Seed the random number generator was initialised with
+    pub seed: u64,
+    ///Command-line arguments that reproduce the run
+    pub command: Vec<String>,
+    pub files: Vec<ManifestEntry>,
+}
+
+///Files collected for a bundle before it is written
+pub struct RunBundle{
+    seed: u64,
+    command: Vec<String>,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl RunBundle{
+    pub fn new(seed: u64, command: Vec<String>)-> Self{
+        Self{ seed, command, files: Vec::new() }
+    }
+
+    ///Add raw bytes under a path inside the bundle
+    pub fn add_bytes(&mut self, name: &str, bytes: Vec<u8>)-> Result<()>{
+        if name=="manifest.json" || self.files.iter().any(|(existing, _)| existing==name){
+            return Err(anyhow!("Bundle already contains '{}'", name));
+        }
+        self.files.push((name.to_string(), bytes));
+        Ok(())
+    }
+
+    ///Add a value serialised as pretty-printed JSON
+    pub fn add_json<T: Serialize>(&mut self, name: &str, value: &T)-> Result<()>{
+        let bytes=serde_json::to_vec_pretty(value).with_context(|| format!("Failed to serialise {}", name))?;
+        self.add_bytes(name, bytes)
+    }
+
+    ///Add a trace in the same `sample,amplitude` CSV layout as `export_to_csv`
+    pub fn add_trace_csv(&mut self, name: &str, data: &[f64])-> Result<()>{
+        let mut writer=csv::Writer::from_writer(Vec::new());
+        writer.write_record(["sample", "amplitude"])?;
+        for (i, value) in data.iter().enumerate(){
+            writer.write_record(&[i.to_string(), value.to_string()])?;
+        }
+        let bytes=writer.into_inner().map_err(|e| anyhow!("Failed to write {}: {}", name, e))?;
+        self.add_bytes(name, bytes)
+    }
+
+    ///Manifest for the files added so far
+    pub fn manifest(&self)-> Manifest{
+        Manifest{
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created_unix: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            seed: self.seed,
+            command: self.command.clone(),
+            files: self.files.iter().map(|(name, bytes)| ManifestEntry{
+                name: name.clone(),
+                bytes: bytes.len(),
+                sha256: sha256_hex(bytes),
+            }).collect(),
+        }
+    }
+
+    ///Write the zip, with the manifest first, and return the manifest
+    pub fn write(&self, path: &str)-> Result<Manifest>{
+        let manifest=self.manifest();
+        let file=File::create(path).with_context(|| format!("Failed to create file: {}", path))?;
+        let mut zip=ZipWriter::new(file);
+        let options=SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file("manifest.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+        for (name, bytes) in &self.files{
+            zip.start_file(name.as_str(), options)?;
+            zip.write_all(bytes)?;
+        }
+        zip.finish()?;
+
+        Ok(manifest)
+    }
+}
+
+///Read one file back out of a bundle, checking it against the manifest hash
+///
+/// Files the manifest does not list are rejected, since they cannot be verified.
+pub fn read_entry(path: &str, name: &str)-> Result<Vec<u8>>{
+    let file=File::open(path).with_context(|| format!("Failed to open file: {}", path))?;
+    let mut archive=ZipArchive::new(file).with_context(|| format!("{} is not a bundle", path))?;
+
+    let mut read=|entry: &str| -> Result<Vec<u8>> {
+        let mut bytes=Vec::new();
+        archive.by_name(entry).with_context(|| format!("{} has no '{}'", path, entry))?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    };
+    let manifest: serde_json::Value=serde_json::from_slice(&read("manifest.json")?)?;
+    let bytes=read(name)?;
+
+    let expected=manifest["files"].as_array().and_then(|files| {
+        files.iter().find(|f| f["name"]==name).and_then(|f| f["sha256"].as_str())
+    }).ok_or_else(|| anyhow!("Manifest of {} has no hash for '{}'", path, name))?;
+    if expected!=sha256_hex(&bytes){
+        return Err(anyhow!("Hash of '{}' in {} does not match its manifest", name, path));
+    }
+    Ok(bytes)
+}
+
+///Lower-case hex SHA-256 of some bytes
+pub fn sha256_hex(bytes: &[u8])-> String{
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_sha256_known_value(){
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn test_bundle_round_trip()-> Result<()>{
+        let mut bundle=RunBundle::new(42, vec!["--seed".to_string(), "42".to_string()]);
+        bundle.add_json("config.json", &serde_json::json!({ "noise_level": 0.01 }))?;
+        bundle.add_trace_csv("outputs/trace.csv", &[0.0, 0.5, -0.25])?;
+        assert!(bundle.add_bytes("outputs/trace.csv", vec![]).is_err());
+
+        let path=std::env::temp_dir().join("bundle_test.zip");
+        let path=path.to_str().unwrap();
+        let manifest=bundle.write(path)?;
+
+        assert_eq!(manifest.seed, 42);
+        assert_eq!(manifest.files.len(), 2);
+
+        assert_eq!(ZipArchive::new(File::open(path)?)?.len(), 3);
+        let contents=read_entry(path, "outputs/trace.csv")?;
+        assert_eq!(contents, b"sample,amplitude\n0,0\n1,0.5\n2,-0.25\n");
+        assert_eq!(manifest.files[1].sha256, sha256_hex(&contents));
+        assert!(read_entry(path, "missing.csv").is_err());
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unlisted_entry_is_rejected()-> Result<()>{
+        let path=std::env::temp_dir().join("bundle_unlisted_test.zip");
+        let path=path.to_str().unwrap();
+
+        //A zip holding a file that was never added to the manifest
+        let mut zip=ZipWriter::new(File::create(path)?);
+        zip.start_file("manifest.json", SimpleFileOptions::default())?;
+        zip.write_all(&serde_json::to_vec_pretty(&RunBundle::new(1, vec![]).manifest())?)?;
+        zip.start_file("outputs/extra.csv", SimpleFileOptions::default())?;
+        zip.write_all(b"sample,amplitude\n0,1\n")?;
+        zip.finish()?;
+
+        assert!(read_entry(path, "outputs/extra.csv").is_err());
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+//! Computed columns for trace exports
+//!
+//! Derived axes that would otherwise need a post-processing script (two-way
+//! time, depth through a velocity function, offset, incidence angle) are
+//! declared in the run configuration and computed per sample when a trace is
+//! written.
+
+use anyhow::{Result, Context};
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+
+///Velocity increasing linearly with depth, `v(z)=v0+gradient*z`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VelocityFunction{
+    ///Velocity at zero depth in m/s
+    pub v0: f64,
+    ///Increase in velocity per metre, 1/s
+    #[serde(default)]
+    pub gradient: f64,
+}
+
+impl VelocityFunction{
+    ///Depth in metres reached at two-way time `twt` seconds
+    pub fn depth_at(&self, twt: f64)-> f64{
+        let one_way=0.5*twt;
+        if self.gradient.abs()<1e-12{
+            self.v0*one_way
+        }else{
+            self.v0/self.gradient*((self.gradient*one_way).exp()-1.0)
+        }
+    }
+
+    pub fn velocity_at(&self, depth: f64)-> f64{
+        self.v0+self.gradient*depth
+    }
+}
+
+///A derived column, declared in the export configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag="kind", rename_all="snake_case")]
+pub enum ComputedColumn{
+    ///Two-way time of each sample in seconds
+    TwoWayTime,
+    ///Depth in metres of each sample through a velocity function
+    Depth{ velocity: VelocityFunction },
+    ///Source-receiver offset of the trace in metres, repeated on every row
+    Offset{ offset: f64 },
+    ///Straight-ray incidence angle in degrees at each sample's depth for a given offset
+    IncidenceAngle{ offset: f64, velocity: VelocityFunction },
+}
+
+impl ComputedColumn{
+    ///Header used for the column
+    pub fn name(&self)-> &'static str{
+        match self{
+            ComputedColumn::TwoWayTime=> "twt",
+            ComputedColumn::Depth{ .. }=> "depth",
+            ComputedColumn::Offset{ .. }=> "offset",
+            ComputedColumn::IncidenceAngle{ .. }=> "incidence_angle",
+        }
+    }
+
+    ///Values for `len` samples starting at `t0` seconds, `dt` apart
+    ///
+    /// Samples at or above zero depth have no defined incidence angle and get NaN.
+    pub fn compute(&self, len: usize, dt: f64, t0: f64)-> Vec<f64>{
+        let twt=(0..len).map(move |i| t0+i as f64*dt);
+        match self{
+            ComputedColumn::TwoWayTime=> twt.collect(),
+            ComputedColumn::Depth{ velocity }=> twt.map(|t| velocity.depth_at(t)).collect(),
+            ComputedColumn::Offset{ offset }=> vec![*offset; len],
+            ComputedColumn::IncidenceAngle{ offset, velocity }=> twt.map(|t| {
+                let depth=velocity.depth_at(t);
+                if depth>0.0 { (0.5*offset/depth).atan().to_degrees() } else { f64::NAN }
+            }).collect(),
+        }
+    }
+}
+
+///Columns appended to exported traces
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig{
+    pub columns: Vec<ComputedColumn>,
+}
+
+///Write a trace as CSV with `sample`, `amplitude` and the configured computed columns
+pub fn export_with_columns(data: &[f64], dt: f64, t0: f64, columns: &[ComputedColumn], filename: &str)-> Result<()>{
+    let file=File::create(filename).with_context(|| format!("Failed to create file: {}", filename))?;
+    let mut writer=Writer::from_writer(file);
+
+    let computed: Vec<Vec<f64>>=columns.iter().map(|c| c.compute(data.len(), dt, t0)).collect();
+    let mut header=vec!["sample", "amplitude"];
+    header.extend(columns.iter().map(|c| c.name()));
+    writer.write_record(&header)?;
+
+    for (i, &value) in data.iter().enumerate(){
+        let mut record=vec![i.to_string(), value.to_string()];
+        record.extend(computed.iter().map(|column| column[i].to_string()));
+        writer.write_record(&record)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_columns_from_config()-> Result<()>{
+        let config: ExportConfig=serde_json::from_str(r#"{ "columns": [
+            { "kind": "two_way_time" },
+            { "kind": "depth", "velocity": { "v0": 2000.0 } },
+            { "kind": "offset", "offset": 500.0 },
+            { "kind": "incidence_angle", "offset": 1000.0, "velocity": { "v0": 2000.0, "gradient": 0.5 } }
+        ] }"#)?;
+        assert_eq!(config.columns.len(), 4);
+
+        let depth=config.columns[1].compute(3, 0.5, 0.0);
+        assert_eq!(depth, vec![0.0, 500.0, 1000.0]);
+        //v0/k (exp(k t)-1) with one-way time 0.5 s
+        assert_abs_diff_eq!(VelocityFunction{ v0: 2000.0, gradient: 0.5 }.depth_at(1.0), 4000.0*(0.25_f64.exp()-1.0), epsilon=1e-9);
+
+        let angles=config.columns[3].compute(2, 1.0, 0.0);
+        assert!(angles[0].is_nan());
+        assert_abs_diff_eq!(angles[1], (500.0/(4000.0*(0.25_f64.exp()-1.0))).atan().to_degrees(), epsilon=1e-9);
+
+        let path=std::env::temp_dir().join("computed_columns_test.csv");
+        let path=path.to_str().unwrap();
+        export_with_columns(&[0.1, -0.2], 0.002, 0.1, &config.columns, path)?;
+        let text=std::fs::read_to_string(path)?;
+        std::fs::remove_file(path)?;
+        let mut lines=text.lines();
+        assert_eq!(lines.next(), Some("sample,amplitude,twt,depth,offset,incidence_angle"));
+        assert!(lines.next().unwrap().starts_with("0,0.1,0.1,100,500,"));
+        Ok(())
+    }
+}
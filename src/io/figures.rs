@@ -0,0 +1,237 @@
+//! Composite QC figures comparing true and inverted models
+//!
+//! Each row of a figure shows one quantity, such as reflectivity or
+//! impedance, as three panels: the true model, the inverted model and the
+//! residual (inverted minus true). Every panel is followed by a colour bar
+//! running from the low end of its scale at the bottom to the high end at
+//! the top. True and inverted share one scale so they can be compared by
+//! eye, and the residual is drawn on a symmetric scale of the same size, so
+//! a faint residual panel means a close fit. Sections are drawn one column
+//! per trace, widened so a single trace reads as a strip.
+
+use anyhow::{Result, Context, anyhow};
+use std::fs::File;
+use std::io::BufWriter;
+use crate::inversion::uncertainty::reflectivity_to_impedance;
+use super::image::{colour, encode_rgb, Colormap};
+
+///Space around and between panels in pixels
+const MARGIN: usize=8;
+///Gap between a panel and its colour bar
+const COLORBAR_GAP: usize=2;
+const COLORBAR_WIDTH: usize=10;
+///Panels narrower than this are widened by repeating each trace
+const MIN_PANEL_WIDTH: usize=48;
+const BACKGROUND: [u8; 3]=[40, 40, 48];
+
+///One section drawn on a fixed colour scale
+#[derive(Debug, Clone)]
+pub struct Panel{
+    pub title: String,
+    ///[trace][sample]
+    pub section: Vec<Vec<f64>>,
+    pub colormap: Colormap,
+    ///Values at the bottom and top of the colour bar
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Panel{
+    fn column_width(&self)-> usize{
+        MIN_PANEL_WIDTH.div_ceil(self.section.len()).max(1)
+    }
+
+    fn width(&self)-> usize{
+        self.section.len()*self.column_width()+COLORBAR_GAP+COLORBAR_WIDTH
+    }
+
+    fn height(&self)-> usize{
+        self.section.first().map_or(0, |t| t.len())
+    }
+}
+
+///Rows of panels rendered into one PNG
+#[derive(Debug, Clone, Default)]
+pub struct QcFigure{
+    pub rows: Vec<Vec<Panel>>,
+}
+
+fn check_section(name: &str, section: &[Vec<f64>])-> Result<()>{
+    let height=section.first().map_or(0, |t| t.len());
+    if height==0{
+        return Err(anyhow!("{} section is empty", name));
+    }
+    if section.iter().any(|t| t.len()!=height){
+        return Err(anyhow!("All {} traces must have {} samples", name, height));
+    }
+    Ok(())
+}
+
+impl QcFigure{
+    pub fn new()-> Self{
+        Self::default()
+    }
+
+    ///Add a row of true, inverted and residual panels for one quantity
+    pub fn with_comparison(mut self, name: &str, truth: &[Vec<f64>], inverted: &[Vec<f64>], colormap: Colormap)-> Result<Self>{
+        check_section(name, truth)?;
+        if inverted.len()!=truth.len() || inverted.iter().any(|t| t.len()!=truth[0].len()){
+            return Err(anyhow!("Inverted {} must match the true section's {} x {} samples", name, truth.len(), truth[0].len()));
+        }
+
+        let values=|| truth.iter().chain(inverted).flatten().copied().filter(|v| v.is_finite());
+        let (min, max)=values().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let (low, high, half_range)=match colormap{
+            Colormap::Seismic=> {
+                let max_abs=min.abs().max(max.abs());
+                (-max_abs, max_abs, max_abs)
+            }
+            Colormap::Grayscale=> (min, max, 0.5*(max-min)),
+        };
+        let residual: Vec<Vec<f64>>=inverted.iter().zip(truth).map(|(a, b)| a.iter().zip(b).map(|(x, y)| x-y).collect()).collect();
+
+        let panel=|title: String, section: Vec<Vec<f64>>, colormap: Colormap, low: f64, high: f64| Panel{ title, section, colormap, low, high };
+        self.rows.push(vec![
+            panel(format!("true {}", name), truth.to_vec(), colormap, low, high),
+            panel(format!("inverted {}", name), inverted.to_vec(), colormap, low, high),
+            panel(format!("{} residual", name), residual, Colormap::Seismic, -half_range, half_range),
+        ]);
+        Ok(self)
+    }
+
+    ///Reflectivity and relative impedance rows for a single inverted trace
+    ///
+    /// Impedance is integrated from each reflectivity series starting at
+    /// `initial_impedance`, so it shows how errors accumulate with depth.
+    pub fn inversion(truth: &[f64], inverted: &[f64], initial_impedance: f64)-> Result<Self>{
+        let impedance=|r: &[f64]| vec![reflectivity_to_impedance(r, initial_impedance)];
+        Self::new()
+            .with_comparison("reflectivity", &[truth.to_vec()], &[inverted.to_vec()], Colormap::Seismic)?
+            .with_comparison("impedance", &impedance(truth), &impedance(inverted), Colormap::Grayscale)
+    }
+
+    ///Width, height and row-major RGB pixels of the figure
+    pub fn render(&self)-> Result<(usize, usize, Vec<u8>)>{
+        if self.rows.iter().all(|row| row.is_empty()){
+            return Err(anyhow!("Figure has no panels"));
+        }
+        for panel in self.rows.iter().flatten(){
+            check_section(&panel.title, &panel.section)?;
+        }
+        let row_width=|row: &[Panel]| MARGIN+row.iter().map(|p| p.width()+MARGIN).sum::<usize>();
+        let width=self.rows.iter().map(|row| row_width(row)).max().unwrap_or(0);
+        let row_heights: Vec<usize>=self.rows.iter().map(|row| row.iter().map(Panel::height).max().unwrap_or(0)).collect();
+        let height=MARGIN+row_heights.iter().map(|h| h+MARGIN).sum::<usize>();
+
+        let mut pixels: Vec<u8>=BACKGROUND.iter().copied().cycle().take(3*width*height).collect();
+        let mut put=|x: usize, y: usize, rgb: [u8; 3]| pixels[3*(y*width+x)..3*(y*width+x)+3].copy_from_slice(&rgb);
+
+        let mut top=MARGIN;
+        for (row, row_height) in self.rows.iter().zip(&row_heights){
+            let mut left=MARGIN;
+            for panel in row{
+                let column_width=panel.column_width();
+                for (i, trace) in panel.section.iter().enumerate(){
+                    for (y, &value) in trace.iter().enumerate(){
+                        let rgb=colour(panel.colormap, value, panel.low, panel.high);
+                        for x in 0..column_width{
+                            put(left+i*column_width+x, top+y, rgb);
+                        }
+                    }
+                }
+
+                //Colour bar: high at the top, low at the bottom
+                let bar_left=left+panel.section.len()*column_width+COLORBAR_GAP;
+                let bar_height=panel.height();
+                for y in 0..bar_height{
+                    let fraction=if bar_height>1 { y as f64/(bar_height-1) as f64 } else { 0.5 };
+                    let rgb=colour(panel.colormap, panel.high-(panel.high-panel.low)*fraction, panel.low, panel.high);
+                    for x in 0..COLORBAR_WIDTH{
+                        put(bar_left+x, top+y, rgb);
+                    }
+                }
+                left+=panel.width()+MARGIN;
+            }
+            top+=row_height+MARGIN;
+        }
+        Ok((width, height, pixels))
+    }
+
+    pub fn write_png(&self, path: &str)-> Result<()>{
+        let (width, height, pixels)=self.render()?;
+        let file=File::create(path).with_context(|| format!("Failed to create file: {}", path))?;
+        encode_rgb(BufWriter::new(file), width, height, &pixels)
+    }
+
+    ///Encode the figure as PNG bytes in memory, e.g. for bundling
+    pub fn png_bytes(&self)-> Result<Vec<u8>>{
+        let (width, height, pixels)=self.render()?;
+        let mut bytes=Vec::new();
+        encode_rgb(&mut bytes, width, height, &pixels)?;
+        Ok(bytes)
+    }
+
+    ///Panel layout and colour scales, which the image itself does not label
+    pub fn print_summary(&self){
+        for (i, row) in self.rows.iter().enumerate(){
+            let panels: Vec<String>=row.iter().map(|p| format!("{} [{:.4e}, {:.4e}]", p.title, p.low, p.high)).collect();
+            println!("  Row {}: {}", i+1, panels.join(" | "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_comparison_scales_and_layout()-> Result<()>{
+        let truth=vec![0.0, 0.2, 0.0, -0.1, 0.0];
+        let inverted=vec![0.0, 0.15, 0.0, -0.1, 0.05];
+        let figure=QcFigure::inversion(&truth, &inverted, 1.0)?;
+        assert_eq!(figure.rows.len(), 2);
+
+        let reflectivity=&figure.rows[0];
+        assert_eq!(reflectivity.iter().map(|p| p.title.as_str()).collect::<Vec<_>>(), ["true reflectivity", "inverted reflectivity", "reflectivity residual"]);
+        assert_eq!((reflectivity[0].low, reflectivity[0].high), (-0.2, 0.2));
+        assert_eq!((reflectivity[2].low, reflectivity[2].high), (-0.2, 0.2));
+        assert!((reflectivity[2].section[0][1]+0.05).abs()<1e-12);
+        //Impedance is on a grey scale over both models, its residual symmetric about zero
+        let impedance=&figure.rows[1];
+        assert_eq!(impedance[0].colormap, Colormap::Grayscale);
+        assert!(impedance[2].low<0.0 && impedance[2].high==-impedance[2].low);
+
+        let (width, height, pixels)=figure.render()?;
+        let panel_width=MIN_PANEL_WIDTH+COLORBAR_GAP+COLORBAR_WIDTH;
+        assert_eq!(width, MARGIN+3*(panel_width+MARGIN));
+        assert_eq!(height, MARGIN+2*(5+MARGIN));
+        assert_eq!(pixels.len(), 3*width*height);
+        let pixel=|x: usize, y: usize| &pixels[3*(y*width+x)..3*(y*width+x)+3];
+        assert_eq!(pixel(0, 0), BACKGROUND);
+        //Strongest positive reflector is full red; colour bar is red at the top and blue at the bottom
+        assert_eq!(pixel(MARGIN, MARGIN+1), [255, 0, 0]);
+        let bar=MARGIN+MIN_PANEL_WIDTH+COLORBAR_GAP;
+        assert_eq!(pixel(bar, MARGIN), [255, 0, 0]);
+        assert_eq!(pixel(bar, MARGIN+4), [0, 0, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_figure()-> Result<()>{
+        let truth: Vec<Vec<f64>>=(0..4).map(|t| (0..20).map(|s| ((t+s) as f64).sin()).collect()).collect();
+        let inverted: Vec<Vec<f64>>=truth.iter().map(|t| t.iter().map(|x| 0.9*x).collect()).collect();
+        let figure=QcFigure::new().with_comparison("amplitude", &truth, &inverted, Colormap::Seismic)?;
+
+        let path=std::env::temp_dir().join("qc_figure_test.png");
+        let path=path.to_str().unwrap();
+        figure.write_png(path)?;
+        let bytes=std::fs::read(path)?;
+        std::fs::remove_file(path)?;
+        assert_eq!(&bytes[1..4], b"PNG");
+        assert_eq!(figure.png_bytes()?, bytes);
+
+        assert!(QcFigure::new().with_comparison("amplitude", &truth, &inverted[..2], Colormap::Seismic).is_err());
+        assert!(QcFigure::new().render().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! PNG rendering of sections
+
+use anyhow::{Result, Context, anyhow};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+///Mapping from sample values to colours
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colormap{
+    ///Blue-white-red, symmetric about zero; for signed data such as amplitude
+    Seismic,
+    ///Black to white from minimum to maximum; for magnitudes and attributes
+    Grayscale,
+}
+
+///Write a section ([trace][sample]) as an RGB PNG, one column per trace
+///
+/// Values are clipped at `clip` (the maximum absolute value, or the data
+/// range for grayscale) when given, otherwise the full data range is used.
+/// Non-finite samples are drawn black.
+pub fn write_section_png(path: &str, section: &[Vec<f64>], colormap: Colormap, clip: Option<f64>)-> Result<()>{
+    if section.is_empty() || section[0].is_empty(){
+        return Err(anyhow!("Cannot render an empty section"));
+    }
+    let file=File::create(path).with_context(|| format!("Failed to create file: {}", path))?;
+    encode_png(BufWriter::new(file), section, colormap, clip)
+}
+
+///Encode a section as PNG bytes in memory, e.g. for bundling
+pub fn section_png_bytes(section: &[Vec<f64>], colormap: Colormap, clip: Option<f64>)-> Result<Vec<u8>>{
+    let mut bytes=Vec::new();
+    encode_png(&mut bytes, section, colormap, clip)?;
+    Ok(bytes)
+}
+
+fn encode_png<W: Write>(output: W, section: &[Vec<f64>], colormap: Colormap, clip: Option<f64>)-> Result<()>{
+    let width=section.len();
+    let height=section.first().map(|t| t.len()).unwrap_or(0);
+    if width==0 || height==0{
+        return Err(anyhow!("Cannot render an empty section"));
+    }
+    if section.iter().any(|t| t.len()!=height){
+        return Err(anyhow!("All traces must have {} samples to render a section", height));
+    }
+
+    encode_rgb(output, width, height, &render_rgb(section, colormap, clip))
+}
+
+///Encode row-major 8-bit RGB pixels as PNG
+pub(crate) fn encode_rgb<W: Write>(output: W, width: usize, height: usize, pixels: &[u8])-> Result<()>{
+    let mut encoder=png::Encoder::new(output, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer=encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
+
+///Row-major RGB pixels: row = sample, column = trace
+pub fn render_rgb(section: &[Vec<f64>], colormap: Colormap, clip: Option<f64>)-> Vec<u8>{
+    let height=section.first().map(|t| t.len()).unwrap_or(0);
+    let finite=|| section.iter().flatten().copied().filter(|v| v.is_finite());
+    let (min, max)=finite().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    let max_abs=clip.unwrap_or_else(|| min.abs().max(max.abs()));
+    let (low, high)=match clip{
+        Some(c)=> (min.max(-c), max.min(c)),
+        None=> (min, max),
+    };
+
+    let (low, high)=match colormap{
+        Colormap::Seismic=> (-max_abs, max_abs),
+        Colormap::Grayscale=> (low, high),
+    };
+
+    let mut pixels=Vec::with_capacity(section.len()*height*3);
+    for sample in 0..height{
+        for trace in section{
+            pixels.extend_from_slice(&colour(colormap, trace[sample], low, high));
+        }
+    }
+    pixels
+}
+
+///Colour of `value` on a scale from `low` to `high`; Seismic takes its scale from the larger magnitude
+pub(crate) fn colour(colormap: Colormap, value: f64, low: f64, high: f64)-> [u8; 3]{
+    if !value.is_finite(){
+        return [0, 0, 0];
+    }
+    match colormap{
+        Colormap::Seismic=> {
+            let max_abs=low.abs().max(high.abs());
+            seismic_colour(if max_abs>0.0 { value/max_abs } else { 0.0 })
+        }
+        Colormap::Grayscale=> {
+            let level=if high>low { (value-low)/(high-low) } else { 0.5 };
+            let grey=(255.0*level.clamp(0.0, 1.0)).round() as u8;
+            [grey, grey, grey]
+        }
+    }
+}
+
+///Blue for negative, white at zero, red for positive; `x` in [-1, 1]
+fn seismic_colour(x: f64)-> [u8; 3]{
+    let x=x.clamp(-1.0, 1.0);
+    let fade=(255.0*(1.0-x.abs())).round() as u8;
+    if x>=0.0 { [255, fade, fade] } else { [fade, fade, 255] }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_render_layout_and_colours(){
+        let section=vec![vec![1.0, 0.0], vec![-1.0, f64::NAN], vec![0.5, 0.0]];
+        let pixels=render_rgb(&section, Colormap::Seismic, None);
+
+        assert_eq!(pixels.len(), 3*2*3);
+        assert_eq!(&pixels[0..3], &[255, 0, 0]);
+        assert_eq!(&pixels[3..6], &[0, 0, 255]);
+        assert_eq!(&pixels[9..12], &[255, 255, 255]);
+        assert_eq!(&pixels[12..15], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn test_write_png()-> Result<()>{
+        let section: Vec<Vec<f64>>=(0..8).map(|t| (0..16).map(|s| (t*s) as f64).collect()).collect();
+        let path=std::env::temp_dir().join("section_test.png");
+        let path=path.to_str().unwrap();
+
+        write_section_png(path, &section, Colormap::Grayscale, None)?;
+        let bytes=std::fs::read(path)?;
+        std::fs::remove_file(path)?;
+        assert_eq!(&bytes[1..4], b"PNG");
+
+        assert!(write_section_png(path, &[], Colormap::Grayscale, None).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,10 @@
+pub mod background;
+pub mod bundle;
+pub mod columns;
+pub mod figures;
+pub mod image;
+pub mod naming;
+pub mod sweep;
+pub mod terminal;
+pub mod trace_store;
+pub mod zarr;
@@ -0,0 +1,286 @@
+//! File naming templates for batch and Monte Carlo exports
+//!
+//! A template such as `{model}_{freq}Hz_{real:04}.csv` names every output
+//! from the model index, the wavelet frequency and the realization number,
+//! so large runs land in one predictable layout under an output directory.
+//! A digit spec (`{real:04}`) zero-pads integers so files sort in order and
+//! `{freq:.1}` fixes the decimals of the frequency. Templates may contain
+//! `/` to spread outputs over subdirectories. A session checks that no two
+//! outputs of one run map to the same file, and can first remove the files
+//! a previous run left that match the template.
+
+use anyhow::{Result, Context, anyhow};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+///Value substituted for a placeholder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field{
+    ///Index of the model in a batch
+    Model,
+    ///Dominant wavelet frequency in Hz
+    Freq,
+    ///Realization number in a Monte Carlo run
+    Real,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment{
+    Literal(String),
+    Placeholder{ field: Field, width: usize, precision: Option<usize> },
+}
+
+///Values describing one output file
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NameFields{
+    pub model: usize,
+    pub freq: f64,
+    pub real: usize,
+}
+
+///Output directory, file name template and whether to clear old outputs first
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputNaming{
+    pub dir: String,
+    template: String,
+    segments: Vec<Segment>,
+    ///Remove files matching the template before writing
+    pub clean: bool,
+}
+
+fn parse_placeholder(spec: &str, template: &str)-> Result<Segment>{
+    let (name, format)=spec.split_once(':').unwrap_or((spec, ""));
+    let field=match name{
+        "model"=> Field::Model,
+        "freq"=> Field::Freq,
+        "real"=> Field::Real,
+        _=> return Err(anyhow!("Unknown placeholder {{{}}} in '{}'; use model, freq or real", name, template)),
+    };
+    let invalid=|| anyhow!("Invalid format '{}' for {{{}}} in '{}'", format, name, template);
+    let (width, precision)=match format.strip_prefix('.'){
+        Some(digits)=> {
+            if field!=Field::Freq{
+                return Err(anyhow!("Only {{freq}} takes a precision, in '{}'", template));
+            }
+            (0, Some(digits.parse().map_err(|_| invalid())?))
+        }
+        None if format.is_empty()=> (0, None),
+        None=> (format.parse().map_err(|_| invalid())?, None),
+    };
+    Ok(Segment::Placeholder{ field, width, precision })
+}
+
+fn parse_template(template: &str)-> Result<Vec<Segment>>{
+    if template.is_empty(){
+        return Err(anyhow!("File name template is empty"));
+    }
+    if template.starts_with('/') || template.split('/').any(|part| part.is_empty() || part==".." || part=="."){
+        return Err(anyhow!("Template '{}' must be a relative path without empty, '.' or '..' components", template));
+    }
+    let mut segments=Vec::new();
+    let mut rest=template;
+    while !rest.is_empty(){
+        match rest.find(['{', '}']){
+            Some(i) if rest.as_bytes()[i]==b'}'=> return Err(anyhow!("Unmatched '}}' in '{}'", template)),
+            Some(i)=> {
+                if i>0{
+                    segments.push(Segment::Literal(rest[..i].to_string()));
+                }
+                let close=rest[i..].find('}').ok_or_else(|| anyhow!("Unclosed '{{' in '{}'", template))?;
+                segments.push(parse_placeholder(&rest[i+1..i+close], template)?);
+                rest=&rest[i+close+1..];
+            }
+            None=> {
+                segments.push(Segment::Literal(rest.to_string()));
+                rest="";
+            }
+        }
+    }
+    Ok(segments)
+}
+
+///Whether `text` is something `render` can produce for one placeholder
+///
+/// Indices are digits with no leading zero beyond the padding width; the
+/// frequency is a decimal number with exactly `precision` decimals when
+/// one is given.
+fn is_rendered(field: Field, width: usize, precision: Option<usize>, text: &str)-> bool{
+    let digits=|s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if text.len()<width{
+        return false;
+    }
+    match field{
+        Field::Model | Field::Real=> digits(text) && (text.len()<=width.max(1) || !text.starts_with('0')),
+        Field::Freq=> match (text.split_once('.'), precision){
+            (None, None | Some(0))=> digits(text),
+            (Some((whole, fraction)), Some(decimals))=> decimals>0 && digits(whole) && digits(fraction) && fraction.len()==decimals,
+            (Some((whole, fraction)), None)=> digits(whole) && digits(fraction),
+            (None, Some(_))=> false,
+        },
+    }
+}
+
+///Whether `name` could have been produced by `segments`
+fn matches(segments: &[Segment], name: &str)-> bool{
+    match segments.split_first(){
+        None=> name.is_empty(),
+        Some((Segment::Literal(text), rest))=> name.strip_prefix(text.as_str()).is_some_and(|tail| matches(rest, tail)),
+        Some((&Segment::Placeholder{ field, width, precision }, rest))=> {
+            (1..=name.len()).filter(|&i| name.is_char_boundary(i))
+                .any(|i| is_rendered(field, width, precision, &name[..i]) && matches(rest, &name[i..]))
+        }
+    }
+}
+
+impl OutputNaming{
+    pub fn new(dir: &str, template: &str)-> Result<Self>{
+        Ok(Self{ dir: dir.to_string(), template: template.to_string(), segments: parse_template(template)?, clean: false })
+    }
+
+    pub fn with_clean(mut self, clean: bool)-> Self{
+        self.clean=clean;
+        self
+    }
+
+    pub fn template(&self)-> &str{
+        &self.template
+    }
+
+    ///File name for one output, relative to the output directory
+    pub fn render(&self, fields: &NameFields)-> String{
+        self.segments.iter().map(|segment| match segment{
+            Segment::Literal(text)=> text.clone(),
+            Segment::Placeholder{ field: Field::Freq, precision: Some(digits), width }=> format!("{:0width$.digits$}", fields.freq, width=*width, digits=*digits),
+            Segment::Placeholder{ field: Field::Freq, width, .. }=> format!("{:0width$}", fields.freq, width=*width),
+            Segment::Placeholder{ field: Field::Model, width, .. }=> format!("{:0width$}", fields.model, width=*width),
+            Segment::Placeholder{ field: Field::Real, width, .. }=> format!("{:0width$}", fields.real, width=*width),
+        }).collect()
+    }
+
+    ///Whether a path relative to the output directory matches the template
+    pub fn matches(&self, relative_path: &str)-> bool{
+        matches(&self.segments, relative_path)
+    }
+
+    ///Create the output directory, remove stale outputs if cleaning, and start tracking names
+    pub fn start(&self)-> Result<OutputSession<'_>>{
+        fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create directory: {}", self.dir))?;
+        let removed=if self.clean { self.remove_matching(Path::new(&self.dir), "")? } else { 0 };
+        Ok(OutputSession{ naming: self, used: HashSet::new(), removed })
+    }
+
+    ///Delete files under `dir` whose path relative to the output directory matches the template
+    fn remove_matching(&self, dir: &Path, prefix: &str)-> Result<usize>{
+        let mut removed=0;
+        for entry in fs::read_dir(dir).with_context(|| format!("Failed to list directory: {}", dir.display()))?{
+            let entry=entry?;
+            let Some(name)=entry.file_name().to_str().map(|n| format!("{}{}", prefix, n)) else { continue };
+            if entry.file_type()?.is_dir(){
+                removed+=self.remove_matching(&entry.path(), &format!("{}/", name))?;
+            } else if self.matches(&name){
+                fs::remove_file(entry.path()).with_context(|| format!("Failed to remove {}", entry.path().display()))?;
+                removed+=1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+///Names handed out during one export run
+#[derive(Debug)]
+pub struct OutputSession<'a>{
+    naming: &'a OutputNaming,
+    used: HashSet<String>,
+    ///Stale files removed when the session started
+    pub removed: usize,
+}
+
+impl OutputSession<'_>{
+    ///Full path for an output, creating its subdirectory; fails if the name was already used in this run
+    pub fn path(&mut self, fields: &NameFields)-> Result<String>{
+        let name=self.naming.render(fields);
+        if !self.used.insert(name.clone()){
+            return Err(anyhow!("Template '{}' gives '{}' for more than one output; add a placeholder that tells them apart", self.naming.template, name));
+        }
+        let path=format!("{}/{}", self.naming.dir, name);
+        if let Some(parent)=Path::new(&path).parent(){
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        Ok(path)
+    }
+
+    ///Number of paths handed out so far
+    pub fn len(&self)-> usize{
+        self.used.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.used.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_render_and_match()-> Result<()>{
+        let naming=OutputNaming::new("out", "{model}_{freq}Hz_{real:04}.csv")?;
+        let fields=NameFields{ model: 3, freq: 30.0, real: 12 };
+        assert_eq!(naming.render(&fields), "3_30Hz_0012.csv");
+        assert_eq!(OutputNaming::new("out", "f{freq:.1}/m{model:03}.csv")?.render(&NameFields{ freq: 25.26, ..fields }), "f25.3/m003.csv");
+
+        assert!(naming.matches("3_30Hz_0012.csv"));
+        assert!(naming.matches("12_42.5Hz_0001.csv"));
+        assert!(!naming.matches("3_30Hz_0012.txt"));
+        assert!(!naming.matches("notes.csv"));
+        assert!(!naming.matches("a/b_30Hz_1.csv"));
+        //Placeholders only match what they render to
+        assert!(!naming.matches("x_30Hz_0012.csv"));
+        assert!(!naming.matches("3_30Hz_12.csv"));
+        assert!(!naming.matches("03_30Hz_0012.csv"));
+        assert!(naming.matches("3_30Hz_12345.csv"));
+        let fixed=OutputNaming::new("out", "f{freq:.1}/m{model:03}.csv")?;
+        assert!(fixed.matches("f25.3/m003.csv"));
+        assert!(!fixed.matches("f25/m003.csv") && !fixed.matches("f25.30/m003.csv") && !fixed.matches("fxx.3/m003.csv"));
+
+        for bad in ["", "/abs/{real}.csv", "../{real}.csv", "{reel}.csv", "{real.csv", "real}.csv", "{real:.2}.csv", "{freq:x}.csv"]{
+            assert!(OutputNaming::new("out", bad).is_err(), "{}", bad);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_creates_cleans_and_rejects_collisions()-> Result<()>{
+        let dir=std::env::temp_dir().join("output_naming_test");
+        let _=fs::remove_dir_all(&dir);
+        let dir_str=dir.to_str().unwrap();
+
+        let naming=OutputNaming::new(dir_str, "f{freq}/real_{real:03}.csv")?;
+        let mut session=naming.start()?;
+        let path=session.path(&NameFields{ freq: 30.0, real: 1, ..NameFields::default() })?;
+        assert_eq!(path, format!("{}/f30/real_001.csv", dir_str));
+        fs::write(&path, "x")?;
+        fs::write(dir.join("keep.txt"), "x")?;
+        //A template without {model} cannot tell models apart
+        assert!(session.path(&NameFields{ model: 1, freq: 30.0, real: 1 }).is_err());
+        assert_eq!(session.len(), 1);
+
+        let naming=naming.with_clean(true);
+        let session=naming.start()?;
+        assert_eq!(session.removed, 1);
+        assert!(!dir.join("f30/real_001.csv").exists());
+        assert!(dir.join("keep.txt").exists());
+
+        //A bare placeholder template leaves unrelated files of the same extension alone
+        let naming=OutputNaming::new(dir_str, "{real}.csv")?.with_clean(true);
+        fs::write(dir.join("7.csv"), "x")?;
+        fs::write(dir.join("velocity.csv"), "x")?;
+        assert_eq!(naming.start()?.removed, 1);
+        assert!(!dir.join("7.csv").exists());
+        assert!(dir.join("velocity.csv").exists());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
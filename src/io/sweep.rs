@@ -0,0 +1,103 @@
+//! Tabular experiment-sweep summaries in Apache Arrow IPC format
+//!
+//! One row per run, with a column per swept parameter followed by the
+//! misfit, SNR and timing columns. The files open directly in DuckDB,
+//! pandas (`pyarrow.ipc.open_file`) and polars.
+
+use anyhow::{Result, Context};
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, UInt64Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::sync::Arc;
+
+///Summary metrics for a single run of a parameter sweep
+#[derive(Debug, Clone)]
+pub struct SweepRecord{
+    ///Run number within the sweep
+    pub run: u64,
+    ///Swept parameter values by name (e.g. "frequency", "noise_level")
+    pub parameters: BTreeMap<String, f64>,
+    ///Data misfit for the run
+    pub misfit: f64,
+    ///Signal-to-noise ratio in dB
+    pub snr_db: f64,
+    ///Wall-clock time of the run in milliseconds
+    pub runtime_ms: f64,
+}
+
+///Write sweep records as an Arrow IPC file
+///
+/// Parameter columns are the union of all parameter names across records;
+/// runs that did not set a parameter get a null in that column.
+pub fn write_sweep_arrow(path: &str, records: &[SweepRecord])-> Result<()>{
+    let parameter_names: BTreeSet<&String>=records.iter().flat_map(|r| r.parameters.keys()).collect();
+
+    let mut fields=vec![Field::new("run", DataType::UInt64, false)];
+    let mut columns: Vec<ArrayRef>=vec![Arc::new(UInt64Array::from_iter_values(records.iter().map(|r| r.run)))];
+
+    for name in &parameter_names{
+        fields.push(Field::new(name.as_str(), DataType::Float64, true));
+        columns.push(Arc::new(records.iter().map(|r| r.parameters.get(*name).copied()).collect::<Float64Array>()));
+    }
+
+    let metrics=[
+        ("misfit", records.iter().map(|r| r.misfit).collect::<Vec<f64>>()),
+        ("snr_db", records.iter().map(|r| r.snr_db).collect()),
+        ("runtime_ms", records.iter().map(|r| r.runtime_ms).collect()),
+    ];
+    for (name, values) in metrics{
+        fields.push(Field::new(name, DataType::Float64, false));
+        columns.push(Arc::new(Float64Array::from(values)));
+    }
+
+    let schema=Arc::new(Schema::new(fields));
+    let batch=RecordBatch::try_new(schema.clone(), columns)?;
+
+    let file=File::create(path).with_context(|| format!("Failed to create file: {}", path))?;
+    let mut writer=FileWriter::try_new(file, &schema)?;
+    writer.write(&batch)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use arrow_array::Array;
+    use arrow_ipc::reader::FileReader;
+
+    #[test]
+    fn test_sweep_round_trip()-> Result<()>{
+        let records: Vec<SweepRecord>=(0..6).map(|i| SweepRecord{
+            run: i,
+            parameters: BTreeMap::from([
+                ("frequency".to_string(), 20.0+5.0*i as f64),
+                ("noise_level".to_string(), 0.01),
+            ]),
+            misfit: 1.0/(i+1) as f64,
+            snr_db: 20.0,
+            runtime_ms: 1.5,
+        }).collect();
+
+        let path=std::env::temp_dir().join("seismic_sweep_test.arrow");
+        let path=path.to_str().unwrap();
+        write_sweep_arrow(path, &records)?;
+
+        let mut reader=FileReader::try_new(File::open(path)?, None)?;
+        let batch=reader.next().unwrap()?;
+        std::fs::remove_file(path)?;
+
+        let names: Vec<String>=batch.schema().fields().iter().map(|f| f.name().clone()).collect();
+        assert_eq!(names, vec!["run", "frequency", "noise_level", "misfit", "snr_db", "runtime_ms"]);
+        assert_eq!(batch.num_rows(), 6);
+
+        let frequency=batch.column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(frequency.value(5), 45.0);
+        assert_eq!(frequency.null_count(), 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+//! Variable-density display of sections in a 256-colour terminal
+//!
+//! Sections are drawn one column per trace with time running down the
+//! screen. Each character cell is an upper half block whose foreground and
+//! background colours show two samples, doubling the vertical resolution.
+//! Large sections are reduced to fit the terminal by keeping, in every bin,
+//! the sample of largest magnitude (or the mean, for grayscale attributes),
+//! so thin events are not lost. Amplitudes are clipped at a percentile so a
+//! few spikes do not wash out the display. Works over plain SSH sessions
+//! where no image viewer is available.
+
+use anyhow::{Result, anyhow};
+use std::fmt::Write;
+use crate::gather::ensemble::percentile_sorted;
+use crate::gather::Gather;
+use crate::utils::decimate::{reduce_bins, BinReduction};
+use super::image::{colour, Colormap};
+
+const UPPER_HALF_BLOCK: char='▀';
+const RESET: &str="\x1b[0m";
+
+///Settings for terminal rendering
+#[derive(Debug, Clone)]
+pub struct TerminalView{
+    pub colormap: Colormap,
+    ///Percentile of the amplitudes (of magnitudes, for Seismic) mapped to the ends of the scale
+    pub clip_percentile: f64,
+    ///Largest number of character columns, one per displayed trace
+    pub max_width: usize,
+    ///Largest number of character rows, two samples each
+    pub max_height: usize,
+    ///Draw a colour bar with the clip values under the section
+    pub colorbar: bool,
+}
+
+impl Default for TerminalView{
+    fn default()-> Self{
+        Self{
+            colormap: Colormap::Seismic,
+            clip_percentile: 99.0,
+            max_width: 100,
+            max_height: 40,
+            colorbar: true,
+        }
+    }
+}
+
+///Index into the xterm 256-colour palette closest to an RGB colour
+pub fn ansi_index(rgb: [u8; 3])-> u8{
+    let [r, g, b]=rgb;
+    if r==g && g==b{
+        //24-step grey ramp, with the cube's black and white at the ends
+        return match r{
+            0..=3=> 16,
+            252..=255=> 231,
+            _=> 232+((r as f64-8.0)/10.0).round().clamp(0.0, 23.0) as u8,
+        };
+    }
+    let level=|c: u8| (c as f64/255.0*5.0).round() as u8;
+    16+36*level(r)+6*level(g)+level(b)
+}
+
+impl TerminalView{
+    ///Colour scale `(low, high)` after percentile clipping
+    pub fn scale(&self, section: &[Vec<f64>])-> (f64, f64){
+        let mut values: Vec<f64>=section.iter().flatten().copied().filter(|v| v.is_finite()).collect();
+        match self.colormap{
+            Colormap::Seismic=> {
+                values.iter_mut().for_each(|v| *v=v.abs());
+                values.sort_by(f64::total_cmp);
+                let clip=percentile_sorted(&values, self.clip_percentile);
+                (-clip, clip)
+            }
+            Colormap::Grayscale=> {
+                values.sort_by(f64::total_cmp);
+                (percentile_sorted(&values, 100.0-self.clip_percentile), percentile_sorted(&values, self.clip_percentile))
+            }
+        }
+    }
+
+    ///ANSI-coloured text of a [trace][sample] section, one line per pair of samples
+    pub fn render(&self, section: &[Vec<f64>])-> Result<String>{
+        let samples=section.first().map_or(0, |t| t.len());
+        if samples==0{
+            return Err(anyhow!("Cannot display an empty section"));
+        }
+        if section.iter().any(|t| t.len()!=samples){
+            return Err(anyhow!("All traces must have {} samples to display a section", samples));
+        }
+        if self.max_width==0 || self.max_height==0{
+            return Err(anyhow!("Display must be at least one character wide and high"));
+        }
+        if !(self.clip_percentile>0.0 && self.clip_percentile<=100.0){
+            return Err(anyhow!("Clip percentile must be in (0, 100], got {}", self.clip_percentile));
+        }
+
+        let (low, high)=self.scale(section);
+        let reduction=if self.colormap==Colormap::Seismic { BinReduction::Peak } else { BinReduction::Mean };
+        //Reduce along time first, then across traces
+        let columns: Vec<Vec<f64>>=section.iter().map(|t| reduce_bins(t, 2*self.max_height, reduction)).collect();
+        let rows=columns[0].len();
+        let displayed: Vec<Vec<f64>>=(0..rows).map(|k| {
+            let across: Vec<f64>=columns.iter().map(|c| c[k]).collect();
+            reduce_bins(&across, self.max_width, reduction)
+        }).collect();
+
+        let index=|value: f64| ansi_index(colour(self.colormap, value, low, high));
+        let mut text=String::new();
+        for pair in displayed.chunks(2){
+            for (i, &upper) in pair[0].iter().enumerate(){
+                let background=pair.get(1).map_or(0, |lower| index(lower[i]));
+                write!(text, "\x1b[38;5;{}m\x1b[48;5;{}m{}", index(upper), background, UPPER_HALF_BLOCK)?;
+            }
+            writeln!(text, "{}", RESET)?;
+        }
+
+        if self.colorbar{
+            let width=displayed[0].len().clamp(10, 40);
+            write!(text, "{:<10.3e} ", low)?;
+            for i in 0..width{
+                let value=low+(high-low)*i as f64/(width-1) as f64;
+                write!(text, "\x1b[48;5;{}m ", index(value))?;
+            }
+            writeln!(text, "{} {:.3e}", RESET, high)?;
+        }
+        Ok(text)
+    }
+
+    pub fn print(&self, section: &[Vec<f64>])-> Result<()>{
+        print!("{}", self.render(section)?);
+        Ok(())
+    }
+
+    ///Display the traces of a gather side by side
+    pub fn render_gather(&self, gather: &Gather)-> Result<String>{
+        let section: Vec<Vec<f64>>=gather.traces.iter().map(|t| t.samples.clone()).collect();
+        self.render(&section)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_palette_indices(){
+        assert_eq!(ansi_index([255, 0, 0]), 196);
+        assert_eq!(ansi_index([0, 0, 255]), 21);
+        assert_eq!(ansi_index([255, 255, 255]), 231);
+        assert_eq!(ansi_index([0, 0, 0]), 16);
+        assert_eq!(ansi_index([128, 128, 128]), 244);
+    }
+
+    #[test]
+    fn test_render_reduces_and_clips()-> Result<()>{
+        //A spike on one trace would set the scale without clipping
+        let mut section: Vec<Vec<f64>>=(0..200).map(|t| (0..300).map(|s| ((t+s) as f64*0.2).sin()).collect()).collect();
+        section[7][10]=100.0;
+        let view=TerminalView{ max_width: 50, max_height: 20, ..TerminalView::default() };
+        let (low, high)=view.scale(&section);
+        assert!(high<1.01 && low== -high);
+
+        let text=view.render(&section)?;
+        let lines: Vec<&str>=text.lines().collect();
+        assert_eq!(lines.len(), 20+1);
+        assert_eq!(lines[0].matches(UPPER_HALF_BLOCK).count(), 50);
+        assert!(lines[0].ends_with(RESET));
+
+        //Peak reduction keeps an isolated spike visible
+        let mut quiet=vec![vec![0.0; 300]; 200];
+        quiet[7][10]=1.0;
+        let text=TerminalView{ clip_percentile: 100.0, colorbar: false, ..view.clone() }.render(&quiet)?;
+        assert_eq!(text.matches(";196m").count(), 1);
+
+        //Gaps in the data are displayed rather than breaking the colour scale
+        section[3][40]=f64::NAN;
+        assert_eq!(view.scale(&section), (low, high));
+        assert_eq!(view.render(&section)?.lines().count(), 20+1);
+
+        let small=TerminalView{ colorbar: false, ..TerminalView::default() }.render(&[vec![1.0, -1.0, 0.5]])?;
+        assert_eq!(small.lines().count(), 2);
+        assert!(view.render(&[vec![]]).is_err());
+        assert!(TerminalView{ clip_percentile: 0.0, ..TerminalView::default() }.render(&section).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! Compressed binary trace store with a trailer index
+//!
+//! Layout: `[header][zstd block 0][zstd block 1]...[index][trailer]`. Blocks are
+//! appended as traces arrive, so Monte Carlo realizations can be streamed to disk,
+//! and the trailer index gives random access to any realization by number.
+
+use anyhow::{Result, Context, anyhow};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+
+const HEADER_MAGIC: &[u8; 8]=b"SEISTRC1";
+const TRAILER_MAGIC: &[u8; 8]=b"SEISIDX1";
+const HEADER_LEN: u64=24;
+const TRAILER_LEN: u64=24;
+
+///Location of one compressed trace block within the file
+#[derive(Debug, Clone, Copy)]
+struct IndexEntry{
+    offset: u64,
+    compressed_len: u64,
+    num_samples: u64,
+}
+
+///Append-only writer for the compressed trace format
+pub struct TraceStoreWriter{
+    writer: BufWriter<File>,
+    index: Vec<IndexEntry>,
+    position: u64,
+    level: i32,
+}
+
+impl TraceStoreWriter{
+    ///Create a new store, recording the sample interval in the header
+    pub fn create(path: &str, dt: f64, level: i32)-> Result<Self>{
+        let file=File::create(path).with_context(|| format!("Failed to create file: {}", path))?;
+        let mut writer=BufWriter::new(file);
+
+        writer.write_all(HEADER_MAGIC)?;
+        writer.write_all(&dt.to_le_bytes())?;
+        writer.write_all(&0u64.to_le_bytes())?;
+
+        Ok(Self{
+            writer,
+            index: Vec::new(),
+            position: HEADER_LEN,
+            level,
+        })
+    }
+
+    ///Append a trace and return its realization index
+    pub fn append(&mut self, trace: &[f64])-> Result<usize>{
+        let bytes: Vec<u8>=trace.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let compressed=zstd::encode_all(&bytes[..], self.level)?;
+
+        self.writer.write_all(&compressed)?;
+        self.index.push(IndexEntry{
+            offset: self.position,
+            compressed_len: compressed.len() as u64,
+            num_samples: trace.len() as u64,
+        });
+        self.position+=compressed.len() as u64;
+
+        Ok(self.index.len()-1)
+    }
+
+    ///Number of traces written so far
+    pub fn len(&self)-> usize{
+        self.index.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.index.is_empty()
+    }
+
+    ///Write the index and trailer; the file is unreadable until this is called
+    pub fn finish(mut self)-> Result<()>{
+        let index_offset=self.position;
+
+        for entry in &self.index{
+            self.writer.write_all(&entry.offset.to_le_bytes())?;
+            self.writer.write_all(&entry.compressed_len.to_le_bytes())?;
+            self.writer.write_all(&entry.num_samples.to_le_bytes())?;
+        }
+
+        self.writer.write_all(&(self.index.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&index_offset.to_le_bytes())?;
+        self.writer.write_all(TRAILER_MAGIC)?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+///Random-access reader for the compressed trace format
+pub struct TraceStoreReader{
+    file: File,
+    index: Vec<IndexEntry>,
+    ///Sample interval recorded by the writer
+    pub dt: f64,
+}
+
+impl TraceStoreReader{
+    ///Open a store and load its trailer index
+    pub fn open(path: &str)-> Result<Self>{
+        let mut file=File::open(path).with_context(|| format!("Failed to open file: {}", path))?;
+
+        let mut header=[0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header).with_context(|| format!("Truncated trace store: {}", path))?;
+        if &header[..8]!=HEADER_MAGIC{
+            return Err(anyhow!("Not a trace store: {}", path));
+        }
+        let dt=f64::from_le_bytes(header[8..16].try_into()?);
+
+        let file_len=file.seek(SeekFrom::End(0))?;
+        if file_len<HEADER_LEN+TRAILER_LEN{
+            return Err(anyhow!("Trace store is missing its trailer (was finish() called?): {}", path));
+        }
+
+        let mut trailer=[0u8; TRAILER_LEN as usize];
+        file.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        file.read_exact(&mut trailer)?;
+        if &trailer[16..]!=TRAILER_MAGIC{
+            return Err(anyhow!("Trace store is missing its trailer (was finish() called?): {}", path));
+        }
+        let count=u64::from_le_bytes(trailer[..8].try_into()?);
+        let index_offset=u64::from_le_bytes(trailer[8..16].try_into()?);
+
+        //The index sits between the last trace and the trailer, so the trailer must agree with the file length
+        let index_end=count.checked_mul(24).and_then(|len| index_offset.checked_add(len));
+        if index_offset<HEADER_LEN || index_end!=Some(file_len-TRAILER_LEN){
+            return Err(anyhow!("Corrupt trace store index ({} traces at offset {}): {}", count, index_offset, path));
+        }
+
+        let mut raw_index=vec![0u8; (count*24) as usize];
+        file.seek(SeekFrom::Start(index_offset))?;
+        file.read_exact(&mut raw_index)?;
+
+        let index=raw_index.chunks_exact(24).map(|entry| IndexEntry{
+            offset: u64::from_le_bytes(entry[..8].try_into().unwrap()),
+            compressed_len: u64::from_le_bytes(entry[8..16].try_into().unwrap()),
+            num_samples: u64::from_le_bytes(entry[16..].try_into().unwrap()),
+        }).collect();
+
+        Ok(Self{ file, index, dt })
+    }
+
+    ///Number of traces in the store
+    pub fn len(&self)-> usize{
+        self.index.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.index.is_empty()
+    }
+
+    ///Read the trace for a given realization index
+    pub fn read(&mut self, realization: usize)-> Result<Vec<f64>>{
+        let entry=*self.index.get(realization).ok_or_else(|| anyhow!("Realization {} out of range (store holds {})", realization, self.index.len()))?;
+
+        let mut compressed=vec![0u8; entry.compressed_len as usize];
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        self.file.read_exact(&mut compressed)?;
+
+        let bytes=zstd::decode_all(&compressed[..])?;
+        if bytes.len()!=entry.num_samples as usize*8{
+            return Err(anyhow!("Corrupt block for realization {}", realization));
+        }
+
+        Ok(bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_round_trip_random_access()-> Result<()>{
+        let path=std::env::temp_dir().join("seismic_trace_store_test.bin");
+        let path=path.to_str().unwrap();
+
+        let traces: Vec<Vec<f64>>=(0..5).map(|r| (0..(10+r)).map(|i| (i*r) as f64*0.5).collect()).collect();
+
+        let mut writer=TraceStoreWriter::create(path, 0.002, 3)?;
+        for trace in &traces{
+            writer.append(trace)?;
+        }
+        assert_eq!(writer.len(), 5);
+        writer.finish()?;
+
+        let mut reader=TraceStoreReader::open(path)?;
+        assert_eq!(reader.len(), 5);
+        assert_eq!(reader.dt, 0.002);
+        assert_eq!(reader.read(3)?, traces[3]);
+        assert_eq!(reader.read(0)?, traces[0]);
+        assert!(reader.read(5).is_err());
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unfinished_store_is_rejected()-> Result<()>{
+        let path=std::env::temp_dir().join("seismic_trace_store_unfinished.bin");
+        let path=path.to_str().unwrap();
+
+        let mut writer=TraceStoreWriter::create(path, 0.001, 1)?;
+        writer.append(&[1.0, 2.0])?;
+        drop(writer);
+
+        assert!(TraceStoreReader::open(path).is_err());
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_trailer_is_rejected()-> Result<()>{
+        let path=std::env::temp_dir().join("seismic_trace_store_corrupt.bin");
+        let path=path.to_str().unwrap();
+
+        let mut writer=TraceStoreWriter::create(path, 0.001, 1)?;
+        writer.append(&[1.0, 2.0])?;
+        writer.finish()?;
+        let bytes=std::fs::read(path)?;
+        let trailer=bytes.len()-TRAILER_LEN as usize;
+
+        //A huge count must not be allocated, and an offset past the index must not be read
+        let mut corrupt=bytes.clone();
+        corrupt[trailer..trailer+8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(path, &corrupt)?;
+        assert!(TraceStoreReader::open(path).is_err());
+
+        let mut corrupt=bytes.clone();
+        corrupt[trailer+8..trailer+16].copy_from_slice(&(bytes.len() as u64).to_le_bytes());
+        std::fs::write(path, &corrupt)?;
+        assert!(TraceStoreReader::open(path).is_err());
+
+        std::fs::write(path, &bytes)?;
+        assert_eq!(TraceStoreReader::open(path)?.len(), 1);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
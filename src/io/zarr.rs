@@ -0,0 +1,186 @@
+//! Zarr v2 chunked array store output for 3D volumes
+//!
+//! Chunks are written uncompressed or with zstd. Blosc is not implemented:
+//! selecting `ZarrCompressor::Blosc` returns an "unsupported codec: blosc"
+//! error before anything is written to disk.
+
+use anyhow::{Result, Context, anyhow};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+///Compressor applied to each chunk of a Zarr store
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZarrCompressor{
+    ///Store chunks uncompressed
+    None,
+    ///Zstandard compression with the given level
+    Zstd{ level: i32 },
+    ///Blosc compression, not yet supported and rejected by `write_zarr_volume`
+    Blosc,
+}
+
+///Options controlling how a volume is laid out on disk
+#[derive(Debug, Clone)]
+pub struct ZarrOptions{
+    ///Chunk shape (inline, crossline, time)
+    pub chunks: [usize; 3],
+    ///Chunk compressor
+    pub compressor: ZarrCompressor,
+    ///Sample interval in seconds, stored as an attribute for xarray
+    pub dt: f64,
+}
+
+impl Default for ZarrOptions{
+    fn default()-> Self{
+        Self{
+            chunks: [32, 32, 256],
+            compressor: ZarrCompressor::Zstd{ level: 3 },
+            dt: 0.001,
+        }
+    }
+}
+
+///Write a 3D volume stored in C order (inline, crossline, time) as a Zarr v2 array
+///
+/// Partial chunks at the volume edges are padded with zeros, as the Zarr v2
+/// spec requires every chunk to have the full chunk shape.
+pub fn write_zarr_volume(path: &str, data: &[f64], shape: [usize; 3], options: &ZarrOptions)-> Result<()>{
+    let expected_len=shape.iter().product::<usize>();
+    if data.len()!=expected_len{
+        return Err(anyhow!("Volume has {} samples but shape {:?} requires {}", data.len(), shape, expected_len));
+    }
+    if options.chunks.contains(&0){
+        return Err(anyhow!("Chunk dimensions must be positive, got {:?}", options.chunks));
+    }
+    if options.compressor==ZarrCompressor::Blosc{
+        return Err(anyhow!("Unsupported codec: blosc; use ZarrCompressor::Zstd or ZarrCompressor::None"));
+    }
+
+    let root=Path::new(path);
+    fs::create_dir_all(root).with_context(|| format!("Failed to create Zarr store: {}", path))?;
+
+    let compressor=match options.compressor{
+        ZarrCompressor::None=> serde_json::Value::Null,
+        ZarrCompressor::Zstd{ level }=> json!({"id": "zstd", "level": level}),
+        ZarrCompressor::Blosc=> unreachable!("blosc is rejected before the store is created"),
+    };
+
+    let metadata=json!({
+        "zarr_format": 2,
+        "shape": shape,
+        "chunks": options.chunks,
+        "dtype": "<f8",
+        "compressor": compressor,
+        "fill_value": 0.0,
+        "order": "C",
+        "filters": null,
+    });
+    fs::write(root.join(".zarray"), serde_json::to_string_pretty(&metadata)?)?;
+
+    let attributes=json!({
+        "_ARRAY_DIMENSIONS": ["inline", "crossline", "time"],
+        "dt": options.dt,
+    });
+    fs::write(root.join(".zattrs"), serde_json::to_string_pretty(&attributes)?)?;
+
+    //Number of chunks along each axis
+    let grid: Vec<usize>=shape.iter().zip(options.chunks.iter()).map(|(&n, &c)| n.div_ceil(c)).collect();
+    let [ci, cj, ck]=options.chunks;
+
+    for gi in 0..grid[0]{
+        for gj in 0..grid[1]{
+            for gk in 0..grid[2]{
+                let mut chunk=vec![0.0f64; ci*cj*ck];
+
+                for i in 0..ci{
+                    let vi=gi*ci+i;
+                    if vi>=shape[0]{
+                        break;
+                    }
+                    for j in 0..cj{
+                        let vj=gj*cj+j;
+                        if vj>=shape[1]{
+                            break;
+                        }
+                        let k_start=gk*ck;
+                        let k_end=(k_start+ck).min(shape[2]);
+                        let src=(vi*shape[1]+vj)*shape[2];
+                        let dst=(i*cj+j)*ck;
+                        chunk[dst..dst+k_end-k_start].copy_from_slice(&data[src+k_start..src+k_end]);
+                    }
+                }
+
+                let bytes: Vec<u8>=chunk.iter().flat_map(|v| v.to_le_bytes()).collect();
+                let encoded=match options.compressor{
+                    ZarrCompressor::None=> bytes,
+                    ZarrCompressor::Zstd{ level }=> zstd::encode_all(&bytes[..], level)?,
+                    ZarrCompressor::Blosc=> unreachable!("blosc is rejected before the store is created"),
+                };
+
+                let chunk_path=root.join(format!("{}.{}.{}", gi, gj, gk));
+                fs::write(&chunk_path, encoded).with_context(|| format!("Failed to write chunk: {}", chunk_path.display()))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_zarr_metadata_and_chunks()-> Result<()>{
+        let shape=[3, 2, 5];
+        let data: Vec<f64>=(0..30).map(|i| i as f64).collect();
+        let options=ZarrOptions{
+            chunks: [2, 2, 4],
+            ..Default::default()
+        };
+
+        let dir=std::env::temp_dir().join("seismic_zarr_test.zarr");
+        let path=dir.to_str().unwrap();
+        write_zarr_volume(path, &data, shape, &options)?;
+
+        let metadata: serde_json::Value=serde_json::from_str(&fs::read_to_string(dir.join(".zarray"))?)?;
+        assert_eq!(metadata["zarr_format"], 2);
+        assert_eq!(metadata["shape"], json!([3, 2, 5]));
+        assert_eq!(metadata["compressor"]["id"], "zstd");
+
+        //Edge chunk along inline and time: holds volume[2, 0..2, 4] then padding
+        let encoded=fs::read(dir.join("1.0.1"))?;
+        let decoded=zstd::decode_all(&encoded[..])?;
+        let values: Vec<f64>=decoded.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect();
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(values.len(), 16);
+        assert_eq!(values[0], 24.0);
+        assert_eq!(values[4], 29.0);
+        assert!(values[1..4].iter().all(|&v| v==0.0));
+        assert!(values[8..].iter().all(|&v| v==0.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zarr_rejects_shape_mismatch(){
+        let options=ZarrOptions::default();
+        assert!(write_zarr_volume("unused.zarr", &[0.0; 5], [2, 2, 2], &options).is_err());
+    }
+
+    #[test]
+    fn test_zarr_rejects_blosc(){
+        let options=ZarrOptions{
+            compressor: ZarrCompressor::Blosc,
+            ..Default::default()
+        };
+
+        let dir=std::env::temp_dir().join(format!("seismic_zarr_blosc_test_{}.zarr", std::process::id()));
+        let err=write_zarr_volume(dir.to_str().unwrap(), &[0.0; 8], [2, 2, 2], &options).unwrap_err();
+
+        assert!(err.to_string().contains("Unsupported codec: blosc"));
+        assert!(!dir.exists());
+    }
+}
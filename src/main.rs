@@ -1,32 +1,94 @@
 use anyhow::Result;
 use std::time::Instant;
 
+mod alignment;
+mod attributes;
+mod cli;
+mod compare;
+mod config;
 mod convolution;
+mod diagnostics;
+mod experiments;
+mod feasibility;
+mod filters;
 mod forward_modelling;
+mod gather;
+mod geometry;
+mod horizon;
+mod inversion;
+mod io;
 mod models;
+mod noise;
+mod operators;
+mod optimization;
+mod planner;
+mod processing;
+mod refraction;
+mod surface_waves;
 mod utils;
+mod vsp;
 mod wavelets;
+mod well;
 
+use config::{validate, RunConfig};
 use convolution::ConvolutionEngine;
 use forward_modelling::SeismicPipeline;
-use models::ReflectivityModel;
+use inversion::sparse::{wavelet_centre, Dictionary};
+use io::bundle::RunBundle;
+use io::columns::export_with_columns;
+use io::figures::QcFigure;
+use io::image::{section_png_bytes, Colormap};
+use noise::SeededRng;
 use utils::{export_to_csv, plot_ascii, Statistics};
-use wavelets::RickerWavelet;
 
 fn main()->Result<()> {
+    let mut args: Vec<String>=std::env::args().skip(1).collect();
+    let command=args.clone();
+    let bundle_path=cli::take_option(&mut args, "--bundle")?;
+    //Seed explicitly so a bundled run can be repeated exactly; it is passed down, since fastrand's global generator is per thread
+    let seed=match cli::take_option(&mut args, "--seed")?{
+        Some(value)=> value.parse()?,
+        None=> fastrand::u64(..),
+    };
+
+    //Run settings come from `--config <file.json>` when given, otherwise the built-in demo
+    let config=match cli::take_option(&mut args, "--config")?{
+        Some(path)=> RunConfig::load(&path)?,
+        None=> RunConfig::default(),
+    };
+    let validate_only=match args.iter().position(|a| a=="--validate"){
+        Some(position)=> { args.remove(position); true }
+        None=> false,
+    };
+    if cli::run(&args, seed)?{
+        return Ok(());
+    }
+
+    if validate_only{
+        let problems=validate(&config);
+        for problem in &problems{
+            println!("{}", problem);
+        }
+        if !problems.is_empty(){
+            return Err(anyhow::anyhow!("Configuration has {} problem(s)", problems.len()));
+        }
+        println!("Configuration is valid");
+        return Ok(());
+    }
+
     println!("Rust Seismic Inversion Tool Starting...\n");
 
     let start_time=Instant::now();
 
     //Step 1: Create a reflectivity model
     println!("Defining reflectivity model...");
-    let reflectivity_model=ReflectivityModel::new(100, vec![20, 40, 60, 80], vec![0.1, -0.05, 0.15, -0.08]);
+    let reflectivity_model=config.reflectivity_model();
     println!("Model length: {} samples", reflectivity_model.coefficients.len());
     println!("Reflectivity coefficients: {:?}\n", reflectivity_model.reflection_coefficients);
 
     //Step 2: Generate a Ricker wavelet
     println!("Generating a Ricker wavelet...");
-    let wavelet=RickerWavelet::new(30.0, 0.001, 200)?;
+    let wavelet=config.wavelet()?;
     println!("Dominant frequency: {} Hz", wavelet.frequency);
     println!("Sample rate: {} s", wavelet.dt);
     println!("Wavelet length: {} samples\n", wavelet.samples.len());
@@ -39,14 +101,15 @@ fn main()->Result<()> {
     println!("Expected output length: {} samples", input_len);
 
     //Perform convolution
-    let synthetic_trace=conv_engine.convolve(&reflectivity_model.coefficients, &wavelet.sample)?;
+    let synthetic_trace=conv_engine.convolve(&reflectivity_model.coefficients, &wavelet.samples)?;
     println!("Convolution completed");
     println!("Actual output length: {} samples\n", synthetic_trace.len());
 
     //Step 4: Run forward modelling pipeline
     println!("Stop 4: Running forward modelling pipeline...");
-    let mut pipeline=SeismicPipeline::new();
-    let results=pipelinerun_forward_modelling(&reflectivity_model, &wavelet)?;
+    let mut pipeline=SeismicPipeline::with_config(config.pipeline.clone());
+    pipeline.set_rng(Box::new(SeededRng::new(seed)));
+    let results=pipeline.run_forward_modelling(&reflectivity_model, &wavelet)?;
 
     //Calculate statistics
     let trace_stats=Statistics::calculate(&synthetic_trace);
@@ -67,7 +130,7 @@ fn main()->Result<()> {
     println!("Exported {} samples to synthetic_trace.csv", synthetic_trace.len());
 
     export_to_csv(&reflectivity_model.coefficients, "reflectivity_model.csv")?;
-    println!("Exported {} samples to reflectivity_model.csv", relfectivity_model.coefficients.len());
+    println!("Exported {} samples to reflectivity_model.csv", reflectivity_model.coefficients.len());
 
     export_to_csv(&wavelet.samples, "ricker_wavelet.csv")?;
     println!("Exported {} samples to ricker_wavelet.csv\n", wavelet.samples.len());
@@ -87,6 +150,51 @@ fn main()->Result<()> {
     println!("\nSeismic forward modelling completed successfully!");
     println!("Total execution time: {:.3}ms", elapsed.as_secs_f64()*1000.0);
     println!("Performance: {:.0} samples/ms", synthetic_trace.len() as f64 / (elapsed.as_secs_f64()*1000.0));
+    for warning in &results.warnings{
+        println!("Warning: {}", warning);
+    }
+
+    if !config.export.columns.is_empty(){
+        let t0=results.time.first().copied().unwrap_or(0.0);
+        export_with_columns(&results.synthetic_trace, wavelet.dt, t0, &config.export.columns, "synthetic_trace_columns.csv")?;
+        println!("Exported {} computed column(s) to synthetic_trace_columns.csv", config.export.columns.len());
+    }
+
+    //With a solver configured, invert the modelled trace and compare against the true model
+    let mut qc_figure=None;
+    if let Some(solver)=&config.solver{
+        println!("\nInverting the modelled trace...");
+        //The full convolution leads the model by the wavelet's centre sample
+        let centre=wavelet_centre(&wavelet);
+        let length=reflectivity_model.coefficients.len();
+        let observed=&results.synthetic_trace[centre..centre+length];
+        let inversion=solver.sparse_inversion(length, wavelet.dt, reflectivity_model.t0)?;
+        let inverted=inversion.invert(observed, &wavelet, &Dictionary::spikes())?;
+        println!("Inversion finished after {} iterations", inverted.iterations);
+
+        let figure=QcFigure::inversion(&reflectivity_model.coefficients, &inverted.reflectivity, 1.0)?;
+        figure.write_png("inversion_qc.png")?;
+        println!("Wrote QC figure to inversion_qc.png");
+        figure.print_summary();
+        qc_figure=Some(figure);
+    }
+
+    if let Some(path)=bundle_path{
+        let mut bundle=RunBundle::new(seed, command);
+        bundle.add_json("config.json", pipeline.config())?;
+        bundle.add_trace_csv("inputs/reflectivity_model.csv", &reflectivity_model.coefficients)?;
+        bundle.add_trace_csv("inputs/ricker_wavelet.csv", &wavelet.samples)?;
+        bundle.add_trace_csv("outputs/synthetic_trace.csv", &synthetic_trace)?;
+        bundle.add_json("outputs/results.json", &results)?;
+        //Variable-density strip of the pipeline output for a quick look
+        let strip=vec![results.synthetic_trace.clone(); 32];
+        bundle.add_bytes("qc/synthetic.png", section_png_bytes(&strip, Colormap::Seismic, None)?)?;
+        if let Some(figure)=&qc_figure{
+            bundle.add_bytes("qc/inversion.png", figure.png_bytes()?)?;
+        }
+        let manifest=bundle.write(&path)?;
+        println!("Wrote bundle {} ({} files, seed {})", path, manifest.files.len(), seed);
+    }
 
     Ok(())
 
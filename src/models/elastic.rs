@@ -0,0 +1,106 @@
+//! Elastic earth models sampled in two-way time
+
+use anyhow::{Result, anyhow, Context};
+use super::ReflectivityModel;
+
+///Elastic properties per time sample
+#[derive(Debug, Clone)]
+pub struct ElasticModel{
+    ///P-wave velocity in m/s
+    pub vp: Vec<f64>,
+    ///S-wave velocity in m/s
+    pub vs: Vec<f64>,
+    ///Density in kg/m^3
+    pub rho: Vec<f64>,
+    ///Sample interval in seconds
+    pub dt: f64,
+}
+
+impl ElasticModel{
+    ///Create a model, checking that all property logs have the same length
+    pub fn new(vp: Vec<f64>, vs: Vec<f64>, rho: Vec<f64>, dt: f64)-> Result<Self>{
+        if vp.len()!=vs.len() || vp.len()!=rho.len(){
+            return Err(anyhow!("Property lengths differ: vp {}, vs {}, rho {}", vp.len(), vs.len(), rho.len()));
+        }
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+        Ok(Self{ vp, vs, rho, dt })
+    }
+
+    ///Load a CSV with a header row and columns `vp, vs, rho`, one row per time sample
+    pub fn load_csv(path: &str, dt: f64)-> Result<Self>{
+        let mut reader=csv::Reader::from_path(path).with_context(|| format!("Failed to open model file: {}", path))?;
+        let (mut vp, mut vs, mut rho)=(Vec::new(), Vec::new(), Vec::new());
+
+        for (line, record) in reader.records().enumerate(){
+            let record=record?;
+            let field=|i: usize| -> Result<f64> {
+                record.get(i)
+                    .ok_or_else(|| anyhow!("Row {} has fewer than three columns", line+1))?
+                    .trim()
+                    .parse::<f64>()
+                    .with_context(|| format!("Invalid number in row {}", line+1))
+            };
+            vp.push(field(0)?);
+            vs.push(field(1)?);
+            rho.push(field(2)?);
+        }
+
+        Self::new(vp, vs, rho, dt)
+    }
+
+    pub fn len(&self)-> usize{
+        self.vp.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.vp.is_empty()
+    }
+
+    ///Acoustic impedance vp*rho per sample
+    pub fn acoustic_impedance(&self)-> Vec<f64>{
+        self.vp.iter().zip(self.rho.iter()).map(|(v, r)| v*r).collect()
+    }
+
+    ///Normal-incidence reflectivity; the interface above sample i sits at position i
+    pub fn reflectivity(&self)-> ReflectivityModel{
+        let impedance=self.acoustic_impedance();
+        let mut positions=Vec::new();
+        let mut coefficients=Vec::new();
+
+        for i in 1..impedance.len(){
+            let (upper, lower)=(impedance[i-1], impedance[i]);
+            let r=(lower-upper)/(lower+upper);
+            if r!=0.0{
+                positions.push(i);
+                coefficients.push(r);
+            }
+        }
+
+        ReflectivityModel::new(impedance.len(), positions, coefficients)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_two_layer_reflectivity()-> Result<()>{
+        let model=ElasticModel::new(
+            vec![2000.0, 2000.0, 3000.0, 3000.0],
+            vec![1000.0; 4],
+            vec![2000.0, 2000.0, 2200.0, 2200.0],
+            0.002,
+        )?;
+
+        let reflectivity=model.reflectivity();
+        assert_eq!(reflectivity.layer_positions, vec![2]);
+        assert_abs_diff_eq!(reflectivity.coefficients[2], (6.6e6-4.0e6)/(6.6e6+4.0e6), epsilon=1e-12);
+        assert!(ElasticModel::new(vec![1.0], vec![], vec![1.0], 0.002).is_err());
+
+        Ok(())
+    }
+}
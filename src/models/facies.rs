@@ -0,0 +1,187 @@
+//! Stochastic facies-based elastic model generator
+//!
+//! Facies sequences are simulated down the trace with a first-order Markov
+//! chain, then each facies is mapped to elastic properties with Gaussian
+//! scatter, giving heterogeneous `ElasticModel` realizations for Monte Carlo
+//! studies.
+
+use anyhow::{Result, anyhow};
+use crate::noise::Rng;
+use super::elastic::ElasticModel;
+
+///Elastic property distribution of one facies
+#[derive(Debug, Clone)]
+pub struct FaciesProperties{
+    pub name: String,
+    ///Mean and standard deviation of vp in m/s
+    pub vp: (f64, f64),
+    ///Mean and standard deviation of vs in m/s
+    pub vs: (f64, f64),
+    ///Mean and standard deviation of density in kg/m^3
+    pub rho: (f64, f64),
+}
+
+///Markov-chain facies simulator with per-facies property distributions
+#[derive(Debug, Clone)]
+pub struct FaciesModelGenerator{
+    ///Row-stochastic transition matrix: transition[i][j]=P(next=j | current=i)
+    pub transition: Vec<Vec<f64>>,
+    pub facies: Vec<FaciesProperties>,
+}
+
+///One simulated realization: facies codes and the matching elastic model
+#[derive(Debug, Clone)]
+pub struct FaciesRealization{
+    pub facies: Vec<usize>,
+    pub model: ElasticModel,
+}
+
+impl FaciesModelGenerator{
+    ///Create a generator, validating the transition matrix
+    pub fn new(transition: Vec<Vec<f64>>, facies: Vec<FaciesProperties>)-> Result<Self>{
+        let n=facies.len();
+        if n==0{
+            return Err(anyhow!("At least one facies is required"));
+        }
+        if transition.len()!=n || transition.iter().any(|row| row.len()!=n){
+            return Err(anyhow!("Transition matrix must be {}x{} to match the facies list", n, n));
+        }
+        for (i, row) in transition.iter().enumerate(){
+            if row.iter().any(|&p| p<0.0){
+                return Err(anyhow!("Transition row {} has negative probabilities", i));
+            }
+            let total: f64=row.iter().sum();
+            if (total-1.0).abs()>1e-9{
+                return Err(anyhow!("Transition row {} sums to {}, expected 1", i, total));
+            }
+        }
+
+        Ok(Self{ transition, facies })
+    }
+
+    ///Long-run facies proportions of the chain (power iteration)
+    pub fn stationary_distribution(&self)-> Vec<f64>{
+        let n=self.facies.len();
+        let mut distribution=vec![1.0/n as f64; n];
+
+        for _ in 0..1000{
+            let mut next=vec![0.0; n];
+            for (i, &p) in distribution.iter().enumerate(){
+                for (j, &t) in self.transition[i].iter().enumerate(){
+                    next[j]+=p*t;
+                }
+            }
+            let change: f64=next.iter().zip(distribution.iter()).map(|(a, b)| (a-b).abs()).sum();
+            distribution=next;
+            if change<1e-12{
+                break;
+            }
+        }
+
+        distribution
+    }
+
+    ///Simulate a facies sequence, starting from the stationary distribution
+    pub fn simulate_facies(&self, rng: &mut dyn Rng, length: usize)-> Vec<usize>{
+        let mut sequence=Vec::with_capacity(length);
+        if length==0{
+            return sequence;
+        }
+
+        let mut current=sample_categorical(rng, &self.stationary_distribution());
+        sequence.push(current);
+        for _ in 1..length{
+            current=sample_categorical(rng, &self.transition[current]);
+            sequence.push(current);
+        }
+
+        sequence
+    }
+
+    ///Simulate facies and draw elastic properties for each sample
+    pub fn realize(&self, rng: &mut dyn Rng, length: usize, dt: f64)-> Result<FaciesRealization>{
+        let facies=self.simulate_facies(rng, length);
+
+        let mut draw=|(mean, std): (f64, f64)| (mean+std*rng.normal()).max(0.0);
+        let vp=facies.iter().map(|&f| draw(self.facies[f].vp)).collect();
+        let vs=facies.iter().map(|&f| draw(self.facies[f].vs)).collect();
+        let rho=facies.iter().map(|&f| draw(self.facies[f].rho)).collect();
+
+        Ok(FaciesRealization{
+            facies,
+            model: ElasticModel::new(vp, vs, rho, dt)?,
+        })
+    }
+
+    ///Generate independent realizations for Monte Carlo studies
+    pub fn realizations(&self, rng: &mut dyn Rng, count: usize, length: usize, dt: f64)-> Result<Vec<FaciesRealization>>{
+        (0..count).map(|_| self.realize(rng, length, dt)).collect()
+    }
+}
+
+///Draw an index with the given probabilities
+fn sample_categorical(rng: &mut dyn Rng, probabilities: &[f64])-> usize{
+    let u=rng.uniform();
+    let mut cumulative=0.0;
+    for (i, &p) in probabilities.iter().enumerate(){
+        cumulative+=p;
+        if u<cumulative{
+            return i;
+        }
+    }
+    probabilities.len()-1
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::noise::SeededRng;
+
+    fn sand_shale()-> FaciesModelGenerator{
+        FaciesModelGenerator::new(
+            vec![vec![0.9, 0.1], vec![0.3, 0.7]],
+            vec![
+                FaciesProperties{ name: "shale".to_string(), vp: (2500.0, 50.0), vs: (1100.0, 30.0), rho: (2400.0, 20.0) },
+                FaciesProperties{ name: "sand".to_string(), vp: (3200.0, 80.0), vs: (1800.0, 50.0), rho: (2200.0, 30.0) },
+            ],
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_invalid_transition_matrix(){
+        let facies=sand_shale().facies;
+        assert!(FaciesModelGenerator::new(vec![vec![0.5, 0.6], vec![0.5, 0.5]], facies.clone()).is_err());
+        assert!(FaciesModelGenerator::new(vec![vec![1.0]], facies).is_err());
+    }
+
+    #[test]
+    fn test_stationary_distribution(){
+        //Solving pi=pi*P gives shale 0.75, sand 0.25
+        let stationary=sand_shale().stationary_distribution();
+        assert_abs_diff_eq!(stationary[0], 0.75, epsilon=1e-9);
+        assert_abs_diff_eq!(stationary[1], 0.25, epsilon=1e-9);
+    }
+
+    #[test]
+    fn test_realization_properties()-> Result<()>{
+        let generator=sand_shale();
+        let mut rng=SeededRng::new(1);
+        let realization=generator.realize(&mut rng, 20000, 0.002)?;
+
+        assert_eq!(realization.model.len(), 20000);
+        let sand_fraction=realization.facies.iter().filter(|&&f| f==1).count() as f64/20000.0;
+        assert_abs_diff_eq!(sand_fraction, 0.25, epsilon=0.05);
+
+        //Sand samples carry sand velocities
+        let sand_vp: Vec<f64>=realization.facies.iter().zip(realization.model.vp.iter()).filter(|(&f, _)| f==1).map(|(_, &v)| v).collect();
+        let mean=sand_vp.iter().sum::<f64>()/sand_vp.len() as f64;
+        assert_abs_diff_eq!(mean, 3200.0, epsilon=20.0);
+
+        assert_eq!(generator.realizations(&mut rng, 3, 50, 0.002)?.len(), 3);
+        //The same seed reproduces the realization
+        assert_eq!(generator.realize(&mut SeededRng::new(2), 100, 0.002)?.facies, generator.realize(&mut SeededRng::new(2), 100, 0.002)?.facies);
+
+        Ok(())
+    }
+}
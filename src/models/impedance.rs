@@ -0,0 +1,178 @@
+//! Acoustic impedance logs and their conversion to and from reflectivity
+//!
+//! At normal incidence `r_i = (Z_i - Z_{i-1}) / (Z_i + Z_{i-1})`, with the
+//! interface above sample i at position i as in `ElasticModel::reflectivity`.
+//! Inverting the recursion, `Z_i = Z_{i-1} (1 + r_i) / (1 - r_i)`, recovers
+//! absolute impedance from full-band reflectivity and a starting value.
+//! Inverted reflectivity is band-limited, so recursion drifts; trace
+//! integration instead gives relative impedance, `ln(Z/Z0) ≈ 2 Σ r` with the
+//! drift removed, which is merged with a low-frequency background model to
+//! give absolute impedance, the usual post-stack inversion product.
+
+use anyhow::{Result, anyhow};
+use super::elastic::ElasticModel;
+use super::ReflectivityModel;
+
+///Acoustic impedance per time sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImpedanceModel{
+    ///Impedance in kg/(m² s)
+    pub values: Vec<f64>,
+    ///Sample interval in seconds
+    pub dt: f64,
+}
+
+///Integrate reflectivity to impedance: `Z[i]=Z[i-1]*(1+r[i])/(1-r[i])` starting above sample zero
+///
+/// Coefficients are clamped to ±0.99 so a wild estimate cannot flip the
+/// sign of the impedance.
+pub fn integrate_reflectivity(reflectivity: &[f64], initial_impedance: f64)-> Vec<f64>{
+    let mut current=initial_impedance;
+    reflectivity.iter().map(|&r| {
+        let r=r.clamp(-0.99, 0.99);
+        current*=(1.0+r)/(1.0-r);
+        current
+    }).collect()
+}
+
+///Relative log impedance `2 Σ r` from band-limited reflectivity, with the linear drift removed
+///
+/// The result is `ln(Z/Z_trend)` to first order in the reflection coefficients.
+pub fn relative_impedance(reflectivity: &[f64])-> Vec<f64>{
+    let mut sum=0.0;
+    let integrated: Vec<f64>=reflectivity.iter().map(|r| { sum+=2.0*r; sum }).collect();
+
+    //Least-squares line through the integrated trace
+    let n=integrated.len() as f64;
+    if integrated.len()<2{
+        return vec![0.0; integrated.len()];
+    }
+    let mean_x=(n-1.0)/2.0;
+    let mean_y=integrated.iter().sum::<f64>()/n;
+    let (sxy, sxx)=integrated.iter().enumerate().fold((0.0, 0.0), |(sxy, sxx), (i, y)| {
+        let dx=i as f64-mean_x;
+        (sxy+dx*(y-mean_y), sxx+dx*dx)
+    });
+    let slope=sxy/sxx;
+    integrated.iter().enumerate().map(|(i, y)| y-mean_y-slope*(i as f64-mean_x)).collect()
+}
+
+impl ImpedanceModel{
+    pub fn new(values: Vec<f64>, dt: f64)-> Result<Self>{
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+        if values.iter().any(|&z| !(z>0.0 && z.is_finite())){
+            return Err(anyhow!("Impedance must be positive and finite"));
+        }
+        Ok(Self{ values, dt })
+    }
+
+    ///Impedance vp*rho of an elastic model
+    pub fn from_elastic(model: &ElasticModel)-> Result<Self>{
+        Self::new(model.acoustic_impedance(), model.dt)
+    }
+
+    ///Absolute impedance by recursion from full-band reflectivity, starting from the impedance above sample zero
+    pub fn from_reflectivity(reflectivity: &[f64], initial_impedance: f64, dt: f64)-> Result<Self>{
+        if !(initial_impedance>0.0 && initial_impedance.is_finite()){
+            return Err(anyhow!("Initial impedance must be positive, got {}", initial_impedance));
+        }
+        Self::new(integrate_reflectivity(reflectivity, initial_impedance), dt)
+    }
+
+    ///Absolute impedance from band-limited reflectivity by trace integration, `Z = Z_background exp(relative)`
+    ///
+    /// The background supplies the frequencies below the seismic band, typically
+    /// a smoothed well log or a velocity-derived trend.
+    pub fn from_band_limited(reflectivity: &[f64], background: &ImpedanceModel)-> Result<Self>{
+        if reflectivity.len()!=background.len(){
+            return Err(anyhow!("Have {} reflectivity samples for a {}-sample background", reflectivity.len(), background.len()));
+        }
+        let relative=relative_impedance(reflectivity);
+        Self::new(background.values.iter().zip(&relative).map(|(z, r)| z*r.exp()).collect(), background.dt)
+    }
+
+    pub fn len(&self)-> usize{
+        self.values.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.values.is_empty()
+    }
+
+    ///Natural logarithm of the impedance, the quantity linear inversions work in
+    pub fn log_impedance(&self)-> Vec<f64>{
+        self.values.iter().map(|z| z.ln()).collect()
+    }
+
+    ///Normal-incidence reflectivity; the interface above sample i sits at position i
+    pub fn reflectivity(&self)-> ReflectivityModel{
+        let (positions, coefficients): (Vec<usize>, Vec<f64>)=self.values.windows(2).enumerate().filter_map(|(i, pair)| {
+            let r=(pair[1]-pair[0])/(pair[1]+pair[0]);
+            (r!=0.0).then_some((i+1, r))
+        }).unzip();
+        ReflectivityModel::new(self.len(), positions, coefficients)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn blocky()-> Result<ImpedanceModel>{
+        let values=(0..200).map(|i| match i{
+            0..=49=> 5.0e6,
+            50..=89=> 6.2e6,
+            90..=139=> 5.5e6,
+            _=> 7.0e6,
+        }).collect();
+        ImpedanceModel::new(values, 0.002)
+    }
+
+    #[test]
+    fn test_reflectivity_round_trip()-> Result<()>{
+        let model=blocky()?;
+        let reflectivity=model.reflectivity();
+        assert_eq!(reflectivity.layer_positions, vec![50, 90, 140]);
+        assert_abs_diff_eq!(reflectivity.coefficients[50], 1.2/11.2, epsilon=1e-12);
+
+        let recovered=ImpedanceModel::from_reflectivity(&reflectivity.coefficients, model.values[0], model.dt)?;
+        for (a, b) in recovered.values.iter().zip(&model.values){
+            assert_abs_diff_eq!(a/b, 1.0, epsilon=1e-12);
+        }
+        assert!(ImpedanceModel::from_reflectivity(&reflectivity.coefficients, 0.0, 0.002).is_err());
+        assert!(ImpedanceModel::new(vec![1.0, -1.0], 0.002).is_err());
+
+        let elastic=ElasticModel::new(vec![2000.0, 2500.0], vec![1000.0, 1200.0], vec![2200.0, 2300.0], 0.002)?;
+        assert_eq!(ImpedanceModel::from_elastic(&elastic)?.values, vec![4.4e6, 5.75e6]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_band_limited_integration()-> Result<()>{
+        let model=blocky()?;
+        let reflectivity=model.reflectivity().coefficients;
+
+        //Relative impedance steps with the log impedance at each interface
+        let relative=relative_impedance(&reflectivity);
+        assert_abs_diff_eq!(relative.iter().sum::<f64>(), 0.0, epsilon=1e-9);
+        let log=model.log_impedance();
+        assert_abs_diff_eq!(relative[50]-relative[49], log[50]-log[49], epsilon=0.005);
+
+        //A background following the straight-line trend of the log impedance restores the blocks
+        let n=log.len() as f64;
+        let (mean_x, mean_y)=((n-1.0)/2.0, log.iter().sum::<f64>()/n);
+        let slope=log.iter().enumerate().map(|(i, y)| (i as f64-mean_x)*(y-mean_y)).sum::<f64>()
+            /(0..log.len()).map(|i| (i as f64-mean_x).powi(2)).sum::<f64>();
+        let trend=(0..log.len()).map(|i| (mean_y+slope*(i as f64-mean_x)).exp()).collect();
+        let background=ImpedanceModel::new(trend, model.dt)?;
+        let merged=ImpedanceModel::from_band_limited(&reflectivity, &background)?;
+        for (a, b) in merged.values.iter().zip(&model.values){
+            assert_abs_diff_eq!(a/b, 1.0, epsilon=0.01);
+        }
+        assert!(ImpedanceModel::from_band_limited(&reflectivity[..10], &background).is_err());
+        Ok(())
+    }
+}
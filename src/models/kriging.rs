@@ -0,0 +1,120 @@
+//! Simple kriging and conditional simulation of property sections
+//!
+//! Control points (e.g. well values at given trace/sample positions) are
+//! honoured exactly: simple kriging interpolates them with the generator's
+//! variogram, and conditional simulation adds back the part of an
+//! unconditional field that kriging cannot predict.
+
+use anyhow::{Result, anyhow};
+use crate::noise::Rng;
+use crate::utils::linalg::solve_linear_system;
+use super::gaussian_field::GaussianFieldGenerator;
+
+///A fixed property value at a grid position
+#[derive(Debug, Clone, Copy)]
+pub struct ControlPoint{
+    pub trace: usize,
+    pub sample: usize,
+    pub value: f64,
+}
+
+impl GaussianFieldGenerator{
+    ///Covariance between two grid nodes
+    fn covariance(&self, a: (usize, usize), b: (usize, usize), dx: f64, dz: f64)-> f64{
+        let lag_x=(a.0 as f64-b.0 as f64)*dx/self.range_x;
+        let lag_z=(a.1 as f64-b.1 as f64)*dz/self.range_z;
+        self.sill*self.variogram.correlation(lag_x.hypot(lag_z))
+    }
+
+    ///Kriging weights applied to control-point residuals: alpha=C^-1 r
+    fn kriging_coefficients(&self, points: &[ControlPoint], residuals: &[f64], dx: f64, dz: f64)-> Result<Vec<f64>>{
+        let matrix=points.iter().map(|p| {
+            points.iter().map(|q| self.covariance((p.trace, p.sample), (q.trace, q.sample), dx, dz)).collect()
+        }).collect();
+        solve_linear_system(matrix, residuals.to_vec()).map_err(|e| anyhow!("Kriging system could not be solved (duplicate control points?): {}", e))
+    }
+
+    fn check_points(points: &[ControlPoint], nx: usize, nz: usize)-> Result<()>{
+        for p in points{
+            if p.trace>=nx || p.sample>=nz{
+                return Err(anyhow!("Control point ({}, {}) lies outside the {}x{} section", p.trace, p.sample, nx, nz));
+            }
+        }
+        Ok(())
+    }
+
+    ///Simple-kriging estimate of the section with known mean `mean`
+    pub fn simple_krige(&self, points: &[ControlPoint], nx: usize, nz: usize, dx: f64, dz: f64, mean: f64)-> Result<Vec<Vec<f64>>>{
+        Self::check_points(points, nx, nz)?;
+        let residuals: Vec<f64>=points.iter().map(|p| p.value-mean).collect();
+        let alpha=self.kriging_coefficients(points, &residuals, dx, dz)?;
+
+        Ok(self.evaluate(points, &alpha, nx, nz, dx, dz, |_, _| mean))
+    }
+
+    ///Random section with the generator's variogram that honours the control points
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_conditional(&self, rng: &mut dyn Rng, points: &[ControlPoint], nx: usize, nz: usize, dx: f64, dz: f64, mean: f64)-> Result<Vec<Vec<f64>>>{
+        Self::check_points(points, nx, nz)?;
+        let unconditional=self.generate(rng, nx, nz, dx, dz);
+
+        let residuals: Vec<f64>=points.iter().map(|p| p.value-mean-unconditional[p.trace][p.sample]).collect();
+        let alpha=self.kriging_coefficients(points, &residuals, dx, dz)?;
+
+        Ok(self.evaluate(points, &alpha, nx, nz, dx, dz, |i, k| mean+unconditional[i][k]))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate(&self, points: &[ControlPoint], alpha: &[f64], nx: usize, nz: usize, dx: f64, dz: f64, base: impl Fn(usize, usize)-> f64)-> Vec<Vec<f64>>{
+        (0..nx).map(|i| (0..nz).map(|k| {
+            let correction: f64=points.iter().zip(alpha.iter())
+                .map(|(p, a)| a*self.covariance((i, k), (p.trace, p.sample), dx, dz))
+                .sum();
+            base(i, k)+correction
+        }).collect()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::models::gaussian_field::Variogram;
+    use crate::noise::SeededRng;
+    use approx::assert_abs_diff_eq;
+
+    fn wells()-> Vec<ControlPoint>{
+        vec![
+            ControlPoint{ trace: 5, sample: 10, value: 3000.0 },
+            ControlPoint{ trace: 5, sample: 30, value: 3400.0 },
+            ControlPoint{ trace: 25, sample: 20, value: 2600.0 },
+        ]
+    }
+
+    #[test]
+    fn test_kriging_honours_wells()-> Result<()>{
+        let generator=GaussianFieldGenerator::new(Variogram::Gaussian, 10.0, 8.0, 200.0*200.0)?;
+        let estimate=generator.simple_krige(&wells(), 30, 40, 1.0, 1.0, 2800.0)?;
+
+        for p in wells(){
+            assert_abs_diff_eq!(estimate[p.trace][p.sample], p.value, epsilon=1e-6);
+        }
+        //Far from every well the estimate returns to the mean
+        assert_abs_diff_eq!(estimate[29][0], 2800.0, epsilon=1.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conditional_simulation_honours_wells()-> Result<()>{
+        let generator=GaussianFieldGenerator::new(Variogram::Exponential, 12.0, 6.0, 100.0*100.0)?;
+        let mut rng=SeededRng::new(5);
+        let section=generator.generate_conditional(&mut rng, &wells(), 30, 40, 1.0, 1.0, 2800.0)?;
+
+        for p in wells(){
+            assert_abs_diff_eq!(section[p.trace][p.sample], p.value, epsilon=1e-6);
+        }
+        assert!(generator.generate_conditional(&mut rng, &[ControlPoint{ trace: 40, sample: 0, value: 1.0 }], 30, 40, 1.0, 1.0, 0.0).is_err());
+
+        Ok(())
+    }
+}
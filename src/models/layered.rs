@@ -0,0 +1,97 @@
+//! 1D layered earth models in depth
+
+use anyhow::{Result, anyhow};
+
+///Flat homogeneous layers over a half-space, properties per layer
+#[derive(Debug, Clone)]
+pub struct LayeredModel{
+    ///Thickness of each layer above the half-space in metres (one fewer than the layers)
+    pub thickness: Vec<f64>,
+    ///P-wave velocity in m/s
+    pub vp: Vec<f64>,
+    ///S-wave velocity in m/s
+    pub vs: Vec<f64>,
+    ///Density in kg/m^3
+    pub rho: Vec<f64>,
+}
+
+impl LayeredModel{
+    pub fn new(thickness: Vec<f64>, vp: Vec<f64>, vs: Vec<f64>, rho: Vec<f64>)-> Result<Self>{
+        if vp.is_empty() || vp.len()!=vs.len() || vp.len()!=rho.len(){
+            return Err(anyhow!("Property lengths differ or are empty: vp {}, vs {}, rho {}", vp.len(), vs.len(), rho.len()));
+        }
+        if thickness.len()+1!=vp.len(){
+            return Err(anyhow!("Expected {} layer thicknesses for {} layers, got {}", vp.len()-1, vp.len(), thickness.len()));
+        }
+        if thickness.iter().any(|&h| !(h>0.0 && h.is_finite())){
+            return Err(anyhow!("Layer thicknesses must be positive"));
+        }
+        if vp.iter().chain(&rho).any(|&v| !(v>0.0 && v.is_finite())) || vs.iter().any(|&v| !(v>=0.0 && v.is_finite())){
+            return Err(anyhow!("Velocities and densities must be positive (vs may be zero)"));
+        }
+        Ok(Self{ thickness, vp, vs, rho })
+    }
+
+    ///Acoustic model with Vs set to zero
+    pub fn acoustic(thickness: Vec<f64>, vp: Vec<f64>, rho: Vec<f64>)-> Result<Self>{
+        let vs=vec![0.0; vp.len()];
+        Self::new(thickness, vp, vs, rho)
+    }
+
+    ///Number of layers including the half-space
+    pub fn num_layers(&self)-> usize{
+        self.vp.len()
+    }
+
+    ///Depth of each interface (the base of every layer above the half-space)
+    pub fn interface_depths(&self)-> Vec<f64>{
+        self.thickness.iter().scan(0.0, |depth, h| { *depth+=h; Some(*depth) }).collect()
+    }
+
+    ///Index of the layer containing `depth`; interfaces belong to the layer below
+    pub fn layer_at(&self, depth: f64)-> usize{
+        self.interface_depths().partition_point(|&d| d<=depth)
+    }
+
+    ///Vertical one-way P-wave travel time from the surface to `depth`
+    pub fn one_way_time(&self, depth: f64)-> f64{
+        let mut time=0.0;
+        let mut top=0.0;
+        for (i, &h) in self.thickness.iter().enumerate(){
+            if depth<=top+h{
+                return time+(depth-top).max(0.0)/self.vp[i];
+            }
+            time+=h/self.vp[i];
+            top+=h;
+        }
+        time+(depth-top).max(0.0)/self.vp[self.num_layers()-1]
+    }
+
+    ///Normal-incidence P reflection coefficient at the base of layer `i`
+    pub fn reflection_coefficient(&self, i: usize)-> f64{
+        let (upper, lower)=(self.vp[i]*self.rho[i], self.vp[i+1]*self.rho[i+1]);
+        (lower-upper)/(lower+upper)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_layer_geometry_and_times()-> Result<()>{
+        let model=LayeredModel::acoustic(vec![100.0, 200.0], vec![1000.0, 2000.0, 4000.0], vec![2000.0; 3])?;
+        assert_eq!(model.interface_depths(), vec![100.0, 300.0]);
+        assert_eq!(model.layer_at(50.0), 0);
+        assert_eq!(model.layer_at(100.0), 1);
+        assert_eq!(model.layer_at(500.0), 2);
+        assert_abs_diff_eq!(model.one_way_time(200.0), 0.1+0.05, epsilon=1e-12);
+        assert_abs_diff_eq!(model.one_way_time(700.0), 0.1+0.1+0.1, epsilon=1e-12);
+        assert_abs_diff_eq!(model.reflection_coefficient(0), 1.0/3.0, epsilon=1e-12);
+
+        assert!(LayeredModel::acoustic(vec![100.0], vec![1000.0], vec![2000.0]).is_err());
+        assert!(LayeredModel::acoustic(vec![-1.0], vec![1000.0, 2000.0], vec![2000.0; 2]).is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,13 @@
 use anyhow::{Result, anyhow};
 
+pub mod elastic;
+pub mod facies;
+pub mod gaussian_field;
+pub mod impedance;
+pub mod kriging;
+pub mod layered;
+pub mod velocity;
+
 ///Reflectivity model representing geological layers
 ///
 ///This represents the Earth's subsurface as a series of acoustic
@@ -14,6 +22,8 @@ pub struct ReflectivityModel{
     pub reflection_coefficients: Vec<f64>,
     ///Total model length in samples
     pub length: usize,
+    ///Two-way time of sample zero in seconds
+    pub t0: f64,
 }
 
 impl ReflectivityModel {
@@ -22,7 +32,7 @@ impl ReflectivityModel {
     ///Arguments
     /// * length -Total length of the model in samples
     /// * layer_positions-Sample positions where reflections occur
-    /// *reflection_coefficients-Reflection strength at each position
+    /// * reflection_coefficients-Reflection strength at each position
     pub fn new(
         length: usize,
         layer_positions: Vec<usize>,
@@ -48,22 +58,33 @@ impl ReflectivityModel {
 
         Self{
             coefficients,
-            layer_positions: layer_postions.clone(),
+            layer_positions: layer_positions.clone(),
             reflection_coefficients: reflection_coefficients.clone(),
             length,
+            t0: 0.0,
         }
     }
 
+    ///Same model with sample zero at `t0` seconds, e.g. a datum below the recording start
+    pub fn with_t0(mut self, t0: f64)-> Self{
+        self.t0=t0;
+        self
+    }
+
+    ///Time of every model sample for sample interval `dt`
+    pub fn time_axis(&self, dt: f64)-> Vec<f64>{
+        (0..self.length).map(|i| self.t0+i as f64*dt).collect()
+    }
+
+    ///Number of layer positions at or beyond the model length, which `new` leaves out
+    pub fn dropped_layers(&self)-> usize{
+        self.layer_positions.iter().filter(|&&p| p>=self.length).count()
+    }
+
     ///Create a simple layered model with evely spaced reflectors
     pub fn new_layered(length: usize, num_layers: usize, layer_spacing: usize)-> Self{
         let layer_positions: Vec<usize> =(1..=num_layers).map(|i|i*layer_spacing).filter(|&pos| pos<length).collect();
 
-        //Generate alternating positive/negative coefficients
-        let reflection_coefficients: Vec<f64>=layer_positions.iter().map(|_| {
-            let coeff=max_coefficient*(2.0*fastrand::f64()-1.0);
-            coeff
-        }).collect();
-
         //Generate alternating positive/negative coefficients
         let reflection_coefficients: Vec<f64>=layer_positions.iter().enumerate().map(|(i, _)| {
             let base_coeff=0.1;
@@ -91,6 +112,22 @@ impl ReflectivityModel {
             position += initial_spacing+i*(initial_spacing/4);
         }
 
-        let reflection_coefficients: vec<f64>=layer_positions.iter().enumerate().map
+        let reflection_coefficients: Vec<f64>=layer_positions.iter().enumerate().map(|(i, _)| if i%2==0 {0.1} else {-0.1}).collect();
+
+        Ok(Self::new(length, layer_positions, reflection_coefficients))
+    }
+
+    ///Get model statistics
+    pub fn stats(&self)-> ModelStats{
+        let non_zero=self.coefficients.iter().filter(|&&c| c!=0.0).count();
+        ModelStats{
+            sparsity: 1.0-non_zero as f64/self.length.max(1) as f64,
+        }
     }
-}
\ No newline at end of file
+}
+
+///Statistics describing a reflectivity model
+#[derive(Debug)]
+pub struct ModelStats{
+    pub sparsity: f64,
+}
@@ -0,0 +1,85 @@
+//! Gridded 2D velocity models in depth
+
+use anyhow::{Result, anyhow};
+
+///P-wave velocity on a regular (x, z) grid, stored trace by trace (`x` outer)
+#[derive(Debug, Clone)]
+pub struct VelocityModel{
+    pub nx: usize,
+    pub nz: usize,
+    ///Grid spacing in metres
+    pub dx: f64,
+    pub dz: f64,
+    ///Velocity in m/s at `[ix*nz+iz]`
+    pub values: Vec<f64>,
+}
+
+impl VelocityModel{
+    pub fn new(nx: usize, nz: usize, dx: f64, dz: f64, values: Vec<f64>)-> Result<Self>{
+        if nx<2 || nz<2{
+            return Err(anyhow!("Velocity grid needs at least 2x2 nodes, got {}x{}", nx, nz));
+        }
+        if dx<=0.0 || dz<=0.0{
+            return Err(anyhow!("Grid spacing must be positive, got dx {} dz {}", dx, dz));
+        }
+        if values.len()!=nx*nz{
+            return Err(anyhow!("Expected {} velocities for a {}x{} grid, got {}", nx*nz, nx, nz, values.len()));
+        }
+        if values.iter().any(|&v| !(v>0.0 && v.is_finite())){
+            return Err(anyhow!("Velocities must be positive and finite"));
+        }
+        Ok(Self{ nx, nz, dx, dz, values })
+    }
+
+    ///Velocity increasing linearly with depth, `v0+gradient*z`
+    pub fn linear_gradient(nx: usize, nz: usize, dx: f64, dz: f64, v0: f64, gradient: f64)-> Result<Self>{
+        let values=(0..nx).flat_map(|_| (0..nz).map(move |iz| v0+gradient*iz as f64*dz)).collect();
+        Self::new(nx, nz, dx, dz, values)
+    }
+
+    ///Lateral extent in metres
+    pub fn width(&self)-> f64{
+        (self.nx-1) as f64*self.dx
+    }
+
+    ///Depth extent in metres
+    pub fn depth(&self)-> f64{
+        (self.nz-1) as f64*self.dz
+    }
+
+    ///Bilinear velocity and its gradient `(v, dv/dx, dv/dz)`, clamped to the grid edges
+    pub fn sample(&self, x: f64, z: f64)-> (f64, f64, f64){
+        let fx=(x/self.dx).clamp(0.0, (self.nx-1) as f64);
+        let fz=(z/self.dz).clamp(0.0, (self.nz-1) as f64);
+        let ix=(fx.floor() as usize).min(self.nx-2);
+        let iz=(fz.floor() as usize).min(self.nz-2);
+        let (wx, wz)=(fx-ix as f64, fz-iz as f64);
+
+        let at=|i: usize, j: usize| self.values[i*self.nz+j];
+        let (v00, v10, v01, v11)=(at(ix, iz), at(ix+1, iz), at(ix, iz+1), at(ix+1, iz+1));
+        let v=v00*(1.0-wx)*(1.0-wz)+v10*wx*(1.0-wz)+v01*(1.0-wx)*wz+v11*wx*wz;
+        let dvdx=((v10-v00)*(1.0-wz)+(v11-v01)*wz)/self.dx;
+        let dvdz=((v01-v00)*(1.0-wx)+(v11-v10)*wx)/self.dz;
+        (v, dvdx, dvdz)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_gradient_model_sampling()-> Result<()>{
+        let model=VelocityModel::linear_gradient(11, 21, 10.0, 5.0, 1500.0, 0.6)?;
+        let (v, dvdx, dvdz)=model.sample(37.0, 42.5);
+        assert_abs_diff_eq!(v, 1500.0+0.6*42.5, epsilon=1e-9);
+        assert_abs_diff_eq!(dvdx, 0.0, epsilon=1e-12);
+        assert_abs_diff_eq!(dvdz, 0.6, epsilon=1e-9);
+        assert_abs_diff_eq!(model.depth(), 100.0, epsilon=1e-12);
+
+        assert!(VelocityModel::new(2, 2, 1.0, 1.0, vec![1.0; 3]).is_err());
+        assert!(VelocityModel::new(2, 2, 1.0, 1.0, vec![1.0, 1.0, 0.0, 1.0]).is_err());
+        Ok(())
+    }
+}
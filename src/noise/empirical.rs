@@ -0,0 +1,159 @@
+//! Empirical noise models estimated from field data
+//!
+//! A window of recorded data with no signal (before the first break, or
+//! above the shallowest reflector) characterises the survey's noise. Its
+//! amplitude spectrum is estimated by Welch averaging and its amplitude
+//! distribution kept as quantiles of the standardised samples. Simulated
+//! noise is Gaussian noise shaped to that spectrum, then mapped rank by rank
+//! onto the recorded distribution so spiky, heavy-tailed noise stays spiky.
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f64::consts::PI;
+use crate::gather::ensemble::percentile_sorted;
+use super::Rng;
+use super::spectral::{shaped_noise, NoiseSpectrum};
+
+///Number of quantiles kept to describe the amplitude distribution
+const NUM_QUANTILES: usize=201;
+
+///Noise model measured from a window of real data
+#[derive(Debug, Clone)]
+pub struct EmpiricalNoise{
+    ///Welch-averaged amplitude spectrum
+    pub spectrum: NoiseSpectrum,
+    ///RMS amplitude of the window after removing its mean
+    pub rms: f64,
+    ///Evenly spaced quantiles (0 to 100%) of the standardised samples
+    pub quantiles: Vec<f64>,
+    ///Excess kurtosis of the window; zero for Gaussian noise
+    pub excess_kurtosis: f64,
+}
+
+impl EmpiricalNoise{
+    ///Estimate the model from `window` sampled every `dt` seconds
+    ///
+    /// The spectrum averages Hann-windowed segments of `segment_length`
+    /// samples with 50% overlap; shorter segments give a smoother but
+    /// coarser spectrum. Non-finite samples (gaps in the record) are dropped.
+    pub fn estimate(window: &[f64], dt: f64, segment_length: usize)-> Result<Self>{
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+        let window: Vec<f64>=window.iter().copied().filter(|x| x.is_finite()).collect();
+        if segment_length<8 || segment_length>window.len(){
+            return Err(anyhow!("Segment length must be between 8 and the window length {}, got {}", window.len(), segment_length));
+        }
+
+        let mean=window.iter().sum::<f64>()/window.len() as f64;
+        let centred: Vec<f64>=window.iter().map(|x| x-mean).collect();
+        let variance=centred.iter().map(|x| x*x).sum::<f64>()/centred.len() as f64;
+        if variance==0.0{
+            return Err(anyhow!("Noise window is constant"));
+        }
+        let rms=variance.sqrt();
+
+        let mut standardised: Vec<f64>=centred.iter().map(|x| x/rms).collect();
+        let excess_kurtosis=standardised.iter().map(|x| x.powi(4)).sum::<f64>()/standardised.len() as f64-3.0;
+        standardised.sort_by(f64::total_cmp);
+        let quantiles=(0..NUM_QUANTILES).map(|i| percentile_sorted(&standardised, 100.0*i as f64/(NUM_QUANTILES-1) as f64)).collect();
+
+        Ok(Self{ spectrum: welch_spectrum(&centred, dt, segment_length)?, rms, quantiles, excess_kurtosis })
+    }
+
+    ///Simulate `length` samples of noise at sample interval `dt`
+    pub fn generate(&self, rng: &mut dyn Rng, length: usize, dt: f64)-> Result<Vec<f64>>{
+        let gaussian=shaped_noise(rng, length, dt, &self.spectrum, 1.0)?;
+
+        //Keep the ordering of the coloured noise, take the amplitudes from the field distribution
+        let mut order: Vec<usize>=(0..length).collect();
+        order.sort_by(|&a, &b| gaussian[a].total_cmp(&gaussian[b]));
+        let mut noise=vec![0.0; length];
+        for (rank, &index) in order.iter().enumerate(){
+            noise[index]=percentile_sorted(&self.quantiles, 100.0*(rank as f64+0.5)/length as f64);
+        }
+
+        //Quantile interpolation trims the extremes slightly; restore the field RMS exactly
+        let mean=noise.iter().sum::<f64>()/length.max(1) as f64;
+        let current=(noise.iter().map(|x| (x-mean).powi(2)).sum::<f64>()/length.max(1) as f64).sqrt();
+        if current>0.0{
+            noise.iter_mut().for_each(|x| *x=(*x-mean)*self.rms/current);
+        }
+        Ok(noise)
+    }
+}
+
+///Welch amplitude spectrum: average power of Hann-windowed, half-overlapping segments
+fn welch_spectrum(signal: &[f64], dt: f64, segment_length: usize)-> Result<NoiseSpectrum>{
+    let hop=(segment_length/2).max(1);
+    let window: Vec<f64>=(0..segment_length).map(|i| 0.5*(1.0-(2.0*PI*i as f64/segment_length as f64).cos())).collect();
+    let fft=FftPlanner::new().plan_fft_forward(segment_length);
+
+    let bins=segment_length/2+1;
+    let mut power=vec![0.0; bins];
+    let mut segments=0;
+    for start in (0..=signal.len()-segment_length).step_by(hop){
+        let mut buffer: Vec<Complex<f64>>=signal[start..start+segment_length].iter().zip(&window).map(|(x, w)| Complex::new(x*w, 0.0)).collect();
+        fft.process(&mut buffer);
+        for (p, value) in power.iter_mut().zip(&buffer){
+            *p+=value.norm_sqr();
+        }
+        segments+=1;
+    }
+
+    let df=1.0/(segment_length as f64*dt);
+    NoiseSpectrum::new((0..bins).map(|k| k as f64*df).collect(), power.iter().map(|p| (p/segments as f64).sqrt()).collect())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::noise::SeededRng;
+
+    #[test]
+    fn test_recovers_band_and_level()-> Result<()>{
+        let band=NoiseSpectrum::band([20.0, 30.0, 60.0, 80.0])?;
+        let field=shaped_noise(&mut SeededRng::new(1), 8000, 0.002, &band, 0.5)?;
+
+        let model=EmpiricalNoise::estimate(&field, 0.002, 128)?;
+        assert_abs_diff_eq!(model.rms, 0.5, epsilon=0.01);
+        assert!(model.excess_kurtosis.abs()<0.3);
+        let peak=model.spectrum.amplitude.iter().fold(0.0_f64, |m, &a| m.max(a));
+        assert!(model.spectrum.amplitude_at(45.0)>0.7*peak);
+        assert!(model.spectrum.amplitude_at(5.0)<0.1*peak);
+        assert!(model.spectrum.amplitude_at(150.0)<0.1*peak);
+
+        let simulated=model.generate(&mut SeededRng::new(2), 4000, 0.002)?;
+        let again=EmpiricalNoise::estimate(&simulated, 0.002, 128)?;
+        assert_abs_diff_eq!(again.rms, 0.5, epsilon=1e-9);
+        let again_peak=again.spectrum.amplitude.iter().fold(0.0_f64, |m, &a| m.max(a));
+        assert!(again.spectrum.amplitude_at(5.0)<0.1*again_peak);
+        assert!(again.spectrum.amplitude_at(45.0)>0.7*again_peak);
+        Ok(())
+    }
+
+    #[test]
+    fn test_keeps_heavy_tails()-> Result<()>{
+        //Gaussian background with occasional large spikes
+        let mut rng=SeededRng::new(3);
+        let field: Vec<f64>=(0..6000).map(|_| if rng.uniform()<0.01 { 20.0*rng.normal() } else { rng.normal() }).collect();
+
+        let model=EmpiricalNoise::estimate(&field, 0.004, 256)?;
+        assert!(model.excess_kurtosis>10.0);
+        let simulated=model.generate(&mut SeededRng::new(4), 6000, 0.004)?;
+        assert!(EmpiricalNoise::estimate(&simulated, 0.004, 256)?.excess_kurtosis>5.0);
+
+        assert!(EmpiricalNoise::estimate(&field, 0.004, 4).is_err());
+        assert!(EmpiricalNoise::estimate(&[1.0; 100], 0.004, 16).is_err());
+
+        //Gaps recorded as NaN are skipped rather than poisoning the model
+        let mut gappy=field.clone();
+        gappy[100]=f64::NAN;
+        gappy[4000]=f64::INFINITY;
+        let model=EmpiricalNoise::estimate(&gappy, 0.004, 256)?;
+        assert!(model.rms.is_finite() && model.quantiles.iter().all(|q| q.is_finite()));
+        Ok(())
+    }
+}
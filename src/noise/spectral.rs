@@ -0,0 +1,148 @@
+//! Coloured noise: white noise shaped to a target amplitude spectrum
+//!
+//! Field noise is rarely white; ground roll, swell and instrument response
+//! concentrate it in particular bands. Filtering white Gaussian noise by a
+//! target amplitude spectrum gives noise with the same spectral colour, and
+//! rescaling sets its RMS level.
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use super::Rng;
+
+///Relative amplitude spectrum, linearly interpolated between control points
+#[derive(Debug, Clone)]
+pub struct NoiseSpectrum{
+    ///Frequencies in Hz, increasing
+    pub freqs: Vec<f64>,
+    ///Amplitude at each frequency; only the shape matters
+    pub amplitude: Vec<f64>,
+}
+
+impl NoiseSpectrum{
+    pub fn new(freqs: Vec<f64>, amplitude: Vec<f64>)-> Result<Self>{
+        if freqs.is_empty() || freqs.len()!=amplitude.len(){
+            return Err(anyhow!("Need matching, non-empty frequency ({}) and amplitude ({}) lists", freqs.len(), amplitude.len()));
+        }
+        if freqs.windows(2).any(|w| w[1]<=w[0]){
+            return Err(anyhow!("Spectrum frequencies must increase"));
+        }
+        if amplitude.iter().any(|&a| a<0.0 || !a.is_finite()){
+            return Err(anyhow!("Spectrum amplitudes must be finite and non-negative"));
+        }
+        Ok(Self{ freqs, amplitude })
+    }
+
+    ///White noise, flat at all frequencies
+    pub fn white()-> Self{
+        Self{ freqs: vec![0.0], amplitude: vec![1.0] }
+    }
+
+    ///Trapezoidal band with corners `[f1, f2, f3, f4]` in Hz, as for an Ormsby wavelet
+    pub fn band(corners: [f64; 4])-> Result<Self>{
+        let [f1, f2, f3, f4]=corners;
+        if !(0.0<=f1 && f1<f2 && f2<=f3 && f3<f4){
+            return Err(anyhow!("Band corners must satisfy 0 <= f1 < f2 <= f3 < f4, got {:?}", corners));
+        }
+        let (freqs, amplitude)=if f2==f3{
+            (vec![f1, f2, f4], vec![0.0, 1.0, 0.0])
+        }else{
+            (vec![f1, f2, f3, f4], vec![0.0, 1.0, 1.0, 0.0])
+        };
+        Self::new(freqs, amplitude)
+    }
+
+    ///Amplitude at frequency `f`; beyond the control points the end values are held
+    pub fn amplitude_at(&self, f: f64)-> f64{
+        let f=f.abs();
+        let upper=self.freqs.partition_point(|&x| x<f);
+        if upper==0{
+            return self.amplitude[0];
+        }
+        if upper==self.freqs.len(){
+            return self.amplitude[upper-1];
+        }
+        let (f0, f1)=(self.freqs[upper-1], self.freqs[upper]);
+        let weight=(f-f0)/(f1-f0);
+        self.amplitude[upper-1]*(1.0-weight)+self.amplitude[upper]*weight
+    }
+}
+
+///Filter `signal` (sampled every `dt` seconds) by the amplitude spectrum, zero phase
+pub fn shape_spectrum(signal: &[f64], dt: f64, spectrum: &NoiseSpectrum)-> Vec<f64>{
+    let n=signal.len();
+    if n==0{
+        return vec![];
+    }
+
+    let mut planner=FftPlanner::new();
+    let mut buffer: Vec<Complex<f64>>=signal.iter().map(|&x| Complex::new(x, 0.0)).collect();
+    planner.plan_fft_forward(n).process(&mut buffer);
+    for (k, value) in buffer.iter_mut().enumerate(){
+        let bin=k.min(n-k);
+        *value*=spectrum.amplitude_at(bin as f64/(n as f64*dt));
+    }
+    planner.plan_fft_inverse(n).process(&mut buffer);
+
+    buffer.iter().map(|c| c.re/n as f64).collect()
+}
+
+///Gaussian noise with the colour of `spectrum` and the given RMS amplitude
+pub fn shaped_noise(rng: &mut dyn Rng, length: usize, dt: f64, spectrum: &NoiseSpectrum, rms: f64)-> Result<Vec<f64>>{
+    if dt<=0.0{
+        return Err(anyhow!("Sample interval must be positive, got {}", dt));
+    }
+    let white: Vec<f64>=(0..length).map(|_| rng.normal()).collect();
+    let mut shaped=shape_spectrum(&white, dt, spectrum);
+
+    let current=(shaped.iter().map(|x| x*x).sum::<f64>()/length.max(1) as f64).sqrt();
+    if current==0.0{
+        return Err(anyhow!("Spectrum has no energy below Nyquist ({} Hz)", 0.5/dt));
+    }
+    shaped.iter_mut().for_each(|x| *x*=rms/current);
+    Ok(shaped)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::noise::SeededRng;
+    use crate::wavelets::spectrum::spectral_analysis;
+
+    #[test]
+    fn test_shaped_noise_follows_band()-> Result<()>{
+        let spectrum=NoiseSpectrum::band([10.0, 20.0, 40.0, 50.0])?;
+        let noise=shaped_noise(&mut SeededRng::new(8), 4000, 0.002, &spectrum, 0.3)?;
+
+        let rms=(noise.iter().map(|x| x*x).sum::<f64>()/noise.len() as f64).sqrt();
+        assert_abs_diff_eq!(rms, 0.3, epsilon=1e-12);
+
+        let measured=spectral_analysis(&noise, 0.002, 0.0);
+        let (mut inside, mut total)=(0.0, 0.0);
+        for (f, a) in measured.freqs.iter().zip(&measured.amplitude){
+            total+=a*a;
+            if (10.0..=50.0).contains(f){
+                inside+=a*a;
+            }
+        }
+        assert!(inside/total>0.99);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spectrum_interpolation()-> Result<()>{
+        let spectrum=NoiseSpectrum::new(vec![0.0, 10.0, 30.0], vec![1.0, 3.0, 0.0])?;
+        assert_abs_diff_eq!(spectrum.amplitude_at(5.0), 2.0, epsilon=1e-12);
+        assert_abs_diff_eq!(spectrum.amplitude_at(-20.0), 1.5, epsilon=1e-12);
+        assert_abs_diff_eq!(spectrum.amplitude_at(100.0), 0.0, epsilon=1e-12);
+
+        let white: Vec<f64>=(0..64).map(|i| (i as f64).sin()).collect();
+        for (a, b) in shape_spectrum(&white, 0.004, &NoiseSpectrum::white()).iter().zip(&white){
+            assert_abs_diff_eq!(a, b, epsilon=1e-12);
+        }
+        assert!(NoiseSpectrum::new(vec![10.0, 5.0], vec![1.0, 1.0]).is_err());
+        assert!(NoiseSpectrum::band([10.0, 5.0, 40.0, 50.0]).is_err());
+        Ok(())
+    }
+}
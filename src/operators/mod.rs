@@ -0,0 +1,383 @@
+//! Linear operators with adjoints
+//!
+//! Inversion solvers only need to apply an operator and its adjoint, so they are
+//! written against `LinearOperator` and any forward model implementing it, or a
+//! composition of several (wavelet convolution after a derivative, say), can be
+//! plugged in.
+
+use anyhow::{Result, anyhow};
+use crate::inversion::sparse::wavelet_centre;
+use crate::noise::Rng;
+use crate::wavelets::RickerWavelet;
+
+///A linear map from `shape().1` inputs to `shape().0` outputs
+pub trait LinearOperator{
+    ///`(rows, columns)`: lengths of the output and of the input
+    fn shape(&self)-> (usize, usize);
+
+    ///Forward application `A x`
+    fn apply(&self, x: &[f64])-> Vec<f64>;
+
+    ///Adjoint application `A^T y`
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>;
+}
+
+impl<T: LinearOperator + ?Sized> LinearOperator for &T{
+    fn shape(&self)-> (usize, usize){
+        (**self).shape()
+    }
+
+    fn apply(&self, x: &[f64])-> Vec<f64>{
+        (**self).apply(x)
+    }
+
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>{
+        (**self).apply_adjoint(y)
+    }
+}
+
+impl<T: LinearOperator + ?Sized> LinearOperator for Box<T>{
+    fn shape(&self)-> (usize, usize){
+        (**self).shape()
+    }
+
+    fn apply(&self, x: &[f64])-> Vec<f64>{
+        (**self).apply(x)
+    }
+
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>{
+        (**self).apply_adjoint(y)
+    }
+}
+
+///Convolution with a wavelet, trimmed to the input length
+///
+/// Sample `centre` of the wavelet is time zero, so a spike at sample `i`
+/// produces the wavelet centred on sample `i` of the output.
+#[derive(Debug, Clone)]
+pub struct ConvolutionOperator{
+    pub wavelet: Vec<f64>,
+    pub centre: usize,
+    pub length: usize,
+}
+
+impl ConvolutionOperator{
+    pub fn new(wavelet: &[f64], centre: usize, length: usize)-> Result<Self>{
+        if wavelet.is_empty(){
+            return Err(anyhow!("Convolution operator needs a non-empty wavelet"));
+        }
+        if centre>=wavelet.len(){
+            return Err(anyhow!("Wavelet centre {} is outside a wavelet of {} samples", centre, wavelet.len()));
+        }
+        Ok(Self{ wavelet: wavelet.to_vec(), centre, length })
+    }
+
+    ///Convolution with a Ricker wavelet centred on its time zero
+    pub fn from_ricker(wavelet: &RickerWavelet, length: usize)-> Result<Self>{
+        Self::new(&wavelet.samples, wavelet_centre(wavelet), length)
+    }
+
+    ///Output sample hit by input sample `i` and wavelet sample `k`, if inside the trace
+    fn target(&self, i: usize, k: usize)-> Option<usize>{
+        let j=(i+k).checked_sub(self.centre)?;
+        (j<self.length).then_some(j)
+    }
+}
+
+impl LinearOperator for ConvolutionOperator{
+    fn shape(&self)-> (usize, usize){
+        (self.length, self.length)
+    }
+
+    fn apply(&self, x: &[f64])-> Vec<f64>{
+        let mut output=vec![0.0; self.length];
+        for (i, &value) in x.iter().enumerate().take(self.length){
+            if value==0.0{
+                continue;
+            }
+            for (k, &w) in self.wavelet.iter().enumerate(){
+                if let Some(j)=self.target(i, k){
+                    output[j]+=value*w;
+                }
+            }
+        }
+        output
+    }
+
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>{
+        (0..self.length).map(|i| {
+            self.wavelet.iter().enumerate().filter_map(|(k, &w)| self.target(i, k).map(|j| w*y[j])).sum()
+        }).collect()
+    }
+}
+
+///Multiplication by a diagonal matrix, sample by sample
+#[derive(Debug, Clone)]
+pub struct DiagonalOperator{
+    pub diagonal: Vec<f64>,
+}
+
+impl LinearOperator for DiagonalOperator{
+    fn shape(&self)-> (usize, usize){
+        (self.diagonal.len(), self.diagonal.len())
+    }
+
+    fn apply(&self, x: &[f64])-> Vec<f64>{
+        self.diagonal.iter().zip(x).map(|(d, v)| d*v).collect()
+    }
+
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>{
+        self.apply(y)
+    }
+}
+
+///Scaled forward difference, `y[i] = scale*(x[i+1]-x[i])`, with the last output zero
+///
+/// With `scale = 1/dt` this is a first derivative; with `scale = 0.5` applied to
+/// log impedance it gives the small-contrast reflectivity `r = ½ Δln Z`.
+#[derive(Debug, Clone, Copy)]
+pub struct DerivativeOperator{
+    pub length: usize,
+    pub scale: f64,
+}
+
+impl DerivativeOperator{
+    ///First time derivative for samples `dt` seconds apart
+    pub fn new(length: usize, dt: f64)-> Result<Self>{
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+        Ok(Self{ length, scale: 1.0/dt })
+    }
+
+    ///Reflectivity from log impedance, `r[i] = ½(ln Z[i+1] - ln Z[i])`
+    pub fn reflectivity(length: usize)-> Self{
+        Self{ length, scale: 0.5 }
+    }
+}
+
+impl LinearOperator for DerivativeOperator{
+    fn shape(&self)-> (usize, usize){
+        (self.length, self.length)
+    }
+
+    fn apply(&self, x: &[f64])-> Vec<f64>{
+        let mut output: Vec<f64>=x[..self.length].windows(2).map(|w| self.scale*(w[1]-w[0])).collect();
+        output.resize(self.length, 0.0);
+        output
+    }
+
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>{
+        //Each y[i] (i < n-1) adds -scale to x[i] and +scale to x[i+1]
+        (0..self.length).map(|j| {
+            let from_left=if j>0 { y[j-1] } else { 0.0 };
+            let from_right=if j+1<self.length { y[j] } else { 0.0 };
+            self.scale*(from_left-from_right)
+        }).collect()
+    }
+}
+
+///Scaled causal integration, `y[i] = scale*(x[0] + ... + x[i-1])`, so `y[0] = 0`
+///
+/// The sum stops short of `x[i]` so that `DerivativeOperator` with the
+/// reciprocal scale undoes it exactly: with `scale = 2` this turns reflectivity
+/// back into log impedance relative to the first sample.
+#[derive(Debug, Clone, Copy)]
+pub struct IntegrationOperator{
+    pub length: usize,
+    pub scale: f64,
+}
+
+impl IntegrationOperator{
+    ///Time integral for samples `dt` seconds apart
+    pub fn new(length: usize, dt: f64)-> Result<Self>{
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+        Ok(Self{ length, scale: dt })
+    }
+
+    ///Log impedance relative to the first sample from reflectivity, the inverse of `DerivativeOperator::reflectivity`
+    pub fn log_impedance(length: usize)-> Self{
+        Self{ length, scale: 2.0 }
+    }
+}
+
+impl LinearOperator for IntegrationOperator{
+    fn shape(&self)-> (usize, usize){
+        (self.length, self.length)
+    }
+
+    fn apply(&self, x: &[f64])-> Vec<f64>{
+        let mut sum=0.0;
+        x[..self.length].iter().map(|&value| {
+            let output=self.scale*sum;
+            sum+=value;
+            output
+        }).collect()
+    }
+
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>{
+        //Reverse-time sum of the outputs strictly after each input
+        let mut output=vec![0.0; self.length];
+        let mut sum=0.0;
+        for (out, &value) in output.iter_mut().zip(&y[..self.length]).rev(){
+            *out=self.scale*sum;
+            sum+=value;
+        }
+        output
+    }
+}
+
+///Convolutional model of a trace from log impedance, `W ∘ ½D`
+pub fn impedance_operator(wavelet: &RickerWavelet, length: usize)-> Result<Composition<ConvolutionOperator, DerivativeOperator>>{
+    Composition::new(ConvolutionOperator::from_ricker(wavelet, length)?, DerivativeOperator::reflectivity(length))
+}
+
+///`outer ∘ inner`: apply `inner` first, then `outer`
+#[derive(Debug, Clone)]
+pub struct Composition<A, B>{
+    pub outer: A,
+    pub inner: B,
+}
+
+impl<A: LinearOperator, B: LinearOperator> Composition<A, B>{
+    pub fn new(outer: A, inner: B)-> Result<Self>{
+        if outer.shape().1!=inner.shape().0{
+            return Err(anyhow!("Cannot compose {:?} after {:?}: inner output does not match outer input", outer.shape(), inner.shape()));
+        }
+        Ok(Self{ outer, inner })
+    }
+}
+
+impl<A: LinearOperator, B: LinearOperator> LinearOperator for Composition<A, B>{
+    fn shape(&self)-> (usize, usize){
+        (self.outer.shape().0, self.inner.shape().1)
+    }
+
+    fn apply(&self, x: &[f64])-> Vec<f64>{
+        self.outer.apply(&self.inner.apply(x))
+    }
+
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>{
+        self.inner.apply_adjoint(&self.outer.apply_adjoint(y))
+    }
+}
+
+///Largest eigenvalue of `A^T A` (the squared operator norm) by power iteration
+pub fn normal_eigenvalue(operator: &impl LinearOperator, iterations: usize)-> f64{
+    let mut v=vec![1.0; operator.shape().1];
+    let mut eigenvalue=0.0;
+    for _ in 0..iterations{
+        let next=operator.apply_adjoint(&operator.apply(&v));
+        let norm=next.iter().map(|x| x*x).sum::<f64>().sqrt();
+        if norm==0.0{
+            return 0.0;
+        }
+        eigenvalue=norm/v.iter().map(|x| x*x).sum::<f64>().sqrt();
+        v=next.iter().map(|x| x/norm).collect();
+    }
+    eigenvalue
+}
+
+///Relative mismatch of the dot-product test `<A x, y> = <x, A^T y>` for random `x`, `y`
+///
+/// A correct adjoint gives a value at rounding level.
+pub fn adjoint_mismatch(operator: &impl LinearOperator, rng: &mut dyn Rng)-> f64{
+    let (rows, columns)=operator.shape();
+    let x: Vec<f64>=(0..columns).map(|_| rng.uniform()-0.5).collect();
+    let y: Vec<f64>=(0..rows).map(|_| rng.uniform()-0.5).collect();
+
+    let lhs: f64=operator.apply(&x).iter().zip(&y).map(|(a, b)| a*b).sum();
+    let rhs: f64=x.iter().zip(operator.apply_adjoint(&y)).map(|(a, b)| a*b).sum();
+    (lhs-rhs).abs()/lhs.abs().max(rhs.abs()).max(f64::MIN_POSITIVE)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::noise::SeededRng;
+
+    #[test]
+    fn test_convolution_operator()-> Result<()>{
+        let mut rng=SeededRng::new(3);
+        let wavelet=RickerWavelet::new(30.0, 0.001, 61)?;
+        let operator=ConvolutionOperator::from_ricker(&wavelet, 150)?;
+        assert_eq!(operator.shape(), (150, 150));
+        assert!(adjoint_mismatch(&operator, &mut rng)<1e-12);
+
+        //A spike reproduces the wavelet centred on it
+        let mut spike=vec![0.0; 150];
+        spike[75]=1.0;
+        let trace=operator.apply(&spike);
+        let peak=trace.iter().enumerate().fold(0, |best, (i, v)| if v.abs()>trace[best].abs() { i } else { best });
+        assert_eq!(peak, 75);
+
+        assert!(ConvolutionOperator::new(&[1.0, 2.0], 2, 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_composition_and_norm()-> Result<()>{
+        let mut rng=SeededRng::new(4);
+        let wavelet=RickerWavelet::new(25.0, 0.002, 41)?;
+        let inner=ConvolutionOperator::from_ricker(&wavelet, 80)?;
+        let outer=ConvolutionOperator::new(&[2.0], 0, 80)?;
+        let composed=Composition::new(&outer, &inner)?;
+        assert!(adjoint_mismatch(&composed, &mut rng)<1e-12);
+
+        //Scaling by two multiplies A^T A by four
+        assert_abs_diff_eq!(normal_eigenvalue(&composed, 50), 4.0*normal_eigenvalue(&inner, 50), epsilon=1e-6);
+
+        let mismatched=ConvolutionOperator::new(&[1.0], 0, 81)?;
+        assert!(Composition::new(&mismatched, &inner).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_derivative_and_integration()-> Result<()>{
+        let mut rng=SeededRng::new(5);
+        let derivative=DerivativeOperator::new(40, 0.004)?;
+        let integral=IntegrationOperator::new(40, 0.004)?;
+        assert!(adjoint_mismatch(&derivative, &mut rng)<1e-12);
+        assert!(adjoint_mismatch(&integral, &mut rng)<1e-12);
+
+        //A ramp differentiates to its slope
+        let ramp: Vec<f64>=(0..40).map(|i| 3.0*i as f64*0.004).collect();
+        for &value in &derivative.apply(&ramp)[..39]{
+            assert_abs_diff_eq!(value, 3.0, epsilon=1e-9);
+        }
+
+        //Differentiating the integral gives back the input, except the last sample
+        let x: Vec<f64>=(0..40).map(|_| rng.uniform()).collect();
+        let roundtrip=derivative.apply(&integral.apply(&x));
+        for (a, b) in roundtrip[..39].iter().zip(&x){
+            assert_abs_diff_eq!(a, b, epsilon=1e-9);
+        }
+        assert!(DerivativeOperator::new(40, 0.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_impedance_operator()-> Result<()>{
+        let mut rng=SeededRng::new(6);
+        let impedance: Vec<f64>=(0..100).map(|i| if i<50 { 6000.0 } else { 7500.0 }).collect();
+        let log_impedance: Vec<f64>=impedance.iter().map(|z| z.ln()).collect();
+
+        let reflectivity=DerivativeOperator::reflectivity(100).apply(&log_impedance);
+        assert_abs_diff_eq!(reflectivity[49], 0.5*(7500.0_f64/6000.0).ln(), epsilon=1e-12);
+        let recovered=IntegrationOperator::log_impedance(100).apply(&reflectivity);
+        assert_abs_diff_eq!(recovered[99]+log_impedance[0], log_impedance[99], epsilon=1e-12);
+
+        let wavelet=RickerWavelet::new(30.0, 0.002, 51)?;
+        let operator=impedance_operator(&wavelet, 100)?;
+        let trace=operator.apply(&log_impedance);
+        let direct=ConvolutionOperator::from_ricker(&wavelet, 100)?.apply(&reflectivity);
+        for (a, b) in trace.iter().zip(&direct){
+            assert_abs_diff_eq!(a, b, epsilon=1e-12);
+        }
+        assert!(adjoint_mismatch(&operator, &mut rng)<1e-12);
+        Ok(())
+    }
+}
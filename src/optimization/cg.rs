@@ -0,0 +1,111 @@
+//! Matrix-free conjugate gradients for symmetric positive definite systems
+
+use super::preconditioner::{IdentityPreconditioner, Preconditioner};
+
+///Settings for conjugate-gradient solves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CgOptions{
+    pub max_iterations: usize,
+    ///Stop when `||b - Ax|| / ||b||` falls below this
+    pub tolerance: f64,
+}
+
+impl Default for CgOptions{
+    fn default()-> Self{
+        Self{
+            max_iterations: 200,
+            tolerance: 1e-8,
+        }
+    }
+}
+
+///Outcome of a conjugate-gradient solve
+#[derive(Debug, Clone)]
+pub struct CgResult{
+    pub solution: Vec<f64>,
+    pub iterations: usize,
+    ///Final `||b - Ax|| / ||b||`
+    pub relative_residual: f64,
+    pub converged: bool,
+}
+
+///Solve `A x = b` for symmetric positive definite `A`, given only `x -> A x`
+///
+/// Starts from `initial`, which lets repeated solves of slowly changing
+/// systems (inside ADMM, say) warm start.
+pub fn conjugate_gradient(apply: impl Fn(&[f64])-> Vec<f64>, rhs: &[f64], initial: Vec<f64>, options: &CgOptions)-> CgResult{
+    preconditioned_conjugate_gradient(apply, &IdentityPreconditioner, rhs, initial, options)
+}
+
+///Conjugate gradients on `A x = b` with a symmetric positive definite preconditioner
+///
+/// The stopping test uses the unpreconditioned residual so results with and
+/// without a preconditioner are directly comparable.
+pub fn preconditioned_conjugate_gradient(apply: impl Fn(&[f64])-> Vec<f64>, preconditioner: &impl Preconditioner, rhs: &[f64], initial: Vec<f64>, options: &CgOptions)-> CgResult{
+    let rhs_norm=norm(rhs);
+    let mut x=initial;
+    if rhs_norm==0.0{
+        let solution=vec![0.0; rhs.len()];
+        return CgResult{ solution, iterations: 0, relative_residual: 0.0, converged: true };
+    }
+
+    let mut residual: Vec<f64>=rhs.iter().zip(apply(&x)).map(|(b, ax)| b-ax).collect();
+    let mut preconditioned=preconditioner.precondition(&residual);
+    let mut direction=preconditioned.clone();
+    let mut rz=dot(&residual, &preconditioned);
+    let mut iterations=0;
+
+    while iterations<options.max_iterations && norm(&residual)>options.tolerance*rhs_norm{
+        iterations+=1;
+        let a_direction=apply(&direction);
+        let curvature=dot(&direction, &a_direction);
+        if curvature<=0.0{
+            break;
+        }
+        let step=rz/curvature;
+        x.iter_mut().zip(&direction).for_each(|(xi, d)| *xi+=step*d);
+        residual.iter_mut().zip(&a_direction).for_each(|(r, ad)| *r-=step*ad);
+
+        preconditioned=preconditioner.precondition(&residual);
+        let rz_next=dot(&residual, &preconditioned);
+        let beta=rz_next/rz;
+        direction.iter_mut().zip(&preconditioned).for_each(|(d, z)| *d=z+beta*(*d));
+        rz=rz_next;
+    }
+
+    let relative_residual=norm(&residual)/rhs_norm;
+    CgResult{ solution: x, iterations, relative_residual, converged: relative_residual<=options.tolerance }
+}
+
+pub(crate) fn dot(a: &[f64], b: &[f64])-> f64{
+    a.iter().zip(b).map(|(x, y)| x*y).sum()
+}
+
+pub(crate) fn norm(a: &[f64])-> f64{
+    dot(a, a).sqrt()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_cg_solves_spd_system(){
+        //Tridiagonal [-1 4 -1], diagonally dominant
+        let apply=|x: &[f64]| -> Vec<f64> {
+            (0..x.len()).map(|i| {
+                4.0*x[i]-if i>0 { x[i-1] } else { 0.0 }-if i+1<x.len() { x[i+1] } else { 0.0 }
+            }).collect()
+        };
+        let truth: Vec<f64>=(0..30).map(|i| (i as f64*0.3).sin()).collect();
+        let rhs=apply(&truth);
+
+        let result=conjugate_gradient(apply, &rhs, vec![0.0; 30], &CgOptions::default());
+        assert!(result.converged);
+        assert!(result.iterations<=30);
+        for (a, b) in result.solution.iter().zip(&truth){
+            assert_abs_diff_eq!(a, b, epsilon=1e-7);
+        }
+    }
+}
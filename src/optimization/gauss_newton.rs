@@ -0,0 +1,202 @@
+//! Matrix-free Gauss-Newton for nonlinear least squares
+//!
+//! The Gauss-Newton Hessian `JᵀJ` is never formed: a product `JᵀJ v` costs one
+//! application of the linearised forward operator and one of its adjoint. The
+//! normal equations of each step are solved inexactly by conjugate gradients
+//! (truncated Newton), which keeps large inversions within memory.
+
+use anyhow::{Result, anyhow};
+use crate::operators::LinearOperator;
+use super::cg::{conjugate_gradient, dot, CgOptions};
+
+///Gauss-Newton Hessian `JᵀJ + damping I` of a Jacobian operator
+#[derive(Debug, Clone)]
+pub struct GaussNewtonHessian<J>{
+    pub jacobian: J,
+    ///Levenberg-Marquardt damping added to the diagonal
+    pub damping: f64,
+}
+
+impl<J: LinearOperator> LinearOperator for GaussNewtonHessian<J>{
+    fn shape(&self)-> (usize, usize){
+        let columns=self.jacobian.shape().1;
+        (columns, columns)
+    }
+
+    fn apply(&self, v: &[f64])-> Vec<f64>{
+        let mut product=hessian_vector_product(&self.jacobian, v);
+        product.iter_mut().zip(v).for_each(|(p, x)| *p+=self.damping*x);
+        product
+    }
+
+    //Symmetric
+    fn apply_adjoint(&self, v: &[f64])-> Vec<f64>{
+        self.apply(v)
+    }
+}
+
+///`JᵀJ v` from one forward and one adjoint application
+pub fn hessian_vector_product(jacobian: &impl LinearOperator, v: &[f64])-> Vec<f64>{
+    jacobian.apply_adjoint(&jacobian.apply(v))
+}
+
+///A nonlinear forward problem linearised about a model
+pub trait NonlinearProblem{
+    type Jacobian: LinearOperator;
+
+    ///Predicted minus observed data
+    fn residual(&self, model: &[f64])-> Result<Vec<f64>>;
+
+    ///Linearised forward operator at `model`
+    fn jacobian(&self, model: &[f64])-> Result<Self::Jacobian>;
+}
+
+///Settings for the Gauss-Newton outer loop
+#[derive(Debug, Clone)]
+pub struct GaussNewton{
+    pub max_iterations: usize,
+    ///Stop when the relative misfit decrease of a step falls below this
+    pub tolerance: f64,
+    pub damping: f64,
+    ///Inner CG solve; a loose tolerance gives truncated Newton steps
+    pub inner: CgOptions,
+    ///Halvings of the step tried before giving up on a descent direction
+    pub max_backtracks: usize,
+}
+
+impl Default for GaussNewton{
+    fn default()-> Self{
+        Self{
+            max_iterations: 20,
+            tolerance: 1e-8,
+            damping: 1e-6,
+            inner: CgOptions{ max_iterations: 50, tolerance: 1e-3 },
+            max_backtracks: 10,
+        }
+    }
+}
+
+///Outcome of a Gauss-Newton inversion
+#[derive(Debug, Clone)]
+pub struct GaussNewtonResult{
+    pub model: Vec<f64>,
+    ///`½||r||²` at the start and after each accepted step
+    pub misfit_history: Vec<f64>,
+    ///Total inner CG iterations, i.e. Hessian-vector products
+    pub hessian_products: usize,
+    pub converged: bool,
+}
+
+impl GaussNewton{
+    pub fn solve(&self, problem: &impl NonlinearProblem, initial: &[f64])-> Result<GaussNewtonResult>{
+        if self.damping<0.0{
+            return Err(anyhow!("Damping must be non-negative, got {}", self.damping));
+        }
+
+        let mut model=initial.to_vec();
+        let mut residual=problem.residual(&model)?;
+        let mut misfit=0.5*dot(&residual, &residual);
+        let mut misfit_history=vec![misfit];
+        let mut hessian_products=0;
+
+        for _ in 0..self.max_iterations{
+            let jacobian=problem.jacobian(&model)?;
+            if jacobian.shape()!=(residual.len(), model.len()){
+                return Err(anyhow!("Jacobian shape {:?} does not match {} data and {} parameters", jacobian.shape(), residual.len(), model.len()));
+            }
+            let gradient=jacobian.apply_adjoint(&residual);
+            let rhs: Vec<f64>=gradient.iter().map(|g| -g).collect();
+            let hessian=GaussNewtonHessian{ jacobian, damping: self.damping };
+            let step=conjugate_gradient(|v| hessian.apply(v), &rhs, vec![0.0; model.len()], &self.inner);
+            hessian_products+=step.iterations;
+
+            //Backtrack until the misfit decreases
+            let mut scale=1.0;
+            let mut accepted=None;
+            for _ in 0..=self.max_backtracks{
+                let trial: Vec<f64>=model.iter().zip(&step.solution).map(|(m, s)| m+scale*s).collect();
+                let trial_residual=problem.residual(&trial)?;
+                let trial_misfit=0.5*dot(&trial_residual, &trial_residual);
+                if trial_misfit<misfit{
+                    accepted=Some((trial, trial_residual, trial_misfit));
+                    break;
+                }
+                scale*=0.5;
+            }
+            let Some((trial, trial_residual, trial_misfit))=accepted else {
+                return Ok(GaussNewtonResult{ model, misfit_history, hessian_products, converged: true });
+            };
+
+            let decrease=(misfit-trial_misfit)/misfit.max(f64::MIN_POSITIVE);
+            model=trial;
+            residual=trial_residual;
+            misfit=trial_misfit;
+            misfit_history.push(misfit);
+            if decrease<self.tolerance{
+                return Ok(GaussNewtonResult{ model, misfit_history, hessian_products, converged: true });
+            }
+        }
+
+        Ok(GaussNewtonResult{ model, misfit_history, hessian_products, converged: false })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::noise::SeededRng;
+    use crate::operators::{adjoint_mismatch, Composition, ConvolutionOperator, DerivativeOperator, DiagonalOperator};
+    use crate::wavelets::RickerWavelet;
+
+    ///Trace from log impedance with the exact reflectivity `tanh(½Δ ln Z)`
+    struct ImpedanceProblem{
+        convolution: ConvolutionOperator,
+        observed: Vec<f64>,
+    }
+
+    impl ImpedanceProblem{
+        fn reflectivity(log_impedance: &[f64])-> Vec<f64>{
+            DerivativeOperator{ length: log_impedance.len(), scale: 0.5 }.apply(log_impedance).iter().map(|x| x.tanh()).collect()
+        }
+    }
+
+    impl NonlinearProblem for ImpedanceProblem{
+        type Jacobian=Composition<ConvolutionOperator, Composition<DiagonalOperator, DerivativeOperator>>;
+
+        fn residual(&self, model: &[f64])-> Result<Vec<f64>>{
+            let predicted=self.convolution.apply(&Self::reflectivity(model));
+            Ok(predicted.iter().zip(&self.observed).map(|(p, o)| p-o).collect())
+        }
+
+        fn jacobian(&self, model: &[f64])-> Result<Self::Jacobian>{
+            let half=DerivativeOperator{ length: model.len(), scale: 0.5 };
+            let slope=DiagonalOperator{ diagonal: half.apply(model).iter().map(|x| 1.0-x.tanh().powi(2)).collect() };
+            Composition::new(self.convolution.clone(), Composition::new(slope, half)?)
+        }
+    }
+
+    #[test]
+    fn test_gauss_newton_impedance()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.002, 41)?;
+        let convolution=ConvolutionOperator::from_ricker(&wavelet, 120)?;
+        let truth: Vec<f64>=(0..120).map(|i| if (40..80).contains(&i) { 9.2 } else { 8.4 }).collect();
+        let observed=convolution.apply(&ImpedanceProblem::reflectivity(&truth));
+        let problem=ImpedanceProblem{ convolution, observed };
+
+        let initial=vec![8.4; 120];
+        let jacobian=problem.jacobian(&truth)?;
+        let mut rng=SeededRng::new(41);
+        assert!(adjoint_mismatch(&jacobian, &mut rng)<1e-12);
+        assert!(adjoint_mismatch(&GaussNewtonHessian{ jacobian, damping: 0.1 }, &mut rng)<1e-12);
+
+        let result=GaussNewton::default().solve(&problem, &initial)?;
+        let (first, last)=(result.misfit_history[0], *result.misfit_history.last().unwrap());
+        assert!(last<1e-6*first, "misfit {} -> {}", first, last);
+        assert!(result.hessian_products>0);
+        //The contrast is recovered; the absolute level is outside the wavelet band
+        let mean=|range: std::ops::Range<usize>| result.model[range.clone()].iter().sum::<f64>()/range.len() as f64;
+        assert!((mean(45..75)-mean(0..35)-0.8).abs()<0.1);
+
+        Ok(())
+    }
+}
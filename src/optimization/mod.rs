@@ -0,0 +1,139 @@
+//! Shared optimizer machinery: robust misfit functions, IRLS reweighting and solvers
+//!
+//! Iteratively reweighted least squares replaces a robust misfit
+//! `sum rho(r_i/s)` by a sequence of weighted L2 problems with weights
+//! `w_i = psi(u_i)/u_i`, where `psi` is the derivative of `rho` and
+//! `u_i = r_i/s`. Residuals are scaled by a robust estimate `s` of the noise
+//! level so thresholds such as the Huber `delta` are in units of standard
+//! deviations.
+
+use anyhow::{Result, anyhow};
+
+pub mod admm;
+pub mod cg;
+pub mod gauss_newton;
+pub mod multistart;
+pub mod preconditioner;
+
+///Misfit applied to data residuals
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misfit{
+    ///Least squares; optimal for Gaussian noise
+    L2,
+    ///Quadratic within `delta` standard deviations, linear beyond
+    Huber{ delta: f64 },
+    ///Negative log-likelihood of Student's t with `nu` degrees of freedom
+    StudentT{ nu: f64 },
+}
+
+impl Misfit{
+    ///Check the parameters are usable
+    pub fn validate(&self)-> Result<()>{
+        match *self{
+            Misfit::Huber{ delta } if delta<=0.0=> Err(anyhow!("Huber delta must be positive, got {}", delta)),
+            Misfit::StudentT{ nu } if nu<=0.0=> Err(anyhow!("Student-t degrees of freedom must be positive, got {}", nu)),
+            _=> Ok(()),
+        }
+    }
+
+    pub fn is_l2(&self)-> bool{
+        matches!(self, Misfit::L2)
+    }
+
+    ///Penalty for a residual in units of the noise scale
+    pub fn value(&self, u: f64)-> f64{
+        match *self{
+            Misfit::L2=> 0.5*u*u,
+            Misfit::Huber{ delta }=> if u.abs()<=delta { 0.5*u*u } else { delta*u.abs()-0.5*delta*delta },
+            Misfit::StudentT{ nu }=> 0.5*(nu+1.0)*(1.0+u*u/nu).ln(),
+        }
+    }
+
+    ///IRLS weight `psi(u)/u` for a scaled residual
+    pub fn weight(&self, u: f64)-> f64{
+        match *self{
+            Misfit::L2=> 1.0,
+            Misfit::Huber{ delta }=> if u.abs()<=delta { 1.0 } else { delta/u.abs() },
+            Misfit::StudentT{ nu }=> (nu+1.0)/(nu+u*u),
+        }
+    }
+}
+
+///Settings for the outer IRLS loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IrlsOptions{
+    pub max_iterations: usize,
+    ///Stop when the largest weight change falls below this
+    pub tolerance: f64,
+}
+
+impl Default for IrlsOptions{
+    fn default()-> Self{
+        Self{
+            max_iterations: 8,
+            tolerance: 1e-3,
+        }
+    }
+}
+
+///Robust noise scale: 1.4826 times the median absolute deviation from the median
+pub fn robust_scale(residuals: &[f64])-> f64{
+    if residuals.is_empty(){
+        return 0.0;
+    }
+    let median=|values: &mut Vec<f64>| {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n=values.len();
+        if n%2==1 { values[n/2] } else { 0.5*(values[n/2-1]+values[n/2]) }
+    };
+    let centre=median(&mut residuals.to_vec());
+    let mut deviations: Vec<f64>=residuals.iter().map(|r| (r-centre).abs()).collect();
+    1.4826*median(&mut deviations)
+}
+
+///IRLS weights for the current residuals
+///
+/// If the residuals have no spread (e.g. a perfect fit) all weights are one.
+pub fn irls_weights(residuals: &[f64], misfit: &Misfit)-> Vec<f64>{
+    let scale=robust_scale(residuals);
+    if misfit.is_l2() || scale<=f64::EPSILON{
+        return vec![1.0; residuals.len()];
+    }
+    residuals.iter().map(|r| misfit.weight(r/scale)).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_weights_downweight_outliers(){
+        let huber=Misfit::Huber{ delta: 1.5 };
+        assert_eq!(huber.weight(1.0), 1.0);
+        assert_abs_diff_eq!(huber.weight(-6.0), 0.25, epsilon=1e-12);
+        assert_abs_diff_eq!(huber.value(3.0), 1.5*3.0-0.5*2.25, epsilon=1e-12);
+
+        let student=Misfit::StudentT{ nu: 3.0 };
+        assert_abs_diff_eq!(student.weight(0.0), 4.0/3.0, epsilon=1e-12);
+        assert!(student.weight(10.0)<0.05);
+
+        let mut residuals=vec![0.1, -0.2, 0.15, -0.1, 0.05, 0.0, -0.05];
+        residuals.push(20.0);
+        let weights=irls_weights(&residuals, &huber);
+        assert!(weights[7]<0.1);
+        assert_eq!(weights[0], 1.0);
+
+        assert!(Misfit::StudentT{ nu: 0.0 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_robust_scale_of_gaussian(){
+        use crate::noise::{Rng, SeededRng};
+        let mut rng=SeededRng::new(9);
+        let mut samples: Vec<f64>=(0..20000).map(|_| 2.0*rng.normal()).collect();
+        samples[0]=1e6;
+
+        assert_abs_diff_eq!(robust_scale(&samples), 2.0, epsilon=0.05);
+    }
+}
@@ -0,0 +1,175 @@
+//! Multi-start driver for nonlinear inversion
+//!
+//! A local solver only finds the minimum nearest its starting model. Running it
+//! from many randomised starts, ranking the results by misfit and grouping
+//! similar solutions gives a quick check for non-uniqueness: several clusters
+//! with comparable misfit mean the data do not pin the model down.
+
+use anyhow::{Result, anyhow};
+use rayon::prelude::*;
+use crate::noise::{Rng, SeededRng};
+use crate::utils::rms;
+use super::gauss_newton::{GaussNewton, GaussNewtonResult, NonlinearProblem};
+
+///Settings for a multi-start run
+#[derive(Debug, Clone)]
+pub struct MultiStart{
+    pub starts: usize,
+    ///Standard deviation of the Gaussian perturbation added to the reference model
+    pub perturbation: f64,
+    ///Relative RMS model distance within which two solutions share a cluster
+    pub cluster_tolerance: f64,
+    pub solver: GaussNewton,
+    ///Seed for the start perturbations
+    pub seed: u64,
+}
+
+impl Default for MultiStart{
+    fn default()-> Self{
+        Self{
+            starts: 16,
+            perturbation: 0.1,
+            cluster_tolerance: 0.05,
+            solver: GaussNewton::default(),
+            seed: 0,
+        }
+    }
+}
+
+///One inversion of the set
+#[derive(Debug, Clone)]
+pub struct StartRun{
+    ///Order in which the start was drawn
+    pub index: usize,
+    pub initial: Vec<f64>,
+    pub result: GaussNewtonResult,
+    ///Final `½||r||²`
+    pub misfit: f64,
+}
+
+///Solutions within `cluster_tolerance` of each other
+#[derive(Debug, Clone)]
+pub struct SolutionCluster{
+    ///Positions in `MultiStartReport::runs`, best first
+    pub members: Vec<usize>,
+    pub best_misfit: f64,
+}
+
+///All runs ranked by misfit and grouped into clusters
+#[derive(Debug, Clone)]
+pub struct MultiStartReport{
+    ///Runs sorted by increasing misfit
+    pub runs: Vec<StartRun>,
+    ///Clusters sorted by their best misfit
+    pub clusters: Vec<SolutionCluster>,
+}
+
+impl MultiStartReport{
+    pub fn best(&self)-> &StartRun{
+        &self.runs[0]
+    }
+
+    ///Number of clusters whose best misfit is within `factor` of the overall best
+    pub fn competing_clusters(&self, factor: f64)-> usize{
+        let best=self.best().misfit;
+        self.clusters.iter().filter(|c| c.best_misfit<=best*factor+f64::EPSILON).count()
+    }
+
+    pub fn print_summary(&self){
+        println!("Multi-start: {} runs, {} solution clusters", self.runs.len(), self.clusters.len());
+        for (i, cluster) in self.clusters.iter().enumerate(){
+            println!("  Cluster {}: {} runs, best misfit {:.6e}", i+1, cluster.members.len(), cluster.best_misfit);
+        }
+        if self.competing_clusters(2.0)>1{
+            println!("Warning: several distinct solutions fit the data almost equally well");
+        }
+    }
+}
+
+impl MultiStart{
+    ///Invert from `starts` perturbations of `reference` in parallel
+    pub fn run<P>(&self, problem: &P, reference: &[f64])-> Result<MultiStartReport>
+    where P: NonlinearProblem + Sync{
+        if self.starts==0{
+            return Err(anyhow!("Multi-start needs at least one start"));
+        }
+        if self.perturbation<0.0 || self.cluster_tolerance<0.0{
+            return Err(anyhow!("Perturbation and cluster tolerance must be non-negative"));
+        }
+
+        //Draw every start up front so the set is reproducible for a given seed
+        let mut rng=SeededRng::new(self.seed);
+        let initials: Vec<Vec<f64>>=(0..self.starts).map(|_| {
+            reference.iter().map(|m| m+self.perturbation*rng.normal()).collect()
+        }).collect();
+
+        let mut runs=initials.into_par_iter().enumerate().map(|(index, initial)| {
+            let result=self.solver.solve(problem, &initial)?;
+            let misfit=*result.misfit_history.last().unwrap_or(&f64::INFINITY);
+            Ok(StartRun{ index, initial, result, misfit })
+        }).collect::<Result<Vec<_>>>()?;
+        runs.sort_by(|a, b| a.misfit.total_cmp(&b.misfit));
+
+        let clusters=cluster_runs(&runs, self.cluster_tolerance);
+        Ok(MultiStartReport{ runs, clusters })
+    }
+}
+
+///Greedy clustering: each run joins the first cluster whose best model is close enough
+fn cluster_runs(runs: &[StartRun], tolerance: f64)-> Vec<SolutionCluster>{
+    let mut clusters: Vec<SolutionCluster>=Vec::new();
+    for (position, run) in runs.iter().enumerate(){
+        let model=&run.result.model;
+        let home=clusters.iter_mut().find(|cluster| {
+            let representative=&runs[cluster.members[0]].result.model;
+            let distance=rms(&model.iter().zip(representative).map(|(a, b)| a-b).collect::<Vec<_>>());
+            distance<=tolerance*rms(representative).max(f64::MIN_POSITIVE)
+        });
+        match home{
+            Some(cluster)=> cluster.members.push(position),
+            None=> clusters.push(SolutionCluster{ members: vec![position], best_misfit: run.misfit }),
+        }
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::operators::DiagonalOperator;
+
+    ///`m² = 1` for every parameter: each sign is an equally good answer
+    struct SquareProblem;
+
+    impl NonlinearProblem for SquareProblem{
+        type Jacobian=DiagonalOperator;
+
+        fn residual(&self, model: &[f64])-> Result<Vec<f64>>{
+            Ok(model.iter().map(|m| m*m-1.0).collect())
+        }
+
+        fn jacobian(&self, model: &[f64])-> Result<Self::Jacobian>{
+            Ok(DiagonalOperator{ diagonal: model.iter().map(|m| 2.0*m).collect() })
+        }
+    }
+
+    #[test]
+    fn test_multistart_finds_both_minima()-> Result<()>{
+        let driver=MultiStart{ starts: 12, perturbation: 1.0, seed: 61, ..MultiStart::default() };
+        let report=driver.run(&SquareProblem, &[0.0])?;
+
+        assert_eq!(report.runs.len(), 12);
+        assert!(report.runs.windows(2).all(|w| w[0].misfit<=w[1].misfit));
+        assert!(report.best().misfit<1e-12);
+        assert_eq!(report.clusters.len(), 2);
+        assert_eq!(report.competing_clusters(2.0), 2);
+        assert_eq!(report.clusters.iter().map(|c| c.members.len()).sum::<usize>(), 12);
+
+        //A single well-posed start gives one cluster
+        let narrow=MultiStart{ starts: 4, perturbation: 0.1, ..MultiStart::default() };
+        assert_eq!(narrow.run(&SquareProblem, &[2.0])?.clusters.len(), 1);
+        assert!(MultiStart{ starts: 0, ..MultiStart::default() }.run(&SquareProblem, &[0.0]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+//! Preconditioners for the iterative solvers
+//!
+//! A preconditioner applies an approximation of the inverse normal matrix to a
+//! residual. Deep, weak reflectors contribute little to `AᵀA`, so unpreconditioned
+//! CG spends most of its iterations on the shallow part; depth weighting and
+//! Jacobi scaling even this out, while smoothing and band-pass shaping keep
+//! updates within the resolvable band.
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use crate::wavelets::ormsby::ormsby_amplitude_spectrum;
+
+///Symmetric positive definite approximation of an inverse, applied to residuals
+pub trait Preconditioner{
+    fn precondition(&self, residual: &[f64])-> Vec<f64>;
+}
+
+///No preconditioning
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityPreconditioner;
+
+impl Preconditioner for IdentityPreconditioner{
+    fn precondition(&self, residual: &[f64])-> Vec<f64>{
+        residual.to_vec()
+    }
+}
+
+///Sample-by-sample scaling
+#[derive(Debug, Clone)]
+pub struct DiagonalPreconditioner{
+    pub weights: Vec<f64>,
+}
+
+impl DiagonalPreconditioner{
+    pub fn new(weights: Vec<f64>)-> Result<Self>{
+        if weights.iter().any(|&w| !(w>0.0 && w.is_finite())){
+            return Err(anyhow!("Preconditioner weights must be positive and finite"));
+        }
+        Ok(Self{ weights })
+    }
+
+    ///Jacobi preconditioner from the diagonal of the normal matrix
+    pub fn jacobi(diagonal: &[f64])-> Result<Self>{
+        let floor=diagonal.iter().fold(0.0_f64, |m, &d| m.max(d))*1e-12;
+        Self::new(diagonal.iter().map(|&d| 1.0/d.max(floor).max(f64::MIN_POSITIVE)).collect())
+    }
+
+    ///Weights `(1 + i/reference)^exponent` growing with depth (sample index)
+    ///
+    /// With amplitudes decaying like `(1 + i/reference)^-p`, `exponent = 2p`
+    /// balances the normal matrix.
+    pub fn depth_weighting(length: usize, exponent: f64, reference: f64)-> Result<Self>{
+        if reference<=0.0{
+            return Err(anyhow!("Reference depth must be positive, got {}", reference));
+        }
+        Self::new((0..length).map(|i| (1.0+i as f64/reference).powf(exponent)).collect())
+    }
+}
+
+impl Preconditioner for DiagonalPreconditioner{
+    fn precondition(&self, residual: &[f64])-> Vec<f64>{
+        residual.iter().zip(&self.weights).map(|(r, w)| r*w).collect()
+    }
+}
+
+///Zero-phase filtering with a positive gain spectrum
+///
+/// Filtering is circular over the trace length, which keeps the operator
+/// exactly symmetric; a strictly positive gain makes it positive definite.
+#[derive(Debug, Clone)]
+pub struct SpectralPreconditioner{
+    ///Gain for each FFT bin of a trace of `gain.len()` samples
+    gain: Vec<f64>,
+}
+
+impl SpectralPreconditioner{
+    ///Triangle smoothing over `2*half_width+1` samples, plus `floor` times the identity
+    pub fn smoothing(length: usize, half_width: usize, floor: f64)-> Result<Self>{
+        if floor<=0.0{
+            return Err(anyhow!("Smoothing floor must be positive, got {}", floor));
+        }
+        //The triangle is a box convolved with itself, so its spectrum is a squared Dirichlet kernel
+        let width=(half_width+1) as f64;
+        let gain=(0..length).map(|k| {
+            let x=std::f64::consts::PI*k.min(length-k) as f64/length as f64;
+            let dirichlet=if x.abs()<1e-12 { 1.0 } else { (width*x).sin()/(width*x.sin()) };
+            dirichlet*dirichlet+floor
+        }).collect();
+        Ok(Self{ gain })
+    }
+
+    ///Trapezoidal band-pass `[f1, f2, f3, f4]` plus `floor` outside the band
+    pub fn bandpass(length: usize, dt: f64, corners: [f64; 4], floor: f64)-> Result<Self>{
+        if dt<=0.0 || floor<=0.0{
+            return Err(anyhow!("Sample interval and floor must be positive"));
+        }
+        let df=1.0/(length as f64*dt);
+        let gain=(0..length).map(|k| {
+            ormsby_amplitude_spectrum(k.min(length-k) as f64*df, corners)*(1.0-floor)+floor
+        }).collect();
+        Ok(Self{ gain })
+    }
+}
+
+impl Preconditioner for SpectralPreconditioner{
+    fn precondition(&self, residual: &[f64])-> Vec<f64>{
+        let n=self.gain.len();
+        let mut planner=FftPlanner::new();
+        let mut buffer: Vec<Complex<f64>>=residual.iter().map(|&r| Complex::new(r, 0.0)).collect();
+        planner.plan_fft_forward(n).process(&mut buffer);
+        buffer.iter_mut().zip(&self.gain).for_each(|(c, g)| *c*=g);
+        planner.plan_fft_inverse(n).process(&mut buffer);
+        buffer.iter().map(|c| c.re/n as f64).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::noise::{Rng, SeededRng};
+    use crate::operators::{ConvolutionOperator, LinearOperator};
+    use crate::optimization::cg::{conjugate_gradient, preconditioned_conjugate_gradient, CgOptions};
+    use crate::wavelets::RickerWavelet;
+
+    #[test]
+    fn test_depth_weighting_speeds_up_cg()-> Result<()>{
+        //Convolution after spherical-divergence-like decay (1 + i/10)^-1
+        let wavelet=RickerWavelet::new(30.0, 0.002, 41)?;
+        let convolution=ConvolutionOperator::from_ricker(&wavelet, 300)?;
+        let decay: Vec<f64>=(0..300).map(|i| 1.0/(1.0+i as f64/10.0)).collect();
+        //S (WᵀW + εI) S with S the decay
+        let apply=|x: &[f64]| -> Vec<f64> {
+            let scaled: Vec<f64>=x.iter().zip(&decay).map(|(a, d)| a*d).collect();
+            let mut normal=convolution.apply_adjoint(&convolution.apply(&scaled));
+            normal.iter_mut().zip(&scaled).for_each(|(n, v)| *n+=0.05*v);
+            normal.iter().zip(&decay).map(|(a, d)| a*d).collect()
+        };
+        let truth: Vec<f64>=(0..300).map(|i| if i%37==5 { 1.0 } else { 0.0 }).collect();
+        let rhs=apply(&truth);
+
+        let options=CgOptions{ max_iterations: 2000, tolerance: 1e-6 };
+        let plain=conjugate_gradient(apply, &rhs, vec![0.0; 300], &options);
+        let weighting=DiagonalPreconditioner::depth_weighting(300, 2.0, 10.0)?;
+        let weighted=preconditioned_conjugate_gradient(apply, &weighting, &rhs, vec![0.0; 300], &options);
+        assert!(plain.converged && weighted.converged);
+        assert!(2*weighted.iterations<plain.iterations, "{} vs {}", weighted.iterations, plain.iterations);
+
+        assert!(DiagonalPreconditioner::new(vec![1.0, 0.0]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_spectral_preconditioners_are_spd()-> Result<()>{
+        let mut rng=SeededRng::new(51);
+        let n=128;
+        let bandpass=SpectralPreconditioner::bandpass(n, 0.004, [5.0, 10.0, 40.0, 60.0], 0.01)?;
+        let smoothing=SpectralPreconditioner::smoothing(n, 3, 1e-3)?;
+        for preconditioner in [&bandpass, &smoothing]{
+            let x: Vec<f64>=(0..n).map(|_| rng.uniform()-0.5).collect();
+            let y: Vec<f64>=(0..n).map(|_| rng.uniform()-0.5).collect();
+            let xmy: f64=x.iter().zip(preconditioner.precondition(&y)).map(|(a, b)| a*b).sum();
+            let ymx: f64=y.iter().zip(preconditioner.precondition(&x)).map(|(a, b)| a*b).sum();
+            assert!((xmy-ymx).abs()<1e-12);
+            assert!(x.iter().zip(preconditioner.precondition(&x)).map(|(a, b)| a*b).sum::<f64>()>0.0);
+        }
+
+        //An in-band sinusoid passes, an out-of-band one is suppressed to the floor
+        let df=1.0/(n as f64*0.004);
+        let sinusoid=|bin: usize| -> Vec<f64> { (0..n).map(|i| (2.0*std::f64::consts::PI*(bin as f64*df)*i as f64*0.004).cos()).collect() };
+        let ratio=|bin: usize| bandpass.precondition(&sinusoid(bin))[0]/sinusoid(bin)[0];
+        assert!((ratio(12)-1.0).abs()<1e-9);
+        assert!((ratio(50)-0.01).abs()<1e-9);
+
+        Ok(())
+    }
+}
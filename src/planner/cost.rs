@@ -0,0 +1,268 @@
+//! Dry-run cost estimates for batch modelling and inversion jobs
+//!
+//! Every forward model is one FFT convolution (two forward transforms and
+//! one inverse, padded to the next `2^a 3^b 5^c` length), and every inversion iteration
+//! applies the time-domain convolution operator and its adjoint. Counting
+//! those from the job dimensions, and timing a few transforms and
+//! multiply-adds on this machine, predicts the runtime before anything is
+//! run. Disk use follows from the CSV layout each trace will be written in.
+//! The estimate is for sizing sweeps; runtimes are good to a small factor.
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use std::time::Instant;
+use crate::config::RunConfig;
+use crate::convolution::next_fast_len;
+use crate::utils::csv_export::CsvExportOptions;
+use super::format_bytes;
+
+///Transforms per convolution: both inputs forward, the product back
+const FFTS_PER_CONVOLUTION: u64=3;
+///Characters of a full-precision `f64`, e.g. `-0.0123456789012345678`
+const FULL_PRECISION_WIDTH: u64=21;
+///Rough gzip ratio for CSV of noisy floats
+const GZIP_RATIO: f64=0.45;
+
+///Shape of a batch: every model is modelled with every wavelet, `num_realizations` times each
+#[derive(Debug, Clone)]
+pub struct BatchJob{
+    pub model_length: usize,
+    pub wavelet_length: usize,
+    pub num_models: usize,
+    pub num_wavelets: usize,
+    ///Noise realizations per model and wavelet
+    pub num_realizations: usize,
+    ///Sparse inversion iterations per synthetic; zero when nothing is inverted
+    pub inversion_iterations: usize,
+    ///How traces are written; `None` when nothing goes to disk
+    pub csv: Option<CsvExportOptions>,
+}
+
+impl BatchJob{
+    ///One model and wavelet from a run configuration, inverted if a solver is configured
+    pub fn from_config(config: &RunConfig)-> Self{
+        Self{
+            model_length: config.model.length,
+            wavelet_length: config.wavelet.length,
+            num_models: 1,
+            num_wavelets: 1,
+            num_realizations: 1,
+            inversion_iterations: config.solver.as_ref().map_or(0, |s| s.max_iterations),
+            csv: Some(CsvExportOptions::default()),
+        }
+    }
+
+    fn validate(&self)-> Result<()>{
+        if self.model_length==0 || self.wavelet_length==0{
+            return Err(anyhow!("Model and wavelet lengths must be positive"));
+        }
+        if self.num_models==0 || self.num_wavelets==0 || self.num_realizations==0{
+            return Err(anyhow!("Model, wavelet and realization counts must be positive"));
+        }
+        Ok(())
+    }
+
+    ///Number of synthetic traces produced
+    pub fn num_runs(&self)-> u64{
+        (self.num_models*self.num_wavelets*self.num_realizations) as u64
+    }
+
+    ///Length of each synthetic, the full convolution
+    pub fn trace_length(&self)-> usize{
+        self.model_length+self.wavelet_length-1
+    }
+
+    pub fn fft_length(&self)-> usize{
+        next_fast_len(self.trace_length())
+    }
+
+    ///Bytes of one trace written with `options`
+    pub fn csv_bytes_per_trace(options: &CsvExportOptions, length: usize)-> u64{
+        let value_width=match (options.precision, options.scientific){
+            //Sign, leading digit, point, digits and for scientific a three-character exponent
+            (Some(digits), false)=> digits as u64+3,
+            (Some(digits), true)=> digits as u64+6,
+            (None, _)=> FULL_PRECISION_WIDTH,
+        };
+        let index_digits: u64=(0..length).map(|i| i.checked_ilog10().unwrap_or(0) as u64+1).sum();
+        //Times are widest at the end of the trace, plus a sign if they start negative
+        let time_width=options.format_time(length.saturating_sub(1)).map_or(0, |last| {
+            let sign=options.format_time(0).is_some_and(|first| first.starts_with('-'));
+            last.len() as u64+sign as u64+1
+        });
+        //Each column after the index adds a delimiter; every row ends in a newline
+        let body=index_digits+length as u64*(value_width+1+time_width+1);
+        let header=if options.header { if options.time_axis.is_some() { 22 } else { 17 } } else { 0 };
+        let bytes=header+body;
+        if options.gzip { (bytes as f64*GZIP_RATIO) as u64 } else { bytes }
+    }
+
+    ///Count the work and size the output, timing the arithmetic with `calibration`
+    pub fn estimate(&self, calibration: &Calibration)-> Result<CostEstimate>{
+        self.validate()?;
+        let runs=self.num_runs();
+        let fft_length=self.fft_length();
+        let ffts=runs*FFTS_PER_CONVOLUTION;
+        //Operator and adjoint: one multiply-add per model sample and wavelet tap each
+        let inversion_macs=runs*self.inversion_iterations as u64*2*(self.model_length*self.wavelet_length) as u64;
+
+        let runtime=ffts as f64*calibration.fft_seconds(fft_length)+inversion_macs as f64*calibration.seconds_per_mac;
+        let disk=self.csv.as_ref().map_or(0, |options| runs*Self::csv_bytes_per_trace(options, self.trace_length()));
+        Ok(CostEstimate{ runs, fft_length, ffts, inversion_macs, runtime_seconds: runtime, disk_bytes: disk })
+    }
+}
+
+///Measured speed of this machine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration{
+    ///Length of the benchmarked transform
+    pub fft_length: usize,
+    ///Seconds per complex FFT of `fft_length`
+    pub seconds_per_fft: f64,
+    ///Seconds per multiply-add in a time-domain convolution loop
+    pub seconds_per_mac: f64,
+}
+
+impl Calibration{
+    ///Time `repeats` transforms of `fft_length` and a comparable number of multiply-adds
+    pub fn measure(fft_length: usize, repeats: usize)-> Result<Self>{
+        if fft_length<2 || repeats==0{
+            return Err(anyhow!("Need a transform of at least two points and at least one repeat"));
+        }
+        let mut planner=FftPlanner::new();
+        let fft=planner.plan_fft_forward(fft_length);
+        let mut buffer: Vec<Complex<f64>>=(0..fft_length).map(|i| Complex::new((i as f64*0.37).sin(), 0.0)).collect();
+        let start=Instant::now();
+        for _ in 0..repeats{
+            fft.process(&mut buffer);
+            //Keep values bounded across repeats
+            buffer.iter_mut().for_each(|c| *c/=fft_length as f64);
+        }
+        let seconds_per_fft=start.elapsed().as_secs_f64()/repeats as f64;
+
+        let (signal, taps): (Vec<f64>, Vec<f64>)=((0..fft_length).map(|i| (i as f64).cos()).collect(), (0..64).map(|i| 1.0/(i as f64+1.0)).collect());
+        let start=Instant::now();
+        let mut output=vec![0.0; fft_length];
+        for _ in 0..repeats{
+            for (i, &x) in signal.iter().enumerate(){
+                for (k, &w) in taps.iter().enumerate(){
+                    if let Some(out)=output.get_mut(i+k){
+                        *out+=x*w;
+                    }
+                }
+            }
+        }
+        let macs=(repeats*fft_length*taps.len()) as f64;
+        let seconds_per_mac=start.elapsed().as_secs_f64()/macs;
+        //Stop the optimiser discarding the loops
+        std::hint::black_box((&buffer, &output));
+
+        Ok(Self{ fft_length, seconds_per_fft: seconds_per_fft.max(f64::MIN_POSITIVE), seconds_per_mac })
+    }
+
+    ///Predicted time for a transform of `length`, scaled as `n log n` from the benchmark
+    pub fn fft_seconds(&self, length: usize)-> f64{
+        let work=|n: usize| n as f64*(n.max(2) as f64).log2();
+        self.seconds_per_fft*work(length)/work(self.fft_length)
+    }
+}
+
+///Predicted cost of a batch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostEstimate{
+    pub runs: u64,
+    pub fft_length: usize,
+    pub ffts: u64,
+    ///Multiply-adds spent in inversion operators
+    pub inversion_macs: u64,
+    pub runtime_seconds: f64,
+    pub disk_bytes: u64,
+}
+
+impl CostEstimate{
+    pub fn print_summary(&self){
+        println!("Dry run: {} synthetic traces", self.runs);
+        println!("  FFTs: {} of length {}", self.ffts, self.fft_length);
+        if self.inversion_macs>0{
+            println!("  Inversion multiply-adds: {:.3e}", self.inversion_macs as f64);
+        }
+        println!("  Estimated runtime: {}", format_duration(self.runtime_seconds));
+        println!("  Disk: {}", format_bytes(self.disk_bytes));
+    }
+}
+
+///Seconds as `1h 02m`, `3m 05s` or `12.3 s`
+pub fn format_duration(seconds: f64)-> String{
+    if seconds<60.0{
+        return format!("{:.1} s", seconds);
+    }
+    let total=seconds.round() as u64;
+    if total<3600{
+        format!("{}m {:02}s", total/60, total%60)
+    } else {
+        format!("{}h {:02}m", total/3600, total%3600/60)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn job()-> BatchJob{
+        BatchJob{
+            model_length: 1000,
+            wavelet_length: 200,
+            num_models: 10,
+            num_wavelets: 3,
+            num_realizations: 50,
+            inversion_iterations: 0,
+            csv: None,
+        }
+    }
+
+    #[test]
+    fn test_counts_and_disk()-> Result<()>{
+        let calibration=Calibration{ fft_length: 1200, seconds_per_fft: 1e-5, seconds_per_mac: 1e-9 };
+        let estimate=job().estimate(&calibration)?;
+        assert_eq!(estimate.runs, 1500);
+        assert_eq!(estimate.fft_length, 1200);
+        assert_eq!(estimate.ffts, 4500);
+        assert!((estimate.runtime_seconds-0.045).abs()<1e-12);
+        assert_eq!(estimate.disk_bytes, 0);
+
+        let inverted=BatchJob{ inversion_iterations: 100, ..job() }.estimate(&calibration)?;
+        assert_eq!(inverted.inversion_macs, 1500*100*2*200_000);
+        assert!(inverted.runtime_seconds>estimate.runtime_seconds);
+
+        //The byte count matches what the writer produces
+        let options=CsvExportOptions::default().with_precision(4).with_time_axis(0.002, 0.0);
+        let data: Vec<f64>=(0..1199).map(|i| 0.5*(i as f64*0.1).sin()).collect();
+        let mut written=Vec::new();
+        options.write(&data, &mut written)?;
+        let predicted=BatchJob::csv_bytes_per_trace(&options, 1199);
+        //Positive values have no sign character
+        assert!(predicted>=written.len() as u64 && predicted<written.len() as u64+2*1199, "{} vs {}", predicted, written.len());
+
+        let written=BatchJob{ csv: Some(options.clone()), ..job() }.estimate(&calibration)?;
+        assert_eq!(written.disk_bytes, 1500*predicted);
+        assert!(BatchJob{ num_models: 0, ..job() }.estimate(&calibration).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_calibration_and_config()-> Result<()>{
+        let calibration=Calibration::measure(1024, 5)?;
+        assert!(calibration.seconds_per_fft>0.0);
+        assert!(calibration.fft_seconds(4096)>calibration.fft_seconds(1024));
+        assert!(Calibration::measure(1, 5).is_err());
+
+        let job=BatchJob::from_config(&RunConfig::default());
+        assert_eq!(job.trace_length(), 100+200-1);
+        assert_eq!(job.fft_length(), 300);
+        assert_eq!(format_duration(42.0), "42.0 s");
+        assert_eq!(format_duration(185.0), "3m 05s");
+        assert_eq!(format_duration(3720.0), "1h 02m");
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! Memory and disk budgeting for large modelling jobs
+//!
+//! The planner works out peak memory and output size from the job
+//! dimensions before anything is allocated. When a budget would be exceeded
+//! it first runs fewer shots at once (chunking), then keeps only periodic
+//! wavefield checkpoints instead of every snapshot, and refuses the job if
+//! neither fits.
+
+use anyhow::{Result, anyhow};
+
+pub mod cost;
+
+///Floating-point precision of the wavefields
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision{
+    Single,
+    Double,
+}
+
+impl Precision{
+    pub fn bytes(&self)-> u64{
+        match self{
+            Precision::Single=> 4,
+            Precision::Double=> 8,
+        }
+    }
+}
+
+///Dimensions of a modelling job
+#[derive(Debug, Clone)]
+pub struct JobSpec{
+    pub nx: usize,
+    pub nz: usize,
+    pub nt: usize,
+    pub num_shots: usize,
+    ///Receivers recorded per shot
+    pub num_receivers: usize,
+    pub precision: Precision,
+    ///Keep a wavefield snapshot every this many steps (e.g. for imaging); `None` keeps none
+    pub snapshot_interval: Option<usize>,
+    ///Shots modelled at the same time
+    pub concurrent_shots: usize,
+}
+
+///Resource limits; `None` means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Budget{
+    pub memory_bytes: Option<u64>,
+    pub disk_bytes: Option<u64>,
+}
+
+///Estimated resource use of a job
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceEstimate{
+    ///Memory for one shot: model, time levels, snapshots and the shot gather
+    pub memory_per_shot: u64,
+    ///Peak memory with all concurrent shots in flight
+    pub peak_memory: u64,
+    ///Shot gathers written to disk
+    pub disk: u64,
+}
+
+///What the planner decided
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanDecision{
+    ///The job fits as specified
+    Run,
+    ///Run fewer shots at once
+    Chunked{ concurrent_shots: usize },
+    ///Keep only every `interval`-th wavefield in memory and recompute in between
+    Checkpointed{ concurrent_shots: usize, interval: usize },
+    ///The job cannot fit the budget
+    Refused(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecutionPlan{
+    ///Estimate for the job as specified
+    pub requested: ResourceEstimate,
+    ///Estimate after any chunking/checkpointing adjustments
+    pub planned: ResourceEstimate,
+    pub decision: PlanDecision,
+}
+
+impl ExecutionPlan{
+    pub fn is_runnable(&self)-> bool{
+        !matches!(self.decision, PlanDecision::Refused(_))
+    }
+
+    pub fn print_summary(&self){
+        println!("Requested: peak memory {}, disk {}", format_bytes(self.requested.peak_memory), format_bytes(self.requested.disk));
+        println!("Planned:   peak memory {}, disk {}", format_bytes(self.planned.peak_memory), format_bytes(self.planned.disk));
+        match &self.decision{
+            PlanDecision::Run=> println!("Decision: run as specified"),
+            PlanDecision::Chunked{ concurrent_shots }=> println!("Decision: run {} shot(s) at a time", concurrent_shots),
+            PlanDecision::Checkpointed{ concurrent_shots, interval }=>
+                println!("Decision: run {} shot(s) at a time, checkpointing every {} steps", concurrent_shots, interval),
+            PlanDecision::Refused(reason)=> println!("Decision: refused ({})", reason),
+        }
+    }
+}
+
+impl JobSpec{
+    fn validate(&self)-> Result<()>{
+        if self.nx==0 || self.nz==0 || self.nt==0 || self.num_shots==0{
+            return Err(anyhow!("Grid size, time steps and shot count must all be positive"));
+        }
+        if self.concurrent_shots==0{
+            return Err(anyhow!("At least one shot must run at a time"));
+        }
+        if self.snapshot_interval==Some(0){
+            return Err(anyhow!("Snapshot interval must be positive"));
+        }
+        Ok(())
+    }
+
+    ///Estimate with the given concurrency and number of stored wavefields
+    fn estimate_with(&self, concurrent_shots: usize, stored_wavefields: u64)-> ResourceEstimate{
+        let word=self.precision.bytes();
+        let cells=(self.nx*self.nz) as u64;
+        //Velocity and density models, plus three time levels of pressure
+        let model=2*cells*word;
+        let time_levels=3*cells*word;
+        let gather=(self.nt*self.num_receivers) as u64*word;
+        let memory_per_shot=time_levels+stored_wavefields*cells*word+gather;
+
+        ResourceEstimate{
+            memory_per_shot,
+            //The model is shared between concurrent shots
+            peak_memory: model+concurrent_shots.min(self.num_shots) as u64*memory_per_shot,
+            disk: self.num_shots as u64*gather,
+        }
+    }
+
+    fn snapshots(&self)-> u64{
+        self.snapshot_interval.map_or(0, |interval| self.nt.div_ceil(interval) as u64)
+    }
+
+    ///Estimate resource use as specified
+    pub fn estimate(&self)-> Result<ResourceEstimate>{
+        self.validate()?;
+        Ok(self.estimate_with(self.concurrent_shots, self.snapshots()))
+    }
+
+    ///Fit the job to a budget, adjusting concurrency and checkpointing when `auto_adjust` is set
+    pub fn plan(&self, budget: &Budget, auto_adjust: bool)-> Result<ExecutionPlan>{
+        let requested=self.estimate()?;
+        let memory_limit=budget.memory_bytes.unwrap_or(u64::MAX);
+        let refuse=|reason: String| ExecutionPlan{ requested, planned: requested, decision: PlanDecision::Refused(reason) };
+
+        if requested.disk>budget.disk_bytes.unwrap_or(u64::MAX){
+            return Ok(refuse(format!("output needs {} of disk, budget is {}", format_bytes(requested.disk), format_bytes(budget.disk_bytes.unwrap_or(0)))));
+        }
+        if requested.peak_memory<=memory_limit{
+            return Ok(ExecutionPlan{ requested, planned: requested, decision: PlanDecision::Run });
+        }
+        if !auto_adjust{
+            return Ok(refuse(format!("peak memory {} exceeds budget {}", format_bytes(requested.peak_memory), format_bytes(memory_limit))));
+        }
+
+        //Largest concurrency that fits with all snapshots kept
+        let snapshots=self.snapshots();
+        let fits=|concurrent: usize, stored: u64| self.estimate_with(concurrent, stored).peak_memory<=memory_limit;
+        if let Some(concurrent)=(1..self.concurrent_shots).rev().find(|&c| fits(c, snapshots)){
+            return Ok(ExecutionPlan{
+                requested,
+                planned: self.estimate_with(concurrent, snapshots),
+                decision: PlanDecision::Chunked{ concurrent_shots: concurrent },
+            });
+        }
+
+        //One shot at a time, storing as many checkpoints as fit
+        if snapshots>0{
+            let interval=self.snapshot_interval.unwrap_or(1);
+            if let Some(stored)=(1..snapshots).rev().find(|&s| fits(1, s)){
+                let checkpoint_interval=self.nt.div_ceil(stored as usize).max(interval);
+                return Ok(ExecutionPlan{
+                    requested,
+                    planned: self.estimate_with(1, stored),
+                    decision: PlanDecision::Checkpointed{ concurrent_shots: 1, interval: checkpoint_interval },
+                });
+            }
+        }
+
+        Ok(refuse(format!(
+            "a single shot needs {} but the budget is {}",
+            format_bytes(self.estimate_with(1, snapshots.min(1)).peak_memory),
+            format_bytes(memory_limit),
+        )))
+    }
+}
+
+///Human-readable byte count using binary units
+pub fn format_bytes(bytes: u64)-> String{
+    const UNITS: [&str; 5]=["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value=bytes as f64;
+    let mut unit=0;
+    while value>=1024.0 && unit<UNITS.len()-1{
+        value/=1024.0;
+        unit+=1;
+    }
+    if unit==0 { format!("{} B", bytes) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn job()-> JobSpec{
+        JobSpec{
+            nx: 1000,
+            nz: 500,
+            nt: 2000,
+            num_shots: 100,
+            num_receivers: 1000,
+            precision: Precision::Double,
+            snapshot_interval: Some(10),
+            concurrent_shots: 8,
+        }
+    }
+
+    #[test]
+    fn test_estimate_counts_arrays()-> Result<()>{
+        let estimate=job().estimate()?;
+        let cells=500_000u64;
+        let per_shot=3*cells*8+200*cells*8+2_000_000*8;
+        assert_eq!(estimate.memory_per_shot, per_shot);
+        assert_eq!(estimate.peak_memory, 2*cells*8+8*per_shot);
+        assert_eq!(estimate.disk, 100*2_000_000*8);
+
+        let single=JobSpec{ precision: Precision::Single, ..job() }.estimate()?;
+        assert_eq!(single.peak_memory*2, estimate.peak_memory);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_chunks_then_checkpoints_then_refuses()-> Result<()>{
+        let requested=job().estimate()?;
+        let gib=1u64<<30;
+
+        let generous=job().plan(&Budget{ memory_bytes: Some(requested.peak_memory), disk_bytes: None }, true)?;
+        assert_eq!(generous.decision, PlanDecision::Run);
+
+        let chunked=job().plan(&Budget{ memory_bytes: Some(3*gib), disk_bytes: None }, true)?;
+        assert_eq!(chunked.decision, PlanDecision::Chunked{ concurrent_shots: 3 });
+        assert!(chunked.planned.peak_memory<=3*gib);
+
+        let checkpointed=job().plan(&Budget{ memory_bytes: Some(gib/2), disk_bytes: None }, true)?;
+        assert!(matches!(checkpointed.decision, PlanDecision::Checkpointed{ concurrent_shots: 1, .. }));
+        assert!(checkpointed.planned.peak_memory<=gib/2);
+
+        let strict=job().plan(&Budget{ memory_bytes: Some(3*gib), disk_bytes: None }, false)?;
+        assert!(!strict.is_runnable());
+        let tiny=job().plan(&Budget{ memory_bytes: Some(1<<20), disk_bytes: None }, true)?;
+        assert!(!tiny.is_runnable());
+        let no_disk=job().plan(&Budget{ memory_bytes: None, disk_bytes: Some(gib) }, true)?;
+        assert!(!no_disk.is_runnable());
+
+        assert!(JobSpec{ nt: 0, ..job() }.estimate().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_bytes(){
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3*1024*1024), "3.0 MiB");
+    }
+}
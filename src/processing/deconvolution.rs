@@ -0,0 +1,221 @@
+//! Wiener-Levinson spiking and gapped predictive deconvolution
+//!
+//! Both treat the trace as white reflectivity convolved with a minimum-phase
+//! wavelet, so the trace autocorrelation stands in for the wavelet's. A
+//! prediction filter of `n` coefficients forecasts the trace `α` samples
+//! ahead from its past by solving the Toeplitz normal equations
+//! `R p = [r(α), ..., r(α+n-1)]` with Levinson recursion; subtracting the
+//! forecast is the prediction error filter `[1, 0 ×(α-1), -p]`. With a gap
+//! `α` of one sample this is spiking deconvolution, which whitens the
+//! spectrum and compresses the wavelet to a spike. A longer gap leaves the
+//! first `α` samples of the wavelet alone and removes only what is
+//! predictable from further back, such as the period of water-layer
+//! reverberations. Prewhitening adds a fraction of `r(0)` to the zero lag,
+//! as if white noise were present, so the normal equations stay well
+//! conditioned and weak frequencies are not boosted without limit.
+
+use anyhow::{Result, anyhow};
+use crate::gather::Gather;
+use crate::utils::linalg::levinson_solve;
+use super::ProcessingStage;
+
+///Autocorrelation lags `0..lags` of a trace
+pub fn autocorrelation(trace: &[f64], lags: usize)-> Vec<f64>{
+    (0..lags).map(|lag| trace.iter().zip(trace.iter().skip(lag)).map(|(a, b)| a*b).sum()).collect()
+}
+
+///Prediction error filter of `length` prediction coefficients and gap `gap` samples
+///
+/// The returned filter has `gap+length` samples and a leading one, so
+/// the first `gap` samples of the wavelet pass unchanged in amplitude.
+pub fn prediction_error_filter(trace: &[f64], length: usize, gap: usize, prewhitening: f64)-> Result<Vec<f64>>{
+    if length==0 || gap==0{
+        return Err(anyhow!("Operator length and prediction distance must be at least one sample"));
+    }
+    if !(prewhitening>=0.0 && prewhitening.is_finite()){
+        return Err(anyhow!("Prewhitening must not be negative, got {}", prewhitening));
+    }
+    if gap+length>trace.len(){
+        return Err(anyhow!("A {}-sample operator with a {}-sample gap needs more than the trace's {} samples", length, gap, trace.len()));
+    }
+    let mut r=autocorrelation(trace, gap+length);
+    if r[0]<=0.0{
+        return Err(anyhow!("Cannot design a filter from a trace with no energy"));
+    }
+    r[0]*=1.0+prewhitening;
+
+    let prediction=levinson_solve(&r[..length], &r[gap..gap+length])?;
+    let mut filter=vec![0.0; gap+length];
+    filter[0]=1.0;
+    filter[gap..].iter_mut().zip(&prediction).for_each(|(f, p)| *f= -p);
+    Ok(filter)
+}
+
+///Causal convolution of `trace` with `filter`, truncated to the trace length
+pub fn apply_filter(trace: &[f64], filter: &[f64])-> Vec<f64>{
+    (0..trace.len()).map(|i| filter.iter().take(i+1).enumerate().map(|(k, f)| f*trace[i-k]).sum()).collect()
+}
+
+///Spiking deconvolution designed trace by trace
+#[derive(Debug, Clone)]
+pub struct SpikingDeconvolution{
+    ///Operator length in seconds
+    pub operator_length: f64,
+    ///Fraction of the zero-lag autocorrelation added as white noise
+    pub prewhitening: f64,
+}
+
+impl Default for SpikingDeconvolution{
+    fn default()-> Self{
+        Self{
+            operator_length: 0.08,
+            prewhitening: 0.001,
+        }
+    }
+}
+
+///Gapped predictive deconvolution designed trace by trace
+#[derive(Debug, Clone)]
+pub struct PredictiveDeconvolution{
+    ///Operator length in seconds
+    pub operator_length: f64,
+    ///Prediction distance (gap) in seconds
+    pub prediction_distance: f64,
+    ///Fraction of the zero-lag autocorrelation added as white noise
+    pub prewhitening: f64,
+}
+
+impl Default for PredictiveDeconvolution{
+    fn default()-> Self{
+        Self{
+            operator_length: 0.12,
+            prediction_distance: 0.024,
+            prewhitening: 0.001,
+        }
+    }
+}
+
+///Filter every trace with its own prediction error filter; dead traces are left alone
+///
+/// Without a prediction distance the gap is one sample (spiking deconvolution).
+fn deconvolve_gather(gather: &mut Gather, operator_length: f64, prediction_distance: Option<f64>, prewhitening: f64)-> Result<()>{
+    for trace in gather.traces.iter_mut(){
+        if trace.samples.iter().all(|&x| x==0.0){
+            continue;
+        }
+        let samples=|seconds: f64| (seconds/trace.dt).round() as usize;
+        let filter=prediction_error_filter(&trace.samples, samples(operator_length), prediction_distance.map_or(1, samples), prewhitening)?;
+        trace.samples=apply_filter(&trace.samples, &filter);
+    }
+    Ok(())
+}
+
+impl SpikingDeconvolution{
+    ///Spiking deconvolution of one trace with an operator of `length` samples
+    pub fn deconvolve(&self, trace: &[f64], length: usize)-> Result<Vec<f64>>{
+        Ok(apply_filter(trace, &prediction_error_filter(trace, length, 1, self.prewhitening)?))
+    }
+}
+
+impl PredictiveDeconvolution{
+    ///Predictive deconvolution of one trace with operator and gap in samples
+    pub fn deconvolve(&self, trace: &[f64], length: usize, gap: usize)-> Result<Vec<f64>>{
+        Ok(apply_filter(trace, &prediction_error_filter(trace, length, gap, self.prewhitening)?))
+    }
+}
+
+impl ProcessingStage for SpikingDeconvolution{
+    fn name(&self)-> &str{
+        "spiking_deconvolution"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        deconvolve_gather(gather, self.operator_length, None, self.prewhitening)
+    }
+}
+
+impl ProcessingStage for PredictiveDeconvolution{
+    fn name(&self)-> &str{
+        "predictive_deconvolution"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        deconvolve_gather(gather, self.operator_length, Some(self.prediction_distance), self.prewhitening)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::gather::Trace;
+    use crate::noise::{Rng, SeededRng};
+
+    fn sparse_reflectivity(length: usize, seed: u64)-> Vec<f64>{
+        let mut rng=SeededRng::new(seed);
+        (0..length).map(|_| if rng.uniform()<0.1 { 0.2*rng.normal() } else { 0.0 }).collect()
+    }
+
+    fn correlation(a: &[f64], b: &[f64])-> f64{
+        let dot=|x: &[f64], y: &[f64]| x.iter().zip(y).map(|(p, q)| p*q).sum::<f64>();
+        dot(a, b)/(dot(a, a)*dot(b, b)).sqrt()
+    }
+
+    #[test]
+    fn test_spiking_recovers_reflectivity()-> Result<()>{
+        let reflectivity=sparse_reflectivity(2000, 7);
+        //Minimum-phase wavelet (zeros at -0.5 and -0.4)
+        let wavelet=[1.0, 0.9, 0.2];
+        let trace=apply_filter(&reflectivity, &wavelet);
+        assert!(correlation(&trace, &reflectivity)<0.8);
+
+        let output=SpikingDeconvolution::default().deconvolve(&trace, 20)?;
+        assert!(correlation(&output, &reflectivity)>0.98, "{}", correlation(&output, &reflectivity));
+
+        let filter=prediction_error_filter(&trace, 20, 1, 0.0)?;
+        assert_eq!(filter.len(), 21);
+        assert_eq!(filter[0], 1.0);
+        assert!(prediction_error_filter(&trace, 0, 1, 0.0).is_err());
+        assert!(prediction_error_filter(&trace[..10], 20, 1, 0.0).is_err());
+        assert!(prediction_error_filter(&vec![0.0; 100], 10, 1, 0.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_predictive_removes_reverberation()-> Result<()>{
+        //Water-layer reverberation: each bounce 12 samples later with coefficient -0.6
+        let reflectivity=sparse_reflectivity(3000, 9);
+        let mut trace=reflectivity.clone();
+        for i in 12..trace.len(){
+            trace[i]-=0.6*trace[i-12];
+        }
+        assert!(correlation(&trace, &reflectivity)<0.9);
+
+        let decon=PredictiveDeconvolution::default();
+        let output=decon.deconvolve(&trace, 10, 12)?;
+        assert!(correlation(&output, &reflectivity)>0.98, "{}", correlation(&output, &reflectivity));
+        //Primaries within the gap keep their amplitude
+        let filter=prediction_error_filter(&trace, 10, 12, decon.prewhitening)?;
+        assert!(filter[1..12].iter().all(|&f| f==0.0));
+        assert!((filter[12]-0.6).abs()<0.05, "{}", filter[12]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stages_apply_per_trace()-> Result<()>{
+        let dt=0.004;
+        let reflectivity=sparse_reflectivity(1000, 3);
+        let mut gather=Gather::new(vec![
+            Trace::new(apply_filter(&reflectivity, &[1.0, 0.9, 0.2]), dt),
+            Trace::new(vec![0.0; 1000], dt),
+        ])?;
+        SpikingDeconvolution::default().apply(&mut gather)?;
+        assert!(correlation(&gather.traces[0].samples, &reflectivity)>0.95);
+        assert!(gather.traces[1].samples.iter().all(|&x| x==0.0));
+
+        let mut gather=Gather::new(vec![Trace::new(reflectivity.clone(), dt)])?;
+        let stage=PredictiveDeconvolution{ operator_length: 0.0, ..PredictiveDeconvolution::default() };
+        assert!(stage.apply(&mut gather).is_err());
+        assert_eq!(stage.name(), "predictive_deconvolution");
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! Flattening a gather or section along a picked horizon
+//!
+//! Each trace is shifted so the horizon lands at one datum time, turning a
+//! structured event into a flat one. Windowed attributes and thin-bed
+//! analysis around the target then use the same samples on every trace.
+//! Shifts are fractional, applied by frequency-domain phase ramps, and
+//! traces where the horizon is unpicked take a shift interpolated from their
+//! picked neighbours.
+
+use anyhow::{Result, anyhow};
+use crate::gather::Gather;
+use crate::horizon::Horizon;
+use super::ProcessingStage;
+use super::static_shift::fourier_shift;
+
+///Static shift per trace moving `horizon` to `datum`
+#[derive(Debug, Clone)]
+pub struct HorizonFlattening{
+    pub horizon: Horizon,
+    ///Two-way time in seconds the horizon is moved to
+    pub datum: f64,
+}
+
+impl HorizonFlattening{
+    ///Flatten to the mean picked time of the horizon
+    pub fn new(horizon: Horizon)-> Result<Self>{
+        let picks: Vec<f64>=horizon.times.iter().flatten().copied().collect();
+        if picks.is_empty(){
+            return Err(anyhow!("Horizon {} has no picks", horizon.name));
+        }
+        let datum=picks.iter().sum::<f64>()/picks.len() as f64;
+        Ok(Self{ horizon, datum })
+    }
+
+    pub fn with_datum(mut self, datum: f64)-> Self{
+        self.datum=datum;
+        self
+    }
+
+    ///Delay of each trace in seconds; gaps are filled linearly and held past the end picks
+    pub fn shifts(&self)-> Result<Vec<f64>>{
+        let picked: Vec<(usize, f64)>=self.horizon.times.iter().enumerate().filter_map(|(i, t)| t.map(|t| (i, t))).collect();
+        if picked.is_empty(){
+            return Err(anyhow!("Horizon {} has no picks", self.horizon.name));
+        }
+        Ok((0..self.horizon.len()).map(|i| {
+            let after=picked.partition_point(|&(j, _)| j<i);
+            let time=match (after.checked_sub(1).map(|k| picked[k]), picked.get(after)){
+                (_, Some(&(j, t))) if j==i=> t,
+                (Some((a, ta)), Some(&(b, tb)))=> ta+(tb-ta)*(i-a) as f64/(b-a) as f64,
+                (Some((_, t)), None) | (None, Some(&(_, t)))=> t,
+                (None, None)=> unreachable!(),
+            };
+            self.datum-time
+        }).collect())
+    }
+
+    ///The stage that restores the original times
+    pub fn unflattening(&self)-> Result<Unflattening>{
+        Ok(Unflattening{ shifts: self.shifts()?.iter().map(|s| -s).collect() })
+    }
+}
+
+///Delay each `(samples, dt)` trace by its shift in seconds
+fn shift_traces<'a>(traces: impl Iterator<Item=(&'a mut Vec<f64>, f64)>, shifts: &[f64]){
+    for ((samples, dt), &shift) in traces.zip(shifts){
+        *samples=fourier_shift(samples, shift/dt);
+    }
+}
+
+impl ProcessingStage for HorizonFlattening{
+    fn name(&self)-> &str{
+        "horizon_flattening"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        if gather.traces.len()!=self.horizon.len(){
+            return Err(anyhow!("Horizon {} covers {} traces, gather has {}", self.horizon.name, self.horizon.len(), gather.traces.len()));
+        }
+        shift_traces(gather.traces.iter_mut().map(|t| (&mut t.samples, t.dt)), &self.shifts()?);
+        Ok(())
+    }
+}
+
+///Undo a flattening by applying the opposite shifts
+#[derive(Debug, Clone)]
+pub struct Unflattening{
+    ///Delay of each trace in seconds
+    pub shifts: Vec<f64>,
+}
+
+impl ProcessingStage for Unflattening{
+    fn name(&self)-> &str{
+        "horizon_unflattening"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        if gather.traces.len()!=self.shifts.len(){
+            return Err(anyhow!("Have {} shifts for {} traces", self.shifts.len(), gather.traces.len()));
+        }
+        shift_traces(gather.traces.iter_mut().map(|t| (&mut t.samples, t.dt)), &self.shifts);
+        Ok(())
+    }
+}
+
+///Flatten a [trace][sample] section sampled at `dt` along `horizon` to `datum`
+pub fn flatten_section(section: &[Vec<f64>], dt: f64, horizon: &Horizon, datum: f64)-> Result<Vec<Vec<f64>>>{
+    if section.len()!=horizon.len(){
+        return Err(anyhow!("Horizon {} covers {} traces, section has {}", horizon.name, horizon.len(), section.len()));
+    }
+    if dt<=0.0{
+        return Err(anyhow!("Sample interval must be positive, got {}", dt));
+    }
+    let shifts=HorizonFlattening{ horizon: horizon.clone(), datum }.shifts()?;
+    let mut flattened=section.to_vec();
+    shift_traces(flattened.iter_mut().map(|t| (t, dt)), &shifts);
+    Ok(flattened)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::gather::Trace;
+
+    fn pulse(centre: f64)-> Vec<f64>{
+        (0..300).map(|i| (-((i as f64-centre)/4.0).powi(2)).exp()).collect()
+    }
+
+    ///Dipping event at sample `100 + 3.7 i` on trace `i`
+    fn dipping()-> (Vec<Vec<f64>>, Horizon){
+        let dt=0.002;
+        let section=(0..8).map(|i| pulse(100.0+3.7*i as f64)).collect();
+        let mut times: Vec<Option<f64>>=(0..8).map(|i| Some((100.0+3.7*i as f64)*dt)).collect();
+        times[2]=None;
+        times[4]=None;
+        (section, Horizon::new("target", times))
+    }
+
+    #[test]
+    fn test_shifts_fill_gaps()-> Result<()>{
+        let (_, horizon)=dipping();
+        let flattening=HorizonFlattening::new(horizon)?.with_datum(0.3);
+        let shifts=flattening.shifts()?;
+        assert_eq!(shifts.len(), 8);
+        assert_abs_diff_eq!(shifts[4], 0.3-(100.0+3.7*4.0)*0.002, epsilon=1e-12);
+        //Held at the first and last picks beyond them
+        let ends=HorizonFlattening::new(Horizon::new("ends", vec![None, Some(0.2), Some(0.3), None]))?.shifts()?;
+        assert_abs_diff_eq!(ends[0], ends[1], epsilon=1e-12);
+        assert_abs_diff_eq!(ends[3], ends[2], epsilon=1e-12);
+        assert!(HorizonFlattening::new(Horizon::new("empty", vec![None; 3])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_section_aligns_event()-> Result<()>{
+        let (section, horizon)=dipping();
+        let flattened=flatten_section(&section, 0.002, &horizon, 0.3)?;
+        for trace in &flattened{
+            for (a, b) in trace.iter().zip(&pulse(150.0)){
+                assert_abs_diff_eq!(a, b, epsilon=1e-4);
+            }
+        }
+        assert!(flatten_section(&section[..4], 0.002, &horizon, 0.3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stage_round_trip()-> Result<()>{
+        let (section, horizon)=dipping();
+        let original=Gather::new(section.into_iter().map(|s| Trace::new(s, 0.002)).collect())?;
+        let flattening=HorizonFlattening::new(horizon)?;
+
+        let mut gather=original.clone();
+        flattening.apply(&mut gather)?;
+        let peak=|t: &Trace| t.samples.iter().enumerate().max_by(|a, b| a.1.partial_cmp(b.1).unwrap()).unwrap().0;
+        assert!(gather.traces.iter().all(|t| peak(t)==peak(&gather.traces[0])));
+
+        flattening.unflattening()?.apply(&mut gather)?;
+        for (trace, reference) in gather.traces.iter().zip(&original.traces){
+            for (a, b) in trace.samples.iter().zip(&reference.samples){
+                assert_abs_diff_eq!(a, b, epsilon=1e-6);
+            }
+        }
+        assert!(flattening.apply(&mut Gather::new(vec![Trace::new(vec![0.0; 10], 0.002)])?).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Recording-system response: geophone and anti-alias filter
+//!
+//! A moving-coil geophone turns ground velocity into voltage with a
+//! second-order high-pass response set by its natural frequency and damping;
+//! the recorder's anti-alias filter then removes energy approaching Nyquist.
+//! Applying the combined response makes a synthetic look like a field
+//! record (low-frequency roll-off, phase rotation, minimum-phase delay of the
+//! anti-alias filter), and removing it by stabilised spectral division takes
+//! a field record back to ground velocity within the recorded band.
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f64::consts::PI;
+use crate::gather::Gather;
+use super::ProcessingStage;
+
+///Moving-coil geophone, output per unit ground velocity
+#[derive(Debug, Clone, Copy)]
+pub struct Geophone{
+    ///Natural frequency in Hz
+    pub natural_frequency: f64,
+    ///Fraction of critical damping
+    pub damping: f64,
+    ///Output per m/s well above the natural frequency
+    pub sensitivity: f64,
+}
+
+impl Default for Geophone{
+    fn default()-> Self{
+        Self{
+            natural_frequency: 10.0,
+            damping: 0.7,
+            sensitivity: 1.0,
+        }
+    }
+}
+
+impl Geophone{
+    ///`G s² / (s² + 2hω0 s + ω0²)` at `s = 2πif`
+    pub fn response(&self, f: f64)-> Complex<f64>{
+        let s=Complex::new(0.0, 2.0*PI*f);
+        let omega0=2.0*PI*self.natural_frequency;
+        self.sensitivity*s*s/(s*s+2.0*self.damping*omega0*s+omega0*omega0)
+    }
+}
+
+///Analogue Butterworth low-pass standing in for the recorder's anti-alias filter
+#[derive(Debug, Clone, Copy)]
+pub struct AntiAliasFilter{
+    ///-3 dB corner in Hz
+    pub corner: f64,
+    ///Number of poles
+    pub order: usize,
+}
+
+impl AntiAliasFilter{
+    ///Eight-pole filter with its corner at 80% of Nyquist
+    pub fn for_sample_interval(dt: f64)-> Self{
+        Self{ corner: 0.4/dt, order: 8 }
+    }
+
+    ///Minimum-phase response `Π ωc / (s - p_k)` with the poles on the left half circle
+    pub fn response(&self, f: f64)-> Complex<f64>{
+        let s=Complex::new(0.0, 2.0*PI*f);
+        let omega_c=2.0*PI*self.corner;
+        let n=self.order as f64;
+        (1..=self.order).map(|k| {
+            let pole=Complex::from_polar(omega_c, PI*(2.0*k as f64+n-1.0)/(2.0*n));
+            omega_c/(s-pole)
+        }).product()
+    }
+}
+
+///Geophone followed by an anti-alias filter; applying it is a processing stage
+#[derive(Debug, Clone, Default)]
+pub struct InstrumentResponse{
+    pub geophone: Option<Geophone>,
+    pub anti_alias: Option<AntiAliasFilter>,
+}
+
+impl InstrumentResponse{
+    fn validate(&self)-> Result<()>{
+        if let Some(g)=&self.geophone{
+            if !(g.natural_frequency>0.0 && g.damping>0.0 && g.sensitivity>0.0){
+                return Err(anyhow!("Geophone natural frequency, damping and sensitivity must be positive"));
+            }
+        }
+        if let Some(a)=&self.anti_alias{
+            if a.corner<=0.0 || a.order==0{
+                return Err(anyhow!("Anti-alias filter needs a positive corner and at least one pole"));
+            }
+        }
+        Ok(())
+    }
+
+    ///Combined response at frequency `f` Hz
+    pub fn response(&self, f: f64)-> Complex<f64>{
+        let geophone=self.geophone.map_or(Complex::new(1.0, 0.0), |g| g.response(f));
+        let anti_alias=self.anti_alias.map_or(Complex::new(1.0, 0.0), |a| a.response(f));
+        geophone*anti_alias
+    }
+
+    ///The stage that removes this response, stabilised by `water_level`
+    pub fn removal(&self, water_level: f64)-> InstrumentRemoval{
+        InstrumentRemoval{ instrument: self.clone(), water_level }
+    }
+}
+
+///Multiply each trace's spectrum by `filter(response)` at every frequency
+fn filter_traces(gather: &mut Gather, instrument: &InstrumentResponse, filter: impl Fn(Complex<f64>, f64)-> Complex<f64>){
+    let mut planner=FftPlanner::new();
+    for trace in &mut gather.traces{
+        let n=trace.samples.len();
+        if n==0{
+            continue;
+        }
+        let nfft=(2*n).next_power_of_two();
+        let (fft, ifft)=(planner.plan_fft_forward(nfft), planner.plan_fft_inverse(nfft));
+        let response: Vec<Complex<f64>>=(0..=nfft/2).map(|k| instrument.response(k as f64/(nfft as f64*trace.dt))).collect();
+        let peak=response.iter().fold(0.0_f64, |m, h| m.max(h.norm_sqr()));
+
+        let mut buffer: Vec<Complex<f64>>=trace.samples.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        buffer.resize(nfft, Complex::new(0.0, 0.0));
+        fft.process(&mut buffer);
+        for (k, value) in buffer.iter_mut().enumerate(){
+            //Negative frequencies take the conjugate response so the output stays real
+            let h=if k<=nfft/2 { response[k] } else { response[nfft-k].conj() };
+            *value*=filter(h, peak);
+        }
+        ifft.process(&mut buffer);
+        trace.samples=buffer.iter().take(n).map(|c| c.re/nfft as f64).collect();
+    }
+}
+
+impl ProcessingStage for InstrumentResponse{
+    fn name(&self)-> &str{
+        "instrument_response"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        self.validate()?;
+        filter_traces(gather, self, |h, _| h);
+        Ok(())
+    }
+}
+
+///Removal of a recording-system response by `H* / (|H|² + ε max|H|²)`
+#[derive(Debug, Clone)]
+pub struct InstrumentRemoval{
+    pub instrument: InstrumentResponse,
+    ///Water level `ε` relative to the peak power of the response
+    pub water_level: f64,
+}
+
+impl ProcessingStage for InstrumentRemoval{
+    fn name(&self)-> &str{
+        "instrument_removal"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        self.instrument.validate()?;
+        if self.water_level.is_nan() || self.water_level<=0.0{
+            return Err(anyhow!("Water level must be positive, got {}", self.water_level));
+        }
+        filter_traces(gather, &self.instrument, |h, peak| h.conj()/(h.norm_sqr()+self.water_level*peak));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::gather::Trace;
+    use crate::wavelets::RickerWavelet;
+
+    #[test]
+    fn test_response_shape()-> Result<()>{
+        let geophone=Geophone::default();
+        //|H(f0)| = 1/2h, and flat at the sensitivity well above f0
+        assert_abs_diff_eq!(geophone.response(10.0).norm(), 1.0/1.4, epsilon=1e-12);
+        assert_abs_diff_eq!(geophone.response(1000.0).norm(), 1.0, epsilon=1e-3);
+        assert!(geophone.response(1.0).norm()<0.011);
+
+        let anti_alias=AntiAliasFilter::for_sample_interval(0.002);
+        assert_abs_diff_eq!(anti_alias.corner, 200.0, epsilon=1e-9);
+        assert_abs_diff_eq!(anti_alias.response(200.0).norm(), 0.5_f64.sqrt(), epsilon=1e-9);
+        assert_abs_diff_eq!(anti_alias.response(0.0).norm(), 1.0, epsilon=1e-12);
+        assert!(anti_alias.response(250.0).norm()<0.2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_then_remove_round_trip()-> Result<()>{
+        let dt=0.002;
+        let ricker=RickerWavelet::new(30.0, dt, 100)?;
+        let mut samples=vec![0.0; 500];
+        samples[150..250].copy_from_slice(&ricker.samples);
+        let original=Gather::new(vec![Trace::new(samples.clone(), dt); 3])?;
+
+        let instrument=InstrumentResponse{ geophone: Some(Geophone::default()), anti_alias: Some(AntiAliasFilter::for_sample_interval(dt)) };
+        let mut gather=original.clone();
+        instrument.apply(&mut gather)?;
+        //The recorded pulse is delayed and reshaped
+        let error=|a: &[f64], b: &[f64]| (a.iter().zip(b).map(|(x, y)| (x-y)*(x-y)).sum::<f64>()/b.iter().map(|y| y*y).sum::<f64>()).sqrt();
+        assert!(error(&gather.traces[0].samples, &samples)>0.1);
+
+        instrument.removal(1e-6).apply(&mut gather)?;
+        assert!(error(&gather.traces[0].samples, &samples)<0.02, "error {}", error(&gather.traces[0].samples, &samples));
+
+        let broken=InstrumentResponse{ geophone: Some(Geophone{ damping: 0.0, ..Geophone::default() }), anti_alias: None };
+        assert!(broken.apply(&mut gather).is_err());
+        assert!(instrument.removal(0.0).apply(&mut gather).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! Processing stages that can be chained and registered on a pipeline
+
+use anyhow::{Result, anyhow};
+use crate::gather::ensemble::percentile_sorted;
+use crate::gather::Gather;
+
+pub mod deconvolution;
+pub mod flatten;
+pub mod instrument;
+pub mod iterative_decon;
+pub mod polarization;
+pub mod static_shift;
+pub mod total_variation;
+
+///A processing step applied in place to a gather
+pub trait ProcessingStage: Send{
+    ///Short name used in logs
+    fn name(&self)-> &str;
+
+    ///Process the gather in place
+    fn apply(&self, gather: &mut Gather)-> Result<()>;
+}
+
+///Ordered list of processing stages
+#[derive(Default)]
+pub struct ProcessingChain{
+    stages: Vec<Box<dyn ProcessingStage>>,
+}
+
+impl ProcessingChain{
+    pub fn new()-> Self{
+        Self::default()
+    }
+
+    ///Append a stage; stages run in the order they were added
+    pub fn add(&mut self, stage: Box<dyn ProcessingStage>){
+        self.stages.push(stage);
+    }
+
+    pub fn len(&self)-> usize{
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.stages.is_empty()
+    }
+
+    ///Names of the registered stages in order
+    pub fn names(&self)-> Vec<&str>{
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    ///Run every stage over the gather
+    pub fn run(&self, gather: &mut Gather)-> Result<()>{
+        for stage in &self.stages{
+            stage.apply(gather).map_err(|e| anyhow!("Stage '{}' failed: {}", stage.name(), e))?;
+        }
+        Ok(())
+    }
+}
+
+///RMS equalisation: scale every trace to a common RMS amplitude
+///
+/// With no explicit target the gather's mean trace RMS is used, so relative
+/// source-strength variations are removed while the overall level is kept.
+#[derive(Debug, Clone, Default)]
+pub struct TraceBalance{
+    pub target_rms: Option<f64>,
+}
+
+impl ProcessingStage for TraceBalance{
+    fn name(&self)-> &str{
+        "trace_balance"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        let rms: Vec<f64>=gather.traces.iter().map(|t| {
+            (t.samples.iter().map(|x| x*x).sum::<f64>()/t.len().max(1) as f64).sqrt()
+        }).collect();
+
+        let live: Vec<f64>=rms.iter().copied().filter(|&r| r>0.0).collect();
+        if live.is_empty(){
+            return Ok(());
+        }
+        let target=self.target_rms.unwrap_or(live.iter().sum::<f64>()/live.len() as f64);
+
+        for (trace, &trace_rms) in gather.traces.iter_mut().zip(rms.iter()){
+            //Dead traces stay dead
+            if trace_rms>0.0{
+                let factor=target/trace_rms;
+                trace.samples.iter_mut().for_each(|x| *x*=factor);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+///Robust per-trace scaling: map a percentile of |amplitude| to a target value
+///
+/// Less sensitive to isolated spikes than RMS balancing; the 98th percentile
+/// is a typical choice.
+#[derive(Debug, Clone)]
+pub struct PercentileScaling{
+    ///Percentile (0-100) of absolute amplitude to normalise
+    pub percentile: f64,
+    ///Value that percentile is scaled to
+    pub target: f64,
+}
+
+impl Default for PercentileScaling{
+    fn default()-> Self{
+        Self{
+            percentile: 98.0,
+            target: 1.0,
+        }
+    }
+}
+
+impl ProcessingStage for PercentileScaling{
+    fn name(&self)-> &str{
+        "percentile_scaling"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        if !(0.0..=100.0).contains(&self.percentile){
+            return Err(anyhow!("Percentile must be in [0, 100], got {}", self.percentile));
+        }
+
+        for trace in &mut gather.traces{
+            //Gaps and NaNs in field data must not set or break the reference level
+            let mut magnitudes: Vec<f64>=trace.samples.iter().filter(|x| x.is_finite()).map(|x| x.abs()).collect();
+            magnitudes.sort_by(f64::total_cmp);
+
+            let reference=percentile_sorted(&magnitudes, self.percentile);
+            if reference>0.0{
+                let factor=self.target/reference;
+                trace.samples.iter_mut().for_each(|x| *x*=factor);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::gather::Trace;
+    use crate::utils::rms;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_trace_balance()-> Result<()>{
+        let mut gather=Gather::new(vec![
+            Trace::new(vec![1.0, -1.0, 1.0, -1.0], 0.002),
+            Trace::new(vec![3.0, -3.0, 3.0, -3.0], 0.002),
+            Trace::new(vec![0.0; 4], 0.002),
+        ])?;
+
+        TraceBalance::default().apply(&mut gather)?;
+
+        assert_abs_diff_eq!(rms(&gather.traces[0].samples), 2.0, epsilon=1e-12);
+        assert_abs_diff_eq!(rms(&gather.traces[1].samples), 2.0, epsilon=1e-12);
+        assert_eq!(gather.traces[2].samples, vec![0.0; 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_percentile_scaling_ignores_spike()-> Result<()>{
+        let mut samples=vec![0.5; 99];
+        samples.push(50.0);
+        let mut gather=Gather::new(vec![Trace::new(samples, 0.002)])?;
+
+        let stage=PercentileScaling{ percentile: 90.0, target: 1.0 };
+        stage.apply(&mut gather)?;
+
+        assert_abs_diff_eq!(gather.traces[0].samples[0], 1.0, epsilon=1e-12);
+
+        let mut samples=vec![0.5; 99];
+        samples.push(f64::NAN);
+        let mut gather=Gather::new(vec![Trace::new(samples, 0.002)])?;
+        stage.apply(&mut gather)?;
+        assert_abs_diff_eq!(gather.traces[0].samples[0], 1.0, epsilon=1e-12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chain_runs_in_order()-> Result<()>{
+        let mut chain=ProcessingChain::new();
+        chain.add(Box::new(TraceBalance{ target_rms: Some(4.0) }));
+        chain.add(Box::new(PercentileScaling{ percentile: 100.0, target: 1.0 }));
+        assert_eq!(chain.names(), vec!["trace_balance", "percentile_scaling"]);
+
+        let mut gather=Gather::new(vec![Trace::new(vec![2.0, -1.0], 0.002)])?;
+        chain.run(&mut gather)?;
+
+        assert_abs_diff_eq!(gather.traces[0].samples[0], 1.0, epsilon=1e-12);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+//! Three-component polarization analysis, LQT rotation and polarization filtering
+//!
+//! The covariance of the Z, X and Y samples in a short window has one large
+//! eigenvalue for straight-line particle motion (body waves) and two
+//! comparable ones for elliptical motion in a plane (Rayleigh waves). Its
+//! principal eigenvector gives the direction of motion: the back-azimuth and
+//! incidence angle needed to rotate a station into L (along the ray), Q and T.
+//! Weighting each sample by the local rectilinearity keeps body waves and
+//! suppresses ground roll (Montalbetti & Kanasewich, 1970).
+
+use anyhow::{Result, anyhow};
+use crate::gather::components::MultiComponentTrace;
+use crate::gather::{Component, Gather, Trace};
+use crate::utils::linalg::symmetric_eigen;
+use super::ProcessingStage;
+
+///Polarization of the particle motion in one window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Polarization{
+    ///`1 - λ2/λ1`: 1 for straight-line motion, 0 for circular (Flinn)
+    pub rectilinearity: f64,
+    ///`1 - 2λ3/(λ1+λ2)`: 1 for motion confined to a plane (Jurkevics)
+    pub planarity: f64,
+    ///Horizontal direction of the principal axis in degrees from X towards Y, in [0, 360)
+    pub azimuth: f64,
+    ///Angle of the principal axis from vertical in degrees, in [0, 90]
+    pub incidence: f64,
+}
+
+fn components(station: &MultiComponentTrace)-> Result<[&Trace; 3]>{
+    let get=|c: Component| station.get(c).ok_or_else(|| anyhow!("Polarization needs Z, X and Y; station has no {:?}", c));
+    Ok([get(Component::Z)?, get(Component::X)?, get(Component::Y)?])
+}
+
+///Polarization of the Z, X and Y samples in `window`
+///
+/// The principal axis is taken pointing upwards, so the azimuth is the
+/// direction of horizontal motion for upward motion (away from the source
+/// for a compressional P arrival from below).
+pub fn polarization(station: &MultiComponentTrace, window: std::ops::Range<usize>)-> Result<Polarization>{
+    let traces=components(station)?;
+    if window.is_empty() || window.end>traces[0].len(){
+        return Err(anyhow!("Window {:?} is empty or outside the {} samples", window, traces[0].len()));
+    }
+    let n=window.len() as f64;
+    let means: Vec<f64>=traces.iter().map(|t| t.samples[window.clone()].iter().sum::<f64>()/n).collect();
+    let mut covariance=vec![vec![0.0; 3]; 3];
+    for k in window{
+        let x: Vec<f64>=(0..3).map(|i| traces[i].samples[k]-means[i]).collect();
+        for i in 0..3{
+            for j in 0..3{
+                covariance[i][j]+=x[i]*x[j]/n;
+            }
+        }
+    }
+
+    let pairs=symmetric_eigen(covariance)?;
+    let (l1, l2, l3)=(pairs[0].0.max(0.0), pairs[1].0.max(0.0), pairs[2].0.max(0.0));
+    if l1==0.0{
+        return Ok(Polarization{ rectilinearity: 0.0, planarity: 0.0, azimuth: 0.0, incidence: 0.0 });
+    }
+    let sign=if pairs[0].1[0]<0.0 { -1.0 } else { 1.0 };
+    let axis: Vec<f64>=pairs[0].1.iter().map(|v| sign*v).collect();
+    Ok(Polarization{
+        rectilinearity: 1.0-l2/l1,
+        planarity: if l1+l2>0.0 { 1.0-2.0*l3/(l1+l2) } else { 0.0 },
+        azimuth: axis[2].atan2(axis[1]).to_degrees().rem_euclid(360.0),
+        incidence: axis[0].abs().min(1.0).acos().to_degrees(),
+    })
+}
+
+///Polarization in a window of `2 half_window + 1` samples centred on every sample
+pub fn polarization_attributes(station: &MultiComponentTrace, half_window: usize)-> Result<Vec<Polarization>>{
+    let length=components(station)?[0].len();
+    (0..length).map(|k| polarization(station, k.saturating_sub(half_window)..(k+half_window+1).min(length))).collect()
+}
+
+///Rotate Z, X, Y to L, Q, T for a source-to-receiver azimuth and incidence angle in degrees
+///
+/// With radial `R = cos a X + sin a Y`: `L = cos i Z + sin i R`,
+/// `Q = -sin i Z + cos i R`, `T = -sin a X + cos a Y`.
+pub fn rotate_to_lqt(station: &MultiComponentTrace, azimuth: f64, incidence: f64)-> Result<MultiComponentTrace>{
+    let zrt=station.rotate_to_radial_transverse(azimuth)?;
+    let get=|c: Component| zrt.get(c).ok_or_else(|| anyhow!("Station has no {:?} component", c));
+    let (z, radial)=(get(Component::Z)?, get(Component::Radial)?);
+    let (sin, cos)=incidence.to_radians().sin_cos();
+    let combine=|a: f64, b: f64, component: Component| {
+        let samples=z.samples.iter().zip(&radial.samples).map(|(&zs, &rs)| a*zs+b*rs).collect();
+        Trace::new(samples, z.dt).with_t0(z.t0).with_component(component)
+    };
+    let mut traces: Vec<Trace>=zrt.traces.iter().filter(|t| !matches!(t.component, Some(Component::Z | Component::Radial))).cloned().collect();
+    traces.insert(0, combine(-sin, cos, Component::Q));
+    traces.insert(0, combine(cos, sin, Component::L));
+    MultiComponentTrace::new(traces)
+}
+
+///Weight every component by the local rectilinearity raised to `exponent`
+#[derive(Debug, Clone)]
+pub struct PolarizationFilter{
+    ///Half length of the analysis window in samples
+    pub half_window: usize,
+    ///Larger values reject elliptical motion more strongly
+    pub exponent: f64,
+}
+
+impl Default for PolarizationFilter{
+    fn default()-> Self{
+        Self{
+            half_window: 10,
+            exponent: 2.0,
+        }
+    }
+}
+
+impl ProcessingStage for PolarizationFilter{
+    fn name(&self)-> &str{
+        "polarization_filter"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        if self.exponent<0.0{
+            return Err(anyhow!("Exponent must not be negative, got {}", self.exponent));
+        }
+        let mut stations=gather.stations()?;
+        for station in &mut stations{
+            let weights: Vec<f64>=polarization_attributes(station, self.half_window)?.iter().map(|p| p.rectilinearity.powf(self.exponent)).collect();
+            for trace in &mut station.traces{
+                trace.samples.iter_mut().zip(&weights).for_each(|(x, w)| *x*=w);
+            }
+        }
+        *gather=Gather::from_stations(&stations)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn pulse(centre: f64, width: f64)-> Vec<f64>{
+        (0..400).map(|i| (-((i as f64-centre)/width).powi(2)).exp()).collect()
+    }
+
+    fn station(z: Vec<f64>, x: Vec<f64>, y: Vec<f64>)-> Result<MultiComponentTrace>{
+        MultiComponentTrace::new(vec![
+            Trace::new(z, 0.002).with_component(Component::Z),
+            Trace::new(x, 0.002).with_component(Component::X),
+            Trace::new(y, 0.002).with_component(Component::Y),
+        ])
+    }
+
+    ///P arrival with the given azimuth and incidence, then a retrograde Rayleigh wave along X
+    fn record()-> Result<MultiComponentTrace>{
+        let (azimuth, incidence)=(40.0_f64.to_radians(), 30.0_f64.to_radians());
+        let p=pulse(100.0, 4.0);
+        let envelope=pulse(280.0, 25.0);
+        let phase=|i: usize| 2.0*std::f64::consts::PI*i as f64/20.0;
+        let z=(0..400).map(|i| incidence.cos()*p[i]+envelope[i]*phase(i).sin()).collect();
+        let x=(0..400).map(|i| incidence.sin()*azimuth.cos()*p[i]+envelope[i]*phase(i).cos()).collect();
+        let y=(0..400).map(|i| incidence.sin()*azimuth.sin()*p[i]).collect();
+        station(z, x, y)
+    }
+
+    #[test]
+    fn test_polarization_and_lqt_rotation()-> Result<()>{
+        let station=record()?;
+        let p=polarization(&station, 90..111)?;
+        assert_abs_diff_eq!(p.rectilinearity, 1.0, epsilon=1e-9);
+        assert_abs_diff_eq!(p.azimuth, 40.0, epsilon=1e-6);
+        assert_abs_diff_eq!(p.incidence, 30.0, epsilon=1e-6);
+
+        let rayleigh=polarization(&station, 260..300)?;
+        assert!(rayleigh.rectilinearity<0.2, "{:?}", rayleigh);
+        assert!(rayleigh.planarity>0.99);
+
+        let lqt=rotate_to_lqt(&station, p.azimuth, p.incidence)?;
+        assert_eq!(lqt.components(), vec![Component::L, Component::Q, Component::Transverse]);
+        let l=lqt.get(Component::L).unwrap();
+        assert_abs_diff_eq!(l.samples[100], 1.0, epsilon=1e-9);
+        for c in [Component::Q, Component::Transverse]{
+            assert!(lqt.get(c).unwrap().samples[80..120].iter().all(|v| v.abs()<1e-9));
+        }
+        assert!(polarization(&lqt, 0..10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_keeps_body_waves()-> Result<()>{
+        let station=record()?;
+        let mut gather=Gather::from_stations(&[station.clone(), station.clone()])?;
+        PolarizationFilter::default().apply(&mut gather)?;
+        assert_eq!(gather.len(), 6);
+
+        let energy=|t: &Trace, range: std::ops::Range<usize>| t.samples[range].iter().map(|x| x*x).sum::<f64>();
+        let (before, after)=(station.get(Component::Z).unwrap(), &gather.traces[3]);
+        assert!(energy(after, 80..120)>0.8*energy(before, 80..120));
+        assert!(energy(after, 230..330)<0.05*energy(before, 230..330));
+        assert!(PolarizationFilter{ exponent: -1.0, ..PolarizationFilter::default() }.apply(&mut gather).is_err());
+        Ok(())
+    }
+}
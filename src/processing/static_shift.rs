@@ -0,0 +1,151 @@
+//! Fractional-sample time shifts by frequency-domain phase ramps
+//!
+//! Delaying a trace by `s` samples multiplies its spectrum by `exp(-i w s)`,
+//! which is exact for any `s` on a band-limited trace. The trace is zero
+//! padded beyond the shift before the FFT so nothing wraps around.
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f64::consts::PI;
+use crate::gather::Gather;
+use crate::noise::Rng;
+use crate::operators::LinearOperator;
+use super::ProcessingStage;
+
+///Delay `trace` by `shift` samples (fractional allowed); negative shifts advance it
+pub fn fourier_shift(trace: &[f64], shift: f64)-> Vec<f64>{
+    let length=trace.len();
+    if length==0{
+        return vec![];
+    }
+    //Depends on |shift| only, so a shift and its reverse share one grid and are exact adjoints
+    let n=(2*length+shift.abs().ceil() as usize).next_power_of_two();
+
+    let mut planner=FftPlanner::new();
+    let mut buffer: Vec<Complex<f64>>=trace.iter().map(|&x| Complex::new(x, 0.0)).collect();
+    buffer.resize(n, Complex::new(0.0, 0.0));
+    planner.plan_fft_forward(n).process(&mut buffer);
+
+    for (k, value) in buffer.iter_mut().enumerate(){
+        //Signed frequency so the ramp is Hermitian and the output real
+        let bin=if 2*k>n { k as f64-n as f64 } else { k as f64 };
+        let omega=2.0*PI*bin/n as f64;
+        *value*=if 2*k==n { Complex::new((omega*shift).cos(), 0.0) } else { Complex::from_polar(1.0, -omega*shift) };
+    }
+
+    planner.plan_fft_inverse(n).process(&mut buffer);
+    buffer.iter().take(length).map(|c| c.re/n as f64).collect()
+}
+
+///Constant fractional delay of a fixed-length trace as a linear operator
+///
+/// The adjoint is the opposite shift, so the operator can sit inside
+/// least-squares solves for statics or timing errors.
+#[derive(Debug, Clone)]
+pub struct StaticShiftOperator{
+    pub length: usize,
+    ///Delay in samples
+    pub shift: f64,
+}
+
+impl LinearOperator for StaticShiftOperator{
+    fn shape(&self)-> (usize, usize){
+        (self.length, self.length)
+    }
+
+    fn apply(&self, model: &[f64])-> Vec<f64>{
+        fourier_shift(model, self.shift)
+    }
+
+    fn apply_adjoint(&self, data: &[f64])-> Vec<f64>{
+        fourier_shift(data, -self.shift)
+    }
+}
+
+///Per-trace static shifts in seconds, applied as a processing stage
+///
+/// Positive statics delay a trace. Use `random` to simulate near-surface
+/// statics and `correcting` to remove a known set.
+#[derive(Debug, Clone, Default)]
+pub struct StaticShift{
+    pub shifts: Vec<f64>,
+}
+
+impl StaticShift{
+    pub fn new(shifts: Vec<f64>)-> Self{
+        Self{ shifts }
+    }
+
+    ///Gaussian statics with standard deviation `std_dev` seconds
+    pub fn random(rng: &mut dyn Rng, num_traces: usize, std_dev: f64)-> Self{
+        Self::new((0..num_traces).map(|_| std_dev*rng.normal()).collect())
+    }
+
+    ///The stage that undoes these statics
+    pub fn correcting(&self)-> Self{
+        Self::new(self.shifts.iter().map(|s| -s).collect())
+    }
+}
+
+impl ProcessingStage for StaticShift{
+    fn name(&self)-> &str{
+        "static_shift"
+    }
+
+    fn apply(&self, gather: &mut Gather)-> Result<()>{
+        if self.shifts.len()!=gather.traces.len(){
+            return Err(anyhow!("Have {} statics for {} traces", self.shifts.len(), gather.traces.len()));
+        }
+        for (trace, &shift) in gather.traces.iter_mut().zip(&self.shifts){
+            trace.samples=fourier_shift(&trace.samples, shift/trace.dt);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::gather::Trace;
+    use crate::noise::SeededRng;
+    use crate::operators::adjoint_mismatch;
+
+    fn pulse(length: usize, centre: f64, width: f64)-> Vec<f64>{
+        (0..length).map(|i| (-((i as f64-centre)/width).powi(2)).exp()).collect()
+    }
+
+    #[test]
+    fn test_fourier_shift_is_fractional_and_adjoint()-> Result<()>{
+        let shifted=fourier_shift(&pulse(128, 50.0, 4.0), 3.3);
+        for (a, b) in shifted.iter().zip(&pulse(128, 53.3, 4.0)){
+            assert_abs_diff_eq!(a, b, epsilon=1e-6);
+        }
+        for (a, b) in fourier_shift(&pulse(128, 50.0, 4.0), 0.0).iter().zip(&pulse(128, 50.0, 4.0)){
+            assert_abs_diff_eq!(a, b, epsilon=1e-12);
+        }
+
+        assert!(adjoint_mismatch(&StaticShiftOperator{ length: 100, shift: -2.7 }, &mut SeededRng::new(5))<1e-10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_statics_stage_round_trip()-> Result<()>{
+        let original=Gather::new((0..6).map(|_| Trace::new(pulse(200, 100.0, 5.0), 0.002)).collect())?;
+        let statics=StaticShift::random(&mut SeededRng::new(21), 6, 0.008);
+        assert!(statics.shifts.iter().any(|s| s.abs()>0.002));
+
+        let mut gather=original.clone();
+        statics.apply(&mut gather)?;
+        assert!(gather.traces[0].samples.iter().zip(&original.traces[0].samples).any(|(a, b)| (a-b).abs()>1e-3));
+        statics.correcting().apply(&mut gather)?;
+        for (trace, reference) in gather.traces.iter().zip(&original.traces){
+            for (a, b) in trace.samples.iter().zip(&reference.samples){
+                assert_abs_diff_eq!(a, b, epsilon=1e-6);
+            }
+        }
+        assert!(StaticShift::new(vec![0.0]).apply(&mut gather).is_err());
+        Ok(())
+    }
+}
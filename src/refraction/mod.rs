@@ -0,0 +1,251 @@
+//! Refraction first-arrival modelling and inversion
+//!
+//! In a flat layered earth whose velocity increases with depth, a critically
+//! refracted head wave runs along the top of every faster layer. Its travel
+//! time is a straight line in offset with slope `1/v` and an intercept set
+//! by the layers above. First breaks are the earliest of the direct wave and
+//! the head waves. The intercept-time method fits a line to each branch of
+//! picked first breaks and peels the layers off from the top; the plus-minus
+//! method uses a reversed pair of shots to get the refractor velocity and
+//! the depth to it under every receiver.
+
+use anyhow::{Result, anyhow};
+use crate::models::layered::LayeredModel;
+use crate::operators::{ConvolutionOperator, LinearOperator};
+use crate::wavelets::RickerWavelet;
+
+///Earliest arrival at one offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FirstArrival{
+    pub offset: f64,
+    pub time: f64,
+    ///0 for the direct wave, otherwise the layer whose top the head wave travels along
+    pub refractor: usize,
+}
+
+///Head-wave travel time along the top of layer `n` at `offset`, `None` if it does not exist there
+///
+/// The head wave needs `n` to be faster than every layer above it and only
+/// emerges beyond the critical distance.
+pub fn head_wave_time(model: &LayeredModel, n: usize, offset: f64)-> Option<f64>{
+    if n==0 || n>=model.num_layers(){
+        return None;
+    }
+    let v=model.vp[n];
+    if model.vp[..n].iter().any(|&vi| vi>=v){
+        return None;
+    }
+    let (mut intercept, mut critical)=(0.0, 0.0);
+    for i in 0..n{
+        let sin=model.vp[i]/v;
+        let cos=(1.0-sin*sin).sqrt();
+        intercept+=2.0*model.thickness[i]*cos/model.vp[i];
+        critical+=2.0*model.thickness[i]*sin/cos;
+    }
+    (offset>=critical).then_some(offset/v+intercept)
+}
+
+///First-break time and branch at each offset
+pub fn first_arrivals(model: &LayeredModel, offsets: &[f64])-> Vec<FirstArrival>{
+    offsets.iter().map(|&x| {
+        let offset=x.abs();
+        let direct=FirstArrival{ offset: x, time: offset/model.vp[0], refractor: 0 };
+        (1..model.num_layers())
+            .filter_map(|n| head_wave_time(model, n, offset).map(|time| FirstArrival{ offset: x, time, refractor: n }))
+            .fold(direct, |best, arrival| if arrival.time<best.time { arrival } else { best })
+    }).collect()
+}
+
+///Shot gather of the direct wave and every head wave, `[offset][sample]`
+///
+/// Kinematic only: each arrival is a wavelet of unit amplitude placed at
+/// its travel time, so all branches stay visible past their crossovers.
+pub fn refraction_gather(model: &LayeredModel, offsets: &[f64], wavelet: &RickerWavelet, num_samples: usize)-> Result<Vec<Vec<f64>>>{
+    let operator=ConvolutionOperator::from_ricker(wavelet, num_samples)?;
+    Ok(offsets.iter().map(|&x| {
+        let offset=x.abs();
+        let mut spikes=vec![0.0; num_samples];
+        let times=std::iter::once(offset/model.vp[0]).chain((1..model.num_layers()).filter_map(|n| head_wave_time(model, n, offset)));
+        for time in times{
+            let index=(time/wavelet.dt).round() as usize;
+            if index<num_samples{
+                spikes[index]+=1.0;
+            }
+        }
+        operator.apply(&spikes)
+    }).collect())
+}
+
+///Layer velocities and thicknesses recovered from first breaks
+#[derive(Debug, Clone)]
+pub struct RefractionSolution{
+    ///Velocity of each branch, top layer first
+    pub velocities: Vec<f64>,
+    ///Intercept time of each branch (zero for the direct wave)
+    pub intercepts: Vec<f64>,
+    ///Thickness of every layer above the deepest refractor
+    pub thicknesses: Vec<f64>,
+}
+
+impl RefractionSolution{
+    ///Depth to the top of each refractor
+    pub fn refractor_depths(&self)-> Vec<f64>{
+        self.thicknesses.iter().scan(0.0, |depth, h| { *depth+=h; Some(*depth) }).collect()
+    }
+}
+
+///Straight-line least-squares fit `t = intercept + slowness*x`
+fn fit_line(points: &[(f64, f64)])-> Option<(f64, f64)>{
+    let n=points.len() as f64;
+    let (sx, st)=points.iter().fold((0.0, 0.0), |(a, b), &(x, t)| (a+x, b+t));
+    let (mx, mt)=(sx/n, st/n);
+    let sxx: f64=points.iter().map(|&(x, _)| (x-mx)*(x-mx)).sum();
+    if points.len()<2 || sxx==0.0{
+        return None;
+    }
+    let slowness=points.iter().map(|&(x, t)| (x-mx)*(t-mt)).sum::<f64>()/sxx;
+    Some((mt-slowness*mx, slowness))
+}
+
+///Intercept-time inversion of picked first breaks `(offset, time)`
+///
+/// `crossovers` are the offsets where the first break changes branch, in
+/// increasing order: picks before the first crossover are the direct wave,
+/// and so on. The direct-wave branch is fitted through the origin.
+pub fn intercept_time_inversion(picks: &[(f64, f64)], crossovers: &[f64])-> Result<RefractionSolution>{
+    if crossovers.windows(2).any(|w| w[1]<=w[0]){
+        return Err(anyhow!("Crossover offsets must increase"));
+    }
+    let branch_of=|x: f64| crossovers.partition_point(|&c| c<=x);
+    let mut branches=vec![Vec::new(); crossovers.len()+1];
+    for &(x, t) in picks{
+        branches[branch_of(x.abs())].push((x.abs(), t));
+    }
+
+    let mut velocities=Vec::with_capacity(branches.len());
+    let mut intercepts=Vec::with_capacity(branches.len());
+    for (i, branch) in branches.iter().enumerate(){
+        let (intercept, slowness)=if i==0 {
+            //Through the origin
+            let sxx: f64=branch.iter().map(|(x, _)| x*x).sum();
+            if branch.is_empty() || sxx==0.0{
+                return Err(anyhow!("Direct-wave branch needs picks at non-zero offset"));
+            }
+            (0.0, branch.iter().map(|(x, t)| x*t).sum::<f64>()/sxx)
+        } else {
+            fit_line(branch).ok_or_else(|| anyhow!("Branch {} needs at least two picks at different offsets", i))?
+        };
+        if slowness<=0.0{
+            return Err(anyhow!("Branch {} has non-positive slope", i));
+        }
+        velocities.push(1.0/slowness);
+        intercepts.push(intercept);
+    }
+    if velocities.windows(2).any(|w| w[1]<=w[0]){
+        return Err(anyhow!("Branch velocities must increase with depth, got {:?}", velocities));
+    }
+
+    //t_n = sum_{i<n} 2 h_i cos(asin(v_i/v_n))/v_i, solved for h_{n-1} from the top down
+    let mut thicknesses: Vec<f64>=Vec::with_capacity(velocities.len()-1);
+    for n in 1..velocities.len(){
+        let cosine=|i: usize| (1.0-(velocities[i]/velocities[n]).powi(2)).sqrt();
+        let above: f64=(0..n-1).map(|i| 2.0*thicknesses[i]*cosine(i)/velocities[i]).sum();
+        thicknesses.push((intercepts[n]-above)*velocities[n-1]/(2.0*cosine(n-1)));
+    }
+    Ok(RefractionSolution{ velocities, intercepts, thicknesses })
+}
+
+///Refractor velocity and depths under the receivers from the plus-minus method
+#[derive(Debug, Clone)]
+pub struct PlusMinus{
+    pub refractor_velocity: f64,
+    ///Depth to the refractor below each receiver
+    pub depths: Vec<f64>,
+    ///Plus times `t_A + t_B - T_AB` (twice the delay time) at each receiver
+    pub plus_times: Vec<f64>,
+}
+
+///Plus-minus interpretation of a reversed refraction profile over one refractor
+///
+/// `forward` and `reverse` are the head-wave times at `positions` from shots
+/// at `source_a` and `source_b`, and `v0` the velocity above the refractor.
+/// Every receiver must lie between the shots and beyond both critical distances.
+pub fn plus_minus(positions: &[f64], forward: &[f64], reverse: &[f64], source_a: f64, source_b: f64, v0: f64)-> Result<PlusMinus>{
+    if positions.len()!=forward.len() || positions.len()!=reverse.len(){
+        return Err(anyhow!("Need forward and reverse times for every receiver"));
+    }
+    if positions.len()<2 || v0<=0.0{
+        return Err(anyhow!("Need at least two receivers and a positive overburden velocity"));
+    }
+    //Reciprocal time: the head wave from A recorded at B, extrapolated from the picks
+    let reciprocal_forward=fit_line(&positions.iter().zip(forward).map(|(&x, &t)| ((x-source_a).abs(), t)).collect::<Vec<_>>())
+        .ok_or_else(|| anyhow!("Cannot fit the forward branch"))?;
+    let reciprocal=reciprocal_forward.0+reciprocal_forward.1*(source_b-source_a).abs();
+
+    //Minus times t_A - t_B vary as 2x/v1, so their slope against position gives the refractor velocity
+    let minus: Vec<(f64, f64)>=positions.iter().zip(forward.iter().zip(reverse)).map(|(&x, (a, b))| (x, a-b)).collect();
+    let (_, slope)=fit_line(&minus).ok_or_else(|| anyhow!("Cannot fit the minus times"))?;
+    let refractor_velocity=2.0/slope.abs();
+    if refractor_velocity<=v0{
+        return Err(anyhow!("Refractor velocity {:.0} m/s is not faster than the overburden {:.0} m/s", refractor_velocity, v0));
+    }
+
+    let plus_times: Vec<f64>=forward.iter().zip(reverse).map(|(a, b)| a+b-reciprocal).collect();
+    let scale=v0*refractor_velocity/(2.0*(refractor_velocity*refractor_velocity-v0*v0).sqrt());
+    let depths=plus_times.iter().map(|t| t*scale).collect();
+    Ok(PlusMinus{ refractor_velocity, depths, plus_times })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn three_layers()-> LayeredModel{
+        LayeredModel::acoustic(vec![10.0, 25.0], vec![600.0, 1500.0, 3500.0], vec![1800.0, 2000.0, 2300.0]).unwrap()
+    }
+
+    #[test]
+    fn test_first_breaks_and_intercept_inversion()-> Result<()>{
+        let model=three_layers();
+        let offsets: Vec<f64>=(1..=120).map(|i| i as f64*2.5).collect();
+        let arrivals=first_arrivals(&model, &offsets);
+        assert_eq!(arrivals[0].refractor, 0);
+        assert_eq!(arrivals.last().unwrap().refractor, 2);
+        assert_abs_diff_eq!(arrivals[0].time, 2.5/600.0, epsilon=1e-12);
+
+        //Crossovers where the branch changes
+        let crossovers: Vec<f64>=arrivals.windows(2).filter(|w| w[0].refractor!=w[1].refractor).map(|w| w[1].offset).collect();
+        assert_eq!(crossovers.len(), 2);
+        let picks: Vec<(f64, f64)>=arrivals.iter().map(|a| (a.offset, a.time)).collect();
+        let solution=intercept_time_inversion(&picks, &crossovers)?;
+        for (estimate, truth) in solution.velocities.iter().zip(&model.vp){
+            assert_abs_diff_eq!(*estimate, *truth, epsilon=1e-6*truth);
+        }
+        assert_abs_diff_eq!(solution.thicknesses[0], 10.0, epsilon=1e-6);
+        assert_abs_diff_eq!(solution.thicknesses[1], 25.0, epsilon=1e-6);
+        assert_abs_diff_eq!(solution.refractor_depths()[1], 35.0, epsilon=1e-6);
+
+        let gather=refraction_gather(&model, &offsets[..3], &RickerWavelet::new(60.0, 0.0005, 40)?, 400)?;
+        assert_eq!(gather.len(), 3);
+        assert!(intercept_time_inversion(&picks, &[50.0, 40.0]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plus_minus_on_flat_refractor()-> Result<()>{
+        let model=LayeredModel::acoustic(vec![20.0], vec![800.0, 2400.0], vec![1800.0, 2100.0])?;
+        let (a, b)=(0.0, 300.0);
+        let positions: Vec<f64>=(0..=20).map(|i| 100.0+i as f64*5.0).collect();
+        let forward: Vec<f64>=positions.iter().map(|&x| head_wave_time(&model, 1, x-a).unwrap()).collect();
+        let reverse: Vec<f64>=positions.iter().map(|&x| head_wave_time(&model, 1, b-x).unwrap()).collect();
+
+        let result=plus_minus(&positions, &forward, &reverse, a, b, 800.0)?;
+        assert_abs_diff_eq!(result.refractor_velocity, 2400.0, epsilon=1e-6);
+        for depth in &result.depths{
+            assert_abs_diff_eq!(*depth, 20.0, epsilon=1e-6);
+        }
+        assert!(plus_minus(&positions, &forward, &reverse[1..], a, b, 800.0).is_err());
+        Ok(())
+    }
+}
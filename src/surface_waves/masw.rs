@@ -0,0 +1,180 @@
+//! MASW-style inversion of a Rayleigh dispersion curve for a shear-velocity profile
+//!
+//! Layer thicknesses are fixed and Vp and density follow Vs through a fixed
+//! Vp/Vs ratio and a constant density, so the unknowns are the shear
+//! velocities of the layers and the half-space. They are solved for in
+//! `ln Vs`, which keeps them positive, by Gauss-Newton on the relative
+//! phase-velocity misfit with a finite-difference Jacobian.
+
+use anyhow::{Result, anyhow};
+use crate::models::layered::LayeredModel;
+use crate::operators::LinearOperator;
+use crate::optimization::cg::CgOptions;
+use crate::optimization::gauss_newton::{GaussNewton, NonlinearProblem};
+use super::dispersion_curve;
+
+///Settings for a dispersion-curve inversion
+#[derive(Debug, Clone)]
+pub struct MaswInversion{
+    ///Thickness of each layer above the half-space in metres
+    pub thicknesses: Vec<f64>,
+    pub vp_vs_ratio: f64,
+    ///Density of every layer in kg/m^3
+    pub density: f64,
+    ///Relative perturbation of Vs used for the finite-difference Jacobian
+    pub perturbation: f64,
+    pub solver: GaussNewton,
+}
+
+impl MaswInversion{
+    pub fn new(thicknesses: Vec<f64>)-> Self{
+        Self{
+            thicknesses,
+            vp_vs_ratio: 3.0_f64.sqrt(),
+            density: 1900.0,
+            perturbation: 1e-4,
+            solver: GaussNewton{ max_iterations: 30, damping: 1e-4, inner: CgOptions{ max_iterations: 50, tolerance: 1e-8 }, ..GaussNewton::default() },
+        }
+    }
+
+    ///Layered model for a shear-velocity profile, one value per layer and the half-space
+    pub fn model(&self, vs: &[f64])-> Result<LayeredModel>{
+        let vp=vs.iter().map(|v| v*self.vp_vs_ratio).collect();
+        LayeredModel::new(self.thicknesses.clone(), vp, vs.to_vec(), vec![self.density; vs.len()])
+    }
+
+    ///Invert observed phase velocities at `frequencies` starting from `initial_vs`
+    pub fn invert(&self, frequencies: &[f64], observed: &[f64], initial_vs: &[f64])-> Result<MaswResult>{
+        if frequencies.is_empty() || frequencies.len()!=observed.len(){
+            return Err(anyhow!("Need one observed phase velocity per frequency ({} frequencies, {} velocities)", frequencies.len(), observed.len()));
+        }
+        if initial_vs.len()!=self.thicknesses.len()+1{
+            return Err(anyhow!("Expected {} starting shear velocities, got {}", self.thicknesses.len()+1, initial_vs.len()));
+        }
+        if self.vp_vs_ratio<=2.0_f64.sqrt() || self.perturbation<=0.0{
+            return Err(anyhow!("Vp/Vs must exceed √2 and the Jacobian perturbation must be positive"));
+        }
+        if initial_vs.iter().any(|&v| v<=0.0){
+            return Err(anyhow!("Starting shear velocities must be positive"));
+        }
+
+        let problem=DispersionProblem{ inversion: self, frequencies, observed };
+        let initial: Vec<f64>=initial_vs.iter().map(|v| v.ln()).collect();
+        let result=self.solver.solve(&problem, &initial)?;
+
+        let vs: Vec<f64>=result.model.iter().map(|m| m.exp()).collect();
+        let model=self.model(&vs)?;
+        let predicted=dispersion_curve(&model, frequencies)?;
+        let rms_misfit=(predicted.iter().zip(observed).map(|(p, o)| (p-o)*(p-o)).sum::<f64>()/observed.len() as f64).sqrt();
+        Ok(MaswResult{ vs, model, predicted, rms_misfit, iterations: result.misfit_history.len()-1, converged: result.converged })
+    }
+}
+
+///Recovered profile and its fit to the observed curve
+#[derive(Debug, Clone)]
+pub struct MaswResult{
+    pub vs: Vec<f64>,
+    pub model: LayeredModel,
+    ///Phase velocities of the recovered model at the observed frequencies
+    pub predicted: Vec<f64>,
+    ///RMS phase-velocity misfit in m/s
+    pub rms_misfit: f64,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+impl MaswResult{
+    pub fn print_summary(&self){
+        println!("MASW inversion: {} iterations{}, RMS misfit {:.2} m/s", self.iterations, if self.converged { "" } else { " (not converged)" }, self.rms_misfit);
+        let mut top=0.0;
+        for (i, vs) in self.vs.iter().enumerate(){
+            match self.model.thickness.get(i){
+                Some(h)=> println!("  {:7.1} - {:7.1} m: Vs {:7.1} m/s", top, top+h, vs),
+                None=> println!("  {:7.1} m -       : Vs {:7.1} m/s", top, vs),
+            }
+            top+=self.model.thickness.get(i).copied().unwrap_or(0.0);
+        }
+    }
+}
+
+struct DispersionProblem<'a>{
+    inversion: &'a MaswInversion,
+    frequencies: &'a [f64],
+    observed: &'a [f64],
+}
+
+impl DispersionProblem<'_>{
+    ///Relative misfit; frequencies with no guided fundamental mode predict zero, so such steps are rejected
+    fn predict(&self, log_vs: &[f64])-> Result<Vec<f64>>{
+        let vs: Vec<f64>=log_vs.iter().map(|m| m.exp()).collect();
+        let model=self.inversion.model(&vs)?;
+        let predicted=dispersion_curve(&model, self.frequencies).unwrap_or_else(|_| vec![0.0; self.frequencies.len()]);
+        Ok(predicted.iter().zip(self.observed).map(|(p, o)| (p-o)/o).collect())
+    }
+}
+
+impl NonlinearProblem for DispersionProblem<'_>{
+    type Jacobian=DenseJacobian;
+
+    fn residual(&self, model: &[f64])-> Result<Vec<f64>>{
+        self.predict(model)
+    }
+
+    fn jacobian(&self, model: &[f64])-> Result<DenseJacobian>{
+        let base=self.predict(model)?;
+        let step=self.inversion.perturbation;
+        let mut columns=Vec::with_capacity(model.len());
+        for j in 0..model.len(){
+            let mut perturbed=model.to_vec();
+            perturbed[j]+=step;
+            let shifted=self.predict(&perturbed)?;
+            columns.push(shifted.iter().zip(&base).map(|(a, b)| (a-b)/step).collect());
+        }
+        Ok(DenseJacobian{ columns })
+    }
+}
+
+///Explicit Jacobian stored by column
+struct DenseJacobian{
+    columns: Vec<Vec<f64>>,
+}
+
+impl LinearOperator for DenseJacobian{
+    fn shape(&self)-> (usize, usize){
+        (self.columns.first().map(|c| c.len()).unwrap_or(0), self.columns.len())
+    }
+
+    fn apply(&self, x: &[f64])-> Vec<f64>{
+        let mut y=vec![0.0; self.shape().0];
+        for (column, &xj) in self.columns.iter().zip(x){
+            y.iter_mut().zip(column).for_each(|(yi, c)| *yi+=c*xj);
+        }
+        y
+    }
+
+    fn apply_adjoint(&self, y: &[f64])-> Vec<f64>{
+        self.columns.iter().map(|column| column.iter().zip(y).map(|(c, yi)| c*yi).sum()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_recovers_three_layer_profile()-> Result<()>{
+        let inversion=MaswInversion::new(vec![4.0, 8.0]);
+        let truth=[180.0, 300.0, 450.0];
+        let frequencies: Vec<f64>=(0..16).map(|i| 4.0+i as f64*3.0).collect();
+        let observed=dispersion_curve(&inversion.model(&truth)?, &frequencies)?;
+
+        let result=inversion.invert(&frequencies, &observed, &[250.0, 250.0, 400.0])?;
+        for (estimate, t) in result.vs.iter().zip(&truth){
+            assert!((estimate-t).abs()<0.02*t, "Vs {:?} vs {:?}", result.vs, truth);
+        }
+        assert!(result.rms_misfit<0.5);
+
+        assert!(inversion.invert(&frequencies, &observed, &[250.0, 400.0]).is_err());
+        Ok(())
+    }
+}
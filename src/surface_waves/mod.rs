@@ -0,0 +1,209 @@
+//! Rayleigh-wave dispersion in layered elastic media
+//!
+//! The P-SV displacement-stress vector `(r1, r2, r3, r4)` (horizontal and
+//! vertical displacement, shear and normal traction) obeys `dr/dz = A r` in
+//! each homogeneous layer (Aki & Richards, eq. 7.28). A Rayleigh mode is a
+//! combination of the two solutions decaying into the half-space whose
+//! tractions vanish at the free surface. Both solutions are carried up to the
+//! surface together and re-orthonormalised after every short step, which keeps
+//! them from collapsing onto the fastest-growing one at high frequency; the
+//! orthonormalisation scales the surface traction determinant by a positive
+//! factor, so its sign changes still bracket the dispersion roots.
+
+use anyhow::{Result, anyhow};
+use rayon::prelude::*;
+use crate::models::layered::LayeredModel;
+
+pub mod masw;
+
+type Matrix4=[[f64; 4]; 4];
+
+///Lowest phase velocity searched, as a fraction of the slowest shear velocity
+const MIN_VELOCITY_FRACTION: f64=0.8;
+///Trial velocities in the bracketing scan
+const SCAN_STEPS: usize=100;
+
+fn check_model(model: &LayeredModel)-> Result<()>{
+    if model.vs.iter().any(|&v| v<=0.0){
+        return Err(anyhow!("Rayleigh waves need a positive shear velocity in every layer"));
+    }
+    Ok(())
+}
+
+///System matrix of layer `i` with the tractions divided by `scale`
+fn system_matrix(model: &LayeredModel, i: usize, omega: f64, k: f64, scale: f64)-> Matrix4{
+    let rho=model.rho[i];
+    let mu=rho*model.vs[i]*model.vs[i];
+    let modulus=rho*model.vp[i]*model.vp[i];
+    let lambda=modulus-2.0*mu;
+    let zeta=4.0*mu*(lambda+mu)/modulus;
+    let a=[
+        [0.0, k, 1.0/mu, 0.0],
+        [-k*lambda/modulus, 0.0, 0.0, 1.0/modulus],
+        [k*k*zeta-omega*omega*rho, 0.0, 0.0, k*lambda/modulus],
+        [0.0, -omega*omega*rho, -k, 0.0],
+    ];
+    let d=[1.0, 1.0, 1.0/scale, 1.0/scale];
+    let mut scaled=a;
+    for (r, row) in scaled.iter_mut().enumerate(){
+        for (c, value) in row.iter_mut().enumerate(){
+            *value*=d[r]/d[c];
+        }
+    }
+    scaled
+}
+
+fn multiply(a: &Matrix4, b: &Matrix4)-> Matrix4{
+    let mut product=[[0.0; 4]; 4];
+    for (r, row) in product.iter_mut().enumerate(){
+        for (c, value) in row.iter_mut().enumerate(){
+            *value=(0..4).map(|i| a[r][i]*b[i][c]).sum();
+        }
+    }
+    product
+}
+
+///`exp(-A h)` as `steps` repeats of a short-step matrix, each with norm at most ½
+fn upward_step(a: &Matrix4, h: f64)-> (Matrix4, usize){
+    let norm=a.iter().map(|row| row.iter().map(|x| x.abs()).sum::<f64>()).fold(0.0, f64::max);
+    let steps=((2.0*norm*h).ceil() as usize).max(1);
+    let mut m=*a;
+    m.iter_mut().flatten().for_each(|x| *x*= -h/steps as f64);
+
+    //Taylor series to well below rounding for ||m|| <= 1/2
+    let mut result=[[0.0; 4]; 4];
+    let mut term=[[0.0; 4]; 4];
+    for i in 0..4{
+        result[i][i]=1.0;
+        term[i][i]=1.0;
+    }
+    for n in 1..=16{
+        term=multiply(&term, &m);
+        term.iter_mut().flatten().for_each(|x| *x/=n as f64);
+        result.iter_mut().flatten().zip(term.iter().flatten()).for_each(|(r, t)| *r+=t);
+    }
+    (result, steps)
+}
+
+///Gram-Schmidt on the two solution columns; R has a positive diagonal
+fn orthonormalise(y: &mut [[f64; 4]; 2]){
+    let norm=|v: &[f64; 4]| v.iter().map(|x| x*x).sum::<f64>().sqrt();
+    let first=norm(&y[0]);
+    y[0].iter_mut().for_each(|x| *x/=first);
+    let projection: f64=y[0].iter().zip(&y[1]).map(|(a, b)| a*b).sum();
+    let (q, v)=(y[0], &mut y[1]);
+    v.iter_mut().zip(&q).for_each(|(x, q)| *x-=projection*q);
+    let second=norm(v);
+    v.iter_mut().for_each(|x| *x/=second);
+}
+
+///Rayleigh secular function: the surface traction determinant at phase velocity `velocity`
+///
+/// Zero on every mode of the dispersion curve. Only defined below the shear
+/// velocity of the half-space, where both starting solutions decay with depth.
+pub fn rayleigh_secular(model: &LayeredModel, frequency: f64, velocity: f64)-> f64{
+    let omega=2.0*std::f64::consts::PI*frequency;
+    let k=omega/velocity;
+    let n=model.num_layers()-1;
+    let (vp, vs, rho)=(model.vp[n], model.vs[n], model.rho[n]);
+    let mu=rho*vs*vs;
+    let lambda=rho*vp*vp-2.0*mu;
+    let scale=mu*k;
+    let nu_p=k*(1.0-(velocity/vp).powi(2)).sqrt();
+    let nu_s=k*(1.0-(velocity/vs).powi(2)).max(0.0).sqrt();
+
+    //Eigenvectors of A for the decaying P and S solutions, eigenvalues -nu_p and -nu_s
+    let mut y=[
+        [k, nu_p, -2.0*mu*k*nu_p/scale, (lambda*k*k-(lambda+2.0*mu)*nu_p*nu_p)/scale],
+        [nu_s, k, -mu*(nu_s*nu_s+k*k)/scale, -2.0*mu*k*nu_s/scale],
+    ];
+    orthonormalise(&mut y);
+    for i in (0..n).rev(){
+        let (step, repeats)=upward_step(&system_matrix(model, i, omega, k, scale), model.thickness[i]);
+        for _ in 0..repeats{
+            for column in y.iter_mut(){
+                let v=*column;
+                for (r, value) in column.iter_mut().enumerate(){
+                    *value=(0..4).map(|c| step[r][c]*v[c]).sum();
+                }
+            }
+            orthonormalise(&mut y);
+        }
+    }
+    y[0][2]*y[1][3]-y[1][2]*y[0][3]
+}
+
+///Fundamental-mode Rayleigh phase velocity at `frequency` Hz
+///
+/// The lowest root of the secular function between a fraction of the slowest
+/// shear velocity and the half-space shear velocity, bracketed by a scan and
+/// refined by bisection.
+pub fn rayleigh_phase_velocity(model: &LayeredModel, frequency: f64)-> Result<f64>{
+    check_model(model)?;
+    if frequency.is_nan() || frequency<=0.0{
+        return Err(anyhow!("Frequency must be positive, got {}", frequency));
+    }
+    let slowest=model.vs.iter().cloned().fold(f64::INFINITY, f64::min);
+    let (low, high)=(MIN_VELOCITY_FRACTION*slowest, model.vs[model.num_layers()-1]*(1.0-1e-9));
+    let secular=|c: f64| rayleigh_secular(model, frequency, c);
+
+    let mut lower=(low, secular(low));
+    for i in 1..=SCAN_STEPS{
+        let c=low+(high-low)*i as f64/SCAN_STEPS as f64;
+        let value=secular(c);
+        if value.signum()!=lower.1.signum(){
+            let (mut a, mut b)=(lower.0, c);
+            for _ in 0..60{
+                let mid=0.5*(a+b);
+                if secular(mid).signum()==lower.1.signum() { a=mid } else { b=mid }
+            }
+            return Ok(0.5*(a+b));
+        }
+        lower=(c, value);
+    }
+    Err(anyhow!("No fundamental Rayleigh mode below the half-space shear velocity at {} Hz", frequency))
+}
+
+///Fundamental-mode phase velocity at each frequency
+pub fn dispersion_curve(model: &LayeredModel, frequencies: &[f64])-> Result<Vec<f64>>{
+    frequencies.par_iter().map(|&f| rayleigh_phase_velocity(model, f)).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    ///Rayleigh velocity of a Poisson solid (Vp = √3 Vs) over its shear velocity
+    fn poisson_rayleigh()-> f64{
+        (2.0-2.0/3.0_f64.sqrt()).sqrt()
+    }
+
+    #[test]
+    fn test_half_space_is_non_dispersive()-> Result<()>{
+        let model=LayeredModel::new(vec![], vec![300.0*3.0_f64.sqrt()], vec![300.0], vec![1900.0])?;
+        for f in [2.0, 20.0, 200.0]{
+            assert_abs_diff_eq!(rayleigh_phase_velocity(&model, f)?, poisson_rayleigh()*300.0, epsilon=1e-4);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_over_half_space_limits()-> Result<()>{
+        let root3=3.0_f64.sqrt();
+        let model=LayeredModel::new(vec![10.0], vec![200.0*root3, 400.0*root3], vec![200.0, 400.0], vec![1800.0, 1800.0])?;
+        let frequencies=[0.25, 5.0, 10.0, 20.0, 40.0, 150.0];
+        let curve=dispersion_curve(&model, &frequencies)?;
+        //Normally dispersive: slower at high frequency, bounded by the two Rayleigh velocities
+        assert!(curve.windows(2).all(|w| w[1]<w[0]), "{:?}", curve);
+        assert!((curve[0]-poisson_rayleigh()*400.0).abs()<0.02*400.0, "{:?}", curve);
+        assert_abs_diff_eq!(curve[5], poisson_rayleigh()*200.0, epsilon=0.1);
+        //Every point is a root of the secular function
+        let value=rayleigh_secular(&model, 10.0, curve[2]);
+        assert!(value.abs()<1e-6, "secular {}", value);
+
+        let fluid=LayeredModel::acoustic(vec![10.0], vec![1500.0, 2000.0], vec![1000.0, 2000.0])?;
+        assert!(rayleigh_phase_velocity(&fluid, 10.0).is_err());
+        Ok(())
+    }
+}
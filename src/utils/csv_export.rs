@@ -0,0 +1,197 @@
+//! Configurable CSV output for traces
+//!
+//! `f64::to_string` writes the shortest representation that round-trips,
+//! which for computed amplitudes is usually seventeen significant digits.
+//! Fixing the precision (optionally in scientific notation) keeps files
+//! small, and a custom delimiter, optional header and gzip compression suit
+//! readers that expect something other than the default `sample,amplitude`
+//! layout.
+
+use anyhow::{Result, Context, anyhow};
+use csv::WriterBuilder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+///Layout and number formatting of an exported trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvExportOptions{
+    ///Digits after the decimal point; `None` writes the shortest round-trip value
+    pub precision: Option<usize>,
+    ///Write values as `1.234e-3` rather than `0.001234`
+    pub scientific: bool,
+    pub delimiter: u8,
+    ///Write the column names as the first row
+    pub header: bool,
+    ///Sample interval and start time in seconds; adds a `time` column, written
+    ///with the decimals `dt` and `t0` need rather than the amplitude format
+    pub time_axis: Option<(f64, f64)>,
+    ///Compress the output with gzip
+    pub gzip: bool,
+}
+
+impl Default for CsvExportOptions{
+    fn default()-> Self{
+        Self{
+            precision: None,
+            scientific: false,
+            delimiter: b',',
+            header: true,
+            time_axis: None,
+            gzip: false,
+        }
+    }
+}
+
+impl CsvExportOptions{
+    pub fn with_precision(mut self, digits: usize)-> Self{
+        self.precision=Some(digits);
+        self
+    }
+
+    pub fn with_scientific(mut self, scientific: bool)-> Self{
+        self.scientific=scientific;
+        self
+    }
+
+    pub fn with_delimiter(mut self, delimiter: u8)-> Self{
+        self.delimiter=delimiter;
+        self
+    }
+
+    pub fn with_header(mut self, header: bool)-> Self{
+        self.header=header;
+        self
+    }
+
+    pub fn with_time_axis(mut self, dt: f64, t0: f64)-> Self{
+        self.time_axis=Some((dt, t0));
+        self
+    }
+
+    pub fn with_gzip(mut self, gzip: bool)-> Self{
+        self.gzip=gzip;
+        self
+    }
+
+    fn validate(&self)-> Result<()>{
+        let d=self.delimiter;
+        if d.is_ascii_alphanumeric() || matches!(d, b'.' | b'-' | b'+' | b'"' | b'\n' | b'\r'){
+            return Err(anyhow!("Delimiter '{}' would be confused with the numbers", d.escape_ascii()));
+        }
+        if let Some((dt, _))=self.time_axis{
+            if !(dt>0.0 && dt.is_finite()){
+                return Err(anyhow!("Sample interval must be positive, got {}", dt));
+            }
+        }
+        Ok(())
+    }
+
+    fn format(&self, value: f64)-> String{
+        match (self.precision, self.scientific){
+            (Some(digits), false)=> format!("{:.*}", digits, value),
+            (Some(digits), true)=> format!("{:.*e}", digits, value),
+            (None, false)=> value.to_string(),
+            (None, true)=> format!("{:e}", value),
+        }
+    }
+
+    ///Time of sample `index` as written, or `None` without a time axis
+    ///
+    /// Times take the decimals of the shortest forms of `dt` and `t0`,
+    /// enough to tell every sample apart.
+    pub fn format_time(&self, index: usize)-> Option<String>{
+        let (dt, t0)=self.time_axis?;
+        let decimals=|x: f64| x.to_string().split_once('.').map_or(0, |(_, fraction)| fraction.len());
+        Some(format!("{:.*}", decimals(dt).max(decimals(t0)).min(17), t0+index as f64*dt))
+    }
+
+    ///Write `data` as CSV to any writer
+    pub fn write<W: Write>(&self, data: &[f64], output: W)-> Result<()>{
+        self.validate()?;
+        if self.gzip{
+            self.write_records(data, GzEncoder::new(output, Compression::default()))?.finish()?;
+        } else {
+            self.write_records(data, output)?.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_records<W: Write>(&self, data: &[f64], output: W)-> Result<W>{
+        let mut writer=WriterBuilder::new().delimiter(self.delimiter).from_writer(output);
+
+        if self.header{
+            if self.time_axis.is_some(){
+                writer.write_record(["sample", "amplitude", "time"])?;
+            } else {
+                writer.write_record(["sample", "amplitude"])?;
+            }
+        }
+        for (i, &value) in data.iter().enumerate(){
+            match self.format_time(i){
+                Some(time)=> writer.write_record([i.to_string(), self.format(value), time])?,
+                None=> writer.write_record([i.to_string(), self.format(value)])?,
+            }
+        }
+        writer.into_inner().map_err(|e| anyhow!("Failed to flush CSV: {}", e.error()))
+    }
+
+    ///Write `data` to a file
+    pub fn export(&self, data: &[f64], filename: &str)-> Result<()>{
+        let file=File::create(filename).with_context(|| format!("Failed to create file: {}", filename))?;
+        self.write(data, BufWriter::new(file)).with_context(|| format!("Failed to write {}", filename))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn text(options: &CsvExportOptions, data: &[f64])-> Result<String>{
+        let mut bytes=Vec::new();
+        options.write(data, &mut bytes)?;
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    #[test]
+    fn test_formatting_options()-> Result<()>{
+        let data=[0.1+0.2, -0.00125];
+        assert_eq!(text(&CsvExportOptions::default(), &data)?, "sample,amplitude\n0,0.30000000000000004\n1,-0.00125\n");
+        assert_eq!(text(&CsvExportOptions::default().with_precision(3), &data)?, "sample,amplitude\n0,0.300\n1,-0.001\n");
+        assert_eq!(text(&CsvExportOptions::default().with_precision(2).with_scientific(true).with_header(false), &data)?, "0,3.00e-1\n1,-1.25e-3\n");
+
+        let timed=CsvExportOptions::default().with_precision(4).with_delimiter(b';').with_time_axis(0.002, 0.1);
+        assert_eq!(text(&timed, &data)?, "sample;amplitude;time\n0;0.3000;0.100\n1;-0.0013;0.102\n");
+
+        //Times keep the resolution of the sample interval whatever the amplitude precision
+        let fine=CsvExportOptions::default().with_precision(3).with_header(false).with_time_axis(0.0005, 0.0);
+        assert_eq!(text(&fine, &[1.0, 2.0, 3.0])?, "0,1.000,0.0000\n1,2.000,0.0005\n2,3.000,0.0010\n");
+        let scientific=CsvExportOptions::default().with_precision(1).with_scientific(true).with_header(false).with_time_axis(0.1, 0.0);
+        assert_eq!(text(&scientific, &[0.25; 4])?.lines().last(), Some("3,2.5e-1,0.3"));
+
+        assert!(text(&CsvExportOptions::default().with_delimiter(b'.'), &data).is_err());
+        assert!(text(&CsvExportOptions::default().with_time_axis(0.0, 0.0), &data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzip_round_trip()-> Result<()>{
+        let data: Vec<f64>=(0..500).map(|i| (i as f64*0.1).sin()).collect();
+        let options=CsvExportOptions::default().with_precision(6).with_gzip(true);
+        let path=std::env::temp_dir().join("csv_export_test.csv.gz");
+        let path=path.to_str().unwrap();
+        options.export(&data, path)?;
+
+        let mut decoded=String::new();
+        GzDecoder::new(File::open(path)?).read_to_string(&mut decoded)?;
+        std::fs::remove_file(path)?;
+        assert_eq!(decoded, text(&options.with_gzip(false), &data)?);
+        let values=crate::utils::import_from_csv(decoded.as_bytes())?;
+        assert_eq!(values.len(), 500);
+        assert!((values[7]-data[7]).abs()<1e-6);
+        Ok(())
+    }
+}
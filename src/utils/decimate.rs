@@ -0,0 +1,128 @@
+//! Decimation of traces for display
+//!
+//! Picking every k-th sample aliases anything above the new Nyquist
+//! frequency back into the band, so a dense trace can plot as a slow
+//! oscillation that is not there. `downsample_data` low-passes with a
+//! Hann-windowed sinc at the output Nyquist before picking one value per
+//! bin. When individual events matter more than the waveform, the bin
+//! reductions keep every extreme instead: `min_max_envelope` gives the
+//! range covered by each bin and `reduce_bins` its peak or mean.
+
+use std::f64::consts::PI;
+
+///Windowed-sinc half length in output samples
+const HALF_WIDTH: f64=4.0;
+
+///How a bin of samples collapses to one value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinReduction{
+    ///Sample of largest magnitude, sign kept, so isolated spikes survive
+    Peak,
+    Mean,
+}
+
+///Sample range `[start, end)` of bin `b` when `len` samples are split into `bins`
+fn bin_range(b: usize, len: usize, bins: usize)-> (usize, usize){
+    let start=b*len/bins;
+    (start, ((b+1)*len/bins).max(start+1))
+}
+
+///Reduce `data` to `width` samples, low-passing at the new Nyquist frequency before picking
+///
+/// Each output sample is the filtered value at the centre of its bin. The
+/// filter gain is normalised over the taps inside the trace, so the ends
+/// keep their level. Data no longer than `width` are returned unchanged.
+pub fn downsample_data(data: &[f64], width: usize)-> Vec<f64>{
+    if width==0{
+        return Vec::new();
+    }
+    if data.len()<=width{
+        return data.to_vec();
+    }
+    let factor=data.len() as f64/width as f64;
+    let half=HALF_WIDTH*factor;
+    (0..width).map(|b| {
+        let centre=(b as f64+0.5)*factor-0.5;
+        let first=(centre-half).ceil().max(0.0) as usize;
+        let last=((centre+half).floor() as usize).min(data.len()-1);
+        let (sum, gain)=(first..=last).fold((0.0, 0.0), |(sum, gain), k| {
+            let x=(k as f64-centre)/factor;
+            let sinc=if x==0.0 { 1.0 } else { (PI*x).sin()/(PI*x) };
+            let weight=sinc*0.5*(1.0+(PI*x/HALF_WIDTH).cos());
+            (sum+weight*data[k], gain+weight)
+        });
+        sum/gain
+    }).collect()
+}
+
+///Smallest and largest value in each of `bins` bins, so every peak and trough is kept
+pub fn min_max_envelope(data: &[f64], bins: usize)-> Vec<(f64, f64)>{
+    if data.len()<=bins{
+        return data.iter().map(|&v| (v, v)).collect();
+    }
+    (0..bins).map(|b| {
+        let (start, end)=bin_range(b, data.len(), bins);
+        data[start..end].iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)))
+    }).collect()
+}
+
+///Reduce `data` to `bins` values, one per bin; shorter data are returned unchanged
+pub fn reduce_bins(data: &[f64], bins: usize, reduction: BinReduction)-> Vec<f64>{
+    if data.len()<=bins{
+        return data.to_vec();
+    }
+    (0..bins).map(|b| {
+        let (start, end)=bin_range(b, data.len(), bins);
+        let bin=&data[start..end];
+        match reduction{
+            BinReduction::Peak=> bin.iter().copied().fold(0.0, |best: f64, v| if v.abs()>best.abs() { v } else { best }),
+            BinReduction::Mean=> bin.iter().sum::<f64>()/bin.len() as f64,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_downsample_rejects_aliases(){
+        //0.45 cycles per sample folds to 0.05 cycles per output sample when every 10th sample is picked
+        let fast: Vec<f64>=(0..1000).map(|i| (2.0*PI*0.45*i as f64).sin()).collect();
+        let naive: Vec<f64>=(0..100).map(|i| fast[10*i+5]).collect();
+        let naive_rms=(naive.iter().map(|x| x*x).sum::<f64>()/100.0).sqrt();
+        assert!(naive_rms>0.5, "{}", naive_rms);
+        let filtered=downsample_data(&fast, 100);
+        assert_eq!(filtered.len(), 100);
+        assert!(filtered.iter().all(|x| x.abs()<0.05), "{:?}", filtered.iter().cloned().fold(0.0, f64::max));
+
+        //Signals inside the output band, and constants up to the ends, pass
+        let slow: Vec<f64>=(0..1000).map(|i| (2.0*PI*0.005*i as f64).sin()).collect();
+        let passed=downsample_data(&slow, 100);
+        for (b, value) in passed.iter().enumerate().skip(5).take(90){
+            assert_abs_diff_eq!(*value, (2.0*PI*0.005*(10.0*b as f64+4.5)).sin(), epsilon=0.02);
+        }
+        for value in downsample_data(&[2.5; 333], 40){
+            assert_abs_diff_eq!(value, 2.5, epsilon=1e-12);
+        }
+        assert_eq!(downsample_data(&[1.0, 2.0], 5), vec![1.0, 2.0]);
+        assert!(downsample_data(&slow, 0).is_empty());
+    }
+
+    #[test]
+    fn test_bin_reductions_keep_extremes(){
+        let mut data=vec![0.0; 100];
+        data[33]= -3.0;
+        data[34]=2.0;
+        let envelope=min_max_envelope(&data, 10);
+        assert_eq!(envelope.len(), 10);
+        assert_eq!(envelope[3], (-3.0, 2.0));
+        assert_eq!(envelope[0], (0.0, 0.0));
+
+        let peaks=reduce_bins(&data, 10, BinReduction::Peak);
+        assert_eq!(peaks[3], -3.0);
+        assert_abs_diff_eq!(reduce_bins(&data, 10, BinReduction::Mean)[3], -0.1, epsilon=1e-12);
+        assert_eq!(reduce_bins(&data[..5], 10, BinReduction::Peak), data[..5].to_vec());
+    }
+}
@@ -0,0 +1,242 @@
+//! Small dense linear algebra helpers
+
+use anyhow::{Result, anyhow};
+
+///Solve the square system `a x = b` by Gaussian elimination with partial pivoting
+pub fn solve_linear_system(mut a: Vec<Vec<f64>>, mut b: Vec<f64>)-> Result<Vec<f64>>{
+    let n=b.len();
+    if a.len()!=n || a.iter().any(|row| row.len()!=n){
+        return Err(anyhow!("Matrix must be {}x{} to match the right-hand side", n, n));
+    }
+
+    for col in 0..n{
+        let pivot=(col..n).max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap()).unwrap();
+        if a[pivot][col].abs()<1e-14{
+            return Err(anyhow!("Matrix is singular or nearly singular"));
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        for row in col+1..n{
+            let factor=a[row][col]/a[col][col];
+            if factor==0.0{
+                continue;
+            }
+            let (upper, lower)=a.split_at_mut(row);
+            for (target, &source) in lower[0][col..].iter_mut().zip(upper[col][col..].iter()){
+                *target-=factor*source;
+            }
+            b[row]-=factor*b[col];
+        }
+    }
+
+    let mut x=vec![0.0; n];
+    for row in (0..n).rev(){
+        let sum: f64=(row+1..n).map(|k| a[row][k]*x[k]).sum();
+        x[row]=(b[row]-sum)/a[row][row];
+    }
+
+    Ok(x)
+}
+
+///Levinson-Durbin recursion for the order-`order` prediction error filter
+///
+/// Given autocorrelation lags `r[0..=order]`, returns the filter
+/// `[1, a1, ..., ap]` minimising the prediction error power, together with
+/// that error power.
+pub fn levinson_durbin(r: &[f64], order: usize)-> Result<(Vec<f64>, f64)>{
+    if r.len()<=order{
+        return Err(anyhow!("Need {} autocorrelation lags for order {}, got {}", order+1, order, r.len()));
+    }
+    if r[0]<=0.0{
+        return Err(anyhow!("Zero-lag autocorrelation must be positive, got {}", r[0]));
+    }
+
+    let mut a=vec![0.0; order+1];
+    a[0]=1.0;
+    let mut error=r[0];
+
+    for m in 1..=order{
+        levinson_step(&mut a, r, m, &mut error)?;
+    }
+
+    Ok((a, error))
+}
+
+///Grow the prediction error filter `a[..m]` to order `m`, updating the error power
+fn levinson_step(a: &mut [f64], r: &[f64], m: usize, error: &mut f64)-> Result<()>{
+    let acc: f64=(0..m).map(|k| a[k]*r[m-k]).sum();
+    let reflection=-acc/ *error;
+    let previous=a[..m].to_vec();
+    for k in 1..m{
+        a[k]=previous[k]+reflection*previous[m-k];
+    }
+    a[m]=reflection;
+    *error*=1.0-reflection*reflection;
+    if *error<=0.0{
+        return Err(anyhow!("Autocorrelation is not positive definite at order {}", m));
+    }
+    Ok(())
+}
+
+///Solve the symmetric Toeplitz system `R x = b` by Levinson recursion
+///
+/// `r` is the first row of `R` (autocorrelation lags `0..b.len()`). The
+/// prediction error filter is grown alongside the solution by the same step
+/// as `levinson_durbin`, so the cost is O(n^2) rather than the O(n^3) of
+/// elimination; this is the Wiener-Levinson solve behind least-squares
+/// shaping and predictive filters.
+pub fn levinson_solve(r: &[f64], b: &[f64])-> Result<Vec<f64>>{
+    let n=b.len();
+    if n==0{
+        return Ok(vec![]);
+    }
+    if r.len()<n{
+        return Err(anyhow!("Need {} autocorrelation lags for a {}-point system, got {}", n, n, r.len()));
+    }
+    if r[0]<=0.0{
+        return Err(anyhow!("Zero-lag autocorrelation must be positive, got {}", r[0]));
+    }
+
+    let mut a=vec![0.0; n];
+    a[0]=1.0;
+    let mut error=r[0];
+    let mut x=vec![0.0; n];
+    x[0]=b[0]/r[0];
+
+    for m in 1..n{
+        levinson_step(&mut a, r, m, &mut error)?;
+
+        //[x; 0] misses b[m] by this much; the reversed filter fixes the last row only
+        let predicted: f64=(0..m).map(|k| x[k]*r[m-k]).sum();
+        let scale=(b[m]-predicted)/error;
+        for k in 0..=m{
+            x[k]+=scale*a[m-k];
+        }
+    }
+
+    Ok(x)
+}
+
+///Eigenvalues and unit eigenvectors of a symmetric matrix by cyclic Jacobi rotations
+///
+/// Returned as `(value, vector)` pairs, largest eigenvalue first.
+pub fn symmetric_eigen(mut a: Vec<Vec<f64>>)-> Result<Vec<(f64, Vec<f64>)>>{
+    let n=a.len();
+    if a.iter().any(|row| row.len()!=n){
+        return Err(anyhow!("Matrix must be square"));
+    }
+    if (0..n).any(|i| (0..i).any(|j| (a[i][j]-a[j][i]).abs()>1e-10*(a[i][j].abs()+a[j][i].abs()).max(f64::MIN_POSITIVE))){
+        return Err(anyhow!("Matrix must be symmetric"));
+    }
+    let mut v: Vec<Vec<f64>>=(0..n).map(|i| (0..n).map(|j| if i==j { 1.0 } else { 0.0 }).collect()).collect();
+
+    for _ in 0..100{
+        let off: f64=(0..n).map(|i| (0..n).filter(|&j| j!=i).map(|j| a[i][j]*a[i][j]).sum::<f64>()).sum();
+        let diagonal: f64=(0..n).map(|i| a[i][i]*a[i][i]).sum();
+        if off<=1e-30*diagonal.max(f64::MIN_POSITIVE){
+            break;
+        }
+        for p in 0..n{
+            for q in p+1..n{
+                if a[p][q]==0.0{
+                    continue;
+                }
+                //Rotation angle that zeroes a[p][q]
+                let theta=(a[q][q]-a[p][p])/(2.0*a[p][q]);
+                let t=theta.signum()/(theta.abs()+(theta*theta+1.0).sqrt());
+                let c=1.0/(t*t+1.0).sqrt();
+                let s=t*c;
+                for row in a.iter_mut(){
+                    let (x, y)=(row[p], row[q]);
+                    row[p]=c*x-s*y;
+                    row[q]=s*x+c*y;
+                }
+                let (upper, lower)=a.split_at_mut(q);
+                for (x, y) in upper[p].iter_mut().zip(lower[0].iter_mut()){
+                    let (u, w)=(*x, *y);
+                    *x=c*u-s*w;
+                    *y=s*u+c*w;
+                }
+                for row in v.iter_mut(){
+                    let (x, y)=(row[p], row[q]);
+                    row[p]=c*x-s*y;
+                    row[q]=s*x+c*y;
+                }
+            }
+        }
+    }
+
+    let mut pairs: Vec<(f64, Vec<f64>)>=(0..n).map(|i| (a[i][i], v.iter().map(|row| row[i]).collect())).collect();
+    pairs.sort_by(|x, y| y.0.partial_cmp(&x.0).unwrap());
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_solve_linear_system()-> Result<()>{
+        let a=vec![vec![0.0, 2.0, 1.0], vec![1.0, 1.0, 0.0], vec![3.0, 0.0, 1.0]];
+        let x=solve_linear_system(a, vec![5.0, 3.0, 6.0])?;
+
+        assert_abs_diff_eq!(x[0], 1.4, epsilon=1e-12);
+        assert_abs_diff_eq!(x[1], 1.6, epsilon=1e-12);
+        assert_abs_diff_eq!(x[2], 1.8, epsilon=1e-12);
+
+        assert!(solve_linear_system(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_levinson_durbin_ar1()-> Result<()>{
+        //Autocorrelation of an AR(1) process x[n]=0.6*x[n-1]+e[n]
+        let r: Vec<f64>=(0..4).map(|k| 0.6f64.powi(k)).collect();
+        let (a, error)=levinson_durbin(&r, 3)?;
+
+        assert_abs_diff_eq!(a[1], -0.6, epsilon=1e-12);
+        assert_abs_diff_eq!(a[2], 0.0, epsilon=1e-12);
+        assert_abs_diff_eq!(a[3], 0.0, epsilon=1e-12);
+        assert_abs_diff_eq!(error, 1.0-0.36, epsilon=1e-12);
+        assert!(levinson_durbin(&r, 4).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_levinson_solve_matches_elimination()-> Result<()>{
+        let r=[4.0, 2.0, 1.0, 0.5, 0.1];
+        let b=[1.0, -2.0, 0.5, 3.0, 0.0];
+        let toeplitz: Vec<Vec<f64>>=(0..5usize).map(|i| (0..5usize).map(|j| r[i.abs_diff(j)]).collect()).collect();
+
+        let x=levinson_solve(&r, &b)?;
+        for (a, e) in x.iter().zip(solve_linear_system(toeplitz, b.to_vec())?){
+            assert_abs_diff_eq!(*a, e, epsilon=1e-12);
+        }
+        assert!(levinson_solve(&r[..3], &b).is_err());
+        assert!(levinson_solve(&[0.0, 1.0], &[1.0, 1.0]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_symmetric_eigen()-> Result<()>{
+        let a=vec![vec![2.0, 1.0, 0.0], vec![1.0, 2.0, 0.0], vec![0.0, 0.0, 5.0]];
+        let pairs=symmetric_eigen(a.clone())?;
+        let values: Vec<f64>=pairs.iter().map(|p| p.0).collect();
+        for (value, expected) in values.iter().zip([5.0, 3.0, 1.0]){
+            assert_abs_diff_eq!(*value, expected, epsilon=1e-12);
+        }
+        for (value, vector) in &pairs{
+            for i in 0..3{
+                let product: f64=(0..3).map(|j| a[i][j]*vector[j]).sum();
+                assert_abs_diff_eq!(product, value*vector[i], epsilon=1e-12);
+            }
+        }
+        assert!(symmetric_eigen(vec![vec![1.0, 2.0], vec![0.0, 1.0]]).is_err());
+
+        Ok(())
+    }
+}
@@ -1,24 +1,47 @@
 use anyhow::{Result, Context};
-use csv::Writer;
-use std::fs::File;
-use std::io::Write;
+use csv_export::CsvExportOptions;
+use decimate::{downsample_data, min_max_envelope};
+
+pub mod csv_export;
+pub mod decimate;
+pub mod linalg;
+pub mod spline;
 
 ///Export data to CSV file
+///
+/// Values are written at full precision; see `CsvExportOptions` for
+/// precision, delimiter, header and compression settings.
 pub fn export_to_csv(data: &[f64], filename: &str)-> Result<()>{
-    let file=File::create(filename).with_context(|| format!("Failed to create file: {}", filename))?;
+    CsvExportOptions::default().export(data, filename)
+}
 
-    let mut writer=Writer::from_writer(file);
+///Read a trace written by `export_to_csv` (columns `sample,amplitude`)
+pub fn import_from_csv<R: std::io::Read>(reader: R)-> Result<Vec<f64>>{
+    let mut csv_reader=csv::Reader::from_reader(reader);
+    let mut data=Vec::new();
+    for (row, record) in csv_reader.records().enumerate(){
+        let record=record?;
+        let value=record.get(1).with_context(|| format!("Row {} has no amplitude column", row+1))?;
+        data.push(value.trim().parse().with_context(|| format!("Invalid amplitude '{}' in row {}", value, row+1))?);
+    }
+    Ok(data)
+}
 
-    //Write header
-    writer.write_record(&["sample", "amplitude"])?;
+///Export a trace with its time axis (columns `sample,amplitude,time`)
+///
+/// The amplitude stays in the second column so `import_from_csv` reads the
+/// file unchanged; `time` is `t0+i*dt` in seconds.
+pub fn export_to_csv_timed(data: &[f64], dt: f64, t0: f64, filename: &str)-> Result<()>{
+    CsvExportOptions::default().with_time_axis(dt, t0).export(data, filename)
+}
 
-    //Write data
-    for (i, &value) in data.iter().enumerate(){
-        writer.write_record(&[i.to_string(), value.to_string()])?;
+///ASCII plot with the start and end times printed under the trace
+pub fn plot_ascii_timed(data: &[f64], height: usize, dt: f64, t0: f64){
+    plot_ascii(data, height);
+    if let Some(last)=data.len().checked_sub(1){
+        let end=t0+last as f64*dt;
+        println!(" {:<width$}{:.3} s", format!("{:.3} s", t0), end, width=data.len().saturating_sub(7).max(10));
     }
-
-    writer.flush()?;
-    Ok(())
 }
 
 /// Simple ASCII plotting for terminal visualzation
@@ -83,12 +106,9 @@ pub fn plot_ascii_with_axis(
 
     let range=max_val-min_val;
 
-    // Downsampl data if needed
-    let plot_data=if data.len()>plot_width{
-        downsample_data(data, plot_width)
-    }else{
-        data.to_vec()
-    };
+    //Anti-aliased curve, plus the extremes of each bin so spikes stay visible as dots
+    let plot_data=downsample_data(data, plot_width);
+    let envelope=min_max_envelope(data, plot_width);
 
     //Plot
     for row in (0..height).rev(){
@@ -104,6 +124,46 @@ pub fn plot_ascii_with_axis(
             print!("{:>8.3}|", (max_val+min_val)/2.0);
         }else{
             print!("        |");
-        }   
+        }
+
+        for (&value, &(_, high)) in plot_data.iter().zip(&envelope){
+            if value>=threshold{
+                print!("*");
+            }else if high>=threshold{
+                print!(".");
+            }else{
+                print!(" ");
+            }
+        }
+        println!();
+    }
+    println!("         +{}", "-".repeat(plot_data.len()));
+}
+
+///Root mean square of a trace, zero when it is empty
+pub fn rms(data: &[f64])-> f64{
+    (data.iter().map(|x| x*x).sum::<f64>()/data.len().max(1) as f64).sqrt()
+}
+
+///Summary statistics for a trace
+#[derive(Debug, Clone)]
+pub struct Statistics{
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub rms: f64,
+    pub energy: f64,
+}
+
+impl Statistics{
+    pub fn calculate(data: &[f64])-> Self{
+        let n=data.len().max(1) as f64;
+        let min=data.iter().fold(f64::INFINITY, |a, &b| a.min(b));
+        let max=data.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+        let mean=data.iter().sum::<f64>()/n;
+        let var=data.iter().map(|x| (x-mean).powi(2)).sum::<f64>()/n;
+        let energy=data.iter().map(|x| x*x).sum::<f64>();
+        Self{ min, max, mean, std_dev: var.sqrt(), rms: (energy/n).sqrt(), energy }
     }
 }
@@ -0,0 +1,96 @@
+//! Natural cubic spline interpolation
+
+use anyhow::{Result, anyhow};
+
+///Interpolating cubic spline with zero curvature at both ends
+#[derive(Debug, Clone)]
+pub struct CubicSpline{
+    x: Vec<f64>,
+    y: Vec<f64>,
+    ///Second derivative at each knot
+    m: Vec<f64>,
+}
+
+impl CubicSpline{
+    ///Fit a natural spline through knots with strictly increasing `x`
+    pub fn natural(x: &[f64], y: &[f64])-> Result<Self>{
+        let n=x.len();
+        if n!=y.len(){
+            return Err(anyhow!("Knot lengths differ: x {}, y {}", n, y.len()));
+        }
+        if n<2{
+            return Err(anyhow!("A spline needs at least two knots, got {}", n));
+        }
+        if x.windows(2).any(|w| w[1]<=w[0]){
+            return Err(anyhow!("Spline knots must be strictly increasing"));
+        }
+
+        let h: Vec<f64>=x.windows(2).map(|w| w[1]-w[0]).collect();
+        let mut m=vec![0.0; n];
+
+        //Thomas algorithm on the interior rows of the tridiagonal system
+        if n>2{
+            let interior=n-2;
+            let mut diag=vec![0.0; interior];
+            let mut rhs=vec![0.0; interior];
+            for k in 0..interior{
+                let i=k+1;
+                diag[k]=2.0*(h[i-1]+h[i]);
+                rhs[k]=6.0*((y[i+1]-y[i])/h[i]-(y[i]-y[i-1])/h[i-1]);
+            }
+            for k in 1..interior{
+                let factor=h[k]/diag[k-1];
+                diag[k]-=factor*h[k];
+                rhs[k]-=factor*rhs[k-1];
+            }
+            m[interior]=rhs[interior-1]/diag[interior-1];
+            for k in (0..interior-1).rev(){
+                m[k+1]=(rhs[k]-h[k+1]*m[k+2])/diag[k];
+            }
+        }
+
+        Ok(Self{ x: x.to_vec(), y: y.to_vec(), m })
+    }
+
+    ///Evaluate the spline; points outside the knots take the end values
+    pub fn evaluate(&self, x: f64)-> f64{
+        let n=self.x.len();
+        if x<=self.x[0]{
+            return self.y[0];
+        }
+        if x>=self.x[n-1]{
+            return self.y[n-1];
+        }
+
+        let k=self.x.partition_point(|&knot| knot<=x)-1;
+        let h=self.x[k+1]-self.x[k];
+        let a=(self.x[k+1]-x)/h;
+        let b=(x-self.x[k])/h;
+
+        a*self.y[k]+b*self.y[k+1]+((a.powi(3)-a)*self.m[k]+(b.powi(3)-b)*self.m[k+1])*h*h/6.0
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_spline_reproduces_knots_and_lines()-> Result<()>{
+        let x=[0.0, 1.0, 2.5, 4.0];
+        let spline=CubicSpline::natural(&x, &[1.0, 3.0, 6.0, 9.0])?;
+        for (&xi, &yi) in x.iter().zip([1.0, 3.0, 6.0, 9.0].iter()){
+            assert_abs_diff_eq!(spline.evaluate(xi), yi, epsilon=1e-12);
+        }
+
+        //A straight line has zero curvature, so the natural spline is exact
+        let line=CubicSpline::natural(&x, &[0.0, 2.0, 5.0, 8.0])?;
+        assert_abs_diff_eq!(line.evaluate(3.3), 6.6, epsilon=1e-12);
+        assert_abs_diff_eq!(line.evaluate(10.0), 8.0, epsilon=1e-12);
+
+        assert!(CubicSpline::natural(&[0.0, 0.0], &[1.0, 2.0]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Zero-offset vertical seismic profiles
+//!
+//! A source at the surface is recorded by receivers down a vertical well.
+//! For a 1D layered model each receiver sees the direct downgoing arrival at
+//! its first-break time and, from every interface below it, a primary
+//! reflection travelling back up. Amplitudes are plane-wave (no spherical
+//! divergence) and include the normal-incidence transmission losses on the
+//! way down and up; multiples are not modelled.
+//!
+//! Shifting the upgoing field by each receiver's first-break time puts the
+//! reflections at their surface two-way time; summing a narrow corridor just
+//! after the first break gives the corridor stack used to tie the VSP to
+//! surface seismic.
+
+use anyhow::{Result, anyhow};
+use crate::gather::Trace;
+use crate::models::layered::LayeredModel;
+use crate::operators::{ConvolutionOperator, LinearOperator};
+use crate::processing::static_shift::fourier_shift;
+use crate::wavelets::RickerWavelet;
+
+pub mod separation;
+
+///Modelled zero-offset VSP, one trace per receiver depth
+#[derive(Debug, Clone)]
+pub struct VspPanel{
+    ///Receiver depths in metres
+    pub depths: Vec<f64>,
+    ///Sample interval in seconds
+    pub dt: f64,
+    ///Direct-arrival time at each receiver in seconds
+    pub first_breaks: Vec<f64>,
+    ///Downgoing wavefield, `[receiver][sample]`
+    pub downgoing: Vec<Vec<f64>>,
+    ///Upgoing wavefield, `[receiver][sample]`
+    pub upgoing: Vec<Vec<f64>>,
+}
+
+impl VspPanel{
+    ///Recorded wavefield, the sum of down- and upgoing
+    pub fn total(&self)-> Vec<Vec<f64>>{
+        self.downgoing.iter().zip(&self.upgoing).map(|(d, u)| d.iter().zip(u).map(|(a, b)| a+b).collect()).collect()
+    }
+
+    ///Number of samples per trace
+    pub fn num_samples(&self)-> usize{
+        self.downgoing.first().map(|t| t.len()).unwrap_or(0)
+    }
+
+    ///Upgoing field shifted to surface two-way time (each trace delayed by its first break)
+    pub fn upgoing_twt(&self)-> Vec<Vec<f64>>{
+        two_way_time(&self.upgoing, &self.first_breaks, self.dt)
+    }
+
+    ///Corridor stack of the upgoing field
+    pub fn corridor_stack(&self, width: f64)-> Result<Trace>{
+        corridor_stack(&self.upgoing, &self.first_breaks, self.dt, width)
+    }
+}
+
+///Shift upgoing traces by their first-break times so reflections line up at two-way time
+pub fn two_way_time(upgoing: &[Vec<f64>], first_breaks: &[f64], dt: f64)-> Vec<Vec<f64>>{
+    upgoing.iter().zip(first_breaks).map(|(trace, &fb)| fourier_shift(trace, fb/dt)).collect()
+}
+
+///Average of each flattened upgoing trace over `width` seconds after twice its first break
+///
+/// Keeping only the window just below each receiver excludes the multiples
+/// generated above it, so the stack is a primaries-only trace in two-way time.
+pub fn corridor_stack(upgoing: &[Vec<f64>], first_breaks: &[f64], dt: f64, width: f64)-> Result<Trace>{
+    if upgoing.is_empty() || upgoing.len()!=first_breaks.len(){
+        return Err(anyhow!("Need one first break per upgoing trace ({} traces, {} first breaks)", upgoing.len(), first_breaks.len()));
+    }
+    if width<=0.0 || dt<=0.0{
+        return Err(anyhow!("Corridor width and sample interval must be positive"));
+    }
+    let flattened=two_way_time(upgoing, first_breaks, dt);
+    let length=flattened[0].len();
+    let (mut sum, mut count)=(vec![0.0; length], vec![0usize; length]);
+    for (trace, &fb) in flattened.iter().zip(first_breaks){
+        let start=(2.0*fb/dt).round() as usize;
+        let end=((2.0*fb+width)/dt).round() as usize;
+        for k in start..end.min(length){
+            sum[k]+=trace[k];
+            count[k]+=1;
+        }
+    }
+    Ok(Trace::new(sum.iter().zip(&count).map(|(s, &c)| if c>0 { s/c as f64 } else { 0.0 }).collect(), dt))
+}
+
+///Add a unit arrival of amplitude `amplitude` at time `t`, split linearly between the two nearest samples
+fn add_arrival(trace: &mut [f64], t: f64, dt: f64, amplitude: f64){
+    let position=t/dt;
+    let index=position.floor() as usize;
+    let fraction=position-index as f64;
+    if let Some(sample)=trace.get_mut(index){
+        *sample+=amplitude*(1.0-fraction);
+    }
+    if let Some(sample)=trace.get_mut(index+1){
+        *sample+=amplitude*fraction;
+    }
+}
+
+///Model a zero-offset VSP recorded at `depths` for `num_samples` samples of the wavelet's interval
+pub fn model_vsp(model: &LayeredModel, depths: &[f64], wavelet: &RickerWavelet, num_samples: usize)-> Result<VspPanel>{
+    if depths.is_empty() || depths.iter().any(|&z| z.is_nan() || z<0.0){
+        return Err(anyhow!("Need at least one receiver at a non-negative depth"));
+    }
+    let dt=wavelet.dt;
+    let interfaces=model.interface_depths();
+    let reflection: Vec<f64>=(0..interfaces.len()).map(|i| model.reflection_coefficient(i)).collect();
+    //Amplitude of the downgoing wave just below interface i: product of (1+R) above it
+    let transmitted_down: Vec<f64>=reflection.iter().scan(1.0, |a, r| { *a*=1.0+r; Some(*a) }).collect();
+    let operator=ConvolutionOperator::from_ricker(wavelet, num_samples)?;
+
+    let mut first_breaks=Vec::with_capacity(depths.len());
+    let mut downgoing=Vec::with_capacity(depths.len());
+    let mut upgoing=Vec::with_capacity(depths.len());
+    for &depth in depths{
+        let layer=model.layer_at(depth);
+        let first_break=model.one_way_time(depth);
+        let mut down=vec![0.0; num_samples];
+        add_arrival(&mut down, first_break, dt, if layer==0 { 1.0 } else { transmitted_down[layer-1] });
+
+        let mut up=vec![0.0; num_samples];
+        for i in (0..interfaces.len()).filter(|&i| interfaces[i]>depth){
+            let incident=if i==0 { 1.0 } else { transmitted_down[i-1] };
+            //Upgoing crosses interfaces layer..i-1 from below: transmission 1-R each
+            let transmitted_up: f64=(layer..i).map(|k| 1.0-reflection[k]).product();
+            let time=2.0*model.one_way_time(interfaces[i])-first_break;
+            add_arrival(&mut up, time, dt, incident*reflection[i]*transmitted_up);
+        }
+
+        first_breaks.push(first_break);
+        downgoing.push(operator.apply(&down));
+        upgoing.push(operator.apply(&up));
+    }
+
+    Ok(VspPanel{ depths: depths.to_vec(), dt, first_breaks, downgoing, upgoing })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn peak_index(trace: &[f64])-> usize{
+        trace.iter().enumerate().max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap()).unwrap().0
+    }
+
+    #[test]
+    fn test_arrival_times_and_amplitudes()-> Result<()>{
+        let model=LayeredModel::acoustic(vec![500.0, 500.0], vec![2000.0, 2500.0, 3000.0], vec![2200.0, 2300.0, 2400.0])?;
+        let wavelet=RickerWavelet::new(30.0, 0.001, 100)?;
+        let depths: Vec<f64>=(1..=11).map(|i| i as f64*100.0).collect();
+        let panel=model_vsp(&model, &depths, &wavelet, 1000)?;
+
+        //Receiver at 300 m: first break 0.15 s, reflection from 500 m at 0.5-0.15=0.35 s
+        assert_abs_diff_eq!(panel.first_breaks[2], 0.15, epsilon=1e-12);
+        assert_eq!(peak_index(&panel.downgoing[2]), 150);
+        assert_eq!(peak_index(&panel.upgoing[2]), 350);
+        let r0=model.reflection_coefficient(0);
+        assert_abs_diff_eq!(panel.upgoing[2][350]/panel.downgoing[2][150], r0, epsilon=1e-9);
+
+        //Below both interfaces nothing comes back up, and the downgoing amplitude carries both transmissions
+        let r1=model.reflection_coefficient(1);
+        assert!(panel.upgoing[10].iter().all(|&x| x==0.0));
+        let area=|trace: &[f64]| trace.iter().sum::<f64>();
+        assert_abs_diff_eq!(area(&panel.downgoing[10]), (1.0+r0)*(1.0+r1)*area(&wavelet.samples), epsilon=1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corridor_stack_lands_at_two_way_time()-> Result<()>{
+        let model=LayeredModel::acoustic(vec![600.0], vec![2000.0, 3000.0], vec![2200.0, 2400.0])?;
+        let wavelet=RickerWavelet::new(30.0, 0.001, 100)?;
+        let depths: Vec<f64>=(1..=5).map(|i| i as f64*100.0).collect();
+        let panel=model_vsp(&model, &depths, &wavelet, 1000)?;
+
+        //Every flattened trace peaks at the interface two-way time 0.6 s
+        for trace in panel.upgoing_twt(){
+            assert_eq!(peak_index(&trace), 600);
+        }
+        let stack=panel.corridor_stack(0.5)?;
+        assert_eq!(peak_index(&stack.samples), 600);
+        assert!(stack.samples[600]>0.0);
+
+        assert!(corridor_stack(&panel.upgoing, &[0.1], 0.001, 0.1).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Up/down wavefield separation and deconvolution for VSP panels
+//!
+//! Downgoing energy arrives later at deeper receivers and upgoing energy
+//! earlier, so the two fields have opposite moveout across the array. The
+//! median method aligns the first breaks, takes a running median across
+//! receivers (which keeps the flat downgoing field and rejects the dipping
+//! upgoing one) and subtracts it from the record. The f-k method keeps the
+//! quadrants of the 2D spectrum matching each propagation direction and needs
+//! evenly spaced receivers. Deconvolving the upgoing field by the downgoing
+//! field at each receiver then removes the source signature and the
+//! downgoing multiples, leaving reflectivity in two-way time.
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use crate::processing::static_shift::fourier_shift;
+
+///Estimated downgoing and upgoing fields, `[receiver][sample]`
+#[derive(Debug, Clone)]
+pub struct Separated{
+    pub downgoing: Vec<Vec<f64>>,
+    pub upgoing: Vec<Vec<f64>>,
+}
+
+fn check_panel(panel: &[Vec<f64>])-> Result<usize>{
+    let length=panel.first().map(|t| t.len()).unwrap_or(0);
+    if length==0 || panel.iter().any(|t| t.len()!=length){
+        return Err(anyhow!("VSP panel must have at least one trace, all the same non-zero length"));
+    }
+    Ok(length)
+}
+
+///Separate by a running median of `window` receivers along the aligned first breaks
+pub fn median_separation(panel: &[Vec<f64>], first_breaks: &[f64], dt: f64, window: usize)-> Result<Separated>{
+    let length=check_panel(panel)?;
+    if first_breaks.len()!=panel.len(){
+        return Err(anyhow!("Need one first break per trace ({} traces, {} first breaks)", panel.len(), first_breaks.len()));
+    }
+    if window<3 || window.is_multiple_of(2){
+        return Err(anyhow!("Median window must be an odd number of at least 3 traces, got {}", window));
+    }
+
+    //Delay every trace so its first break lines up with the deepest one; nothing moves before time zero
+    let reference=first_breaks.iter().fold(f64::NEG_INFINITY, |m, &t| m.max(t));
+    let shifts: Vec<f64>=first_breaks.iter().map(|&t| (reference-t)/dt).collect();
+    let aligned: Vec<Vec<f64>>=panel.iter().zip(&shifts).map(|(trace, &s)| fourier_shift(trace, s)).collect();
+
+    let half=window/2;
+    let mut column=Vec::with_capacity(window);
+    let downgoing: Vec<Vec<f64>>=(0..panel.len()).map(|r| {
+        let (first, last)=(r.saturating_sub(half), (r+half).min(panel.len()-1));
+        let filtered: Vec<f64>=(0..length).map(|k| {
+            column.clear();
+            column.extend(aligned[first..=last].iter().map(|t| t[k]));
+            column.sort_by(f64::total_cmp);
+            column[column.len()/2]
+        }).collect();
+        fourier_shift(&filtered, -shifts[r])
+    }).collect();
+
+    let upgoing=panel.iter().zip(&downgoing).map(|(t, d)| t.iter().zip(d).map(|(a, b)| a-b).collect()).collect();
+    Ok(Separated{ downgoing, upgoing })
+}
+
+///Separate by direction in the f-k domain; receivers must be evenly spaced in depth
+///
+/// With time increasing down the panel and depth across it, downgoing
+/// events map to wavenumbers of the opposite sign to frequency and upgoing
+/// events to the same sign. Energy at zero frequency or wavenumber is split
+/// evenly between the two.
+pub fn fk_separation(panel: &[Vec<f64>])-> Result<Separated>{
+    let length=check_panel(panel)?;
+    let (nz, nt)=((2*panel.len()).next_power_of_two(), (2*length).next_power_of_two());
+    let mut spectrum=vec![vec![Complex::new(0.0, 0.0); nt]; nz];
+    for (row, trace) in spectrum.iter_mut().zip(panel){
+        row.iter_mut().zip(trace).for_each(|(c, &x)| c.re=x);
+    }
+    fft2(&mut spectrum, false);
+
+    let signed=|i: usize, n: usize| if i<=n/2 { i as f64 } else { i as f64-n as f64 };
+    let mut down=spectrum.clone();
+    for (kz, (up_row, down_row)) in spectrum.iter_mut().zip(down.iter_mut()).enumerate(){
+        for (kt, (u, d)) in up_row.iter_mut().zip(down_row.iter_mut()).enumerate(){
+            let sign=(signed(kz, nz)*signed(kt, nt)).signum();
+            let up_weight=if signed(kz, nz)==0.0 || signed(kt, nt)==0.0 { 0.5 } else if sign>0.0 { 1.0 } else { 0.0 };
+            *u*=up_weight;
+            *d*=1.0-up_weight;
+        }
+    }
+    fft2(&mut spectrum, true);
+    fft2(&mut down, true);
+
+    let crop=|field: Vec<Vec<Complex<f64>>>| -> Vec<Vec<f64>> {
+        field.into_iter().take(panel.len()).map(|row| row.iter().take(length).map(|c| c.re).collect()).collect()
+    };
+    Ok(Separated{ downgoing: crop(down), upgoing: crop(spectrum) })
+}
+
+///In-place 2D FFT of `[row][column]` data; the inverse is normalised
+fn fft2(data: &mut [Vec<Complex<f64>>], inverse: bool){
+    let (rows, columns)=(data.len(), data[0].len());
+    let mut planner=FftPlanner::new();
+    let (row_fft, column_fft)=if inverse {
+        (planner.plan_fft_inverse(columns), planner.plan_fft_inverse(rows))
+    } else {
+        (planner.plan_fft_forward(columns), planner.plan_fft_forward(rows))
+    };
+    data.iter_mut().for_each(|row| row_fft.process(row));
+    let mut column=vec![Complex::new(0.0, 0.0); rows];
+    for c in 0..columns{
+        column.iter_mut().zip(data.iter()).for_each(|(v, row)| *v=row[c]);
+        column_fft.process(&mut column);
+        column.iter().zip(data.iter_mut()).for_each(|(v, row)| row[c]= *v);
+    }
+    if inverse{
+        let scale=1.0/(rows*columns) as f64;
+        data.iter_mut().flatten().for_each(|v| *v*=scale);
+    }
+}
+
+///Deconvolve each upgoing trace by the downgoing trace at the same receiver
+///
+/// Spectral division `U D* / (|D|² + ε max|D|²)` with white-noise level
+/// `epsilon`. The result has zero lag at the first break and is delayed by
+/// twice the first-break time, so a reflector appears at its surface two-way
+/// time as a band-limited spike scaled by its reflection coefficient.
+pub fn deconvolve_upgoing(upgoing: &[Vec<f64>], downgoing: &[Vec<f64>], first_breaks: &[f64], dt: f64, epsilon: f64)-> Result<Vec<Vec<f64>>>{
+    let length=check_panel(upgoing)?;
+    if check_panel(downgoing)?!=length || downgoing.len()!=upgoing.len(){
+        return Err(anyhow!("Upgoing and downgoing panels must have the same shape"));
+    }
+    if epsilon<0.0{
+        return Err(anyhow!("Stabilisation must not be negative, got {}", epsilon));
+    }
+    let n=(2*length).next_power_of_two();
+    let mut planner=FftPlanner::new();
+    let (fft, ifft)=(planner.plan_fft_forward(n), planner.plan_fft_inverse(n));
+    let spectrum=|trace: &[f64]| -> Vec<Complex<f64>> {
+        let mut buffer: Vec<Complex<f64>>=trace.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        buffer.resize(n, Complex::new(0.0, 0.0));
+        fft.process(&mut buffer);
+        buffer
+    };
+
+    let deconvolved: Vec<Vec<f64>>=upgoing.iter().zip(downgoing).map(|(up, down)| {
+        let (u, d)=(spectrum(up), spectrum(down));
+        let peak=d.iter().fold(0.0_f64, |m, c| m.max(c.norm_sqr()));
+        let mut ratio: Vec<Complex<f64>>=u.iter().zip(&d).map(|(u, d)| u*d.conj()/(d.norm_sqr()+epsilon*peak).max(f64::MIN_POSITIVE)).collect();
+        ifft.process(&mut ratio);
+        ratio.iter().take(length).map(|c| c.re/n as f64).collect()
+    }).collect();
+    //Lag zero is the first break; reflections sit at 2(T-fb), so delay by twice the first break
+    Ok(deconvolved.iter().zip(first_breaks).map(|(trace, &fb)| fourier_shift(trace, 2.0*fb/dt)).collect())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::models::layered::LayeredModel;
+    use crate::vsp::model_vsp;
+    use crate::wavelets::RickerWavelet;
+
+    fn relative_error(estimate: &[Vec<f64>], truth: &[Vec<f64>])-> f64{
+        let (mut error, mut energy)=(0.0, 0.0);
+        for (e, t) in estimate.iter().zip(truth){
+            for (a, b) in e.iter().zip(t){
+                error+=(a-b)*(a-b);
+                energy+=b*b;
+            }
+        }
+        (error/energy).sqrt()
+    }
+
+    fn panel()-> Result<crate::vsp::VspPanel>{
+        let model=LayeredModel::acoustic(vec![400.0, 300.0], vec![2000.0, 2600.0, 3200.0], vec![2200.0, 2300.0, 2450.0])?;
+        let wavelet=RickerWavelet::new(35.0, 0.001, 100)?;
+        let depths: Vec<f64>=(0..36).map(|i| 20.0+i as f64*10.0).collect();
+        model_vsp(&model, &depths, &wavelet, 800)
+    }
+
+    #[test]
+    fn test_median_and_fk_recover_fields()-> Result<()>{
+        let panel=panel()?;
+        let total=panel.total();
+
+        let median=median_separation(&total, &panel.first_breaks, panel.dt, 7)?;
+        assert!(relative_error(&median.downgoing, &panel.downgoing)<0.05);
+        assert!(relative_error(&median.upgoing, &panel.upgoing)<0.2);
+
+        let fk=fk_separation(&total)?;
+        //The weak upgoing field picks up leakage from the truncated downgoing field at the panel edges
+        assert!(relative_error(&fk.downgoing, &panel.downgoing)<0.15);
+        assert!(relative_error(&fk.upgoing, &panel.upgoing)<0.5);
+
+        assert!(median_separation(&total, &panel.first_breaks, panel.dt, 4).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deconvolution_gives_reflectivity_in_twt()-> Result<()>{
+        let panel=panel()?;
+        let result=deconvolve_upgoing(&panel.upgoing, &panel.downgoing, &panel.first_breaks, panel.dt, 1e-3)?;
+
+        //Interfaces at 400 m and 700 m: two-way times 0.4 s and 0.4+2*300/2600 s
+        let r0=(2600.0*2300.0-2000.0*2200.0)/(2600.0*2300.0+2000.0*2200.0);
+        let r1=(3200.0*2450.0-2600.0*2300.0)/(3200.0*2450.0+2600.0*2300.0);
+        let second=(1000.0_f64*(0.4+600.0/2600.0)).round() as usize;
+        for trace in &result[..5]{
+            let peak=trace.iter().enumerate().max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap()).unwrap().0;
+            assert_eq!(peak, 400);
+            //Transmission losses through the first interface are kept, the source signature is not
+            let ratio=trace[second]/trace[400];
+            let expected=(1.0+r0)*(1.0-r0)*r1/r0;
+            assert!((ratio-expected).abs()<0.05*expected, "ratio {} vs {}", ratio, expected);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! On-disk catalog of named wavelets
+//!
+//! Calibrated wavelets are stored with their parameters and samples in a single
+//! JSON file so they can be shared and reused across projects.
+
+use anyhow::{Result, Context, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use super::RickerWavelet;
+
+///A named wavelet stored in the catalog
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry{
+    ///Unique name within the catalog
+    pub name: String,
+    ///Wavelet family (e.g. "ricker", "estimated")
+    pub kind: String,
+    ///Free-form description (source, well, project)
+    pub description: String,
+    ///Generating parameters, e.g. dominant frequency
+    pub parameters: BTreeMap<String, f64>,
+    ///Sample interval in seconds
+    pub dt: f64,
+    ///Wavelet samples
+    pub samples: Vec<f64>,
+}
+
+impl CatalogEntry{
+    ///Create an entry from a Ricker wavelet
+    pub fn from_ricker(name: &str, description: &str, wavelet: &RickerWavelet)-> Self{
+        let mut parameters=BTreeMap::new();
+        parameters.insert("frequency".to_string(), wavelet.frequency);
+        parameters.insert("length".to_string(), wavelet.samples.len() as f64);
+
+        Self{
+            name: name.to_string(),
+            kind: "ricker".to_string(),
+            description: description.to_string(),
+            parameters,
+            dt: wavelet.dt,
+            samples: wavelet.samples.clone(),
+        }
+    }
+}
+
+///Collection of named wavelets backed by a JSON file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WaveletCatalog{
+    wavelets: BTreeMap<String, CatalogEntry>,
+}
+
+impl WaveletCatalog{
+    ///Load a catalog, returning an empty one if the file does not exist yet
+    pub fn load(path: &str)-> Result<Self>{
+        if !Path::new(path).exists(){
+            return Ok(Self::default());
+        }
+
+        let contents=fs::read_to_string(path).with_context(|| format!("Failed to read catalog: {}", path))?;
+        serde_json::from_str(&contents).with_context(|| format!("Invalid wavelet catalog: {}", path))
+    }
+
+    ///Save the catalog, replacing the file
+    pub fn save(&self, path: &str)-> Result<()>{
+        let contents=serde_json::to_string_pretty(self)?;
+        fs::write(path, contents).with_context(|| format!("Failed to write catalog: {}", path))
+    }
+
+    ///Add a wavelet; names must be unique
+    pub fn add(&mut self, entry: CatalogEntry)-> Result<()>{
+        if self.wavelets.contains_key(&entry.name){
+            return Err(anyhow!("Wavelet '{}' already exists in catalog", entry.name));
+        }
+        self.wavelets.insert(entry.name.clone(), entry);
+        Ok(())
+    }
+
+    ///Remove a wavelet by name
+    pub fn remove(&mut self, name: &str)-> Result<CatalogEntry>{
+        self.wavelets.remove(name).ok_or_else(|| anyhow!("Wavelet '{}' not found in catalog", name))
+    }
+
+    ///Look up a wavelet by name
+    pub fn get(&self, name: &str)-> Option<&CatalogEntry>{
+        self.wavelets.get(name)
+    }
+
+    ///All entries in name order
+    pub fn entries(&self)-> impl Iterator<Item=&CatalogEntry>{
+        self.wavelets.values()
+    }
+
+    pub fn len(&self)-> usize{
+        self.wavelets.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.wavelets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_catalog_round_trip()-> Result<()>{
+        let path=std::env::temp_dir().join("seismic_wavelet_catalog_test.json");
+        let path=path.to_str().unwrap();
+        let _=fs::remove_file(path);
+
+        let mut catalog=WaveletCatalog::load(path)?;
+        assert!(catalog.is_empty());
+
+        let wavelet=RickerWavelet::new(25.0, 0.002, 64)?;
+        catalog.add(CatalogEntry::from_ricker("field_a", "Well A tie", &wavelet))?;
+        assert!(catalog.add(CatalogEntry::from_ricker("field_a", "duplicate", &wavelet)).is_err());
+        catalog.save(path)?;
+
+        let reloaded=WaveletCatalog::load(path)?;
+        fs::remove_file(path)?;
+
+        let entry=reloaded.get("field_a").unwrap();
+        assert_eq!(entry.kind, "ricker");
+        assert_eq!(entry.parameters["frequency"], 25.0);
+        assert_eq!(entry.samples, wavelet.samples);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+//! Side-by-side comparison of source wavelet families
+//!
+//! Choosing a source signature is a trade between bandwidth, side lobes and
+//! causality. This module generates a Ricker, an Ormsby, a Klauder (the
+//! autocorrelation of a linear Vibroseis sweep) and a Berlage (a causal,
+//! exponentially decaying pulse typical of impulsive marine sources) at one
+//! dominant frequency, on a shared time axis and normalised to unit peak, so
+//! their traces and spectra can be overlaid directly. The Ricker and
+//! Berlage peak at the dominant frequency; the Ormsby and Klauder pass bands
+//! are centred on it. For each wavelet the comparison reports spectral
+//! metrics (peak and centroid frequency, -6 dB bandwidth, energy) and time
+//! metrics that bear on resolution: the half-amplitude width of the main
+//! lobe, the peak-to-trough time (close to the tuning thickness of a bed),
+//! the largest side lobe and the energy-weighted centre time, which is zero
+//! for zero-phase wavelets and positive for causal ones.
+
+use anyhow::{Result, Context, anyhow};
+use std::f64::consts::PI;
+use super::RickerWavelet;
+use super::klauder::KlauderWavelet;
+use super::ormsby::OrmsbyWavelet;
+use super::spectrum::{spectral_analysis, WaveletSpectrum};
+
+///Klauder sweep length in seconds; the wavelet hardly changes for longer sweeps
+const SWEEP_LENGTH: f64=8.0;
+///Exponent of the Berlage `t^n` onset
+const BERLAGE_EXPONENT: i32=2;
+
+///Wavelet families that can be compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveletFamily{
+    Ricker,
+    ///Trapezoid with corners at 0.2, 0.5, 1.5 and 1.8 times the dominant frequency
+    Ormsby,
+    ///Autocorrelation of a sweep from 0.5 to 1.5 times the dominant frequency
+    Klauder,
+    ///`t^2 exp(-pi f t) cos(2 pi f t)` for `t>=0`
+    Berlage,
+}
+
+impl WaveletFamily{
+    pub const ALL: [WaveletFamily; 4]=[WaveletFamily::Ricker, WaveletFamily::Ormsby, WaveletFamily::Klauder, WaveletFamily::Berlage];
+
+    pub fn name(&self)-> &'static str{
+        match self{
+            WaveletFamily::Ricker=> "ricker",
+            WaveletFamily::Ormsby=> "ormsby",
+            WaveletFamily::Klauder=> "klauder",
+            WaveletFamily::Berlage=> "berlage",
+        }
+    }
+
+    ///Samples at `dominant` Hz on the `RickerWavelet::new` time axis, scaled to a peak of one
+    pub fn generate(&self, dominant: f64, dt: f64, length: usize)-> Result<Vec<f64>>{
+        let ricker=RickerWavelet::new(dominant, dt, length)?;
+        if length==0{
+            return Err(anyhow!("Wavelet length must be positive"));
+        }
+        let samples=match self{
+            WaveletFamily::Ricker=> ricker.samples,
+            WaveletFamily::Ormsby=> OrmsbyWavelet::new([0.2*dominant, 0.5*dominant, 1.5*dominant, 1.8*dominant], dt, length)?.samples,
+            WaveletFamily::Klauder=> KlauderWavelet::new(0.5*dominant, 1.5*dominant, SWEEP_LENGTH, dt, length)?.samples,
+            WaveletFamily::Berlage=> ricker.time.iter().map(|&t| berlage(t, dominant)).collect(),
+        };
+        let peak=samples.iter().fold(0.0f64, |m, x| m.max(x.abs()));
+        if peak==0.0{
+            return Err(anyhow!("The {} wavelet vanishes on a {}-sample axis; use a longer wavelet", self.name(), length));
+        }
+        Ok(samples.iter().map(|x| x/peak).collect())
+    }
+}
+
+fn berlage(t: f64, frequency: f64)-> f64{
+    if t<0.0{
+        return 0.0;
+    }
+    t.powi(BERLAGE_EXPONENT)*(-PI*frequency*t).exp()*(2.0*PI*frequency*t).cos()
+}
+
+///Resolution and spectral figures of one wavelet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FamilyMetrics{
+    ///Frequency of the amplitude spectrum peak in Hz
+    pub peak_frequency: f64,
+    ///Energy-weighted mean frequency in Hz
+    pub centroid_frequency: f64,
+    ///Width in Hz of the band where the amplitude is at least half its peak
+    pub bandwidth: f64,
+    ///`sum x^2 dt` of the unit-peak wavelet
+    pub energy: f64,
+    ///Width in seconds of the main lobe at half its amplitude
+    pub main_lobe_width: f64,
+    ///Time in seconds from the main peak to the nearest extremum of opposite sign
+    pub peak_to_trough: f64,
+    ///Largest amplitude outside the main lobe relative to the peak
+    pub side_lobe_ratio: f64,
+    ///Energy-weighted mean time in seconds
+    pub centre_time: f64,
+}
+
+///One generated wavelet with its spectrum and metrics
+#[derive(Debug, Clone)]
+pub struct FamilyWavelet{
+    pub family: WaveletFamily,
+    ///Samples on the comparison's time axis, peak amplitude one
+    pub samples: Vec<f64>,
+    pub spectrum: WaveletSpectrum,
+    pub metrics: FamilyMetrics,
+}
+
+///Every family at one dominant frequency, ready to overlay
+#[derive(Debug, Clone)]
+pub struct FamilyComparison{
+    pub dominant_frequency: f64,
+    pub dt: f64,
+    ///Shared time axis in seconds
+    pub time: Vec<f64>,
+    pub wavelets: Vec<FamilyWavelet>,
+}
+
+///Metrics of `samples` on `time`, whose amplitude spectrum is `spectrum`
+fn metrics(samples: &[f64], time: &[f64], dt: f64, spectrum: &WaveletSpectrum)-> FamilyMetrics{
+    let amplitude=&spectrum.amplitude;
+    let peak_amplitude=amplitude.iter().fold(0.0f64, |m, &a| m.max(a));
+    let total: f64=amplitude.iter().map(|a| a*a).sum();
+    let centroid_frequency=if total>0.0 { spectrum.freqs.iter().zip(amplitude).map(|(f, a)| f*a*a).sum::<f64>()/total } else { 0.0 };
+    let df=spectrum.freqs.get(1).copied().unwrap_or(0.0);
+    let bandwidth=amplitude.iter().filter(|&&a| a>=0.5*peak_amplitude).count() as f64*df;
+
+    let energy_samples: f64=samples.iter().map(|x| x*x).sum();
+    let centre_time=samples.iter().zip(time).map(|(x, t)| x*x*t).sum::<f64>()/energy_samples;
+
+    let peak=samples.iter().enumerate().max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap()).map(|(i, _)| i).unwrap_or(0);
+    let sign=samples[peak].signum();
+    let level=0.5*samples[peak].abs();
+    let above=|i: &usize| samples[*i]*sign>=level;
+    //Samples above half amplitude either side, plus the fraction of the next interval before the crossing
+    let half_width=|steps: Vec<usize>| -> f64 {
+        let inside=steps.iter().take_while(|i| above(i)).count();
+        let last=if inside==0 { peak } else { steps[inside-1] };
+        match steps.get(inside){
+            Some(&next)=> inside as f64+(samples[last]*sign-level)/(samples[last]*sign-samples[next]*sign),
+            None=> inside as f64,
+        }
+    };
+    let main_lobe_width=(half_width((0..peak).rev().collect())+half_width((peak+1..samples.len()).collect()))*dt;
+
+    //Main lobe runs to the first sign change either side of the peak
+    let same_sign=|i: &usize| samples[*i]*sign>0.0;
+    let lobe_start=peak-(0..peak).rev().take_while(same_sign).count();
+    let lobe_end=peak+(peak+1..samples.len()).take_while(same_sign).count();
+    let outside=samples[..lobe_start].iter().chain(&samples[lobe_end+1..]);
+    let side_lobe_ratio=outside.fold(0.0f64, |m, x| m.max(x.abs()))/samples[peak].abs();
+
+    //Nearest opposite-sign extremum: the largest excursion in the adjacent opposite lobe on each side
+    let opposite_extremum=|range: Vec<usize>| -> Option<usize> {
+        range.into_iter().skip_while(|i| samples[*i]*sign>=0.0).take_while(|i| samples[*i]*sign<=0.0)
+            .max_by(|a, b| samples[*a].abs().partial_cmp(&samples[*b].abs()).unwrap())
+    };
+    let troughs=[opposite_extremum((0..lobe_start).rev().collect()), opposite_extremum((lobe_end+1..samples.len()).collect())];
+    let peak_to_trough=troughs.iter().flatten().map(|&i| i.abs_diff(peak) as f64*dt).fold(f64::INFINITY, f64::min);
+
+    FamilyMetrics{
+        peak_frequency: spectrum.peak_frequency(),
+        centroid_frequency,
+        bandwidth,
+        energy: energy_samples*dt,
+        main_lobe_width,
+        peak_to_trough: if peak_to_trough.is_finite() { peak_to_trough } else { 0.0 },
+        side_lobe_ratio,
+        centre_time,
+    }
+}
+
+///Generate every family at `dominant` Hz with `length` samples `dt` seconds apart
+pub fn compare_families(dominant: f64, dt: f64, length: usize)-> Result<FamilyComparison>{
+    let time=RickerWavelet::new(dominant, dt, length)?.time;
+    let t0=time.first().copied().unwrap_or(0.0);
+    let wavelets=WaveletFamily::ALL.iter().map(|&family| {
+        let samples=family.generate(dominant, dt, length)?;
+        let spectrum=spectral_analysis(&samples, dt, t0);
+        let metrics=metrics(&samples, &time, dt, &spectrum);
+        Ok(FamilyWavelet{ family, samples, spectrum, metrics })
+    }).collect::<Result<Vec<_>>>()?;
+    Ok(FamilyComparison{ dominant_frequency: dominant, dt, time, wavelets })
+}
+
+impl FamilyComparison{
+    pub fn get(&self, family: WaveletFamily)-> Option<&FamilyWavelet>{
+        self.wavelets.iter().find(|w| w.family==family)
+    }
+
+    pub fn print_summary(&self){
+        println!("Wavelet families at {} Hz dominant frequency (dt={} s)", self.dominant_frequency, self.dt);
+        println!("{:<8} {:>8} {:>9} {:>9} {:>9} {:>9} {:>10} {:>9} {:>9}",
+            "family", "peak Hz", "centroid", "bw Hz", "energy", "lobe ms", "p-t ms", "sidelobe", "centre ms");
+        for wavelet in &self.wavelets{
+            let m=&wavelet.metrics;
+            println!("{:<8} {:>8.1} {:>9.1} {:>9.1} {:>9.2e} {:>9.1} {:>10.1} {:>9.3} {:>9.1}",
+                wavelet.family.name(), m.peak_frequency, m.centroid_frequency, m.bandwidth, m.energy,
+                1000.0*m.main_lobe_width, 1000.0*m.peak_to_trough, m.side_lobe_ratio, 1000.0*m.centre_time);
+        }
+    }
+
+    ///Overlay of the wavelets: a `time` column and one column per family
+    pub fn write_time_csv(&self, path: &str)-> Result<()>{
+        let mut writer=csv::Writer::from_path(path).with_context(|| format!("Failed to create file: {}", path))?;
+        let mut header=vec!["time"];
+        header.extend(self.wavelets.iter().map(|w| w.family.name()));
+        writer.write_record(&header)?;
+        for (i, t) in self.time.iter().enumerate(){
+            let mut record=vec![t.to_string()];
+            record.extend(self.wavelets.iter().map(|w| w.samples[i].to_string()));
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    ///Overlay of the spectra: `freq`, then amplitude and energy per family, each normalised to a peak of one
+    pub fn write_spectrum_csv(&self, path: &str)-> Result<()>{
+        let mut writer=csv::Writer::from_path(path).with_context(|| format!("Failed to create file: {}", path))?;
+        let mut header=vec!["freq".to_string()];
+        for wavelet in &self.wavelets{
+            header.push(format!("{}_amplitude", wavelet.family.name()));
+            header.push(format!("{}_energy", wavelet.family.name()));
+        }
+        writer.write_record(&header)?;
+        let peaks: Vec<f64>=self.wavelets.iter().map(|w| w.spectrum.amplitude.iter().fold(f64::MIN_POSITIVE, |m, &a| m.max(a))).collect();
+        let freqs=self.wavelets.first().map_or(&[][..], |w| &w.spectrum.freqs[..]);
+        for (k, f) in freqs.iter().enumerate(){
+            let mut record=vec![f.to_string()];
+            for (wavelet, peak) in self.wavelets.iter().zip(&peaks){
+                let a=wavelet.spectrum.amplitude[k]/peak;
+                record.push(a.to_string());
+                record.push((a*a).to_string());
+            }
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_families_match_dominant_frequency()-> Result<()>{
+        let comparison=compare_families(30.0, 0.001, 301)?;
+        assert_eq!(comparison.wavelets.len(), 4);
+        for wavelet in &comparison.wavelets{
+            assert_eq!(wavelet.samples.len(), 301);
+            assert!((wavelet.samples.iter().fold(0.0f64, |a, x| a.max(x.abs()))-1.0).abs()<1e-12);
+        }
+
+        let ricker=&comparison.get(WaveletFamily::Ricker).unwrap().metrics;
+        let ormsby=&comparison.get(WaveletFamily::Ormsby).unwrap().metrics;
+        let klauder=&comparison.get(WaveletFamily::Klauder).unwrap().metrics;
+        let berlage=&comparison.get(WaveletFamily::Berlage).unwrap().metrics;
+        assert!((ricker.peak_frequency-30.0).abs()<2.0);
+        assert!((berlage.peak_frequency-30.0).abs()<3.0);
+        assert!((ormsby.centroid_frequency-30.0).abs()<3.0);
+        assert!((klauder.centroid_frequency-30.0).abs()<3.0);
+
+        //Ricker trough at sqrt(6)/(2 pi f) and side lobe 2 exp(-3/2)
+        assert!((ricker.peak_to_trough-6.0f64.sqrt()/(2.0*PI*30.0)).abs()<0.001);
+        assert!((ricker.side_lobe_ratio-2.0*(-1.5f64).exp()).abs()<0.01);
+        assert!(ricker.centre_time.abs()<1e-9 && berlage.centre_time>0.01);
+        //The sweep's sinc envelope and the Berlage decay ring longer than a Ricker
+        assert!(klauder.side_lobe_ratio>ricker.side_lobe_ratio && berlage.side_lobe_ratio>ricker.side_lobe_ratio);
+        assert!(compare_families(30.0, 0.02, 101).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlay_csv()-> Result<()>{
+        let comparison=compare_families(25.0, 0.002, 101)?;
+        let dir=std::env::temp_dir();
+        let (time_path, spectrum_path)=(dir.join("families_time.csv"), dir.join("families_spectrum.csv"));
+        comparison.write_time_csv(time_path.to_str().unwrap())?;
+        comparison.write_spectrum_csv(spectrum_path.to_str().unwrap())?;
+
+        let time=std::fs::read_to_string(&time_path)?;
+        let spectrum=std::fs::read_to_string(&spectrum_path)?;
+        std::fs::remove_file(&time_path)?;
+        std::fs::remove_file(&spectrum_path)?;
+        assert!(time.starts_with("time,ricker,ormsby,klauder,berlage\n"));
+        assert_eq!(time.lines().count(), 102);
+        assert!(spectrum.starts_with("freq,ricker_amplitude,ricker_energy,ormsby_amplitude"));
+        assert_eq!(spectrum.lines().count(), 1+comparison.wavelets[0].spectrum.freqs.len());
+        Ok(())
+    }
+}
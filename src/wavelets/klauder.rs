@@ -0,0 +1,125 @@
+//! Klauder wavelet: the autocorrelation of a linear Vibroseis sweep
+//!
+//! A vibrator emits a long sweep `cos(2 pi (f1 t + k t^2/2))` over
+//! `0<=t<=T`, with `k=(f2-f1)/T`, and the recorded trace is correlated with
+//! the pilot sweep. The correlated data are then the earth response
+//! convolved with the sweep's autocorrelation, which for a linear sweep is
+//! close to `cos(pi (f1+f2) t) sin(pi k t (T-|t|)) / (pi k t T)`: a
+//! zero-phase pulse whose spectrum is the flat sweep band.
+
+use anyhow::{Result, anyhow};
+use std::f64::consts::PI;
+use super::spectrum::{spectral_analysis, WaveletSpectrum};
+
+///Zero-phase wavelet recorded from correlated linear-sweep Vibroseis data
+///
+/// Samples are normalised to one at zero lag. The sweep's amplitude
+/// taper is not modelled, so the side lobes are those of an untapered
+/// sweep.
+#[derive(Debug, Clone)]
+pub struct KlauderWavelet{
+    ///Sweep start frequency in Hz
+    pub start_frequency: f64,
+    ///Sweep end frequency in Hz
+    pub end_frequency: f64,
+    ///Sweep length in seconds
+    pub sweep_length: f64,
+    ///Sample interval in seconds
+    pub dt: f64,
+    ///Wavelet samples
+    pub samples: Vec<f64>,
+    ///Time vector
+    pub time: Vec<f64>,
+}
+
+impl KlauderWavelet{
+    ///Create a Klauder wavelet with the same time axis convention as `RickerWavelet::new`
+    ///
+    /// The autocorrelation is the same for an up- or a downsweep, so the
+    /// frequencies may be given in either order.
+    pub fn new(start_frequency: f64, end_frequency: f64, sweep_length: f64, dt: f64, length: usize)-> Result<Self>{
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+        if length==0{
+            return Err(anyhow!("Wavelet length must be positive"));
+        }
+        if !(sweep_length>0.0 && sweep_length.is_finite()){
+            return Err(anyhow!("Sweep length must be positive, got {}", sweep_length));
+        }
+        let (low, high)=(start_frequency.min(end_frequency), start_frequency.max(end_frequency));
+        if !(low>=0.0 && low<high){
+            return Err(anyhow!("Sweep must cover a band of non-negative frequencies, got {} to {} Hz", start_frequency, end_frequency));
+        }
+        if high>=0.5/dt{
+            return Err(anyhow!("Sweep frequency {} Hz must be below Nyquist ({} Hz)", high, 0.5/dt));
+        }
+
+        let half_length=length as f64/2.0;
+        let time: Vec<f64>=(0..length).map(|i| (i as f64-half_length)*dt).collect();
+        let samples=time.iter().map(|&t| klauder(t, low, high, sweep_length)).collect();
+
+        Ok(Self{ start_frequency, end_frequency, sweep_length, dt, samples, time })
+    }
+
+    ///The pilot sweep, sampled at `dt` from zero to the sweep length
+    pub fn sweep(&self)-> Vec<f64>{
+        let rate=(self.end_frequency-self.start_frequency)/self.sweep_length;
+        let count=(self.sweep_length/self.dt).round() as usize+1;
+        (0..count).map(|i| {
+            let t=i as f64*self.dt;
+            (2.0*PI*(self.start_frequency*t+0.5*rate*t*t)).cos()
+        }).collect()
+    }
+
+    ///Amplitude spectrum, phase spectrum and group delay of the sampled wavelet
+    pub fn spectral_analysis(&self)-> WaveletSpectrum{
+        let t0=self.time.first().copied().unwrap_or(0.0);
+        spectral_analysis(&self.samples, self.dt, t0)
+    }
+}
+
+///Autocorrelation at lag `t` of a linear sweep from `f1` to `f2` Hz lasting `sweep` seconds, one at zero lag
+fn klauder(t: f64, f1: f64, f2: f64, sweep: f64)-> f64{
+    if t.abs()>=sweep{
+        return 0.0;
+    }
+    let rate=(f2-f1)/sweep;
+    let envelope=if t==0.0 { 1.0 } else { (PI*rate*t*(sweep-t.abs())).sin()/(PI*rate*t*sweep) };
+    envelope*(PI*(f1+f2)*t).cos()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_matches_sweep_autocorrelation()-> Result<()>{
+        let wavelet=KlauderWavelet::new(10.0, 60.0, 2.0, 0.001, 200)?;
+        assert_eq!(wavelet.samples[100], 1.0);
+
+        //Autocorrelate the pilot sweep directly; the sum-frequency terms are what differ
+        let sweep=wavelet.sweep();
+        assert_eq!(sweep.len(), 2001);
+        let lag=|k: usize| sweep.iter().zip(&sweep[k..]).map(|(a, b)| a*b).sum::<f64>();
+        let zero=lag(0);
+        for (i, &t) in wavelet.time.iter().enumerate(){
+            let k=(t/wavelet.dt).round().abs() as usize;
+            assert!((lag(k)/zero-wavelet.samples[i]).abs()<0.01, "lag {}: {} vs {}", k, lag(k)/zero, wavelet.samples[i]);
+        }
+
+        //Spans the sweep band, with the Fresnel ripple of an untapered sweep, and is quiet outside it
+        let spectrum=KlauderWavelet::new(10.0, 60.0, 2.0, 0.001, 2000)?.spectral_analysis();
+        let peak=spectrum.amplitude.iter().fold(0.0f64, |m, &a| m.max(a));
+        let at=|f: f64| spectrum.amplitude[spectrum.freqs.iter().position(|&x| x>=f).unwrap()]/peak;
+        assert!(at(20.0)>0.5 && at(50.0)>0.5);
+        assert!(at(5.0)<0.05 && at(80.0)<0.05);
+
+        let downsweep=KlauderWavelet::new(60.0, 10.0, 2.0, 0.001, 200)?;
+        assert_eq!(downsweep.samples, wavelet.samples);
+        assert!(KlauderWavelet::new(10.0, 10.0, 2.0, 0.001, 200).is_err());
+        assert!(KlauderWavelet::new(10.0, 600.0, 2.0, 0.001, 200).is_err());
+        assert!(KlauderWavelet::new(10.0, 60.0, 0.0, 0.001, 200).is_err());
+        Ok(())
+    }
+}
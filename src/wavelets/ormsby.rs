@@ -0,0 +1,105 @@
+//! Ormsby (trapezoidal band-pass) wavelet
+
+use anyhow::{Result, anyhow};
+use std::f64::consts::PI;
+use super::spectrum::{spectral_analysis, WaveletSpectrum};
+
+///Zero-phase wavelet whose amplitude spectrum is a trapezoid
+///
+/// The spectrum is zero below `f1`, ramps up linearly to one at `f2`, is flat
+/// to `f3` and ramps down to zero at `f4`. Amplitudes are not normalised so the
+/// continuous spectrum has a plateau of exactly one.
+#[derive(Debug, Clone)]
+pub struct OrmsbyWavelet{
+    ///Corner frequencies `[f1, f2, f3, f4]` in Hz
+    pub corners: [f64; 4],
+    ///Sample interval in seconds
+    pub dt: f64,
+    ///Wavelet samples
+    pub samples: Vec<f64>,
+    ///Time vector
+    pub time: Vec<f64>,
+}
+
+impl OrmsbyWavelet{
+    ///Create an Ormsby wavelet with the same time axis convention as `RickerWavelet::new`
+    pub fn new(corners: [f64; 4], dt: f64, length: usize)-> Result<Self>{
+        let [f1, f2, f3, f4]=corners;
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+        if length==0{
+            return Err(anyhow!("Wavelet length must be positive"));
+        }
+        if !(f1>=0.0 && f1<f2 && f2<=f3 && f3<f4){
+            return Err(anyhow!("Corner frequencies must satisfy 0 <= f1 < f2 <= f3 < f4, got {:?}", corners));
+        }
+        if f4>=0.5/dt{
+            return Err(anyhow!("f4 ({} Hz) must be below Nyquist ({} Hz)", f4, 0.5/dt));
+        }
+
+        let half_length=length as f64/2.0;
+        let time: Vec<f64>=(0..length).map(|i| (i as f64-half_length)*dt).collect();
+
+        //a^2 sinc^2(a t) transforms to the triangle max(a-|f|, 0)
+        let triangle=|a: f64, t: f64| a*a*sinc(a*t).powi(2);
+        let samples=time.iter().map(|&t| {
+            (triangle(f4, t)-triangle(f3, t))/(f4-f3)-(triangle(f2, t)-triangle(f1, t))/(f2-f1)
+        }).collect();
+
+        Ok(Self{ corners, dt, samples, time })
+    }
+
+    ///Closed-form amplitude spectrum at frequency `f` (the trapezoid)
+    pub fn analytic_amplitude(&self, f: f64)-> f64{
+        ormsby_amplitude_spectrum(f.abs(), self.corners)
+    }
+
+    ///Amplitude spectrum, phase spectrum and group delay of the sampled wavelet
+    pub fn spectral_analysis(&self)-> WaveletSpectrum{
+        let t0=self.time.first().copied().unwrap_or(0.0);
+        spectral_analysis(&self.samples, self.dt, t0)
+    }
+}
+
+///Trapezoidal amplitude spectrum with corners `[f1, f2, f3, f4]`
+pub fn ormsby_amplitude_spectrum(f: f64, corners: [f64; 4])-> f64{
+    let [f1, f2, f3, f4]=corners;
+    if f<=f1 || f>=f4{
+        0.0
+    }else if f<f2{
+        (f-f1)/(f2-f1)
+    }else if f<=f3{
+        1.0
+    }else{
+        (f4-f)/(f4-f3)
+    }
+}
+
+///Normalised sinc, `sin(pi x)/(pi x)`
+fn sinc(x: f64)-> f64{
+    if x.abs()<1e-12 { 1.0 } else { (PI*x).sin()/(PI*x) }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_ormsby_matches_trapezoid()-> Result<()>{
+        let corners=[5.0, 10.0, 40.0, 60.0];
+        let wavelet=OrmsbyWavelet::new(corners, 0.002, 2001)?;
+
+        //Truncating the slowly decaying sinc tails costs a few percent
+        let error=wavelet.spectral_analysis().max_relative_error(wavelet.dt, |f| wavelet.analytic_amplitude(f));
+        assert!(error<0.05, "error {}", error);
+
+        let short=OrmsbyWavelet::new(corners, 0.002, 51)?;
+        assert!(short.spectral_analysis().max_relative_error(short.dt, |f| short.analytic_amplitude(f))>0.1);
+
+        assert!(OrmsbyWavelet::new([10.0, 5.0, 40.0, 60.0], 0.002, 101).is_err());
+        assert!(OrmsbyWavelet::new([5.0, 10.0, 40.0, 300.0], 0.002, 101).is_err());
+
+        Ok(())
+    }
+}
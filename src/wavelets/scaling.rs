@@ -0,0 +1,163 @@
+//! Frequency scaling of existing wavelets
+//!
+//! Stretching resamples a wavelet's time axis at a fixed sample interval, which
+//! divides every frequency by the stretch factor. Bandwidth scaling instead keeps
+//! the peak frequency and widens or narrows the spectrum around it. Both work on
+//! any sampled wavelet, so frequency-sensitivity sweeps do not need an analytic form.
+
+use anyhow::{Result, anyhow};
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f64::consts::PI;
+use crate::utils::spline::CubicSpline;
+use super::RickerWavelet;
+use super::spectrum::spectral_analysis;
+
+///Resample `samples` (first sample at `t0`) so the output is `w(t/factor)` at the same `dt`
+///
+/// `factor>1` stretches the wavelet and lowers its frequencies, `factor<1`
+/// squeezes it. The output keeps time zero in place and has `factor` times as
+/// many samples so nothing is clipped. Amplitudes are preserved, not energy.
+pub fn stretch(samples: &[f64], dt: f64, t0: f64, factor: f64)-> Result<(f64, Vec<f64>)>{
+    if !(factor>0.0 && factor.is_finite()){
+        return Err(anyhow!("Stretch factor must be positive, got {}", factor));
+    }
+    if samples.len()<2{
+        return Err(anyhow!("Need at least two samples to stretch a wavelet"));
+    }
+
+    let time: Vec<f64>=(0..samples.len()).map(|i| t0+i as f64*dt).collect();
+    let spline=CubicSpline::natural(&time, samples)?;
+    let (first, last)=(time[0], time[time.len()-1]);
+
+    let new_t0=(t0*factor/dt).floor()*dt;
+    let length=((last*factor-new_t0)/dt).floor() as usize+1;
+    let stretched=(0..length).map(|i| {
+        let source=(new_t0+i as f64*dt)/factor;
+        if source<first || source>last { 0.0 } else { spline.evaluate(source) }
+    }).collect();
+
+    Ok((new_t0, stretched))
+}
+
+///Widen (`factor>1`) or narrow (`factor<1`) the bandwidth around the peak frequency
+///
+/// Frequencies are mapped on a log scale, `f -> fp*(f/fp)^(1/factor)`, so the
+/// peak `fp` stays put, zero frequency stays at zero and the bandwidth measured
+/// in octaves is multiplied by `factor`. The phase spectrum is carried along
+/// with the amplitude. The output has the same length and time axis as the
+/// input; narrowing the band lengthens the wavelet, so leave room in the tails.
+pub fn scale_bandwidth(samples: &[f64], dt: f64, t0: f64, factor: f64)-> Result<Vec<f64>>{
+    if !(factor>0.0 && factor.is_finite()){
+        return Err(anyhow!("Bandwidth factor must be positive, got {}", factor));
+    }
+    if samples.is_empty(){
+        return Err(anyhow!("Cannot scale the bandwidth of an empty wavelet"));
+    }
+    let peak=spectral_analysis(samples, dt, t0).peak_frequency();
+    if peak<=0.0{
+        return Err(anyhow!("Wavelet has no energy away from zero frequency"));
+    }
+
+    let n=(4*samples.len()).next_power_of_two();
+    let df=1.0/(n as f64*dt);
+    let mut planner=FftPlanner::new();
+    let mut spectrum: Vec<Complex<f64>>=samples.iter().map(|&x| Complex::new(x, 0.0)).collect();
+    spectrum.resize(n, Complex::new(0.0, 0.0));
+    planner.plan_fft_forward(n).process(&mut spectrum);
+
+    //Spectrum with the linear phase of the start time removed, so it varies slowly
+    let shift=|f: f64| Complex::from_polar(1.0, -2.0*PI*f*t0);
+    let referenced: Vec<Complex<f64>>=(0..=n/2).map(|k| spectrum[k]*shift(k as f64*df)).collect();
+
+    let mut scaled=vec![Complex::new(0.0, 0.0); n];
+    for (k, value) in scaled.iter_mut().enumerate().take(n/2+1).skip(1){
+        let f=k as f64*df;
+        let position=peak*(f/peak).powf(1.0/factor)/df;
+        let lower=position.floor() as usize;
+        if lower>=n/2{
+            continue;
+        }
+        let weight=position-lower as f64;
+        *value=(referenced[lower]*(1.0-weight)+referenced[lower+1]*weight)*shift(f).conj();
+    }
+    //Keep the spectrum Hermitian so the wavelet stays real
+    let (positive, negative)=scaled.split_at_mut(n/2+1);
+    for (low, high) in positive[1..n/2].iter().zip(negative.iter_mut().rev()){
+        *high=low.conj();
+    }
+    scaled[n/2]=Complex::new(scaled[n/2].re, 0.0);
+
+    planner.plan_fft_inverse(n).process(&mut scaled);
+    Ok(scaled[..samples.len()].iter().map(|c| c.re/n as f64).collect())
+}
+
+impl RickerWavelet{
+    ///The same wavelet resampled to a new dominant frequency at the same `dt`
+    ///
+    /// Unlike `RickerWavelet::new` this stretches the existing samples, so it
+    /// also works after the samples have been filtered or rotated.
+    pub fn with_frequency(&self, frequency: f64)-> Result<Self>{
+        if frequency<=0.0{
+            return Err(anyhow!("Frequency must be positive, got {}", frequency));
+        }
+        let t0=self.time.first().copied().unwrap_or(0.0);
+        let (new_t0, samples)=stretch(&self.samples, self.dt, t0, self.frequency/frequency)?;
+        let time=(0..samples.len()).map(|i| new_t0+i as f64*self.dt).collect();
+
+        Ok(Self{ frequency, dt: self.dt, samples, time })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_with_frequency_matches_analytic()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.001, 200)?;
+        let stretched=wavelet.with_frequency(20.0)?;
+        assert_eq!(stretched.frequency, 20.0);
+        assert!(stretched.samples.len()>=299);
+
+        for (&t, &value) in stretched.time.iter().zip(&stretched.samples){
+            let arg=(PI*20.0*t).powi(2);
+            assert_abs_diff_eq!(value, (1.0-2.0*arg)*(-arg).exp(), epsilon=1e-4);
+        }
+
+        let squeezed=wavelet.with_frequency(45.0)?;
+        assert_abs_diff_eq!(squeezed.spectral_analysis().peak_frequency(), 45.0, epsilon=1.5);
+        assert!(wavelet.with_frequency(0.0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scale_bandwidth_keeps_peak()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.001, 256)?;
+        let t0=wavelet.time[0];
+
+        let unchanged=scale_bandwidth(&wavelet.samples, wavelet.dt, t0, 1.0)?;
+        for (a, b) in unchanged.iter().zip(&wavelet.samples){
+            assert_abs_diff_eq!(a, b, epsilon=1e-9);
+        }
+
+        //Half-amplitude width of the spectrum in octaves
+        let octaves=|samples: &[f64]| {
+            let spectrum=spectral_analysis(samples, wavelet.dt, t0);
+            let peak=spectrum.amplitude.iter().fold(0.0_f64, |m, &a| m.max(a));
+            let band: Vec<f64>=spectrum.freqs.iter().zip(&spectrum.amplitude).filter(|(_, &a)| a>=0.5*peak).map(|(&f, _)| f).collect();
+            (band[band.len()-1]/band[0]).log2()
+        };
+
+        let wider=scale_bandwidth(&wavelet.samples, wavelet.dt, t0, 1.5)?;
+        assert_abs_diff_eq!(spectral_analysis(&wider, wavelet.dt, t0).peak_frequency(), 30.0, epsilon=1.5);
+        assert_abs_diff_eq!(octaves(&wider)/octaves(&wavelet.samples), 1.5, epsilon=0.1);
+
+        let narrower=scale_bandwidth(&wavelet.samples, wavelet.dt, t0, 0.75)?;
+        assert_abs_diff_eq!(octaves(&narrower)/octaves(&wavelet.samples), 0.75, epsilon=0.1);
+
+        Ok(())
+    }
+}
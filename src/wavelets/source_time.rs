@@ -0,0 +1,255 @@
+//! Earthquake and microseismic source time functions
+//!
+//! Passive sources are described by their moment-rate function rather than a
+//! zero-phase pulse: it is causal, starts at the origin time and integrates to
+//! the seismic moment. The functions here are built with unit area and then
+//! scaled to a moment in N·m, either directly or from a moment magnitude.
+//! Every wavelet in the crate implements `Wavelet`, so solvers that take a
+//! source signature accept a source time function in place of a Ricker.
+
+use anyhow::{Result, anyhow};
+use std::f64::consts::PI;
+use super::RickerWavelet;
+use super::klauder::KlauderWavelet;
+use super::ormsby::OrmsbyWavelet;
+
+///A sampled source signature
+pub trait Wavelet{
+    ///Sample interval in seconds
+    fn dt(&self)-> f64;
+
+    ///Wavelet samples
+    fn samples(&self)-> &[f64];
+
+    ///Time of each sample in seconds
+    fn time(&self)-> &[f64];
+
+    ///Time integral of the samples
+    fn area(&self)-> f64{
+        self.samples().iter().sum::<f64>()*self.dt()
+    }
+}
+
+impl Wavelet for RickerWavelet{
+    fn dt(&self)-> f64{
+        self.dt
+    }
+
+    fn samples(&self)-> &[f64]{
+        &self.samples
+    }
+
+    fn time(&self)-> &[f64]{
+        &self.time
+    }
+}
+
+impl Wavelet for OrmsbyWavelet{
+    fn dt(&self)-> f64{
+        self.dt
+    }
+
+    fn samples(&self)-> &[f64]{
+        &self.samples
+    }
+
+    fn time(&self)-> &[f64]{
+        &self.time
+    }
+}
+
+impl Wavelet for KlauderWavelet{
+    fn dt(&self)-> f64{
+        self.dt
+    }
+
+    fn samples(&self)-> &[f64]{
+        &self.samples
+    }
+
+    fn time(&self)-> &[f64]{
+        &self.time
+    }
+}
+
+///Shape of a source time function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceKind{
+    ///Gaussian moment rate of the given half duration in seconds
+    Gaussian{ half_duration: f64 },
+    ///Isosceles triangle rising over the half duration and falling over the next
+    Triangle{ half_duration: f64 },
+    ///Brune (1970) pulse `ωc² t exp(-ωc t)` with an ω² spectrum above the corner frequency in Hz
+    Brune{ corner_frequency: f64 },
+    ///All of the moment released in the first sample
+    Delta,
+}
+
+///Moment-rate function sampled from the origin time, in N·m/s once scaled
+#[derive(Debug, Clone)]
+pub struct SourceTimeFunction{
+    pub kind: SourceKind,
+    ///Sample interval in seconds
+    pub dt: f64,
+    ///Moment-rate samples
+    pub samples: Vec<f64>,
+    ///Time after the origin of each sample
+    pub time: Vec<f64>,
+}
+
+///Seismic moment in N·m of moment magnitude `magnitude` (Hanks & Kanamori)
+pub fn moment_from_magnitude(magnitude: f64)-> f64{
+    10f64.powf(1.5*magnitude+9.1)
+}
+
+///Moment magnitude of a seismic moment in N·m
+pub fn magnitude_from_moment(moment: f64)-> f64{
+    (moment.log10()-9.1)/1.5
+}
+
+impl SourceTimeFunction{
+    ///Unit-moment source time function of `length` samples
+    pub fn new(kind: SourceKind, dt: f64, length: usize)-> Result<Self>{
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+        if length==0{
+            return Err(anyhow!("Source time function length must be positive"));
+        }
+        let duration=match kind{
+            SourceKind::Gaussian{ half_duration } | SourceKind::Triangle{ half_duration }=> half_duration,
+            SourceKind::Brune{ corner_frequency }=> 1.0/corner_frequency,
+            SourceKind::Delta=> dt,
+        };
+        if duration.is_nan() || duration<=0.0{
+            return Err(anyhow!("Source duration parameters must be positive, got {:?}", kind));
+        }
+
+        let time: Vec<f64>=(0..length).map(|i| i as f64*dt).collect();
+        let samples=time.iter().map(|&t| match kind{
+            SourceKind::Gaussian{ half_duration }=> {
+                //Half duration as defined for CMT solutions; centred two half durations after the origin so the onset is negligible
+                let alpha=1.628/half_duration;
+                let t0=2.0*half_duration;
+                alpha/PI.sqrt()*(-(alpha*(t-t0)).powi(2)).exp()
+            }
+            SourceKind::Triangle{ half_duration }=> ((half_duration-(t-half_duration).abs())/(half_duration*half_duration)).max(0.0),
+            SourceKind::Brune{ corner_frequency }=> {
+                let omega=2.0*PI*corner_frequency;
+                omega*omega*t*(-omega*t).exp()
+            }
+            SourceKind::Delta=> if t==0.0 { 1.0/dt } else { 0.0 },
+        }).collect();
+
+        Ok(Self{ kind, dt, samples, time })
+    }
+
+    pub fn gaussian(half_duration: f64, dt: f64, length: usize)-> Result<Self>{
+        Self::new(SourceKind::Gaussian{ half_duration }, dt, length)
+    }
+
+    pub fn triangle(half_duration: f64, dt: f64, length: usize)-> Result<Self>{
+        Self::new(SourceKind::Triangle{ half_duration }, dt, length)
+    }
+
+    pub fn brune(corner_frequency: f64, dt: f64, length: usize)-> Result<Self>{
+        Self::new(SourceKind::Brune{ corner_frequency }, dt, length)
+    }
+
+    pub fn delta(dt: f64, length: usize)-> Result<Self>{
+        Self::new(SourceKind::Delta, dt, length)
+    }
+
+    ///Seismic moment released within the sampled window
+    pub fn moment(&self)-> f64{
+        self.area()
+    }
+
+    ///Scale to release `moment` N·m within the sampled window
+    pub fn with_moment(mut self, moment: f64)-> Result<Self>{
+        let current=self.moment();
+        if moment.is_nan() || moment<=0.0 || current<=0.0{
+            return Err(anyhow!("Cannot scale a source time function of moment {} to {}", current, moment));
+        }
+        self.samples.iter_mut().for_each(|s| *s*=moment/current);
+        Ok(self)
+    }
+
+    ///Scale to the moment of magnitude `magnitude`
+    pub fn with_magnitude(self, magnitude: f64)-> Result<Self>{
+        self.with_moment(moment_from_magnitude(magnitude))
+    }
+
+    ///Cumulative moment (the moment function) at each sample
+    pub fn cumulative_moment(&self)-> Vec<f64>{
+        self.samples.iter().scan(0.0, |sum, s| { *sum+=s*self.dt; Some(*sum) }).collect()
+    }
+}
+
+impl Wavelet for SourceTimeFunction{
+    fn dt(&self)-> f64{
+        self.dt
+    }
+
+    fn samples(&self)-> &[f64]{
+        &self.samples
+    }
+
+    fn time(&self)-> &[f64]{
+        &self.time
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    ///Amplitude of the continuous-time Fourier transform at `f`, by direct summation
+    fn spectrum_at(wavelet: &dyn Wavelet, f: f64)-> f64{
+        let (re, im)=wavelet.samples().iter().zip(wavelet.time()).fold((0.0, 0.0), |(re, im), (s, t)| {
+            let phase=2.0*PI*f*t;
+            (re+s*phase.cos(), im-s*phase.sin())
+        });
+        (re*re+im*im).sqrt()*wavelet.dt()
+    }
+
+    #[test]
+    fn test_unit_area_and_moment_scaling()-> Result<()>{
+        let dt=0.001;
+        let functions=[
+            SourceTimeFunction::gaussian(0.05, dt, 400)?,
+            SourceTimeFunction::triangle(0.05, dt, 400)?,
+            SourceTimeFunction::brune(10.0, dt, 1000)?,
+            SourceTimeFunction::delta(dt, 10)?,
+        ];
+        for stf in &functions{
+            assert_abs_diff_eq!(stf.moment(), 1.0, epsilon=1e-3);
+        }
+        //Triangle peaks at 1/half_duration after one half duration
+        assert_abs_diff_eq!(functions[1].samples[50], 20.0, epsilon=1e-9);
+
+        let scaled=functions[0].clone().with_magnitude(2.0)?;
+        assert_abs_diff_eq!(magnitude_from_moment(scaled.moment()), 2.0, epsilon=1e-9);
+        assert_abs_diff_eq!(*scaled.cumulative_moment().last().unwrap(), scaled.moment(), epsilon=1e-6*scaled.moment());
+
+        assert!(SourceTimeFunction::triangle(-0.1, dt, 100).is_err());
+        assert!(SourceTimeFunction::delta(dt, 10)?.with_moment(0.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_brune_spectrum_and_trait_objects()-> Result<()>{
+        let brune=SourceTimeFunction::brune(5.0, 0.0005, 8000)?;
+        //ω² model: flat at the moment, down by half at the corner frequency
+        assert_abs_diff_eq!(spectrum_at(&brune, 0.01), 1.0, epsilon=1e-3);
+        assert_abs_diff_eq!(spectrum_at(&brune, 5.0), 0.5, epsilon=1e-3);
+        assert_abs_diff_eq!(spectrum_at(&brune, 50.0), 1.0/(1.0+100.0), epsilon=1e-3);
+
+        let sources: Vec<Box<dyn Wavelet>>=vec![Box::new(brune), Box::new(RickerWavelet::new(25.0, 0.002, 64)?)];
+        assert_eq!(sources[1].samples().len(), 64);
+        //A Ricker has no net area, so it releases no moment
+        assert_abs_diff_eq!(sources[1].area(), 0.0, epsilon=1e-6);
+        Ok(())
+    }
+}
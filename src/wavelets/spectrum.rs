@@ -0,0 +1,184 @@
+//! Spectral analysis of wavelets: amplitude, phase and group delay
+
+use num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f64::consts::PI;
+use super::RickerWavelet;
+
+///Amplitude, phase and group-delay spectra of a wavelet
+#[derive(Debug, Clone)]
+pub struct WaveletSpectrum{
+    ///Frequencies from 0 to Nyquist in Hz
+    pub freqs: Vec<f64>,
+    ///Amplitude spectrum
+    pub amplitude: Vec<f64>,
+    ///Unwrapped phase in radians, referenced to time zero
+    pub phase: Vec<f64>,
+    ///Group delay `-dphi/domega` in seconds
+    pub group_delay: Vec<f64>,
+}
+
+impl WaveletSpectrum{
+    ///Frequency of the amplitude spectrum peak
+    pub fn peak_frequency(&self)-> f64{
+        self.amplitude.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map(|(i, _)| self.freqs[i]).unwrap_or(0.0)
+    }
+
+    ///Amplitude-weighted mean group delay, a single number for the wavelet's delay
+    pub fn mean_group_delay(&self)-> f64{
+        let weights: f64=self.amplitude.iter().map(|a| a*a).sum();
+        if weights==0.0{
+            return 0.0;
+        }
+        self.group_delay.iter().zip(self.amplitude.iter()).map(|(d, a)| d*a*a).sum::<f64>()/weights
+    }
+
+    ///Largest deviation from a closed-form amplitude spectrum, relative to its peak
+    ///
+    /// The discrete spectrum is scaled by `dt` so it approximates the continuous
+    /// Fourier transform. Errors well above zero point to a wavelet that was
+    /// truncated too short or sampled too coarsely.
+    pub fn max_relative_error(&self, dt: f64, analytic: impl Fn(f64)-> f64)-> f64{
+        let expected: Vec<f64>=self.freqs.iter().map(|&f| analytic(f)).collect();
+        let peak=expected.iter().fold(0.0_f64, |m, &a| m.max(a));
+        if peak==0.0{
+            return 0.0;
+        }
+        self.amplitude.iter().zip(&expected).map(|(a, e)| (a*dt-e).abs()).fold(0.0, f64::max)/peak
+    }
+}
+
+///Continuous amplitude spectrum of a Ricker wavelet with peak frequency `peak`
+///
+/// `A(f) = 2 f^2 / (sqrt(pi) fp^3) exp(-f^2/fp^2)` for the unit-amplitude wavelet
+/// `(1-2 pi^2 fp^2 t^2) exp(-pi^2 fp^2 t^2)`.
+pub fn ricker_amplitude_spectrum(f: f64, peak: f64)-> f64{
+    2.0*f*f/(PI.sqrt()*peak.powi(3))*(-(f*f)/(peak*peak)).exp()
+}
+
+///Compute the spectra of `samples` taken every `dt` seconds with the first sample at `t0`
+///
+/// The trace is zero padded to at least four times its length so the phase
+/// can be unwrapped reliably. Referencing the phase to `t0` means a symmetric
+/// wavelet centred on time zero reports zero phase and zero group delay.
+pub fn spectral_analysis(samples: &[f64], dt: f64, t0: f64)-> WaveletSpectrum{
+    if samples.is_empty(){
+        return WaveletSpectrum{ freqs: vec![], amplitude: vec![], phase: vec![], group_delay: vec![] };
+    }
+
+    let n=(4*samples.len()).next_power_of_two();
+    let mut buffer: Vec<Complex<f64>>=samples.iter().map(|&x| Complex::new(x, 0.0)).collect();
+    buffer.resize(n, Complex::new(0.0, 0.0));
+    FftPlanner::new().plan_fft_forward(n).process(&mut buffer);
+
+    let bins=n/2+1;
+    let df=1.0/(n as f64*dt);
+    let freqs: Vec<f64>=(0..bins).map(|k| k as f64*df).collect();
+
+    let amplitude: Vec<f64>=buffer[..bins].iter().map(|c| c.norm()).collect();
+    let wrapped: Vec<f64>=buffer[..bins].iter().zip(freqs.iter()).map(|(c, &f)| {
+        (c*Complex::from_polar(1.0, -2.0*PI*f*t0)).arg()
+    }).collect();
+    let phase=unwrap_phase(&wrapped);
+
+    //Central differences inside, one-sided at the ends
+    let group_delay=(0..bins).map(|k| {
+        let (lo, hi)=(k.saturating_sub(1), (k+1).min(bins-1));
+        if hi==lo{
+            return 0.0;
+        }
+        -(phase[hi]-phase[lo])/(2.0*PI*(freqs[hi]-freqs[lo]))
+    }).collect();
+
+    WaveletSpectrum{ freqs, amplitude, phase, group_delay }
+}
+
+///Remove 2*pi jumps between consecutive phase samples
+pub fn unwrap_phase(phase: &[f64])-> Vec<f64>{
+    let mut unwrapped=Vec::with_capacity(phase.len());
+    let mut offset=0.0;
+
+    for (i, &p) in phase.iter().enumerate(){
+        if i>0{
+            let jump=p-phase[i-1];
+            if jump>PI{
+                offset-=2.0*PI;
+            }else if jump< -PI{
+                offset+=2.0*PI;
+            }
+        }
+        unwrapped.push(p+offset);
+    }
+
+    unwrapped
+}
+
+impl RickerWavelet{
+    ///Amplitude spectrum, phase spectrum and group delay of the wavelet
+    pub fn spectral_analysis(&self)-> WaveletSpectrum{
+        let t0=self.time.first().copied().unwrap_or(0.0);
+        spectral_analysis(&self.samples, self.dt, t0)
+    }
+
+    ///Closed-form amplitude spectrum at frequency `f`
+    pub fn analytic_amplitude(&self, f: f64)-> f64{
+        ricker_amplitude_spectrum(f.abs(), self.frequency)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use anyhow::Result;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_ricker_is_zero_phase()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.001, 200)?;
+        let spectrum=wavelet.spectral_analysis();
+
+        assert_abs_diff_eq!(spectrum.peak_frequency(), 30.0, epsilon=1.0);
+
+        //Within the band the phase and delay vanish
+        for (k, &f) in spectrum.freqs.iter().enumerate(){
+            if f>5.0 && f<80.0{
+                assert_abs_diff_eq!(spectrum.phase[k], 0.0, epsilon=1e-6);
+                assert_abs_diff_eq!(spectrum.group_delay[k], 0.0, epsilon=1e-6);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delayed_spike_group_delay(){
+        let mut samples=vec![0.0; 64];
+        samples[10]=1.0;
+
+        let spectrum=spectral_analysis(&samples, 0.002, 0.0);
+
+        assert_abs_diff_eq!(spectrum.mean_group_delay(), 0.02, epsilon=1e-9);
+        //Linear phase: -2*pi*f*delay
+        let k=20;
+        assert_abs_diff_eq!(spectrum.phase[k], -2.0*PI*spectrum.freqs[k]*0.02, epsilon=1e-9);
+    }
+
+    #[test]
+    fn test_ricker_matches_analytic_spectrum()-> Result<()>{
+        let wavelet=RickerWavelet::new(30.0, 0.001, 200)?;
+        let error=wavelet.spectral_analysis().max_relative_error(wavelet.dt, |f| wavelet.analytic_amplitude(f));
+        assert!(error<1e-3, "error {}", error);
+
+        //Peak of the closed form sits at the dominant frequency
+        assert!(ricker_amplitude_spectrum(30.0, 30.0)>ricker_amplitude_spectrum(29.0, 30.0));
+        assert!(ricker_amplitude_spectrum(30.0, 30.0)>ricker_amplitude_spectrum(31.0, 30.0));
+
+        //Too short a wavelet, or one aliased by a coarse dt, departs from the closed form
+        let truncated=RickerWavelet::new(30.0, 0.001, 30)?;
+        assert!(truncated.spectral_analysis().max_relative_error(truncated.dt, |f| truncated.analytic_amplitude(f))>0.05);
+        let coarse=RickerWavelet::new(30.0, 0.012, 40)?;
+        assert!(coarse.spectral_analysis().max_relative_error(coarse.dt, |f| coarse.analytic_amplitude(f))>0.05);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! Detection of wavelets clipped by too short a length
+//!
+//! A wavelet cut off inside its tails loses low frequencies and rings in the
+//! synthetics. Comparing the sampled energy with the analytic energy tells how
+//! much was lost, and the length can be grown until the loss is acceptable.
+
+use anyhow::{Result, anyhow};
+use std::f64::consts::PI;
+use super::RickerWavelet;
+use super::ormsby::OrmsbyWavelet;
+
+///Longest wavelet auto-extension will produce
+const MAX_EXTENDED_LENGTH: usize=1<<20;
+
+///What to do when a wavelet loses too much energy to truncation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TruncationPolicy{
+    ///Keep the requested length and record a warning
+    Warn,
+    ///Double the length until the loss is under the threshold
+    Extend,
+}
+
+///Limits on energy lost to truncation
+#[derive(Debug, Clone, Copy)]
+pub struct TruncationCheck{
+    ///Largest acceptable fraction of the analytic energy outside the wavelet
+    pub max_lost_energy: f64,
+    pub policy: TruncationPolicy,
+}
+
+impl Default for TruncationCheck{
+    fn default()-> Self{
+        Self{ max_lost_energy: 1e-3, policy: TruncationPolicy::Warn }
+    }
+}
+
+///Outcome of building a wavelet under a `TruncationCheck`
+#[derive(Debug, Clone)]
+pub struct WaveletReport{
+    pub requested_length: usize,
+    ///Length actually used, larger than requested after auto-extension
+    pub length: usize,
+    ///Fraction of the analytic energy missing from the sampled wavelet
+    pub lost_energy: f64,
+    pub warnings: Vec<String>,
+}
+
+impl WaveletReport{
+    pub fn is_clean(&self)-> bool{
+        self.warnings.is_empty()
+    }
+}
+
+///Fraction of `analytic_energy` missing from `samples`, clamped at zero
+pub fn lost_energy_fraction(samples: &[f64], dt: f64, analytic_energy: f64)-> f64{
+    let sampled=dt*samples.iter().map(|x| x*x).sum::<f64>();
+    (1.0-sampled/analytic_energy).max(0.0)
+}
+
+///Build a wavelet at `length`, checking and if asked extending it
+fn build_checked<W>(length: usize, check: &TruncationCheck, analytic_energy: f64, dt: f64,
+                    build: impl Fn(usize)-> Result<W>, samples: impl Fn(&W)-> &[f64])-> Result<(W, WaveletReport)>{
+    if !(check.max_lost_energy>0.0 && check.max_lost_energy<1.0){
+        return Err(anyhow!("Energy loss threshold must be between 0 and 1, got {}", check.max_lost_energy));
+    }
+
+    let mut current=length;
+    let mut wavelet=build(current)?;
+    let mut lost=lost_energy_fraction(samples(&wavelet), dt, analytic_energy);
+    if check.policy==TruncationPolicy::Extend{
+        while lost>check.max_lost_energy{
+            if current>=MAX_EXTENDED_LENGTH{
+                return Err(anyhow!("Wavelet still loses {:.2e} of its energy at {} samples", lost, current));
+            }
+            current=2*current.max(1);
+            wavelet=build(current)?;
+            lost=lost_energy_fraction(samples(&wavelet), dt, analytic_energy);
+        }
+    }
+
+    let mut warnings=Vec::new();
+    if lost>check.max_lost_energy{
+        warnings.push(format!("Wavelet of {} samples loses {:.2}% of its energy to truncation (limit {:.2}%)",
+                              current, 100.0*lost, 100.0*check.max_lost_energy));
+    }
+    Ok((wavelet, WaveletReport{ requested_length: length, length: current, lost_energy: lost, warnings }))
+}
+
+impl RickerWavelet{
+    ///Energy of the continuous wavelet, `3/(4 sqrt(2 pi) fp)`
+    pub fn analytic_energy(&self)-> f64{
+        3.0/(4.0*(2.0*PI).sqrt()*self.frequency)
+    }
+
+    ///`RickerWavelet::new` that reports, or repairs, energy lost to truncation
+    pub fn new_checked(frequency: f64, dt: f64, length: usize, check: &TruncationCheck)-> Result<(Self, WaveletReport)>{
+        let analytic_energy=Self::new(frequency, dt, 1)?.analytic_energy();
+        build_checked(length, check, analytic_energy, dt, |n| Self::new(frequency, dt, n), |w| &w.samples)
+    }
+}
+
+impl OrmsbyWavelet{
+    ///Energy of the continuous wavelet, the integral of the squared trapezoid over both signs of frequency
+    pub fn analytic_energy(&self)-> f64{
+        let [f1, f2, f3, f4]=self.corners;
+        2.0*((f2-f1)/3.0+(f3-f2)+(f4-f3)/3.0)
+    }
+
+    ///`OrmsbyWavelet::new` that reports, or repairs, energy lost to truncation
+    pub fn new_checked(corners: [f64; 4], dt: f64, length: usize, check: &TruncationCheck)-> Result<(Self, WaveletReport)>{
+        let analytic_energy=Self::new(corners, dt, 1)?.analytic_energy();
+        build_checked(length, check, analytic_energy, dt, |n| Self::new(corners, dt, n), |w| &w.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_ricker_truncation_warning_and_extension()-> Result<()>{
+        let (long, report)=RickerWavelet::new_checked(30.0, 0.001, 200, &TruncationCheck::default())?;
+        assert!(report.is_clean());
+        assert_abs_diff_eq!(report.lost_energy, 0.0, epsilon=1e-9);
+        assert_abs_diff_eq!(long.dt*long.samples.iter().map(|x| x*x).sum::<f64>(), long.analytic_energy(), epsilon=1e-9);
+
+        let (short, report)=RickerWavelet::new_checked(30.0, 0.001, 24, &TruncationCheck::default())?;
+        assert_eq!(short.samples.len(), 24);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.lost_energy>0.01);
+
+        let extend=TruncationCheck{ policy: TruncationPolicy::Extend, ..TruncationCheck::default() };
+        let (extended, report)=RickerWavelet::new_checked(30.0, 0.001, 24, &extend)?;
+        assert!(report.is_clean());
+        assert_eq!(report.requested_length, 24);
+        assert_eq!(extended.samples.len(), report.length);
+        assert!(report.length>24 && report.lost_energy<=1e-3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ormsby_extension()-> Result<()>{
+        let extend=TruncationCheck{ max_lost_energy: 0.01, policy: TruncationPolicy::Extend };
+        let (_, report)=OrmsbyWavelet::new_checked([5.0, 10.0, 40.0, 60.0], 0.002, 21, &extend)?;
+        assert!(report.is_clean() && report.length>21);
+
+        let invalid=TruncationCheck{ max_lost_energy: 0.0, ..TruncationCheck::default() };
+        assert!(RickerWavelet::new_checked(30.0, 0.001, 24, &invalid).is_err());
+
+        Ok(())
+    }
+}
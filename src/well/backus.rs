@@ -0,0 +1,126 @@
+//! Backus averaging of finely sampled elastic logs
+//!
+//! Over a window much shorter than the seismic wavelength a stack of thin
+//! layers behaves like a single effective medium. For vertical propagation
+//! its moduli are the thickness-weighted harmonic means of the layer moduli:
+//! `C33=<1/(rho*vp^2)>^-1`, `C44=<1/(rho*vs^2)>^-1` and `rho=<rho>`.
+
+use anyhow::{Result, anyhow};
+use super::WellLog;
+
+///Length of the running Backus window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackusWindow{
+    ///Window length in metres of depth
+    Depth(f64),
+    ///Window length in milliseconds of two-way time
+    TwoWayTime(f64),
+}
+
+impl WellLog{
+    ///Upscale the logs with a running Backus average centred on every sample
+    ///
+    /// A common rule of thumb is a window of about a third of the dominant
+    /// seismic wavelength.
+    pub fn backus_average(&self, window: BackusWindow)-> Result<WellLog>{
+        let (axis, half_width)=match window{
+            BackusWindow::Depth(metres) if metres>0.0=> (self.depth.clone(), metres/2.0),
+            BackusWindow::TwoWayTime(ms) if ms>0.0=> (self.two_way_time(), ms/2000.0),
+            _=> return Err(anyhow!("Backus window must be positive, got {:?}", window)),
+        };
+
+        let thickness=self.sample_thickness();
+        let n=self.len();
+        let (mut vp, mut vs, mut rho)=(Vec::with_capacity(n), Vec::with_capacity(n), Vec::with_capacity(n));
+        let mut start=0;
+        let mut end=0;
+
+        for i in 0..n{
+            while axis[i]-axis[start]>half_width{
+                start+=1;
+            }
+            while end+1<n && axis[end+1]-axis[i]<=half_width{
+                end+=1;
+            }
+
+            let (mut weight, mut density, mut p_compliance, mut s_compliance)=(0.0, 0.0, 0.0, 0.0);
+            let window=start..=end;
+            let samples=thickness[window.clone()].iter().zip(&self.vp[window.clone()]).zip(&self.vs[window.clone()]).zip(&self.rho[window]);
+            for (((&h, &sample_vp), &sample_vs), &sample_rho) in samples{
+                //A lone sample has no thickness; give it unit weight so it passes through
+                let w=if h>0.0 { h } else { 1.0 };
+                weight+=w;
+                density+=w*sample_rho;
+                p_compliance+=w/(sample_rho*sample_vp.powi(2));
+                s_compliance+=if sample_vs>0.0 { w/(sample_rho*sample_vs.powi(2)) } else { f64::INFINITY };
+            }
+
+            let mean_rho=density/weight;
+            let c33=weight/p_compliance;
+            let c44=weight/s_compliance;
+
+            rho.push(mean_rho);
+            vp.push((c33/mean_rho).sqrt());
+            vs.push((c44/mean_rho).sqrt());
+        }
+
+        WellLog::new(self.depth.clone(), vp, vs, rho)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn alternating()-> WellLog{
+        //0.5 m layers alternating between two lithologies
+        let depth: Vec<f64>=(0..400).map(|i| 1000.0+0.5*i as f64).collect();
+        let pick=|a: f64, b: f64| (0..400).map(|i| if (i/2)%2==0 { a } else { b }).collect::<Vec<f64>>();
+        WellLog::new(depth, pick(2500.0, 3500.0), pick(1200.0, 2000.0), pick(2300.0, 2500.0)).unwrap()
+    }
+
+    #[test]
+    fn test_homogeneous_log_unchanged()-> Result<()>{
+        let depth: Vec<f64>=(0..50).map(|i| i as f64).collect();
+        let log=WellLog::new(depth, vec![3000.0; 50], vec![1500.0; 50], vec![2400.0; 50])?;
+
+        let upscaled=log.backus_average(BackusWindow::Depth(10.0))?;
+        for i in 0..50{
+            assert_abs_diff_eq!(upscaled.vp[i], 3000.0, epsilon=1e-9);
+            assert_abs_diff_eq!(upscaled.vs[i], 1500.0, epsilon=1e-9);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backus_matches_analytic_limit()-> Result<()>{
+        let upscaled=alternating().backus_average(BackusWindow::Depth(20.0))?;
+
+        //Equal proportions of the two layers
+        let rho=0.5*(2300.0+2500.0);
+        let c33=1.0/(0.5/(2300.0*2500.0f64.powi(2))+0.5/(2500.0*3500.0f64.powi(2)));
+        let expected_vp=(c33/rho).sqrt();
+
+        assert_abs_diff_eq!(upscaled.rho[200], rho, epsilon=10.0);
+        assert_abs_diff_eq!(upscaled.vp[200], expected_vp, epsilon=15.0);
+        //Backus velocity lies below the arithmetic mean velocity
+        assert!(upscaled.vp[200]<3000.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_window()-> Result<()>{
+        let log=alternating();
+        let upscaled=log.backus_average(BackusWindow::TwoWayTime(8.0))?;
+
+        assert_eq!(upscaled.len(), log.len());
+        let spread=upscaled.vp[100..300].iter().fold(0.0f64, |a, &b| a.max(b))-upscaled.vp[100..300].iter().fold(f64::INFINITY, |a, &b| a.min(b));
+        assert!(spread<100.0);
+        assert!(log.backus_average(BackusWindow::TwoWayTime(0.0)).is_err());
+
+        Ok(())
+    }
+}
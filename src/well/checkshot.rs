@@ -0,0 +1,211 @@
+//! Checkshot calibration of the sonic time-depth relationship
+//!
+//! Integrated sonic times drift from true seismic times because of
+//! dispersion, washouts and missing log intervals. The drift is measured at
+//! the checkshot depths, fitted with a natural cubic spline and added to the
+//! sonic curve before the logs are resampled to two-way time.
+
+use anyhow::{Result, anyhow, Context};
+use crate::models::elastic::ElasticModel;
+use crate::utils::spline::CubicSpline;
+use super::WellLog;
+
+///A single checkshot: depth in metres and two-way time in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CheckshotPair{
+    pub depth: f64,
+    pub twt: f64,
+}
+
+///Checkshot survey sorted by depth
+#[derive(Debug, Clone)]
+pub struct Checkshots{
+    pub pairs: Vec<CheckshotPair>,
+}
+
+impl Checkshots{
+    ///Create a survey, checking that every pair is finite and that depth and time both increase
+    pub fn new(mut pairs: Vec<CheckshotPair>)-> Result<Self>{
+        if pairs.is_empty(){
+            return Err(anyhow!("Checkshot survey is empty"));
+        }
+        if let Some(pair)=pairs.iter().find(|p| !(p.depth.is_finite() && p.twt.is_finite())){
+            return Err(anyhow!("Checkshot depth and time must be finite, got {} m at {} s", pair.depth, pair.twt));
+        }
+        pairs.sort_by(|a, b| a.depth.total_cmp(&b.depth));
+        if pairs.windows(2).any(|w| w[1].depth<=w[0].depth || w[1].twt<=w[0].twt){
+            return Err(anyhow!("Checkshot depths and times must be strictly increasing"));
+        }
+        Ok(Self{ pairs })
+    }
+
+    ///Load a CSV with a header row and columns `depth_m, owt_ms`
+    ///
+    /// Checkshots are normally delivered as one-way time; they are converted
+    /// to two-way time in seconds on load.
+    pub fn load_csv(path: &str)-> Result<Self>{
+        let mut reader=csv::Reader::from_path(path).with_context(|| format!("Failed to open checkshot file: {}", path))?;
+        let mut pairs=Vec::new();
+
+        for (line, record) in reader.records().enumerate(){
+            let record=record?;
+            let field=|i: usize| -> Result<f64> {
+                record.get(i)
+                    .ok_or_else(|| anyhow!("Row {} has fewer than two columns", line+1))?
+                    .trim()
+                    .parse::<f64>()
+                    .with_context(|| format!("Invalid number in row {}", line+1))
+            };
+            pairs.push(CheckshotPair{ depth: field(0)?, twt: 2.0*field(1)?/1000.0 });
+        }
+
+        Self::new(pairs)
+    }
+}
+
+///Two-way time at each depth sample of a log
+#[derive(Debug, Clone)]
+pub struct TimeDepthCurve{
+    pub depth: Vec<f64>,
+    pub twt: Vec<f64>,
+}
+
+impl TimeDepthCurve{
+    ///Two-way time at a depth by linear interpolation, extrapolated with the end gradient
+    pub fn time_at(&self, depth: f64)-> f64{
+        interpolate(&self.depth, &self.twt, depth)
+    }
+
+    ///Depth at a two-way time by linear interpolation
+    pub fn depth_at(&self, twt: f64)-> f64{
+        interpolate(&self.twt, &self.depth, twt)
+    }
+}
+
+impl WellLog{
+    ///Sonic time-depth curve corrected for drift against the checkshots
+    ///
+    /// Checkshots outside the logged interval are ignored; beyond the first
+    /// and last usable checkshot the drift is held constant.
+    pub fn calibrated_time_depth(&self, checkshots: &Checkshots)-> Result<TimeDepthCurve>{
+        if self.len()<2{
+            return Err(anyhow!("Need at least two log samples to build a time-depth curve"));
+        }
+        let sonic=TimeDepthCurve{ depth: self.depth.clone(), twt: self.two_way_time() };
+        let (top, base)=(self.depth[0], self.depth[self.len()-1]);
+
+        let (knots, drift): (Vec<f64>, Vec<f64>)=checkshots.pairs.iter()
+            .filter(|p| p.depth>=top && p.depth<=base)
+            .map(|p| (p.depth, p.twt-sonic.time_at(p.depth)))
+            .unzip();
+
+        let correction: Box<dyn Fn(f64)-> f64>=match knots.len(){
+            0=> return Err(anyhow!("No checkshots fall within the logged interval {:.1}-{:.1} m", top, base)),
+            1=> { let shift=drift[0]; Box::new(move |_| shift) },
+            _=> { let spline=CubicSpline::natural(&knots, &drift)?; Box::new(move |z| spline.evaluate(z)) },
+        };
+
+        let twt: Vec<f64>=sonic.depth.iter().zip(&sonic.twt).map(|(&z, &t)| t+correction(z)).collect();
+        if twt.windows(2).any(|w| w[1]<=w[0]){
+            return Err(anyhow!("Drift correction produced a non-monotonic time-depth curve; check the checkshots"));
+        }
+
+        Ok(TimeDepthCurve{ depth: sonic.depth, twt })
+    }
+
+    ///Resample the logs onto a regular two-way time axis
+    ///
+    /// Returns the time of the first output sample (the first multiple of
+    /// `dt` at or below the top of the log) together with the model.
+    pub fn to_time_domain(&self, curve: &TimeDepthCurve, dt: f64)-> Result<(f64, ElasticModel)>{
+        if curve.depth.len()!=self.len(){
+            return Err(anyhow!("Time-depth curve has {} samples but the log has {}", curve.depth.len(), self.len()));
+        }
+        if dt<=0.0{
+            return Err(anyhow!("Sample interval must be positive, got {}", dt));
+        }
+
+        let start=(curve.twt[0]/dt).ceil()*dt;
+        let end=curve.twt[curve.twt.len()-1];
+        let n=((end-start)/dt).floor() as usize+1;
+        let times: Vec<f64>=(0..n).map(|i| start+i as f64*dt).collect();
+        let resample=|values: &[f64]| times.iter().map(|&t| interpolate(&curve.twt, values, t)).collect::<Vec<f64>>();
+
+        let model=ElasticModel::new(resample(&self.vp), resample(&self.vs), resample(&self.rho), dt)?;
+        Ok((start, model))
+    }
+}
+
+///Piecewise-linear interpolation on increasing `x`, extrapolating the end segments
+fn interpolate(x: &[f64], y: &[f64], at: f64)-> f64{
+    let n=x.len();
+    let k=x.partition_point(|&v| v<=at).clamp(1, n-1);
+    let w=(at-x[k-1])/(x[k]-x[k-1]);
+    y[k-1]+w*(y[k]-y[k-1])
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn constant_log(vp: f64)-> WellLog{
+        let depth: Vec<f64>=(0..=1000).map(|i| 1000.0+i as f64).collect();
+        WellLog::new(depth, vec![vp; 1001], vec![vp/2.0; 1001], vec![2400.0; 1001]).unwrap()
+    }
+
+    #[test]
+    fn test_calibration_matches_checkshots()-> Result<()>{
+        //Sonic reads 2500 m/s; the true interval velocity is 2400 m/s and the overburden is 0.8 s TWT
+        let log=constant_log(2500.0);
+        let pairs=(0..5).map(|k| {
+            let depth=1000.0+250.0*k as f64;
+            CheckshotPair{ depth, twt: 0.8+2.0*(depth-1000.0)/2400.0 }
+        }).collect();
+        let checkshots=Checkshots::new(pairs)?;
+
+        let curve=log.calibrated_time_depth(&checkshots)?;
+        for pair in &checkshots.pairs{
+            assert_abs_diff_eq!(curve.time_at(pair.depth), pair.twt, epsilon=1e-9);
+        }
+        //The drift is linear in depth, so the spline reproduces it between checkshots
+        assert_abs_diff_eq!(curve.time_at(1600.0), 0.8+1200.0/2400.0, epsilon=1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_single_checkshot_shifts_curve()-> Result<()>{
+        let log=constant_log(2000.0);
+        let checkshots=Checkshots::new(vec![CheckshotPair{ depth: 1000.0, twt: 1.2 }])?;
+        let curve=log.calibrated_time_depth(&checkshots)?;
+
+        assert_abs_diff_eq!(curve.twt[0], 1.2, epsilon=1e-12);
+        assert_abs_diff_eq!(curve.twt[1000], 1.2+1.0, epsilon=1e-9);
+
+        let outside=Checkshots::new(vec![CheckshotPair{ depth: 500.0, twt: 0.5 }])?;
+        assert!(log.calibrated_time_depth(&outside).is_err());
+        assert!(Checkshots::new(vec![CheckshotPair{ depth: 1000.0, twt: 1.2 }, CheckshotPair{ depth: f64::NAN, twt: 1.3 }]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_domain_conversion_and_csv()-> Result<()>{
+        let path=std::env::temp_dir().join("checkshots_test.csv");
+        std::fs::write(&path, "depth_m,owt_ms\n1000,400\n2000,900\n")?;
+        let checkshots=Checkshots::load_csv(path.to_str().unwrap())?;
+        std::fs::remove_file(&path)?;
+        assert_abs_diff_eq!(checkshots.pairs[1].twt, 1.8, epsilon=1e-12);
+
+        let log=constant_log(2000.0);
+        let curve=log.calibrated_time_depth(&checkshots)?;
+        let (start, model)=log.to_time_domain(&curve, 0.002)?;
+
+        assert_abs_diff_eq!(start, 0.8, epsilon=1e-9);
+        assert_eq!(model.len(), 501);
+        assert_abs_diff_eq!(model.vp[250], 2000.0, epsilon=1e-9);
+
+        Ok(())
+    }
+}
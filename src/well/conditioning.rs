@@ -0,0 +1,190 @@
+//! Log conditioning: null handling, despiking, smoothing and sonic conversion
+
+use anyhow::{Result, anyhow};
+use super::WellLog;
+
+///Null value written by most LAS exporters
+pub const LAS_NULL: f64=-999.25;
+
+///Units of a sonic (DT) log
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SonicUnit{
+    MicrosecondsPerFoot,
+    MicrosecondsPerMetre,
+}
+
+///Convert a sonic slowness log to velocity in m/s, keeping nulls as NaN
+pub fn sonic_to_velocity(dt: &[f64], unit: SonicUnit)-> Vec<f64>{
+    let metres_per_unit=match unit{
+        SonicUnit::MicrosecondsPerFoot=> 0.3048,
+        SonicUnit::MicrosecondsPerMetre=> 1.0,
+    };
+    dt.iter().map(|&slowness| {
+        if slowness.is_finite() && slowness>0.0 { 1.0e6*metres_per_unit/slowness } else { f64::NAN }
+    }).collect()
+}
+
+///Replace null markers and non-finite values by NaN
+pub fn mark_nulls(values: &mut [f64], null_value: f64){
+    for v in values.iter_mut(){
+        if !v.is_finite() || (*v-null_value).abs()<1e-6{
+            *v=f64::NAN;
+        }
+    }
+}
+
+///Fill NaN gaps by linear interpolation in depth, holding the end values outwards
+pub fn fill_gaps(depth: &[f64], values: &mut [f64])-> Result<()>{
+    if depth.len()!=values.len(){
+        return Err(anyhow!("Depth ({}) and log ({}) lengths differ", depth.len(), values.len()));
+    }
+    let valid: Vec<usize>=(0..values.len()).filter(|&i| values[i].is_finite()).collect();
+    let (first, last)=match (valid.first(), valid.last()){
+        (Some(&f), Some(&l))=> (f, l),
+        _=> return Err(anyhow!("Log contains no valid samples")),
+    };
+
+    let first_value=values[first];
+    values[..first].fill(first_value);
+    let last_value=values[last];
+    values[last+1..].fill(last_value);
+
+    for pair in valid.windows(2){
+        let (a, b)=(pair[0], pair[1]);
+        for i in a+1..b{
+            let w=(depth[i]-depth[a])/(depth[b]-depth[a]);
+            values[i]=values[a]+w*(values[b]-values[a]);
+        }
+    }
+
+    Ok(())
+}
+
+///Replace spikes with the running median
+///
+/// A sample is a spike when it departs from the median of the surrounding
+/// `half_window` samples on either side by more than `threshold` scaled
+/// median absolute deviations. Returns the number of samples replaced.
+pub fn despike(values: &mut [f64], half_window: usize, threshold: f64)-> usize{
+    let original=values.to_vec();
+    let mut replaced=0;
+
+    for i in 0..original.len(){
+        let lo=i.saturating_sub(half_window);
+        let hi=(i+half_window+1).min(original.len());
+        let mut window: Vec<f64>=original[lo..hi].iter().copied().filter(|v| v.is_finite()).collect();
+        if window.len()<3 || !original[i].is_finite(){
+            continue;
+        }
+
+        let centre=median(&mut window);
+        let mut deviations: Vec<f64>=window.iter().map(|v| (v-centre).abs()).collect();
+        //1.4826 makes the MAD consistent with the standard deviation for Gaussian data
+        let mad=1.4826*median(&mut deviations);
+
+        if (original[i]-centre).abs()>threshold*mad.max(f64::EPSILON*centre.abs()){
+            values[i]=centre;
+            replaced+=1;
+        }
+    }
+
+    replaced
+}
+
+///Centred moving average over `2*half_window+1` samples, ignoring NaNs
+pub fn smooth(values: &[f64], half_window: usize)-> Vec<f64>{
+    (0..values.len()).map(|i| {
+        let lo=i.saturating_sub(half_window);
+        let hi=(i+half_window+1).min(values.len());
+        let (sum, count)=values[lo..hi].iter().filter(|v| v.is_finite()).fold((0.0, 0), |(s, c), &v| (s+v, c+1));
+        if count>0 { sum/count as f64 } else { f64::NAN }
+    }).collect()
+}
+
+fn median(values: &mut [f64])-> f64{
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n=values.len();
+    if n%2==1 { values[n/2] } else { 0.5*(values[n/2-1]+values[n/2]) }
+}
+
+impl WellLog{
+    ///Build a log set from raw sonic, shear sonic and density curves
+    ///
+    /// Nulls are marked, gaps filled and spikes removed before the sonic
+    /// logs are converted to velocity. Density is expected in g/cc.
+    pub fn from_raw_logs(
+        depth: Vec<f64>,
+        mut dtp: Vec<f64>,
+        mut dts: Vec<f64>,
+        mut rhob: Vec<f64>,
+        unit: SonicUnit,
+    )-> Result<Self>{
+        for curve in [&mut dtp, &mut dts, &mut rhob]{
+            mark_nulls(curve, LAS_NULL);
+            fill_gaps(&depth, curve)?;
+            despike(curve, 5, 3.0);
+        }
+
+        let vp=sonic_to_velocity(&dtp, unit);
+        let vs=sonic_to_velocity(&dts, unit);
+        let rho=rhob.iter().map(|g_cc| g_cc*1000.0).collect();
+
+        WellLog::new(depth, vp, vs, rho)
+    }
+
+    ///Smooth all three elastic curves with a centred moving average
+    pub fn smoothed(&self, half_window: usize)-> Result<WellLog>{
+        WellLog::new(self.depth.clone(), smooth(&self.vp, half_window), smooth(&self.vs, half_window), smooth(&self.rho, half_window))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_sonic_conversion(){
+        let vp=sonic_to_velocity(&[100.0, 328.084, LAS_NULL], SonicUnit::MicrosecondsPerFoot);
+        assert_abs_diff_eq!(vp[0], 3048.0, epsilon=1e-9);
+        assert_abs_diff_eq!(vp[1], 929.0, epsilon=0.1);
+        assert!(vp[2].is_nan());
+    }
+
+    #[test]
+    fn test_fill_gaps_interpolates_and_extends()-> Result<()>{
+        let depth=vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let mut values=vec![LAS_NULL, 10.0, LAS_NULL, LAS_NULL, 16.0, LAS_NULL];
+        mark_nulls(&mut values, LAS_NULL);
+        fill_gaps(&depth, &mut values)?;
+
+        assert_eq!(values, vec![10.0, 10.0, 12.0, 14.0, 16.0, 16.0]);
+        assert!(fill_gaps(&depth, &mut [f64::NAN; 6]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_despike_removes_isolated_spike(){
+        let mut values: Vec<f64>=(0..50).map(|i| 2.4+0.001*i as f64).collect();
+        values[20]=5.0;
+
+        let replaced=despike(&mut values, 4, 3.0);
+        assert_eq!(replaced, 1);
+        assert_abs_diff_eq!(values[20], 2.42, epsilon=0.005);
+    }
+
+    #[test]
+    fn test_from_raw_logs()-> Result<()>{
+        let depth: Vec<f64>=(0..20).map(|i| 1500.0+0.15*i as f64).collect();
+        let mut dtp=vec![100.0; 20];
+        dtp[3]=LAS_NULL;
+        let log=WellLog::from_raw_logs(depth, dtp, vec![200.0; 20], vec![2.3; 20], SonicUnit::MicrosecondsPerFoot)?;
+
+        assert!(log.vp.iter().all(|&v| (v-3048.0).abs()<1e-6));
+        assert_abs_diff_eq!(log.vs[0], 1524.0, epsilon=1e-6);
+        assert_abs_diff_eq!(log.rho[0], 2300.0, epsilon=1e-9);
+
+        Ok(())
+    }
+}
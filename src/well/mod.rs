@@ -0,0 +1,73 @@
+//! Well logs, log conditioning and upscaling
+
+use anyhow::{Result, anyhow};
+
+pub mod backus;
+pub mod checkshot;
+pub mod conditioning;
+pub mod extraction;
+pub mod tie;
+
+///Elastic well logs sampled in depth
+#[derive(Debug, Clone)]
+pub struct WellLog{
+    ///Measured depth in metres, increasing
+    pub depth: Vec<f64>,
+    ///P-wave velocity in m/s
+    pub vp: Vec<f64>,
+    ///S-wave velocity in m/s
+    pub vs: Vec<f64>,
+    ///Density in kg/m^3
+    pub rho: Vec<f64>,
+}
+
+impl WellLog{
+    ///Create a log set, checking lengths and that depth increases
+    pub fn new(depth: Vec<f64>, vp: Vec<f64>, vs: Vec<f64>, rho: Vec<f64>)-> Result<Self>{
+        let n=depth.len();
+        if vp.len()!=n || vs.len()!=n || rho.len()!=n{
+            return Err(anyhow!("Log lengths differ: depth {}, vp {}, vs {}, rho {}", n, vp.len(), vs.len(), rho.len()));
+        }
+        if depth.windows(2).any(|w| w[1]<=w[0]){
+            return Err(anyhow!("Depth must be strictly increasing"));
+        }
+        Ok(Self{ depth, vp, vs, rho })
+    }
+
+    pub fn len(&self)-> usize{
+        self.depth.len()
+    }
+
+    pub fn is_empty(&self)-> bool{
+        self.depth.is_empty()
+    }
+
+    ///Thickness represented by each sample (half the distance to each neighbour)
+    pub fn sample_thickness(&self)-> Vec<f64>{
+        let n=self.len();
+        (0..n).map(|i| {
+            let above=if i>0 { self.depth[i]-self.depth[i-1] } else { 0.0 };
+            let below=if i+1<n { self.depth[i+1]-self.depth[i] } else { 0.0 };
+            match (i>0, i+1<n){
+                (true, true)=> 0.5*(above+below),
+                (true, false)=> above,
+                (false, true)=> below,
+                (false, false)=> 0.0,
+            }
+        }).collect()
+    }
+
+    ///Two-way vertical travel time below the first sample, in seconds
+    pub fn two_way_time(&self)-> Vec<f64>{
+        let mut time=Vec::with_capacity(self.len());
+        let mut t=0.0;
+        for i in 0..self.len(){
+            if i>0{
+                let slowness=0.5*(1.0/self.vp[i-1]+1.0/self.vp[i]);
+                t+=2.0*(self.depth[i]-self.depth[i-1])*slowness;
+            }
+            time.push(t);
+        }
+        time
+    }
+}
@@ -0,0 +1,319 @@
+//! Multi-well synthetic ties and calibration reporting
+//!
+//! At each well a synthetic is built from the calibrated logs, shifted to
+//! best match the seismic trace at the well location, and the constant phase
+//! rotation that would further improve the match is measured. Consistent
+//! residual phase across wells points at a wavelet phase error; scattered
+//! values point at individual ties or logs.
+
+use anyhow::{Result, anyhow, Context};
+use crate::convolution::ConvolutionEngine;
+use crate::operators::{ConvolutionOperator, LinearOperator};
+use crate::inversion::sparse::wavelet_centre;
+use crate::wavelets::RickerWavelet;
+use super::WellLog;
+use super::checkshot::Checkshots;
+
+///A well and the section trace it sits on
+#[derive(Debug, Clone)]
+pub struct WellLocation{
+    pub name: String,
+    ///Index of the nearest trace in the section
+    pub trace: usize,
+    pub log: WellLog,
+    pub checkshots: Checkshots,
+}
+
+///Tie result for one well
+#[derive(Debug, Clone)]
+pub struct WellTie{
+    pub name: String,
+    pub trace: usize,
+    ///Normalised zero-phase correlation between synthetic and trace at the best lag
+    pub correlation: f64,
+    ///Shift in samples applied to the synthetic; positive moves it later
+    pub lag_samples: isize,
+    ///Constant phase rotation of the synthetic that best matches the trace, in degrees
+    pub residual_phase_deg: f64,
+    ///Correlation after applying the residual phase rotation
+    pub rotated_correlation: f64,
+    ///Synthetic on the section time axis, before shifting
+    pub synthetic: Vec<f64>,
+}
+
+///Aggregate calibration report over all wells
+#[derive(Debug, Clone)]
+pub struct CalibrationReport{
+    pub ties: Vec<WellTie>,
+    pub mean_correlation: f64,
+    ///Mean residual phase, averaged on the unit circle
+    pub mean_phase_deg: f64,
+}
+
+impl CalibrationReport{
+    ///Print a per-well summary table
+    pub fn print_summary(&self){
+        println!("Well tie calibration ({} wells)", self.ties.len());
+        println!("{:<16} {:>6} {:>8} {:>6} {:>10} {:>8}", "well", "trace", "corr", "lag", "phase", "rotated");
+        for tie in &self.ties{
+            println!("{:<16} {:>6} {:>8.3} {:>6} {:>9.1}° {:>8.3}",
+                tie.name, tie.trace, tie.correlation, tie.lag_samples, tie.residual_phase_deg, tie.rotated_correlation);
+        }
+        println!("Mean correlation: {:.3}, mean residual phase: {:.1}°", self.mean_correlation, self.mean_phase_deg);
+    }
+
+    ///Write the per-well results as CSV
+    pub fn to_csv(&self, path: &str)-> Result<()>{
+        let mut writer=csv::Writer::from_path(path).with_context(|| format!("Failed to create file: {}", path))?;
+        writer.write_record(["well", "trace", "correlation", "lag_samples", "residual_phase_deg", "rotated_correlation"])?;
+        for tie in &self.ties{
+            writer.write_record(&[
+                tie.name.clone(),
+                tie.trace.to_string(),
+                tie.correlation.to_string(),
+                tie.lag_samples.to_string(),
+                tie.residual_phase_deg.to_string(),
+                tie.rotated_correlation.to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+///Tie every well to its trace in `section` (indexed [trace][sample], starting at t=0)
+pub fn tie_wells(
+    section: &[Vec<f64>],
+    dt: f64,
+    wells: &[WellLocation],
+    wavelet: &RickerWavelet,
+    max_lag: usize,
+)-> Result<CalibrationReport>{
+    if wells.is_empty(){
+        return Err(anyhow!("No wells to tie"));
+    }
+
+    let mut engine=ConvolutionEngine::new();
+    let mut ties=Vec::with_capacity(wells.len());
+
+    for well in wells{
+        let trace=section.get(well.trace)
+            .ok_or_else(|| anyhow!("Well {} refers to trace {} but the section has {}", well.name, well.trace, section.len()))?;
+        let synthetic=well_synthetic(&mut engine, well, trace.len(), dt, wavelet)
+            .with_context(|| format!("Failed to build synthetic for well {}", well.name))?;
+
+        //Pick the lag on the correlation envelope so a phase error does not bias the shift
+        let quadrature=engine.hilbert(&synthetic);
+        let (lag, in_phase, out_of_phase)=(-(max_lag as isize)..=max_lag as isize)
+            .map(|lag| (lag, shifted_correlation(&synthetic, trace, lag), shifted_correlation(&quadrature, trace, lag)))
+            .fold((0, 0.0, 0.0), |best: (isize, f64, f64), c| if c.1.hypot(c.2)>best.1.hypot(best.2) { c } else { best });
+
+        //Best constant rotation of s*cos(p)-H(s)*sin(p) against the trace
+        let phase=(-out_of_phase).atan2(in_phase);
+
+        ties.push(WellTie{
+            name: well.name.clone(),
+            trace: well.trace,
+            correlation: in_phase,
+            lag_samples: lag,
+            residual_phase_deg: phase.to_degrees(),
+            rotated_correlation: in_phase.hypot(out_of_phase),
+            synthetic,
+        });
+    }
+
+    let mean_correlation=ties.iter().map(|t| t.correlation).sum::<f64>()/ties.len() as f64;
+    let (sin_sum, cos_sum)=ties.iter().fold((0.0, 0.0), |(s, c), t| {
+        let p=t.residual_phase_deg.to_radians();
+        (s+p.sin(), c+p.cos())
+    });
+
+    Ok(CalibrationReport{ ties, mean_correlation, mean_phase_deg: sin_sum.atan2(cos_sum).to_degrees() })
+}
+
+///Correlation against constant phase rotations of a wavelet
+#[derive(Debug, Clone)]
+pub struct PhaseScan{
+    ///Rotations tried, in degrees from -180 up to (not including) 180
+    pub phases_deg: Vec<f64>,
+    ///Best correlation over the allowed lags at each rotation
+    pub correlations: Vec<f64>,
+    pub best_phase_deg: f64,
+    pub best_correlation: f64,
+    ///Lag of the best match, in samples; positive delays the synthetic
+    pub best_lag: isize,
+}
+
+impl PhaseScan{
+    pub fn print_summary(&self){
+        println!("Phase scan: best {:.1}° (correlation {:.3}, lag {} samples)", self.best_phase_deg, self.best_correlation, self.best_lag);
+        for (phase, correlation) in self.phases_deg.iter().zip(&self.correlations).step_by((self.phases_deg.len()/12).max(1)){
+            println!("  {:>7.1}°  {:>6.3}", phase, correlation);
+        }
+    }
+}
+
+///Scan constant phase rotations of `wavelet` (time zero at `centre`) for the best match of `reflectivity` to `trace`
+///
+/// Each rotation is convolved with the reflectivity and correlated with the
+/// trace at every lag up to `max_lag`; the rotation with the highest
+/// correlation is the wavelet phase the data prefer.
+pub fn phase_scan(
+    reflectivity: &[f64],
+    trace: &[f64],
+    wavelet: &[f64],
+    centre: usize,
+    max_lag: usize,
+    step_deg: f64,
+)-> Result<PhaseScan>{
+    if !(step_deg>0.0 && step_deg<=180.0){
+        return Err(anyhow!("Phase step must be in (0, 180] degrees, got {}", step_deg));
+    }
+    if reflectivity.len()!=trace.len(){
+        return Err(anyhow!("Reflectivity ({}) and trace ({}) lengths differ", reflectivity.len(), trace.len()));
+    }
+
+    //Rotating the wavelet rotates the synthetic, so rotate once after convolving
+    let synthetic=ConvolutionOperator::new(wavelet, centre, trace.len())?.apply(reflectivity);
+    let mut engine=ConvolutionEngine::new();
+    let quadrature=engine.hilbert(&synthetic);
+
+    let steps=(360.0/step_deg).round() as usize;
+    let mut scan=PhaseScan{ phases_deg: Vec::with_capacity(steps), correlations: Vec::with_capacity(steps), best_phase_deg: 0.0, best_correlation: f64::NEG_INFINITY, best_lag: 0 };
+    for k in 0..steps{
+        let phase=-180.0+k as f64*step_deg;
+        let (sin, cos)=phase.to_radians().sin_cos();
+        let rotated: Vec<f64>=synthetic.iter().zip(&quadrature).map(|(s, h)| s*cos-h*sin).collect();
+        let (lag, correlation)=(-(max_lag as isize)..=max_lag as isize)
+            .map(|lag| (lag, shifted_correlation(&rotated, trace, lag)))
+            .fold((0, f64::NEG_INFINITY), |best, c| if c.1>best.1 { c } else { best });
+
+        if correlation>scan.best_correlation{
+            scan.best_phase_deg=phase;
+            scan.best_correlation=correlation;
+            scan.best_lag=lag;
+        }
+        scan.phases_deg.push(phase);
+        scan.correlations.push(correlation);
+    }
+
+    Ok(scan)
+}
+
+///Phase scan of `wavelet` at one well, using the well's calibrated synthetic reflectivity
+pub fn scan_well_phase(section: &[Vec<f64>], dt: f64, well: &WellLocation, wavelet: &RickerWavelet, max_lag: usize, step_deg: f64)-> Result<PhaseScan>{
+    let trace=section.get(well.trace)
+        .ok_or_else(|| anyhow!("Well {} refers to trace {} but the section has {}", well.name, well.trace, section.len()))?;
+    let reflectivity=well_reflectivity(well, trace.len(), dt)?;
+    phase_scan(&reflectivity, trace, &wavelet.samples, wavelet_centre(wavelet), max_lag, step_deg)
+}
+
+///Zero-offset reflectivity of the well on the section time axis
+pub(super) fn well_reflectivity(well: &WellLocation, num_samples: usize, dt: f64)-> Result<Vec<f64>>{
+    let curve=well.log.calibrated_time_depth(&well.checkshots)?;
+    let (start, model)=well.log.to_time_domain(&curve, dt)?;
+    let offset=(start/dt).round() as usize;
+
+    let mut reflectivity=vec![0.0; num_samples];
+    let model_reflectivity=model.reflectivity();
+    for (&position, &coefficient) in model_reflectivity.layer_positions.iter().zip(&model_reflectivity.reflection_coefficients){
+        if let Some(sample)=reflectivity.get_mut(offset+position){
+            *sample=coefficient;
+        }
+    }
+    Ok(reflectivity)
+}
+
+///Zero-offset synthetic at the well on the section time axis
+fn well_synthetic(engine: &mut ConvolutionEngine, well: &WellLocation, num_samples: usize, dt: f64, wavelet: &RickerWavelet)-> Result<Vec<f64>>{
+    let reflectivity=well_reflectivity(well, num_samples, dt)?;
+
+    //Drop the convolution lead-in so the wavelet centre lines up with each reflector
+    let centre=wavelet_centre(wavelet);
+    let full=engine.convolve(&reflectivity, &wavelet.samples)?;
+    Ok(full[centre..centre+num_samples].to_vec())
+}
+
+///Normalised correlation of `synthetic` delayed by `lag` samples against `trace`
+fn shifted_correlation(synthetic: &[f64], trace: &[f64], lag: isize)-> f64{
+    let (mut cross, mut energy_s, mut energy_t)=(0.0, 0.0, 0.0);
+    for (i, &s) in synthetic.iter().enumerate(){
+        let j=i as isize+lag;
+        if j<0 || j as usize>=trace.len(){
+            continue;
+        }
+        let t=trace[j as usize];
+        cross+=s*t;
+        energy_s+=s*s;
+        energy_t+=t*t;
+    }
+    if energy_s==0.0 || energy_t==0.0 { 0.0 } else { cross/(energy_s*energy_t).sqrt() }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::well::checkshot::CheckshotPair;
+
+    fn blocky_well(name: &str, trace: usize)-> WellLocation{
+        let depth: Vec<f64>=(0..=600).map(|i| 1000.0+i as f64).collect();
+        let vp: Vec<f64>=depth.iter().map(|&z| [2500.0, 3000.0][(z as usize/100)%2]).collect();
+        let log=WellLog::new(depth, vp, vec![1400.0; 601], vec![2300.0; 601]).unwrap();
+        let checkshots=Checkshots::new(vec![CheckshotPair{ depth: 1000.0, twt: 0.4 }]).unwrap();
+        WellLocation{ name: name.to_string(), trace, log, checkshots }
+    }
+
+    #[test]
+    fn test_tie_recovers_shift_and_phase()-> Result<()>{
+        let dt=0.002;
+        let wavelet=RickerWavelet::new(30.0, dt, 60)?;
+        let wells=vec![blocky_well("A", 0), blocky_well("B", 2)];
+        let mut engine=ConvolutionEngine::new();
+
+        //Trace 0 is the synthetic itself; trace 2 is delayed by 3 samples and rotated by 90 degrees
+        let synthetic=well_synthetic(&mut engine, &wells[0], 500, dt, &wavelet)?;
+        let quadrature=engine.hilbert(&synthetic);
+        let mut rotated=vec![0.0; 500];
+        for i in 0..497{
+            rotated[i+3]= -quadrature[i];
+        }
+        let section=vec![synthetic.clone(), vec![0.0; 500], rotated];
+
+        let report=tie_wells(&section, dt, &wells, &wavelet, 10)?;
+        assert_abs_diff_eq!(report.ties[0].correlation, 1.0, epsilon=1e-9);
+        assert_eq!(report.ties[0].lag_samples, 0);
+        assert_abs_diff_eq!(report.ties[0].residual_phase_deg, 0.0, epsilon=1e-6);
+
+        assert_eq!(report.ties[1].lag_samples, 3);
+        assert_abs_diff_eq!(report.ties[1].residual_phase_deg, 90.0, epsilon=2.0);
+        assert!(report.ties[1].rotated_correlation>0.95);
+
+        assert!(tie_wells(&section, dt, &[blocky_well("C", 7)], &wavelet, 10).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_phase_scan_finds_rotation()-> Result<()>{
+        let dt=0.002;
+        let wavelet=RickerWavelet::new(30.0, dt, 60)?;
+        let well=blocky_well("A", 0);
+        let mut engine=ConvolutionEngine::new();
+
+        //Observed trace: the well synthetic rotated by 60 degrees and delayed 2 samples
+        let synthetic=well_synthetic(&mut engine, &well, 500, dt, &wavelet)?;
+        let rotated=engine.rotate_phase(&synthetic, 60.0);
+        let mut observed=vec![0.0; 500];
+        observed[2..].copy_from_slice(&rotated[..498]);
+
+        let scan=scan_well_phase(&[observed], dt, &well, &wavelet, 5, 5.0)?;
+        assert_eq!(scan.phases_deg.len(), 72);
+        assert_abs_diff_eq!(scan.best_phase_deg, 60.0, epsilon=5.0);
+        assert_eq!(scan.best_lag, 2);
+        assert!(scan.best_correlation>0.95);
+
+        assert!(phase_scan(&[0.0; 4], &[0.0; 5], &[1.0], 0, 1, 10.0).is_err());
+        Ok(())
+    }
+}